@@ -30,12 +30,12 @@ fn main() -> Result<(), Error> {
     nn.add(Flatten::new());
     nn.add(Dense::new(500, Activation::Tanh));
     nn.add(Dense::new(300, Activation::Tanh));
-    nn.add(Dense::new(10, Activation::Softmax));
+    nn.add(Dense::new(10, Activation::Linear));
     println!("{}", nn);
 
 
     // Fit the network
-    nn.fit(&data, 100, 6, Some(1), Some(vec![Metrics::Accuracy]));
+    nn.fit(&data, 100, 6, Some(1), Some(vec![Metrics::Accuracy]))?;
 
     // Evaluate the trained model on the test set
     nn.evaluate(&data, Some(vec![Metrics::Accuracy]));
@@ -0,0 +1,20 @@
+use arrayfire::{device_count, device_info, get_available_backends, set_backend, set_device};
+use neuro::bench;
+
+fn main() {
+    let iterations = std::env::args().nth(1).and_then(|arg| arg.parse::<u64>().ok()).unwrap_or(20);
+
+    // Run the benchmarks on every available backend and device
+    for backend in get_available_backends() {
+        set_backend(backend);
+        for device in 0..device_count() {
+            set_device(device);
+            let (name, platform, _, _) = device_info();
+            println!("Backend: {:?}, device {}: {} ({})", backend, device, name, platform);
+            for result in bench::run_all(iterations) {
+                println!("{}", result);
+            }
+            println!();
+        }
+    }
+}
@@ -1,7 +1,6 @@
-use neuro::activations::Activation;
 use neuro::data::ImageDataSetBuilder;
 use neuro::errors::*;
-use neuro::layers::{Dense, Conv2D, Padding, MaxPool2D, Dropout, Flatten};
+use neuro::layers::{backbones, heads};
 use neuro::losses;
 use neuro::metrics::Metrics;
 use neuro::models::Network;
@@ -24,22 +23,13 @@ fn main() -> Result<(), Error> {
 
     // Create the neural network
     let mut nn = Network::new(Dim::new(&[32, 32, 3, 1]), losses::SoftmaxCrossEntropy::new(), Adam::new(0.001), None)?;
-    nn.add(Conv2D::new(32, (3, 3), (1, 1), Padding::Same));
-    nn.add(Conv2D::new(32, (3, 3), (1, 1), Padding::Same));
-    nn.add(MaxPool2D::new((2, 2)));
-    nn.add(Conv2D::new(64, (3, 3), (1, 1), Padding::Same));
-    nn.add(Conv2D::new(64, (3, 3), (1, 1), Padding::Same));
-    nn.add(MaxPool2D::new((2, 2)));
-    nn.add(Conv2D::new(64, (3, 3), (1, 1), Padding::Same));
-
-    nn.add(Flatten::new());
-    nn.add(Dense::new(64, Activation::ReLU));
-    nn.add(Dense::new(10, Activation::Softmax));
+    nn.add_all(backbones::simple_cnn(2));
+    nn.add_all(heads::classification(10, 0.5));
 
     println!("{}", nn);
 
     // Fit the model
-    nn.fit(&data, 32, 10, Some(1), Some(vec![Metrics::Accuracy]));
+    nn.fit(&data, 32, 10, Some(1), Some(vec![Metrics::Accuracy]))?;
     nn.save("cifar_model.h5")?;
 
     // Evaluate the trained model on the test set
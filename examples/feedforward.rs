@@ -26,7 +26,7 @@ fn main() -> Result<(), Error> {
     println!("{}", nn);
 
     // Train and save the model
-    nn.fit(&data, 64, 50, Some(10), None);
+    nn.fit(&data, 64, 50, Some(10), None)?;
     nn.save("feedforward.h5")?;
 
     // Predictions: create two inputs: (-0.5, 0.92, 0.35) and (0.45, -0.72, -0.12).
@@ -31,11 +31,11 @@ fn main() -> Result<(), Error> {
     nn.add(Flatten::new());
     nn.add(Dense::new(128, Activation::ReLU));
     nn.add(Dropout::new(0.25));
-    nn.add(Dense::new(10, Activation::Softmax));
+    nn.add(Dense::new(10, Activation::Linear));
     println!("{}", nn);
 
     // Fit the model
-    nn.fit(&data, 128, 10, Some(1), Some(vec![Metrics::Accuracy]));
+    nn.fit(&data, 128, 10, Some(1), Some(vec![Metrics::Accuracy]))?;
     nn.save("mnist_cnn.h5")?;
 
     // Evaluate the trained model on the test set
@@ -27,11 +27,11 @@ fn main() -> Result<(), Error> {
     let mut nn = Network::new(Dim::new(&[28, 28, 1, 1]), losses::SoftmaxCrossEntropy::new(), Adam::new(0.003), Some(Regularizer::L2(1e-3)))?;
     nn.add(Flatten::new());
     nn.add(Dense::new(32, Activation::ReLU));
-    nn.add(Dense::new(10, Activation::Softmax));
+    nn.add(Dense::new(10, Activation::Linear));
     println!("{}", nn);
 
     // Fit the network
-    nn.fit(&data, 128, 10, Some(1), Some(vec![Metrics::Accuracy]));
+    nn.fit(&data, 128, 10, Some(1), Some(vec![Metrics::Accuracy]))?;
     nn.save("mnist_feedforward.h5");
 
     // Evaluate the trained model on the test set
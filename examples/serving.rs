@@ -0,0 +1,20 @@
+use neuro::errors::*;
+use neuro::models::Network;
+use neuro::serving::Server;
+
+use std::time::Duration;
+
+// Run with: cargo run --example serving --features serving --release
+fn main() -> Result<(), Error> {
+
+    // Load a model trained with the feedforward example
+    let nn = Network::load("feedforward.h5")?;
+    println!("{}", nn);
+
+    // Batch up to 32 samples received within 10 ms
+    let server = Server::with_param(nn, 32, Duration::from_millis(10));
+
+    // Predictions can be requested with:
+    // curl -X POST -d '{"values": [-0.5, 0.92, 0.35, 0.45, -0.72, -0.12]}' http://localhost:8000/predict
+    server.serve("0.0.0.0:8000")
+}
@@ -23,7 +23,7 @@ fn main() -> Result<(), Error> {
     nn.add(Dense::with_param(1, Activation::Sigmoid, Initializer::UniformBounded(-1., 1.), Initializer::Zeros));
 
     // Fit the model
-    nn.fit(&data, 4, 10000, Some(1000), Some(vec![metrics::Metrics::Accuracy]));
+    nn.fit(&data, 4, 10000, Some(1000), Some(vec![metrics::Metrics::Accuracy]))?;
 
     // Compute the output for the training data
     let predictions = nn.predict(&x_train);
@@ -0,0 +1,54 @@
+#ifndef NEURO_H
+#define NEURO_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Primitive type of the values. Define NEURO_F64 when the library is built with the f64 feature. */
+#ifdef NEURO_F64
+typedef double neuro_float;
+#else
+typedef float neuro_float;
+#endif
+
+/* Opaque handle to a trained network. */
+typedef struct Network Network;
+
+/* Status codes returned by the functions. A panic inside the library is reported as NEURO_INTERNAL_ERROR. */
+#define NEURO_SUCCESS 0
+#define NEURO_INVALID_ARGUMENT (-1)
+#define NEURO_BUFFER_TOO_SMALL (-2)
+#define NEURO_INTERNAL_ERROR (-3)
+
+/* Returns the message describing the last failure of the calling thread, or NULL if no function failed.
+ * The message is owned by the library and remains valid until the next failure in the same thread. */
+const char *neuro_last_error(void);
+
+/* Loads a network from an HDF5 file. Returns NULL on failure, see neuro_last_error. */
+Network *neuro_network_load(const char *filename);
+
+/* Releases a network created by neuro_network_load. */
+void neuro_network_free(Network *network);
+
+/* Writes the four dimensions of the input shape in shape. Returns 0 on success. */
+int neuro_network_input_shape(const Network *network, uint64_t *shape);
+
+/* Writes the four dimensions of the output shape in shape. Returns 0 on success. */
+int neuro_network_output_shape(const Network *network, uint64_t *shape);
+
+/* Computes the output of the network for num_samples samples stored in column-major order.
+ * Returns 0 on success, -1 for invalid arguments, -2 if the output buffer is too small, and -3 on an internal error. */
+int neuro_network_predict(const Network *network,
+                          const neuro_float *input,
+                          uint64_t num_samples,
+                          neuro_float *output,
+                          uint64_t output_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* NEURO_H */
@@ -3,6 +3,7 @@
 use arrayfire::*;
 use std::convert::TryFrom;
 
+use crate::cpu::CpuActivation;
 use crate::tensor::*;
 use crate::tensor::PrimitiveType;
 
@@ -35,6 +36,19 @@ impl TryFrom<u8> for Activation {
     }
 }
 
+impl From<Activation> for CpuActivation {
+    fn from(activation: Activation) -> Self {
+        match activation {
+            Activation::LeakyReLU => CpuActivation::LeakyReLU,
+            Activation::Linear => CpuActivation::Linear,
+            Activation::ReLU => CpuActivation::ReLU,
+            Activation::Sigmoid => CpuActivation::Sigmoid,
+            Activation::Softmax => CpuActivation::Softmax,
+            Activation::Tanh => CpuActivation::Tanh,
+        }
+    }
+}
+
 impl Activation {
     pub fn eval(self, z: &Tensor) -> Tensor {
         match self {
@@ -0,0 +1,128 @@
+//! Acquisition functions for active learning.
+//!
+//! In active learning, a classifier trained on a small labeled set selects the samples of an unlabeled pool whose
+//! labels would be the most informative. The samples are scored by an acquisition function computed from the predicted
+//! class probabilities, and the samples with the highest scores are labeled next.
+use arrayfire::*;
+
+use crate::models::Network;
+use crate::tensor::*;
+
+/// Acquisition functions scoring the unlabeled samples.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Acquisition {
+    /// Entropy of the predicted class probabilities.
+    Entropy,
+    /// One minus the difference between the two highest class probabilities, such that the samples close to the
+    /// decision boundary have the highest scores.
+    Margin,
+    /// Bayesian Active Learning by Disagreement (Houlsby et al., 2011) estimated with Monte Carlo dropout: the mutual
+    /// information between the prediction and the parameters of the network, computed from `num_samples` stochastic
+    /// forward passes. The network must contain dropout layers.
+    Bald { num_samples: u64 },
+}
+
+/// Scores the samples of the unlabeled pool.
+///
+/// The class probabilities are computed with [Network::predict_proba](../models/struct.Network.html#method.predict_proba)
+/// or [Network::predict_proba_mc](../models/struct.Network.html#method.predict_proba_mc). A single output is
+/// interpreted as the probability of the positive class of a binary classifier.
+///
+/// # Arguments
+///
+/// * `network` - The classifier.
+/// * `pool` - The unlabeled samples, stacked along the fourth dimension.
+/// * `acquisition` - The acquisition function.
+///
+/// # Return value
+///
+/// Tensor of shape [1, 1, 1, number of samples] containing the score of each sample.
+pub fn acquisition_scores(network: &Network, pool: &Tensor, acquisition: Acquisition) -> Tensor {
+    match acquisition {
+        Acquisition::Entropy => entropy(&class_probabilities(network.predict_proba(pool))),
+        Acquisition::Margin => {
+            let probabilities = class_probabilities(network.predict_proba(pool));
+            let sorted = sort(&probabilities, 0, false);
+            let top = |rank: f64| index(&sorted, &[Seq::new(rank, rank, 1.), Seq::default(), Seq::default(), Seq::default()]);
+            sub(&(1 as PrimitiveType), &sub(&top(0.), &top(1.), false), false)
+        },
+        Acquisition::Bald { num_samples } => {
+            assert!(num_samples > 0, "The number of Monte Carlo samples must be greater than zero.");
+            let samples: Vec<Tensor> = network.predict_proba_mc(pool, num_samples).into_iter().map(class_probabilities).collect();
+            let mean_probabilities = samples.iter().skip(1).fold(samples[0].copy(), |acc, p| add(&acc, p, false)) / num_samples as PrimitiveType;
+            let mean_entropy = samples.iter().skip(1).fold(entropy(&samples[0]), |acc, p| add(&acc, &entropy(p), false)) / num_samples as PrimitiveType;
+            sub(&entropy(&mean_probabilities), &mean_entropy, false)
+        },
+    }
+}
+
+/// Returns the indices of the samples of the pool to label next, from the most to the least informative.
+///
+/// # Arguments
+///
+/// * `network` - The classifier.
+/// * `pool` - The unlabeled samples, stacked along the fourth dimension.
+/// * `acquisition` - The acquisition function.
+/// * `num_queries` - The number of samples to select.
+pub fn select_queries(network: &Network, pool: &Tensor, acquisition: Acquisition, num_queries: usize) -> Vec<u64> {
+    let scores = acquisition_scores(network, pool, acquisition);
+    let mut scores_host = vec![0 as PrimitiveType; scores.elements()];
+    scores.host(&mut scores_host);
+    top_indices(&scores_host, num_queries)
+}
+
+/// Returns the indices of the highest scores in decreasing order, the NaN scores coming last.
+fn top_indices(scores: &[PrimitiveType], count: usize) -> Vec<u64> {
+    let mut indices: Vec<u64> = (0..scores.len() as u64).collect();
+    indices.sort_by(|&a, &b| {
+        let (a, b) = (scores[a as usize], scores[b as usize]);
+        b.partial_cmp(&a).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+    });
+    indices.truncate(count);
+    indices
+}
+
+/// Converts the output of a binary classifier with a single output into the probabilities of both classes.
+fn class_probabilities(probabilities: Tensor) -> Tensor {
+    if probabilities.dims()[0] == 1 {
+        join(0, &probabilities, &sub(&(1 as PrimitiveType), &probabilities, false))
+    } else {
+        probabilities
+    }
+}
+
+/// Computes the entropy of the class probabilities of each sample.
+fn entropy(probabilities: &Tensor) -> Tensor {
+    let log_probabilities = log(&clamp(probabilities, &(1e-15 as PrimitiveType), &(1 as PrimitiveType), false));
+    -sum(&mul(probabilities, &log_probabilities, false), 0)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::active_learning::*;
+    use crate::losses::CrossEntropy;
+    use crate::models::Network;
+    use crate::optimizers::SGD;
+
+    #[test]
+    fn test_acquisition_scores() {
+        // Without any layer, the network returns its input as probabilities
+        let network = Network::new(Dim4::new(&[3, 1, 1, 1]), CrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        let pool = Tensor::new(&[0.9, 0.05, 0.05, 0.4, 0.35, 0.25, 1. / 3., 1. / 3., 1. / 3.], Dim4::new(&[3, 1, 1, 3]));
+
+        assert_eq!(select_queries(&network, &pool, Acquisition::Entropy, 2), vec![2, 1]);
+        assert_eq!(select_queries(&network, &pool, Acquisition::Margin, 3), vec![2, 1, 0]);
+
+        // Without dropout, the stochastic passes agree and the mutual information vanishes
+        let mut scores = [0 as PrimitiveType; 3];
+        acquisition_scores(&network, &pool, Acquisition::Bald { num_samples: 3 }).host(&mut scores);
+        assert!(scores.iter().all(|score| score.abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_top_indices() {
+        assert_eq!(top_indices(&[0.1, PrimitiveType::NAN, 0.7, 0.3], 3), vec![2, 3, 0]);
+    }
+}
@@ -0,0 +1,182 @@
+//! Anomaly detection with autoencoders.
+//!
+//! An autoencoder trained to reconstruct normal samples reconstructs anomalous samples poorly. The
+//! [AnomalyDetector](struct.AnomalyDetector.html) scores the samples by their reconstruction error and flags the samples
+//! whose score exceeds a threshold computed on held-out normal samples.
+use arrayfire::*;
+
+use crate::data::{DataSetError, TabularDataSet};
+use crate::errors::Error;
+use crate::io::{read_scalar, write_scalar};
+use crate::models::Network;
+use crate::tensor::*;
+use crate::training::{FitConfig, History};
+
+/// Autoencoder scoring the samples by their reconstruction error.
+pub struct AnomalyDetector {
+    network: Network,
+    threshold: PrimitiveType,
+}
+
+impl AnomalyDetector {
+    /// Trains an autoencoder on normal samples and computes the threshold of the scores.
+    ///
+    /// The threshold is the `quantile` of the reconstruction errors of the validation samples, such that about a
+    /// fraction `1 - quantile` of normal samples are flagged as anomalous.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The autoencoder, whose output shape must match its input shape.
+    /// * `x_train` - The normal samples used for training, stacked along the fourth dimension.
+    /// * `x_valid` - The normal samples used to compute the threshold, stacked along the fourth dimension.
+    /// * `config` - The configuration of the training.
+    /// * `quantile` - The quantile of the validation scores used as threshold, between 0 and 1.
+    ///
+    /// # Return value
+    ///
+    /// The detector and the history of the training.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shapes of the samples or of the output don't match the input shape of the network, if
+    /// there is no validation sample, or if the training fails.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the quantile is not between 0 and 1.
+    pub fn fit(mut network: Network,
+               x_train: &Tensor,
+               x_valid: &Tensor,
+               config: FitConfig,
+               quantile: f64,
+    ) -> Result<(AnomalyDetector, History), Error> {
+        assert!((0. ..=1.).contains(&quantile), "The quantile must be between 0 and 1.");
+        if network.output_shape() != network.input_shape() {
+            return Err(Error::OutputShapeMismatch);
+        }
+        if Self::sample_shape(x_train) != network.input_shape() || Self::sample_shape(x_valid) != network.input_shape() {
+            return Err(Error::InputShapeMismatch);
+        }
+        if x_valid.batch_size() == 0 {
+            return Err(DataSetError::MissingValidationSet.into());
+        }
+
+        // The autoencoder reconstructs its input
+        let data = TabularDataSet::from_tensor(x_train.copy(), x_train.copy(), Some(x_valid.copy()), Some(x_valid.copy()), None, None)?;
+        let history = network.fit_with_config(&data, config)?;
+        network.eval_mode();
+
+        let mut detector = AnomalyDetector { network, threshold: 0. };
+        let mut scores = vec![0 as PrimitiveType; x_valid.batch_size() as usize];
+        detector.score(x_valid).host(&mut scores);
+        // The NaN scores of a diverged training are sorted last instead of panicking
+        scores.sort_by(|a, b| a.total_cmp(b));
+        let index = ((scores.len() - 1) as f64 * quantile).round() as usize;
+        detector.threshold = scores[index];
+
+        Ok((detector, history))
+    }
+
+    /// Creates a detector from a trained autoencoder and a threshold.
+    pub fn from_network(network: Network, threshold: PrimitiveType) -> AnomalyDetector {
+        AnomalyDetector { network, threshold }
+    }
+
+    /// Returns the shape of a sample.
+    fn sample_shape(x: &Tensor) -> Dim {
+        let dims = x.dims();
+        Dim::new(&[dims[0], dims[1], dims[2], 1])
+    }
+
+    /// Returns the autoencoder.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Returns the threshold above which a sample is anomalous.
+    pub fn threshold(&self) -> PrimitiveType {
+        self.threshold
+    }
+
+    /// Sets the threshold above which a sample is anomalous.
+    pub fn set_threshold(&mut self, threshold: PrimitiveType) {
+        self.threshold = threshold;
+    }
+
+    /// Computes the anomaly scores of the samples.
+    ///
+    /// The score of a sample is the mean squared error of its reconstruction.
+    ///
+    /// # Return value
+    ///
+    /// Tensor of shape [1, 1, 1, batch size] containing the score of each sample.
+    pub fn score(&self, x: &Tensor) -> Tensor {
+        let error = pow(&sub(&self.network.predict(x), x, false), &(2 as PrimitiveType), false);
+        let dims = error.dims();
+        let flat_error = moddims(&error, Dim::new(&[dims[0] * dims[1] * dims[2], 1, 1, dims[3]]));
+        mean(&flat_error, 0)
+    }
+
+    /// Flags the samples whose score exceeds the threshold.
+    pub fn is_anomalous(&self, x: &Tensor) -> Vec<bool> {
+        let mut scores = vec![0 as PrimitiveType; x.batch_size() as usize];
+        self.score(x).host(&mut scores);
+        scores.iter().map(|&score| score > self.threshold).collect()
+    }
+
+    /// Saves the autoencoder and the threshold in HDF5 format.
+    pub fn save(&self, filename: &str) -> Result<(), Error> {
+        self.network.save(filename)?;
+        let file = hdf5::File::open_rw(filename)?;
+        let threshold = file.new_dataset::<PrimitiveType>().create("anomaly_threshold", 1)?;
+        write_scalar(&threshold, &self.threshold);
+        Ok(())
+    }
+
+    /// Loads a detector saved with [save](#method.save).
+    pub fn load(filename: &str) -> Result<AnomalyDetector, Error> {
+        let network = Network::load(filename)?;
+        let file = hdf5::File::open(filename)?;
+        let threshold = read_scalar::<PrimitiveType>(&file.dataset("anomaly_threshold")?);
+        Ok(AnomalyDetector { network, threshold })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::activations::Activation;
+    use crate::anomaly::AnomalyDetector;
+    use crate::assert_approx_eq;
+    use crate::data::DataSetError;
+    use crate::errors::Error;
+    use crate::layers::Dense;
+    use crate::losses::MeanSquaredError;
+    use crate::models::Network;
+    use crate::optimizers::SGD;
+    use crate::tensor::*;
+    use crate::training::FitConfig;
+
+    #[test]
+    fn test_score() {
+        // Without any layer, the network reconstructs its input exactly
+        let network = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        let detector = AnomalyDetector::from_network(network, 0.5);
+        let x = Tensor::new(&[1., 2., 3., 4.], Dim4::new(&[2, 1, 1, 2]));
+        let mut scores = [0 as PrimitiveType; 2];
+        detector.score(&x).host(&mut scores);
+        assert_approx_eq!(scores, [0., 0.]);
+        assert_eq!(detector.is_anomalous(&x), vec![false, false]);
+    }
+
+    #[test]
+    fn test_fit_without_validation_samples() {
+        let mut network = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        network.add(Dense::new(2, Activation::Linear));
+        let x_train = Tensor::new(&[1., 2., 3., 4.], Dim4::new(&[2, 1, 1, 2]));
+        let x_valid = Tensor::new(&[] as &[PrimitiveType], Dim4::new(&[2, 1, 1, 0]));
+        let result = AnomalyDetector::fit(network, &x_train, &x_valid, FitConfig::new().verbose(false), 0.9);
+        assert!(matches!(result, Err(Error::DataSetError(DataSetError::MissingValidationSet))));
+    }
+}
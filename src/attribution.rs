@@ -0,0 +1,128 @@
+//! Attribution of the predictions of a network to its input features.
+//!
+//! An attribution method assigns to each input feature of a sample a score measuring its contribution to an output of
+//! the network, which helps explaining individual predictions, for instance to audit a model trained on tabular data.
+//! The attributions have the same dimensions as the samples.
+use arrayfire::*;
+
+use crate::errors::Error;
+use crate::models::Network;
+use crate::tensor::*;
+
+/// Computes the integrated gradients of an output of the network (Sundararajan et al., 2017).
+///
+/// The attribution of a feature is the difference between the sample and the baseline along this feature, multiplied
+/// by the mean gradient of the output with respect to the feature along the straight path from the baseline to the
+/// sample. The integral is approximated by a Riemann sum over `num_steps` points of the path. The attributions of a
+/// sample sum up to the difference between the outputs at the sample and at the baseline, up to the approximation
+/// error, which decreases as the number of steps increases. Between 20 and 300 steps are usually enough.
+///
+/// The network is evaluated in inference mode, such that the dropout layers are disabled and the batch normalization
+/// layers use their running statistics.
+///
+/// # Arguments
+///
+/// * `network` - The network, whose output must be one-dimensional, for instance the class scores of a classifier.
+/// * `x` - The samples, stacked along the fourth dimension.
+/// * `baseline` - The baseline, with the dimensions of the samples or of a single sample. If `None`, the baseline is
+/// zero, which is a neutral input for standardized features.
+/// * `target` - The index of the output to explain. If `None`, the output with the largest value is explained for each
+/// sample, which is the predicted class of a classifier.
+/// * `num_steps` - The number of points of the path at which the gradients are evaluated.
+///
+/// # Return value
+///
+/// Tensor with the dimensions of the samples containing the attribution of each feature.
+///
+/// # Errors
+///
+/// Returns an error if the dimensions of the samples or of the baseline don't match the input shape of the network, if
+/// the output of the network is not one-dimensional, if the target is not a valid output index, or if the number of
+/// steps is zero.
+pub fn integrated_gradients(network: &Network,
+                            x: &Tensor,
+                            baseline: Option<&Tensor>,
+                            target: Option<u64>,
+                            num_steps: u64,
+) -> Result<Tensor, Error> {
+    if num_steps == 0 {
+        return Err(Error::NoIntegrationStep);
+    }
+    let dims = x.dims();
+    let input_shape = network.input_shape();
+    if (0..3).any(|dim| dims[dim] != input_shape[dim]) {
+        return Err(Error::InputShapeMismatch);
+    }
+    let output_shape = network.output_shape();
+    if output_shape[1] != 1 || output_shape[2] != 1 {
+        return Err(Error::InvalidOutputShape);
+    }
+
+    let baseline = match baseline {
+        Some(baseline) => {
+            let baseline_dims = baseline.dims();
+            if (0..3).any(|dim| baseline_dims[dim] != dims[dim]) || (baseline_dims[3] != dims[3] && baseline_dims[3] != 1) {
+                return Err(Error::TensorShapeMismatch(format!("the baseline must have the dimensions {:?} or a single sample, got {:?}", dims.get(), baseline_dims.get())));
+            }
+            baseline.clone()
+        },
+        None => Tensor::zeros(dims),
+    };
+
+    // Select the explained output of each sample
+    let num_outputs = output_shape[0];
+    let labels = match target {
+        Some(target) if target >= num_outputs => {
+            return Err(Error::InvalidLabels(format!("the target must be smaller than the number of outputs {}, got {}", num_outputs, target)));
+        },
+        Some(target) => constant(target as PrimitiveType, Dim::new(&[1, 1, 1, dims[3]])),
+        None => network.predict(x).argmax_to_labels(),
+    };
+    let output_weights = Tensor::one_hot(&labels, num_outputs)?;
+
+    let mut network = network.clone();
+    let difference = sub(x, &baseline, true);
+    let mut gradients_sum = Tensor::zeros(dims);
+    for step in 1..=num_steps {
+        let point = add(&baseline, &(&difference * (step as PrimitiveType / num_steps as PrimitiveType)), true);
+        gradients_sum += network.input_gradient(&point, &output_weights);
+    }
+    Ok(mul(&difference, &(gradients_sum / num_steps as PrimitiveType), false))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::activations::Activation;
+    use crate::attribution::*;
+    use crate::layers::Dense;
+    use crate::losses::MeanSquaredError;
+    use crate::optimizers::SGD;
+
+    #[test]
+    fn test_integrated_gradients() {
+        let mut nn = Network::new(Dim4::new(&[3, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(4, Activation::Tanh));
+        nn.add(Dense::new(2, Activation::Linear));
+        let x = Tensor::new(&[0.5, -1., 2., 1., 0.3, -0.2], Dim4::new(&[3, 1, 1, 2]));
+
+        // The attributions sum up to the difference between the outputs at the samples and at the baseline
+        let attributions = integrated_gradients(&nn, &x, None, Some(1), 300).unwrap();
+        assert_eq!(attributions.dims(), x.dims());
+        let mut totals = [0 as PrimitiveType; 2];
+        sum(&attributions, 0).host(&mut totals);
+        let output = sub(&nn.predict(&x), &nn.predict(&Tensor::zeros(Dim4::new(&[3, 1, 1, 1]))), true);
+        let mut expected = [0 as PrimitiveType; 4];
+        output.host(&mut expected);
+        assert!((totals[0] - expected[1]).abs() < 1e-2);
+        assert!((totals[1] - expected[3]).abs() < 1e-2);
+
+        // A sample equal to the baseline has no attribution
+        let attributions = integrated_gradients(&nn, &x, Some(&x), None, 10).unwrap();
+        assert_eq!(sum_all(&abs(&attributions)).0, 0.);
+
+        assert!(integrated_gradients(&nn, &x, None, Some(2), 10).is_err());
+        assert!(integrated_gradients(&nn, &x, None, None, 0).is_err());
+    }
+}
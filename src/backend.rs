@@ -0,0 +1,176 @@
+//! Abstraction over the tensor operations performed by the layers.
+//!
+//! The [Backend](trait.Backend.html) trait gathers the compute intensive operations (matrix multiplication, convolution,
+//! reductions, and random number generation) such that alternative backends can be added without rewriting the layers.
+//! The ArrayFire implementation is the default backend and the [cpu](../cpu/index.html) module provides a pure Rust
+//! implementation.
+#[cfg(feature = "arrayfire-backend")]
+use arrayfire::*;
+
+#[cfg(feature = "arrayfire-backend")]
+use crate::tensor::*;
+
+/// Operations that must be provided by a tensor backend.
+///
+/// The tensors have four dimensions and are stored in column-major order. For images, the dimensions are
+/// [height, width, channels, batch_size].
+pub trait Backend {
+    /// Tensor type of the backend.
+    type Tensor;
+
+    /// Primitive type of the values stored in the tensors.
+    type Scalar: Copy;
+
+    /// Computes the matrix product of two tensors.
+    ///
+    /// The product is batched along the third and fourth dimensions of `rhs`.
+    fn matmul(lhs: &Self::Tensor, rhs: &Self::Tensor, transpose_lhs: bool, transpose_rhs: bool) -> Self::Tensor;
+
+    /// Rearranges the patches of the images into columns.
+    ///
+    /// The output has dimensions [kernel_height * kernel_width * channels, num_patches * batch_size, 1, 1].
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - The images with dimensions [height, width, channels, batch_size].
+    /// * `kernel_size` - The height and width of the patches.
+    /// * `stride` - The vertical and horizontal strides.
+    /// * `padding_size` - The number of zeros added to the top, right, bottom, and left of the images.
+    fn im2col(input: &Self::Tensor, kernel_size: (u64, u64), stride: (u64, u64), padding_size: (u64, u64, u64, u64)) -> Self::Tensor;
+
+    /// Computes the 2D convolution of the images with the filters and adds the biases.
+    ///
+    /// The weights have dimensions [num_filters, kernel_height * kernel_width * channels] and the biases
+    /// [num_filters, 1]. The output has dimensions [height_out, width_out, num_filters, batch_size].
+    fn conv2d(input: &Self::Tensor,
+              weights: &Self::Tensor,
+              biases: &Self::Tensor,
+              kernel_size: (u64, u64),
+              stride: (u64, u64),
+              padding_size: (u64, u64, u64, u64)
+    ) -> Self::Tensor;
+
+    /// Sums the values along the given axis.
+    fn sum(tensor: &Self::Tensor, axis: usize) -> Self::Tensor;
+
+    /// Computes the mean of the values along the given axis.
+    fn mean(tensor: &Self::Tensor, axis: usize) -> Self::Tensor;
+
+    /// Computes the maximum of the values along the given axis.
+    fn max(tensor: &Self::Tensor, axis: usize) -> Self::Tensor;
+
+    /// Creates a tensor with values drawn from a uniform distribution on [lower_bound, upper_bound).
+    fn random_uniform(lower_bound: Self::Scalar, upper_bound: Self::Scalar, dims: [u64; 4]) -> Self::Tensor;
+
+    /// Creates a tensor with values drawn from a normal distribution.
+    fn random_normal(mean: Self::Scalar, standard_deviation: Self::Scalar, dims: [u64; 4]) -> Self::Tensor;
+}
+
+/// Computes the output size of a convolution along one dimension.
+pub(crate) fn conv_output_size(input_size: u64, kernel_size: u64, stride: u64, padding: u64) -> u64 {
+    (input_size + padding - kernel_size) / stride + 1
+}
+
+
+/// Backend running the operations with ArrayFire.
+#[cfg(feature = "arrayfire-backend")]
+#[derive(Debug, Copy, Clone)]
+pub struct ArrayFireBackend;
+
+/// Backend used by the layers.
+#[cfg(feature = "arrayfire-backend")]
+pub type DefaultBackend = ArrayFireBackend;
+
+#[cfg(feature = "arrayfire-backend")]
+impl ArrayFireBackend {
+    fn mat_prop(transpose: bool) -> MatProp {
+        if transpose { MatProp::TRANS } else { MatProp::NONE }
+    }
+
+    /// Adds zeros around the images.
+    pub(crate) fn pad(input: &Tensor, padding_size: (u64, u64, u64, u64)) -> Tensor {
+        Self::pad_with(input, padding_size, 0.0)
+    }
+
+    /// Adds the given value around the images.
+    pub(crate) fn pad_with(input: &Tensor, padding_size: (u64, u64, u64, u64), value: PrimitiveType) -> Tensor {
+        if padding_size == (0, 0, 0, 0) {
+            return input.clone();
+        }
+
+        let height = input.dims().get()[0];
+        let width = input.dims().get()[1];
+        let num_channels = input.dims().get()[2];
+        let mb_size = input.dims().get()[3];
+
+        let pad_top = constant(value, Dim4::new(&[padding_size.0, width, num_channels, mb_size]));
+        let pad_right = constant(value, Dim4::new(&[height + padding_size.0, padding_size.1, num_channels, mb_size]));
+        let pad_bottom = constant(value, Dim4::new(&[padding_size.2, width + padding_size.1, num_channels, mb_size]));
+        let pad_left = constant(value, Dim4::new(&[height + padding_size.0 + padding_size.2, padding_size.3, num_channels, mb_size]));
+        let mut padded = join(0, &pad_top, input);
+        padded = join(1, &padded, &pad_right);
+        padded = join(0, &padded, &pad_bottom);
+        join(1, &pad_left, &padded)
+    }
+}
+
+#[cfg(feature = "arrayfire-backend")]
+impl Backend for ArrayFireBackend {
+    type Tensor = Tensor;
+    type Scalar = PrimitiveType;
+
+    fn matmul(lhs: &Tensor, rhs: &Tensor, transpose_lhs: bool, transpose_rhs: bool) -> Tensor {
+        matmul(lhs, rhs, Self::mat_prop(transpose_lhs), Self::mat_prop(transpose_rhs))
+    }
+
+    fn im2col(input: &Tensor, kernel_size: (u64, u64), stride: (u64, u64), padding_size: (u64, u64, u64, u64)) -> Tensor {
+        let num_channels = input.dims().get()[2];
+        let padded = Self::pad(input, padding_size);
+        let mut col = unwrap(&padded, kernel_size.0 as i64, kernel_size.1 as i64, stride.0 as i64, stride.1 as i64, 0, 0, true);
+        //col = reorder(&col, Dim4::new(&[0, 2, 1, 3]));
+        col = reorder_v2(&col, 0, 2, Some(vec![1, 3]));
+        moddims(&col, Dim4::new(&[col.dims().get()[0] * num_channels, col.elements() as u64/(col.dims().get()[0] * num_channels), 1, 1]))
+    }
+
+    fn conv2d(input: &Tensor,
+              weights: &Tensor,
+              biases: &Tensor,
+              kernel_size: (u64, u64),
+              stride: (u64, u64),
+              padding_size: (u64, u64, u64, u64)
+    ) -> Tensor {
+        let batch_size = input.dims().get()[3];
+        let num_filters = weights.dims().get()[0];
+        let h_out = conv_output_size(input.dims().get()[0], kernel_size.0, stride.0, padding_size.0 + padding_size.2);
+        let w_out = conv_output_size(input.dims().get()[1], kernel_size.1, stride.1, padding_size.1 + padding_size.3);
+
+        let input_values = Self::im2col(input, kernel_size, stride, padding_size);
+        let mut conv = add(&matmul(weights, &input_values, MatProp::NONE, MatProp::NONE), biases, true);
+
+        // Reshape to have each mini-batch on the last dimension and the correct output dimensions
+        conv = moddims(&conv, Dim4::new(&[num_filters, h_out * w_out, 1, batch_size]));
+        moddims(&transpose(&conv, false), Dim4::new(&[h_out, w_out, num_filters, batch_size]))
+    }
+
+    fn sum(tensor: &Tensor, axis: usize) -> Tensor {
+        sum(tensor, axis as i32)
+    }
+
+    fn mean(tensor: &Tensor, axis: usize) -> Tensor {
+        mean(tensor, axis as i64)
+    }
+
+    fn max(tensor: &Tensor, axis: usize) -> Tensor {
+        max(tensor, axis as i32)
+    }
+
+    fn random_uniform(lower_bound: PrimitiveType, upper_bound: PrimitiveType, dims: [u64; 4]) -> Tensor {
+        let dims = Dim4::new(&dims);
+        constant(lower_bound, dims) + constant(upper_bound - lower_bound, dims) * randu::<PrimitiveType>(dims)
+    }
+
+    fn random_normal(mean: PrimitiveType, standard_deviation: PrimitiveType, dims: [u64; 4]) -> Tensor {
+        let dims = Dim4::new(&dims);
+        constant(standard_deviation, dims) * randn::<PrimitiveType>(dims) + constant(mean, dims)
+    }
+}
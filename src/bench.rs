@@ -0,0 +1,175 @@
+//! Micro-benchmarks of the main computations of the library.
+//!
+//! The benchmarks measure the forward and backward passes of the dense and convolutional layers and the update step of
+//! the optimizers on the active ArrayFire backend and device. The random number generator is seeded before each
+//! benchmark such that the runs are reproducible. The `bench` example runs the standard suite on every available
+//! backend and device:
+//!
+//! ```ignore
+//! cargo run --release --example bench
+//! ```
+use arrayfire::*;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::activations::Activation;
+use crate::layers::{Conv2D, ConvAlgorithm, Dense, Layer, Padding};
+use crate::optimizers::Optimizer;
+use crate::tensor::*;
+
+/// Seed of the random number generator used by the benchmarks.
+const SEED: u64 = 42;
+
+/// Timings of a benchmark.
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    /// The name of the benchmark.
+    pub name: String,
+    /// The number of timed iterations.
+    pub iterations: u64,
+    /// The mean duration of an iteration.
+    pub mean: Duration,
+    /// The shortest duration of an iteration.
+    pub min: Duration,
+    /// The longest duration of an iteration.
+    pub max: Duration,
+}
+
+impl fmt::Display for BenchmarkResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<40} mean: {:>10.3} ms \t min: {:>10.3} ms \t max: {:>10.3} ms ({} iterations)",
+               self.name, self.mean.as_secs_f64() * 1e3, self.min.as_secs_f64() * 1e3, self.max.as_secs_f64() * 1e3, self.iterations)
+    }
+}
+
+/// Times a computation.
+///
+/// The computation is first run `warmup` times, which lets ArrayFire compile its kernels, and then timed over
+/// `iterations` runs. The device is synchronized after each run such that the asynchronous computations are included.
+///
+/// # Panics
+///
+/// Panics if the number of iterations is zero.
+pub fn run<F: FnMut()>(name: &str, warmup: u64, iterations: u64, mut computation: F) -> BenchmarkResult {
+    assert!(iterations > 0, "The number of iterations must be greater than zero.");
+    let device = get_device();
+    for _ in 0..warmup {
+        computation();
+        sync(device);
+    }
+
+    let mut durations = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        computation();
+        sync(device);
+        durations.push(start.elapsed());
+    }
+
+    BenchmarkResult {
+        name: name.to_string(),
+        iterations,
+        mean: durations.iter().sum::<Duration>() / iterations as u32,
+        min: *durations.iter().min().unwrap(),
+        max: *durations.iter().max().unwrap(),
+    }
+}
+
+/// Benchmarks the forward pass of a dense layer.
+pub fn dense_forward(input_size: u64, units: u64, batch_size: u64, iterations: u64) -> BenchmarkResult {
+    set_seed(SEED);
+    let mut layer = Dense::new(units, Activation::ReLU);
+    layer.initialize_parameters(Dim4::new(&[input_size, 1, 1, 1]));
+    let input = randn::<PrimitiveType>(Dim4::new(&[input_size, 1, 1, batch_size]));
+
+    let name = format!("Dense forward {}x{} (batch {})", input_size, units, batch_size);
+    run(&name, 2, iterations, || { layer.compute_activation_mut(&input).eval(); })
+}
+
+/// Benchmarks the forward and backward passes of a dense layer.
+pub fn dense_backward(input_size: u64, units: u64, batch_size: u64, iterations: u64) -> BenchmarkResult {
+    set_seed(SEED);
+    let mut layer = Dense::new(units, Activation::ReLU);
+    layer.initialize_parameters(Dim4::new(&[input_size, 1, 1, 1]));
+    let input = randn::<PrimitiveType>(Dim4::new(&[input_size, 1, 1, batch_size]));
+    let output_grad = randn::<PrimitiveType>(Dim4::new(&[units, 1, 1, batch_size]));
+
+    let name = format!("Dense backward {}x{} (batch {})", input_size, units, batch_size);
+    run(&name, 2, iterations, || {
+        layer.compute_activation_mut(&input).eval();
+        layer.compute_dactivation_mut(&output_grad).eval();
+    })
+}
+
+/// Benchmarks the forward and backward passes of a convolutional layer with the given algorithm.
+///
+/// The layer has 3x3 kernels with a stride of 1 and the same padding.
+pub fn conv2d(algorithm: ConvAlgorithm, image_size: u64, channels: u64, filters: u64, batch_size: u64, iterations: u64) -> BenchmarkResult {
+    set_seed(SEED);
+    let mut layer = Conv2D::new(filters, (3, 3), (1, 1), Padding::Same);
+    layer.set_algorithm(algorithm);
+    layer.initialize_parameters(Dim4::new(&[image_size, image_size, channels, 1]));
+    let input = randn::<PrimitiveType>(Dim4::new(&[image_size, image_size, channels, batch_size]));
+    let output_shape = layer.output_shape();
+    let output_grad = randn::<PrimitiveType>(Dim4::new(&[output_shape[0], output_shape[1], output_shape[2], batch_size]));
+
+    let name = format!("Conv2D {:?} {}x{}x{} -> {} (batch {})", algorithm, image_size, image_size, channels, filters, batch_size);
+    run(&name, 2, iterations, || {
+        layer.compute_activation_mut(&input).eval();
+        layer.compute_dactivation_mut(&output_grad).eval();
+    })
+}
+
+/// Benchmarks the update of the parameters of a dense layer by an optimizer.
+pub fn optimizer_step(mut optimizer: Box<dyn Optimizer>, input_size: u64, units: u64, iterations: u64) -> BenchmarkResult {
+    set_seed(SEED);
+    let mut layer = Dense::new(units, Activation::ReLU);
+    layer.initialize_parameters(Dim4::new(&[input_size, 1, 1, 1]));
+    let input = randn::<PrimitiveType>(Dim4::new(&[input_size, 1, 1, 32]));
+    let output_grad = randn::<PrimitiveType>(Dim4::new(&[units, 1, 1, 32]));
+    layer.compute_activation_mut(&input);
+    layer.compute_dactivation_mut(&output_grad);
+    optimizer.initialize_parameters(vec![(Dim4::new(&[units, input_size, 1, 1]), Dim4::new(&[units, 1, 1, 1]))]);
+
+    let name = format!("{} step {}x{}", optimizer.name(), input_size, units);
+    run(&name, 2, iterations, || {
+        optimizer.update_time_step();
+        optimizer.update_parameters(&mut *layer, 0);
+        if let Some(parameters) = layer.parameters() {
+            for parameter in parameters { parameter.eval(); }
+        }
+    })
+}
+
+/// Runs the standard suite of benchmarks on the active backend and device.
+pub fn run_all(iterations: u64) -> Vec<BenchmarkResult> {
+    use crate::optimizers::{Adam, SGD};
+
+    vec![
+        dense_forward(784, 512, 128, iterations),
+        dense_backward(784, 512, 128, iterations),
+        dense_forward(4096, 4096, 64, iterations),
+        dense_backward(4096, 4096, 64, iterations),
+        conv2d(ConvAlgorithm::Im2Col, 32, 16, 32, 32, iterations),
+        conv2d(ConvAlgorithm::Native, 32, 16, 32, 32, iterations),
+        conv2d(ConvAlgorithm::Im2Col, 64, 32, 64, 16, iterations),
+        conv2d(ConvAlgorithm::Native, 64, 32, 64, 16, iterations),
+        optimizer_step(SGD::with_param(0.01, 0.9), 4096, 4096, iterations),
+        optimizer_step(Adam::new(0.001), 4096, 4096, iterations),
+    ]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::bench::*;
+
+    #[test]
+    fn test_run() {
+        let mut count = 0;
+        let result = run("count", 2, 3, || count += 1);
+        assert_eq!(count, 5);
+        assert_eq!(result.iterations, 3);
+        assert!(result.min <= result.mean && result.mean <= result.max);
+    }
+}
@@ -0,0 +1,87 @@
+//! Calibration of the confidences of classifiers.
+//!
+//! Temperature scaling (Guo et al., 2017) divides the logits of a trained classifier by a single temperature fitted
+//! on the validation set. A temperature greater than one softens overconfident predictions. The predicted classes are
+//! not affected since the order of the logits is preserved.
+use arrayfire::*;
+
+use crate::activations::Activation;
+use crate::losses::log_softmax;
+use crate::tensor::*;
+
+/// Bounds of the search of the temperature.
+const MIN_TEMPERATURE: PrimitiveType = 0.01;
+const MAX_TEMPERATURE: PrimitiveType = 100.;
+
+/// Number of iterations of the golden-section search.
+const NUM_ITERATIONS: usize = 60;
+
+/// Computes the softmax of the logits divided by the temperature.
+pub(crate) fn scaled_softmax(logits: &Tensor, temperature: PrimitiveType) -> Tensor {
+    Activation::Softmax.eval(&(logits / temperature))
+}
+
+/// Computes the mean negative log-likelihood of the labels under the softmax of the scaled logits.
+pub(crate) fn negative_log_likelihood(logits: &Tensor, y_true: &Tensor, temperature: PrimitiveType) -> PrimitiveType {
+    let log_probabilities = log_softmax(&(logits / temperature));
+    let batch_size = logits.dims()[3] as PrimitiveType;
+    -sum_all(&mul(y_true, &log_probabilities, false)).0 as PrimitiveType / batch_size
+}
+
+/// Fits the temperature minimizing the negative log-likelihood of the labels.
+///
+/// The negative log-likelihood is unimodal in the temperature, which is found with a golden-section search on its
+/// logarithm.
+///
+/// # Arguments
+///
+/// * `logits` - The logits of the samples, stacked along the fourth dimension.
+/// * `y_true` - The one-hot encoded labels of the samples.
+pub(crate) fn fit_temperature(logits: &Tensor, y_true: &Tensor) -> PrimitiveType {
+    let ratio = (5f64.sqrt() as PrimitiveType - 1.) / 2.;
+    let nll = |log_temperature: PrimitiveType| negative_log_likelihood(logits, y_true, log_temperature.exp());
+
+    let (mut a, mut b) = (MIN_TEMPERATURE.ln(), MAX_TEMPERATURE.ln());
+    let mut c = b - ratio * (b - a);
+    let mut d = a + ratio * (b - a);
+    let (mut f_c, mut f_d) = (nll(c), nll(d));
+    for _ in 0..NUM_ITERATIONS {
+        if f_c < f_d {
+            b = d;
+            d = c;
+            f_d = f_c;
+            c = b - ratio * (b - a);
+            f_c = nll(c);
+        } else {
+            a = c;
+            c = d;
+            f_c = f_d;
+            d = a + ratio * (b - a);
+            f_d = nll(d);
+        }
+    }
+    ((a + b) / 2.).exp()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::calibration::*;
+
+    #[test]
+    fn test_fit_temperature() {
+        // The logits are overconfident: half of the predictions with a large margin are wrong
+        let logits = Tensor::new(&[4., 0., 4., 0., 0., 4., 0., 4.], Dim4::new(&[2, 1, 1, 4]));
+        let y_true = Tensor::new(&[1., 0., 0., 1., 0., 1., 1., 0.], Dim4::new(&[2, 1, 1, 4]));
+
+        let temperature = fit_temperature(&logits, &y_true);
+        assert!(temperature > 10.);
+        assert!(negative_log_likelihood(&logits, &y_true, temperature) < negative_log_likelihood(&logits, &y_true, 1.));
+
+        let probabilities = scaled_softmax(&logits, temperature);
+        let mut output = [0 as PrimitiveType; 8];
+        probabilities.host(&mut output);
+        assert!((output[0] + output[1] - 1.).abs() < 1e-5);
+    }
+}
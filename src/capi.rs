@@ -0,0 +1,190 @@
+//! C API to embed trained models in C/C++ applications.
+//!
+//! This module is only available when the crate is built with the `capi` feature. A shared library can be created
+//! with `cargo rustc --release --features capi -- --crate-type cdylib` and the functions are declared in the
+//! `include/neuro.h` header. When the crate is built with the `f64` feature, the values are doubles and `NEURO_F64`
+//! must be defined before including the header.
+//!
+//! All the functions return a status code: 0 on success and a negative value on failure. A panic inside the library
+//! never unwinds into the caller: it is caught and reported as a failure. The message describing the last failure of
+//! the calling thread is returned by `neuro_last_error`.
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use crate::models::Network;
+use crate::tensor::*;
+
+const SUCCESS: c_int = 0;
+const INVALID_ARGUMENT: c_int = -1;
+const BUFFER_TOO_SMALL: c_int = -2;
+const INTERNAL_ERROR: c_int = -3;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Records the message of the last failure of the thread.
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Extracts the message of a panic.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => format!("panic: {}", message),
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => format!("panic: {}", message),
+            Err(_) => String::from("panic"),
+        },
+    }
+}
+
+/// Runs the body of a function returning a status code, converting a panic into `INTERNAL_ERROR`.
+fn catch_status<F: FnOnce() -> c_int>(body: F) -> c_int {
+    panic::catch_unwind(AssertUnwindSafe(body)).unwrap_or_else(|payload| {
+        set_last_error(panic_message(payload));
+        INTERNAL_ERROR
+    })
+}
+
+/// Returns the message describing the last failure of the calling thread, or a null pointer if no function failed.
+///
+/// The message is owned by the library and remains valid until the next failure in the same thread.
+#[no_mangle]
+pub extern "C" fn neuro_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| last_error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// Loads a network from an HDF5 file.
+///
+/// Returns a null pointer if the network could not be loaded, in which case the reason is given by
+/// `neuro_last_error`. The network must be released with `neuro_network_free`.
+///
+/// # Safety
+///
+/// `filename` must be null or point to a null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn neuro_network_load(filename: *const c_char) -> *mut Network {
+    if filename.is_null() {
+        set_last_error(String::from("the filename is null"));
+        return ptr::null_mut();
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        let filename = CStr::from_ptr(filename).to_str().map_err(|err| err.to_string())?;
+        Network::load(filename).map_err(|err| format!("could not load the network: {}", err))
+    }));
+    match result {
+        Ok(Ok(network)) => Box::into_raw(Box::new(network)),
+        Ok(Err(message)) => {
+            set_last_error(message);
+            ptr::null_mut()
+        },
+        Err(payload) => {
+            set_last_error(panic_message(payload));
+            ptr::null_mut()
+        },
+    }
+}
+
+/// Releases a network created by `neuro_network_load`.
+///
+/// # Safety
+///
+/// `network` must be null or a pointer returned by `neuro_network_load` that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn neuro_network_free(network: *mut Network) {
+    if !network.is_null() {
+        // A panic while releasing the network is ignored, there is nothing the caller could do about it
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(network))));
+    }
+}
+
+/// Writes the four dimensions of the input shape of the network in `shape`.
+///
+/// # Safety
+///
+/// `network` must be null or a valid network, and `shape` must be null or point to a buffer of four values.
+#[no_mangle]
+pub unsafe extern "C" fn neuro_network_input_shape(network: *const Network, shape: *mut u64) -> c_int {
+    if network.is_null() || shape.is_null() {
+        set_last_error(String::from("null argument"));
+        return INVALID_ARGUMENT;
+    }
+    catch_status(|| {
+        let network = &*network;
+        ptr::copy_nonoverlapping(network.input_shape().get().as_ptr(), shape, 4);
+        SUCCESS
+    })
+}
+
+/// Writes the four dimensions of the output shape of the network in `shape`.
+///
+/// # Safety
+///
+/// `network` must be null or a valid network, and `shape` must be null or point to a buffer of four values.
+#[no_mangle]
+pub unsafe extern "C" fn neuro_network_output_shape(network: *const Network, shape: *mut u64) -> c_int {
+    if network.is_null() || shape.is_null() {
+        set_last_error(String::from("null argument"));
+        return INVALID_ARGUMENT;
+    }
+    catch_status(|| {
+        let network = &*network;
+        ptr::copy_nonoverlapping(network.output_shape().get().as_ptr(), shape, 4);
+        SUCCESS
+    })
+}
+
+/// Computes the output of the network for a batch of samples.
+///
+/// # Arguments
+///
+/// * `network` - The network.
+/// * `input` - The values of the samples in column-major order, concatenated one after the other.
+/// * `num_samples` - The number of samples in `input`.
+/// * `output` - The buffer where the predictions are written.
+/// * `output_len` - The number of values that fit in `output`.
+///
+/// # Safety
+///
+/// `network` must be null or a valid network. `input` must be null or hold the values of `num_samples` samples, and
+/// `output` must be null or hold `output_len` values.
+#[no_mangle]
+pub unsafe extern "C" fn neuro_network_predict(network: *const Network,
+                                               input: *const PrimitiveType,
+                                               num_samples: u64,
+                                               output: *mut PrimitiveType,
+                                               output_len: u64
+) -> c_int {
+    if network.is_null() || input.is_null() || output.is_null() || num_samples == 0 {
+        set_last_error(String::from("null argument or no sample"));
+        return INVALID_ARGUMENT;
+    }
+    catch_status(|| {
+        let network = &*network;
+
+        let input_shape = network.input_shape();
+        let output_shape = network.output_shape();
+        let input_len = input_shape[0] * input_shape[1] * input_shape[2] * num_samples;
+        let required_output_len = output_shape[0] * output_shape[1] * output_shape[2] * num_samples;
+        if output_len < required_output_len {
+            set_last_error(format!("the output buffer holds {} values but {} are required", output_len, required_output_len));
+            return BUFFER_TOO_SMALL;
+        }
+
+        let input_values = slice::from_raw_parts(input, input_len as usize);
+        let x = Tensor::new(input_values, Dim::new(&[input_shape[0], input_shape[1], input_shape[2], num_samples]));
+        let y = network.predict(&x);
+
+        let output_values = slice::from_raw_parts_mut(output, required_output_len as usize);
+        y.host(output_values);
+        SUCCESS
+    })
+}
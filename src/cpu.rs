@@ -0,0 +1,734 @@
+//! Pure Rust CPU backend for inference.
+//!
+//! This module does not depend on ArrayFire nor HDF5 and is therefore available when the crate is built without
+//! the default `arrayfire-backend` feature, for instance to target WebAssembly:
+//! ```toml
+//! [dependencies]
+//! neuro = { version = "0.1.0", default-features = false }
+//! ```
+//!
+//! A network trained with the ArrayFire backend is converted with `Network::to_cpu` and saved in a compact binary
+//! format with [CpuNetwork::save](struct.CpuNetwork.html#method.save). The file can then be loaded by the CPU backend
+//! with [CpuNetwork::load](struct.CpuNetwork.html#method.load) or [CpuNetwork::from_bytes](struct.CpuNetwork.html#method.from_bytes).
+//!
+//! The operations are implemented by [CpuBackend](struct.CpuBackend.html) and the tensors are stored in column-major
+//! order, as in ArrayFire.
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::convert::TryFrom;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::Path;
+
+use crate::backend::{Backend, conv_output_size};
+
+const MAGIC: &[u8; 8] = b"NEUROCPU";
+const FORMAT_VERSION: u8 = 1;
+
+/// Tensor stored in the host memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CpuTensor {
+    dims: [u64; 4],
+    values: Vec<f32>,
+}
+
+impl CpuTensor {
+    /// Creates a tensor with the given values and dimensions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of values does not match the dimensions.
+    pub fn new(values: &[f32], dims: [u64; 4]) -> CpuTensor {
+        assert_eq!(values.len() as u64, dims.iter().product::<u64>(), "The number of values does not match the dimensions.");
+        CpuTensor {
+            dims,
+            values: values.to_vec(),
+        }
+    }
+
+    /// Creates a tensor filled with zeros.
+    pub fn zeros(dims: [u64; 4]) -> CpuTensor {
+        CpuTensor {
+            dims,
+            values: vec![0.; dims.iter().product::<u64>() as usize],
+        }
+    }
+
+    /// Returns the dimensions of the tensor.
+    pub fn dims(&self) -> [u64; 4] {
+        self.dims
+    }
+
+    /// Returns the values of the tensor in column-major order.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Returns the number of samples in the tensor.
+    pub fn batch_size(&self) -> u64 {
+        self.dims[3]
+    }
+
+    fn linear_index(&self, i0: u64, i1: u64, i2: u64, i3: u64) -> usize {
+        let d = self.dims;
+        (i0 + d[0] * (i1 + d[1] * (i2 + d[2] * i3))) as usize
+    }
+
+    fn get(&self, i0: u64, i1: u64, i2: u64, i3: u64) -> f32 {
+        self.values[self.linear_index(i0, i1, i2, i3)]
+    }
+
+    /// Returns the value at the given position, broadcasting the dimensions of size one.
+    fn get_broadcast(&self, i0: u64, i1: u64, i2: u64) -> f32 {
+        let d = self.dims;
+        let i0 = if d[0] == 1 { 0 } else { i0 };
+        let i1 = if d[1] == 1 { 0 } else { i1 };
+        let i2 = if d[2] == 1 { 0 } else { i2 };
+        self.get(i0, i1, i2, 0)
+    }
+}
+
+
+/// Backend running the operations on the CPU in pure Rust.
+#[derive(Debug, Copy, Clone)]
+pub struct CpuBackend;
+
+thread_local! {
+    static RNG_STATE: Cell<u64> = Cell::new(initial_seed());
+}
+
+/// Seeds the random number generator of the current thread.
+fn initial_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0x9E37_79B9_7F4A_7C15);
+    hasher.finish() | 1
+}
+
+/// Draws a value from a uniform distribution on [0, 1) with a xorshift generator.
+fn next_uniform() -> f32 {
+    RNG_STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        (x >> 40) as f32 / (1u64 << 24) as f32
+    })
+}
+
+impl CpuBackend {
+    /// Applies a reduction along the given axis.
+    fn reduce<F: Fn(f32, f32) -> f32>(tensor: &CpuTensor, axis: usize, init: f32, f: F) -> CpuTensor {
+        let mut dims = tensor.dims;
+        dims[axis] = 1;
+        let mut output = CpuTensor {
+            dims,
+            values: vec![init; dims.iter().product::<u64>() as usize],
+        };
+
+        let d = tensor.dims;
+        for i3 in 0..d[3] {
+            for i2 in 0..d[2] {
+                for i1 in 0..d[1] {
+                    for i0 in 0..d[0] {
+                        let mut position = [i0, i1, i2, i3];
+                        position[axis] = 0;
+                        let idx = output.linear_index(position[0], position[1], position[2], position[3]);
+                        output.values[idx] = f(output.values[idx], tensor.get(i0, i1, i2, i3));
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+impl Backend for CpuBackend {
+    type Tensor = CpuTensor;
+    type Scalar = f32;
+
+    fn matmul(lhs: &CpuTensor, rhs: &CpuTensor, transpose_lhs: bool, transpose_rhs: bool) -> CpuTensor {
+        let (m, k) = if transpose_lhs { (lhs.dims[1], lhs.dims[0]) } else { (lhs.dims[0], lhs.dims[1]) };
+        let (k_rhs, n) = if transpose_rhs { (rhs.dims[1], rhs.dims[0]) } else { (rhs.dims[0], rhs.dims[1]) };
+        assert_eq!(k, k_rhs, "The inner dimensions of the matrices do not match.");
+
+        // The matrices of a tensor with a single matrix are broadcast along the batch dimensions
+        let lhs_batched = lhs.dims[2] * lhs.dims[3] > 1;
+        let rhs_batched = rhs.dims[2] * rhs.dims[3] > 1;
+        let (batch2, batch3) = if rhs_batched { (rhs.dims[2], rhs.dims[3]) } else { (lhs.dims[2], lhs.dims[3]) };
+        let (lhs_size, rhs_size) = ((lhs.dims[0] * lhs.dims[1]) as usize, (rhs.dims[0] * rhs.dims[1]) as usize);
+        let (lhs_rows, rhs_rows) = (lhs.dims[0] as usize, rhs.dims[0] as usize);
+
+        let mut output = CpuTensor::zeros([m, n, batch2, batch3]);
+        for b in 0..(batch2 * batch3) as usize {
+            let lhs_offset = if lhs_batched { b * lhs_size } else { 0 };
+            let rhs_offset = if rhs_batched { b * rhs_size } else { 0 };
+            let output_offset = b * (m * n) as usize;
+            for j in 0..n as usize {
+                for i in 0..m as usize {
+                    let mut value = 0.;
+                    for p in 0..k as usize {
+                        let a = if transpose_lhs { lhs.values[lhs_offset + p + lhs_rows * i] } else { lhs.values[lhs_offset + i + lhs_rows * p] };
+                        let b = if transpose_rhs { rhs.values[rhs_offset + j + rhs_rows * p] } else { rhs.values[rhs_offset + p + rhs_rows * j] };
+                        value += a * b;
+                    }
+                    output.values[output_offset + i + m as usize * j] = value;
+                }
+            }
+        }
+        output
+    }
+
+    fn im2col(input: &CpuTensor, kernel_size: (u64, u64), stride: (u64, u64), padding_size: (u64, u64, u64, u64)) -> CpuTensor {
+        let (height, width, num_channels, batch_size) = (input.dims[0], input.dims[1], input.dims[2], input.dims[3]);
+        let h_out = conv_output_size(height, kernel_size.0, stride.0, padding_size.0 + padding_size.2);
+        let w_out = conv_output_size(width, kernel_size.1, stride.1, padding_size.1 + padding_size.3);
+        let num_rows = kernel_size.0 * kernel_size.1 * num_channels;
+        let mut output = CpuTensor::zeros([num_rows, h_out * w_out * batch_size, 1, 1]);
+
+        for b in 0..batch_size {
+            for j in 0..w_out {
+                for i in 0..h_out {
+                    let col = i + h_out * j + h_out * w_out * b;
+                    for c in 0..num_channels {
+                        for kj in 0..kernel_size.1 {
+                            for ki in 0..kernel_size.0 {
+                                // Position in the input, the padding being made of zeros
+                                let row = (i * stride.0 + ki) as i64 - padding_size.0 as i64;
+                                let column = (j * stride.1 + kj) as i64 - padding_size.3 as i64;
+                                if row < 0 || column < 0 || row >= height as i64 || column >= width as i64 { continue; }
+
+                                let idx = output.linear_index(ki + kernel_size.0 * kj + kernel_size.0 * kernel_size.1 * c, col, 0, 0);
+                                output.values[idx] = input.get(row as u64, column as u64, c, b);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    fn conv2d(input: &CpuTensor,
+              weights: &CpuTensor,
+              biases: &CpuTensor,
+              kernel_size: (u64, u64),
+              stride: (u64, u64),
+              padding_size: (u64, u64, u64, u64)
+    ) -> CpuTensor {
+        let batch_size = input.dims[3];
+        let num_filters = weights.dims[0];
+        let h_out = conv_output_size(input.dims[0], kernel_size.0, stride.0, padding_size.0 + padding_size.2);
+        let w_out = conv_output_size(input.dims[1], kernel_size.1, stride.1, padding_size.1 + padding_size.3);
+
+        let columns = Self::im2col(input, kernel_size, stride, padding_size);
+        let conv = Self::matmul(weights, &columns, false, false);
+
+        let mut output = CpuTensor::zeros([h_out, w_out, num_filters, batch_size]);
+        for b in 0..batch_size {
+            for f in 0..num_filters {
+                for j in 0..w_out {
+                    for i in 0..h_out {
+                        let idx = output.linear_index(i, j, f, b);
+                        output.values[idx] = conv.get(f, i + h_out * j + h_out * w_out * b, 0, 0) + biases.values[f as usize];
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    fn sum(tensor: &CpuTensor, axis: usize) -> CpuTensor {
+        Self::reduce(tensor, axis, 0., |acc, v| acc + v)
+    }
+
+    fn mean(tensor: &CpuTensor, axis: usize) -> CpuTensor {
+        let mut output = Self::sum(tensor, axis);
+        let count = tensor.dims[axis] as f32;
+        output.values.iter_mut().for_each(|v| *v /= count);
+        output
+    }
+
+    fn max(tensor: &CpuTensor, axis: usize) -> CpuTensor {
+        Self::reduce(tensor, axis, f32::NEG_INFINITY, f32::max)
+    }
+
+    fn random_uniform(lower_bound: f32, upper_bound: f32, dims: [u64; 4]) -> CpuTensor {
+        let mut output = CpuTensor::zeros(dims);
+        output.values.iter_mut().for_each(|v| *v = lower_bound + (upper_bound - lower_bound) * next_uniform());
+        output
+    }
+
+    fn random_normal(mean: f32, standard_deviation: f32, dims: [u64; 4]) -> CpuTensor {
+        // Box-Muller transform
+        let mut output = CpuTensor::zeros(dims);
+        output.values.iter_mut().for_each(|v| {
+            let u1 = 1. - next_uniform();
+            let u2 = next_uniform();
+            *v = mean + standard_deviation * (-2. * u1.ln()).sqrt() * (2. * std::f32::consts::PI * u2).cos();
+        });
+        output
+    }
+}
+
+
+/// Activation functions supported by the CPU backend.
+///
+/// The discriminants match the ones of `activations::Activation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum CpuActivation {
+    LeakyReLU = 0,
+    Linear = 1,
+    ReLU = 2,
+    Sigmoid = 3,
+    Softmax = 4,
+    Tanh = 5,
+}
+
+impl TryFrom<u8> for CpuActivation {
+    type Error = ();
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        match v {
+            x if x == CpuActivation::LeakyReLU as u8 => Ok(CpuActivation::LeakyReLU),
+            x if x == CpuActivation::Linear as u8 => Ok(CpuActivation::Linear),
+            x if x == CpuActivation::ReLU as u8 => Ok(CpuActivation::ReLU),
+            x if x == CpuActivation::Sigmoid as u8 => Ok(CpuActivation::Sigmoid),
+            x if x == CpuActivation::Softmax as u8 => Ok(CpuActivation::Softmax),
+            x if x == CpuActivation::Tanh as u8 => Ok(CpuActivation::Tanh),
+            _ => Err(()),
+        }
+    }
+}
+
+impl CpuActivation {
+    fn eval(self, z: &mut CpuTensor) {
+        match self {
+            CpuActivation::Sigmoid => z.values.iter_mut().for_each(|v| *v = 1. / (1. + (-*v).exp())),
+            CpuActivation::Tanh => z.values.iter_mut().for_each(|v| *v = v.tanh()),
+            CpuActivation::ReLU => z.values.iter_mut().for_each(|v| *v = v.max(0.)),
+            // Same definition as the ArrayFire implementation
+            CpuActivation::LeakyReLU => z.values.iter_mut().for_each(|v| *v = (0.01 * *v).max(0.)),
+            CpuActivation::Linear => {},
+            CpuActivation::Softmax => {
+                // The softmax is computed along the first dimension and the input is shifted for numerical stability
+                let n = z.dims[0] as usize;
+                for column in z.values.chunks_mut(n) {
+                    let max_value = column.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+                    column.iter_mut().for_each(|v| *v = (*v - max_value).exp());
+                    let total: f32 = column.iter().sum();
+                    column.iter_mut().for_each(|v| *v /= total);
+                }
+            },
+        }
+    }
+}
+
+
+/// Layers supported by the CPU backend.
+#[derive(Clone, Debug)]
+pub enum CpuLayer {
+    BatchNorm {
+        gamma: CpuTensor,
+        beta: CpuTensor,
+        mean: CpuTensor,
+        variance: CpuTensor,
+        eps: f32,
+    },
+    Conv2D {
+        activation: CpuActivation,
+        kernel_size: (u64, u64),
+        stride: (u64, u64),
+        padding_size: (u64, u64, u64, u64),
+        weights: CpuTensor,
+        biases: CpuTensor,
+    },
+    Dense {
+        activation: CpuActivation,
+        weights: CpuTensor,
+        biases: CpuTensor,
+    },
+    Flatten,
+    Identity,
+    MaxPool2D {
+        pool_size: (u64, u64),
+        stride: (u64, u64),
+        padding_size: (u64, u64, u64, u64),
+        output_shape: [u64; 4],
+    },
+}
+
+impl CpuLayer {
+    /// Computes the activation of the layer.
+    fn compute_activation(&self, input: &CpuTensor) -> CpuTensor {
+        match self {
+            CpuLayer::BatchNorm { gamma, beta, mean, variance, eps } => {
+                let mut output = input.clone();
+                let d = input.dims;
+                for i3 in 0..d[3] {
+                    for i2 in 0..d[2] {
+                        for i1 in 0..d[1] {
+                            for i0 in 0..d[0] {
+                                let idx = input.linear_index(i0, i1, i2, i3);
+                                let normalized = (input.values[idx] - mean.get_broadcast(i0, i1, i2)) / (variance.get_broadcast(i0, i1, i2) + eps).sqrt();
+                                output.values[idx] = gamma.get_broadcast(i0, i1, i2) * normalized + beta.get_broadcast(i0, i1, i2);
+                            }
+                        }
+                    }
+                }
+                output
+            },
+            CpuLayer::Conv2D { activation, kernel_size, stride, padding_size, weights, biases } => {
+                let mut output = CpuBackend::conv2d(input, weights, biases, *kernel_size, *stride, *padding_size);
+                activation.eval(&mut output);
+                output
+            },
+            CpuLayer::Dense { activation, weights, biases } => {
+                let mut output = CpuBackend::matmul(weights, input, false, false);
+                let units = biases.values.len();
+                for column in output.values.chunks_mut(units) {
+                    column.iter_mut().zip(&biases.values).for_each(|(v, b)| *v += b);
+                }
+                activation.eval(&mut output);
+                output
+            },
+            CpuLayer::Flatten => {
+                let d = input.dims;
+                CpuTensor {
+                    dims: [d[0] * d[1] * d[2], 1, 1, d[3]],
+                    values: input.values.clone(),
+                }
+            },
+            CpuLayer::Identity => input.clone(),
+            CpuLayer::MaxPool2D { pool_size, stride, padding_size, output_shape } => {
+                let (height, width) = (input.dims[0], input.dims[1]);
+                let (num_channels, batch_size) = (input.dims[2], input.dims[3]);
+                let (h_out, w_out) = (output_shape[0], output_shape[1]);
+                let mut output = CpuTensor::zeros([h_out, w_out, num_channels, batch_size]);
+                for b in 0..batch_size {
+                    for c in 0..num_channels {
+                        for j in 0..w_out {
+                            for i in 0..h_out {
+                                let mut value = f32::NEG_INFINITY;
+                                for kj in 0..pool_size.1 {
+                                    for ki in 0..pool_size.0 {
+                                        // The padded positions never win the max
+                                        let row = (i * stride.0 + ki).wrapping_sub(padding_size.0);
+                                        let col = (j * stride.1 + kj).wrapping_sub(padding_size.3);
+                                        if row < height && col < width {
+                                            value = value.max(input.get(row, col, c, b));
+                                        }
+                                    }
+                                }
+                                let idx = output.linear_index(i, j, c, b);
+                                output.values[idx] = value;
+                            }
+                        }
+                    }
+                }
+                output
+            },
+        }
+    }
+}
+
+
+/// Neural network evaluated by the CPU backend.
+#[derive(Clone, Debug)]
+pub struct CpuNetwork {
+    input_shape: [u64; 4],
+    output_shape: [u64; 4],
+    layers: Vec<CpuLayer>,
+}
+
+impl CpuNetwork {
+    /// Creates a network from its layers.
+    pub fn new(input_shape: [u64; 4], output_shape: [u64; 4], layers: Vec<CpuLayer>) -> CpuNetwork {
+        CpuNetwork {
+            input_shape,
+            output_shape,
+            layers,
+        }
+    }
+
+    /// Returns the shape of the input of the network.
+    pub fn input_shape(&self) -> [u64; 4] {
+        self.input_shape
+    }
+
+    /// Returns the shape of the output of the network.
+    pub fn output_shape(&self) -> [u64; 4] {
+        self.output_shape
+    }
+
+    /// Computes the output of the network for the given input.
+    ///
+    /// Multiple samples can be evaluated at once by stacking them along the fourth dimension of the tensor.
+    pub fn predict(&self, input: &CpuTensor) -> CpuTensor {
+        self.layers.iter().fold(input.clone(), |a_prev, layer| layer.compute_activation(&a_prev))
+    }
+
+    /// Saves the network in the binary format of the CPU backend.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, self.to_bytes())
+    }
+
+    /// Loads a network saved in the binary format of the CPU backend.
+    pub fn load(filename: &str) -> io::Result<CpuNetwork> {
+        let bytes = fs::read(Path::new(filename))?;
+        CpuNetwork::from_bytes(&bytes)
+    }
+
+    /// Serializes the network.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        write_dims(&mut bytes, &self.input_shape);
+        write_dims(&mut bytes, &self.output_shape);
+        write_u64(&mut bytes, self.layers.len() as u64);
+
+        for layer in &self.layers {
+            match layer {
+                CpuLayer::BatchNorm { gamma, beta, mean, variance, eps } => {
+                    bytes.push(0);
+                    write_tensor(&mut bytes, gamma);
+                    write_tensor(&mut bytes, beta);
+                    write_tensor(&mut bytes, mean);
+                    write_tensor(&mut bytes, variance);
+                    bytes.extend_from_slice(&eps.to_le_bytes());
+                },
+                CpuLayer::Conv2D { activation, kernel_size, stride, padding_size, weights, biases } => {
+                    bytes.push(1);
+                    bytes.push(*activation as u8);
+                    write_dims(&mut bytes, &[kernel_size.0, kernel_size.1, stride.0, stride.1]);
+                    write_dims(&mut bytes, &[padding_size.0, padding_size.1, padding_size.2, padding_size.3]);
+                    write_tensor(&mut bytes, weights);
+                    write_tensor(&mut bytes, biases);
+                },
+                CpuLayer::Dense { activation, weights, biases } => {
+                    bytes.push(2);
+                    bytes.push(*activation as u8);
+                    write_tensor(&mut bytes, weights);
+                    write_tensor(&mut bytes, biases);
+                },
+                CpuLayer::Flatten => bytes.push(3),
+                CpuLayer::Identity => bytes.push(4),
+                CpuLayer::MaxPool2D { pool_size, stride, padding_size, output_shape } => {
+                    bytes.push(5);
+                    write_dims(&mut bytes, &[pool_size.0, pool_size.1, stride.0, stride.1]);
+                    write_dims(&mut bytes, &[padding_size.0, padding_size.1, padding_size.2, padding_size.3]);
+                    write_dims(&mut bytes, output_shape);
+                },
+            }
+        }
+        bytes
+    }
+
+    /// Deserializes a network.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<CpuNetwork> {
+        let mut reader = Reader { bytes, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid_data("The data is not a network saved by the CPU backend."));
+        }
+        if reader.u8()? != FORMAT_VERSION {
+            return Err(invalid_data("Unsupported format version."));
+        }
+
+        let input_shape = reader.dims()?;
+        let output_shape = reader.dims()?;
+        let num_layers = reader.u64()?;
+        let mut layers = Vec::with_capacity(num_layers as usize);
+        for _ in 0..num_layers {
+            let layer = match reader.u8()? {
+                0 => CpuLayer::BatchNorm {
+                    gamma: reader.tensor()?,
+                    beta: reader.tensor()?,
+                    mean: reader.tensor()?,
+                    variance: reader.tensor()?,
+                    eps: reader.f32()?,
+                },
+                1 => {
+                    let activation = reader.activation()?;
+                    let params = reader.dims()?;
+                    let padding_size = reader.dims()?;
+                    CpuLayer::Conv2D {
+                        activation,
+                        kernel_size: (params[0], params[1]),
+                        stride: (params[2], params[3]),
+                        padding_size: (padding_size[0], padding_size[1], padding_size[2], padding_size[3]),
+                        weights: reader.tensor()?,
+                        biases: reader.tensor()?,
+                    }
+                },
+                2 => CpuLayer::Dense {
+                    activation: reader.activation()?,
+                    weights: reader.tensor()?,
+                    biases: reader.tensor()?,
+                },
+                3 => CpuLayer::Flatten,
+                4 => CpuLayer::Identity,
+                5 => {
+                    let params = reader.dims()?;
+                    let padding_size = reader.dims()?;
+                    CpuLayer::MaxPool2D {
+                        pool_size: (params[0], params[1]),
+                        stride: (params[2], params[3]),
+                        padding_size: (padding_size[0], padding_size[1], padding_size[2], padding_size[3]),
+                        output_shape: reader.dims()?,
+                    }
+                },
+                _ => return Err(invalid_data("Unknown layer.")),
+            };
+            layers.push(layer);
+        }
+
+        Ok(CpuNetwork {
+            input_shape,
+            output_shape,
+            layers,
+        })
+    }
+}
+
+
+fn write_u64(bytes: &mut Vec<u8>, value: u64) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_dims(bytes: &mut Vec<u8>, dims: &[u64; 4]) {
+    for dim in dims {
+        write_u64(bytes, *dim);
+    }
+}
+
+fn write_tensor(bytes: &mut Vec<u8>, tensor: &CpuTensor) {
+    write_dims(bytes, &tensor.dims);
+    for value in &tensor.values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads the values serialized by the CPU backend.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.position + n > self.bytes.len() {
+            return Err(invalid_data("Unexpected end of data."));
+        }
+        let slice = &self.bytes[self.position..self.position + n];
+        self.position += n;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        let mut buffer = [0u8; 8];
+        buffer.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        let mut buffer = [0u8; 4];
+        buffer.copy_from_slice(self.take(4)?);
+        Ok(f32::from_le_bytes(buffer))
+    }
+
+    fn dims(&mut self) -> io::Result<[u64; 4]> {
+        Ok([self.u64()?, self.u64()?, self.u64()?, self.u64()?])
+    }
+
+    fn activation(&mut self) -> io::Result<CpuActivation> {
+        CpuActivation::try_from(self.u8()?).map_err(|_| invalid_data("Unknown activation."))
+    }
+
+    fn tensor(&mut self) -> io::Result<CpuTensor> {
+        let dims = self.dims()?;
+        let num_values = dims.iter().product::<u64>() as usize;
+        let mut values = Vec::with_capacity(num_values);
+        for _ in 0..num_values {
+            values.push(self.f32()?);
+        }
+        Ok(CpuTensor { dims, values })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::cpu::*;
+    use crate::assert_approx_eq;
+
+    #[test]
+    fn test_cpu_dense_forward() {
+        // Same layer as the test of the ArrayFire Dense layer
+        let layer = CpuLayer::Dense {
+            activation: CpuActivation::Linear,
+            weights: CpuTensor::new(&[1., -2., 5., 3., -7., 0.], [2, 3, 1, 1]),
+            biases: CpuTensor::new(&[2., -1.], [2, 1, 1, 1]),
+        };
+        let network = CpuNetwork::new([3, 1, 1, 1], [2, 1, 1, 1], vec![layer]);
+        let input = CpuTensor::new(&[-2., 1., 4., 3., -1., 2.], [3, 1, 1, 2]);
+        let output = network.predict(&input);
+        let expected_output: [f32; 4] = [-23., 6., -14., -10.];
+        assert_approx_eq!(output.values(), expected_output);
+    }
+
+    #[test]
+    fn test_cpu_conv2d_forward() {
+        // Same layer and images as the test of the ArrayFire Conv2D layer
+        let filters: [f32; 24] = [1., 1., 1., 1., 2., 1., 1., 2., -1., -1., -1., -1., 1., 2., 1., 2., -2., -2., -2., -2., 1., 3., 3., 1.];
+        let weights: Vec<f32> = (0..24).map(|i| filters[i / 2 + 12 * (i % 2)]).collect();
+        let layer = CpuLayer::Conv2D {
+            activation: CpuActivation::Linear,
+            kernel_size: (2, 2),
+            stride: (1, 1),
+            padding_size: (0, 0, 0, 0),
+            weights: CpuTensor::new(&weights, [2, 12, 1, 1]),
+            biases: CpuTensor::new(&[0., 0.], [2, 1, 1, 1]),
+        };
+        let mut images: Vec<f32> = (1..=27).map(|v| v as f32).collect();
+        images.extend(&[4., -1., 2., 2., -3., 1., 6., 9., -10., 7., 5., -3., 1., -2., 4., -12., -21., 1., 2., 9., 8., -4., -3., 7., 1., 1., -2.]);
+        let output = layer.compute_activation(&CpuTensor::new(&images, [3, 3, 3, 2]));
+        let expected_output: [f32; 16] = [0., 6., 18., 24., 91., 97., 109., 115., 14., -9., -35., -25., -10., 25., 79., 43.];
+        assert_approx_eq!(output.values(), expected_output);
+    }
+
+    #[test]
+    fn test_cpu_backend_reductions() {
+        let tensor = CpuTensor::new(&[1., -2., 5., 3., -7., 0.], [2, 3, 1, 1]);
+        let expected_sum: [f32; 3] = [-1., 8., -7.];
+        let expected_mean: [f32; 2] = [-1./3., 1./3.];
+        let expected_max: [f32; 3] = [1., 5., 0.];
+        let (sum, mean, max) = (CpuBackend::sum(&tensor, 0), CpuBackend::mean(&tensor, 1), CpuBackend::max(&tensor, 0));
+        assert_approx_eq!(sum.values(), expected_sum);
+        assert_approx_eq!(mean.values(), expected_mean);
+        assert_approx_eq!(max.values(), expected_max);
+    }
+
+    #[test]
+    fn test_cpu_network_serialization() {
+        let layer = CpuLayer::Dense {
+            activation: CpuActivation::ReLU,
+            weights: CpuTensor::new(&[1., -2., 5., 3., -7., 0.], [2, 3, 1, 1]),
+            biases: CpuTensor::new(&[2., -1.], [2, 1, 1, 1]),
+        };
+        let network = CpuNetwork::new([3, 1, 1, 1], [2, 1, 1, 1], vec![CpuLayer::Flatten, layer, CpuLayer::Identity]);
+        let restored = CpuNetwork::from_bytes(&network.to_bytes()).unwrap();
+        let input = CpuTensor::new(&[-2., 1., 4., 3., -1., 2.], [3, 1, 1, 2]);
+        assert_eq!(network.predict(&input), restored.predict(&input));
+    }
+}
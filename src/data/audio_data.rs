@@ -0,0 +1,334 @@
+//! Helper methods to work with audio data sets.
+use arrayfire::*;
+use rand::thread_rng;
+use rayon::prelude::*;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+use super::{DataSet, DataSetError, ImageDataSet, Scaling};
+use super::spectral;
+use crate::errors::*;
+use crate::tensor::*;
+
+/// Features extracted from the audio clips.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AudioFeatures {
+    /// The raw waveform, of shape [number of values, 1, 1].
+    Waveform,
+    /// Overlapping frames of the waveform, of shape [frame length, number of frames, 1].
+    Frames { frame_length: u64, hop_length: u64 },
+    /// The logarithm of the magnitude spectrogram computed with a Hann window, of shape
+    /// [frame length / 2 + 1, number of frames, 1].
+    Spectrogram { frame_length: u64, hop_length: u64 },
+    /// The logarithm of the mel spectrogram, of shape [number of mels, number of frames, 1].
+    MelSpectrogram { frame_length: u64, hop_length: u64, num_mels: u64 },
+}
+
+impl AudioFeatures {
+    /// Offset added before taking the logarithm of the spectrograms.
+    const LOG_OFFSET: PrimitiveType = 1e-6;
+
+    /// Extracts the features from a batch of waveforms of shape [number of values, 1, 1, batch size].
+    fn extract(self, waveforms: &Tensor, sample_rate: u32) -> Tensor {
+        match self {
+            AudioFeatures::Waveform => waveforms.copy(),
+            AudioFeatures::Frames { frame_length, hop_length } => spectral::frame(waveforms, frame_length, hop_length),
+            AudioFeatures::Spectrogram { frame_length, hop_length } => {
+                let magnitude = spectral::spectrogram(waveforms, frame_length, hop_length, spectral::Window::Hann, 1.);
+                log(&add(&magnitude, &Self::LOG_OFFSET, false))
+            },
+            AudioFeatures::MelSpectrogram { frame_length, hop_length, num_mels } => {
+                let mel = spectral::mel_spectrogram(waveforms, sample_rate as f64, frame_length, hop_length, num_mels);
+                log(&add(&mel, &Self::LOG_OFFSET, false))
+            },
+        }
+    }
+}
+
+
+/// Structure representing a collection of labeled audio clips.
+pub struct AudioDataSet {
+    input_shape: Dim,
+    output_shape: Dim,
+    sample_rate: u32,
+    features: AudioFeatures,
+    num_train_samples: u64,
+    num_valid_samples: u64,
+    classes: Vec<String>,
+    x_train: Tensor,
+    y_train: Tensor,
+    x_valid: Option<Tensor>,
+    y_valid: Option<Tensor>,
+}
+
+impl AudioDataSet {
+    /// Creates an audio data set from a manifest listing the WAV files and their labels.
+    ///
+    /// The manifest is a CSV file without header whose rows contain the path to a WAV file, relative to the directory
+    /// of the manifest, and the name of its class:
+    /// ```text
+    /// clips/dog_bark_01.wav,dog
+    /// clips/siren_07.wav,siren
+    /// ```
+    ///
+    /// The clips are mixed down to mono, resampled to the given sample rate with a linear interpolation, and cropped
+    /// or padded with zeros to the given duration. The labels are one hot encoded if there are more than two classes.
+    ///
+    /// # Arguments
+    ///
+    /// * `manifest` - The path to the manifest.
+    /// * `sample_rate` - The sample rate of the clips in hertz.
+    /// * `duration` - The duration of the clips in seconds.
+    /// * `features` - The features extracted from the clips.
+    /// * `valid_frac` - The fraction of the data used for validation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest doesn't exist, is empty or contains an invalid row, or if a clip can't be
+    /// decoded.
+    pub fn from_manifest(manifest: &Path,
+                         sample_rate: u32,
+                         duration: f64,
+                         features: AudioFeatures,
+                         valid_frac: Option<f64>,
+    ) -> Result<AudioDataSet, Error> {
+        if let Some(valid_frac) = valid_frac {
+            if valid_frac <= 0. || valid_frac >= 1. {
+                return Err(std::convert::From::from(DataSetError::InvalidValidationFraction));
+            }
+        }
+        if !manifest.exists() {
+            return Err(std::convert::From::from(DataSetError::PathDoesNotExist));
+        }
+
+        // Read the manifest
+        let root = manifest.parent().unwrap_or_else(|| Path::new(""));
+        let mut reader = csv::ReaderBuilder::new().has_headers(false).from_path(manifest).map_err(DataSetError::Csv)?;
+        let mut entries: Vec<(String, String)> = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(DataSetError::Csv)?;
+            let path = record.get(0).ok_or(DataSetError::InvalidManifest)?.trim().to_string();
+            let label = record.get(1).ok_or(DataSetError::InvalidManifest)?.trim().to_string();
+            entries.push((path, label));
+        }
+        if entries.is_empty() {
+            return Err(std::convert::From::from(DataSetError::EmptyManifest));
+        }
+        let classes: Vec<String> = entries.iter().map(|(_, label)| label.clone()).collect::<BTreeSet<String>>().into_iter().collect();
+        let num_classes = classes.len();
+
+        // Decode the clips in parallel
+        let num_values = (duration * sample_rate as f64).round() as usize;
+        let clips = entries.par_iter()
+            .map(|(path, _)| Self::load_wav(&root.join(path), sample_rate, num_values))
+            .collect::<Result<Vec<Vec<PrimitiveType>>, DataSetError>>()?;
+
+        let num_clips = clips.len() as u64;
+        let waveforms = Tensor::new(&clips.concat()[..], Dim::new(&[num_values as u64, 1, 1, num_clips]));
+        let mut x = features.extract(&waveforms, sample_rate);
+
+        let y_vec: Vec<PrimitiveType> = entries.iter()
+            .flat_map(|(_, label)| ImageDataSet::one_hot_encode(classes.binary_search(label).unwrap(), num_classes))
+            .collect();
+        let num_outputs = if num_classes < 3 { 1 } else { num_classes as u64 };
+        let mut y = Tensor::new(&y_vec[..], Dim::new(&[num_outputs, 1, 1, num_clips]));
+        Tensor::shuffle_mut(&mut [&mut x, &mut y], &mut thread_rng());
+
+        // Split into train / validation sets
+        let (x_train, y_train, x_valid, y_valid) = match valid_frac {
+            Some(valid_frac) => ImageDataSet::split_data(x, y, valid_frac),
+            None => (x, y, None, None),
+        };
+
+        let x_dims = x_train.dims();
+        let input_shape = Dim::new(&[x_dims[0], x_dims[1], x_dims[2], 1]);
+        let output_shape = Dim::new(&[num_outputs, 1, 1, 1]);
+        let num_train_samples = x_train.batch_size();
+        let num_valid_samples = x_valid.as_ref().map(|x| x.batch_size()).unwrap_or(0);
+
+        Ok(AudioDataSet {
+            input_shape,
+            output_shape,
+            sample_rate,
+            features,
+            num_train_samples,
+            num_valid_samples,
+            classes,
+            x_train,
+            y_train,
+            x_valid,
+            y_valid,
+        })
+    }
+
+    /// Loads a WAV file as a mono waveform with the given sample rate and number of values.
+    ///
+    /// The integer samples are scaled to [-1, 1].
+    pub fn load_wav(path: &Path, sample_rate: u32, num_values: usize) -> Result<Vec<PrimitiveType>, DataSetError> {
+        let mut reader = hound::WavReader::open(path).map_err(|_| DataSetError::InvalidAudioFile)?;
+        let spec = reader.spec();
+        let samples: Vec<PrimitiveType> = match spec.sample_format {
+            hound::SampleFormat::Float => reader.samples::<f32>()
+                .map(|sample| sample.map(|value| value as PrimitiveType))
+                .collect::<Result<_, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = (1u64 << (spec.bits_per_sample - 1)) as PrimitiveType;
+                reader.samples::<i32>()
+                    .map(|sample| sample.map(|value| value as PrimitiveType / scale))
+                    .collect::<Result<_, _>>()
+            },
+        }.map_err(|_| DataSetError::InvalidAudioFile)?;
+
+        // Mix down to mono
+        let num_channels = spec.channels as usize;
+        let mono: Vec<PrimitiveType> = samples.chunks(num_channels)
+            .map(|frame| frame.iter().sum::<PrimitiveType>() / num_channels as PrimitiveType)
+            .collect();
+
+        let mut waveform = Self::resample(&mono, spec.sample_rate, sample_rate);
+        waveform.resize(num_values, 0.);
+        Ok(waveform)
+    }
+
+    /// Resamples a waveform with a linear interpolation.
+    fn resample(waveform: &[PrimitiveType], source_rate: u32, target_rate: u32) -> Vec<PrimitiveType> {
+        if source_rate == target_rate || waveform.is_empty() {
+            return waveform.to_vec();
+        }
+        let ratio = source_rate as f64 / target_rate as f64;
+        let num_values = (waveform.len() as f64 / ratio).floor() as usize;
+        (0..num_values).map(|i| {
+            let position = i as f64 * ratio;
+            let lower = position.floor() as usize;
+            let upper = std::cmp::min(lower + 1, waveform.len() - 1);
+            let fraction = (position - lower as f64) as PrimitiveType;
+            waveform[lower] * (1. - fraction) + waveform[upper] * fraction
+        }).collect()
+    }
+
+    /// Returns the sample rate of the clips in hertz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns the features extracted from the clips.
+    pub fn features(&self) -> AudioFeatures {
+        self.features
+    }
+}
+
+impl DataSet for AudioDataSet {
+    fn input_shape(&self) -> Dim4 { self.input_shape }
+
+    fn output_shape(&self) -> Dim4 { self.output_shape }
+
+    fn num_train_samples(&self) -> u64 { self.num_train_samples }
+
+    fn num_valid_samples(&self) -> u64 { self.num_valid_samples }
+
+    fn classes(&self) -> Option<Vec<String>> {
+        Some(self.classes.clone())
+    }
+
+    fn x_train(&self) -> &Tensor {
+        &self.x_train
+    }
+
+    fn y_train(&self) -> &Tensor {
+        &self.y_train
+    }
+
+    fn x_valid(&self) -> Option<&Tensor> {
+        self.x_valid.as_ref()
+    }
+
+    fn y_valid(&self) -> Option<&Tensor> {
+        self.y_valid.as_ref()
+    }
+
+    fn x_test(&self) -> Option<&Tensor> {
+        None
+    }
+
+    fn y_test(&self) -> Option<&Tensor> {
+        None
+    }
+
+    fn x_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
+        &None
+    }
+
+    fn y_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
+        &None
+    }
+}
+
+impl fmt::Display for AudioDataSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=======")?;
+        writeln!(f, "Dataset")?;
+        writeln!(f, "=======")?;
+        writeln!(f, "Samples shape: [{} {} {}]", self.input_shape[0], self.input_shape[1], self.input_shape[2])?;
+        writeln!(f, "Labels shape: [{} {} {}]", self.output_shape[0], self.output_shape[1], self.output_shape[2])?;
+        writeln!(f, "Sample rate: {} Hz", self.sample_rate)?;
+        writeln!(f, "Number of training samples: {}", self.num_train_samples)?;
+        writeln!(f, "Number of validation samples: {}", self.num_valid_samples)?;
+        writeln!(f, "Number of classes: {}", self.classes.len())?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use crate::assert_approx_eq;
+    use crate::data::{AudioDataSet, AudioFeatures, DataSet};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_from_manifest() {
+        let root = std::env::temp_dir().join("neuro_test_audio_from_manifest");
+        std::fs::create_dir_all(&root).unwrap();
+        let clips: [(&str, u16, i16); 3] = [("dog_1.wav", 1, 16384), ("dog_2.wav", 1, 16384), ("cat_1.wav", 2, -8192)];
+        for &(name, channels, value) in clips.iter() {
+            let spec = hound::WavSpec { channels, sample_rate: 8, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+            let mut writer = hound::WavWriter::create(root.join(name), spec).unwrap();
+            for _ in 0..6 * channels {
+                writer.write_sample(value).unwrap();
+            }
+            writer.finalize().unwrap();
+        }
+        let manifest = root.join("manifest.csv");
+        let mut manifest_file = std::fs::File::create(&manifest).unwrap();
+        writeln!(manifest_file, "dog_1.wav,dog\ndog_2.wav,dog\ncat_1.wav,cat").unwrap();
+        drop(manifest_file);
+
+        let data = AudioDataSet::from_manifest(&manifest, 8, 1., AudioFeatures::Waveform, None).unwrap();
+        assert_eq!(data.input_shape().get(), &[8, 1, 1, 1]);
+        assert_eq!(data.num_train_samples(), 3);
+        assert_eq!(data.classes().unwrap(), vec!["cat".to_string(), "dog".to_string()]);
+
+        // The clips are mixed down to mono, scaled to [-1, 1] and padded with zeros
+        let mut x = [0 as PrimitiveType; 24];
+        data.x_train().host(&mut x);
+        let mut y = [0 as PrimitiveType; 3];
+        data.y_train().host(&mut y);
+        for (sample, &label) in x.chunks(8).zip(y.iter()) {
+            let expected = if label == 1. { 0.5 } else { -0.25 };
+            assert_approx_eq!(sample, [expected, expected, expected, expected, expected, expected, 0., 0.]);
+        }
+
+        std::fs::File::create(&manifest).unwrap();
+        assert!(AudioDataSet::from_manifest(&manifest, 8, 1., AudioFeatures::Waveform, None).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_resample() {
+        let waveform = [0., 1., 2., 3., 4., 5.];
+        assert_approx_eq!(AudioDataSet::resample(&waveform, 2, 1), [0., 2., 4.]);
+        assert_approx_eq!(AudioDataSet::resample(&waveform[..3], 2, 4), [0., 0.5, 1., 1.5, 2., 2.]);
+    }
+}
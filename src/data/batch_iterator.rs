@@ -1,9 +1,12 @@
 use arrayfire::*;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
 
 use crate::tensor::*;
 
 pub struct BatchIterator<'a> {
     data: (&'a Tensor, &'a Tensor),
+    weights: Option<&'a Tensor>,
     num_samples: u64,
     batch_size: u64,
     batch: u64,
@@ -19,6 +22,17 @@ impl<'a> BatchIterator<'a> {
     /// * `batch_size` - size of the mini-batches
     ///
     pub fn new(data: (&'a Tensor, &'a Tensor), batch_size: u64) -> BatchIterator<'a> {
+        BatchIterator::with_weights(data, None, batch_size)
+    }
+
+    /// Creates a batch iterator of given size for the two Tensors and the weights of the samples.
+    ///
+    /// # Arguments
+    /// * `data` - tuple of reference to the Tensors.
+    /// * `weights` - reference to the weights of the samples, if any.
+    /// * `batch_size` - size of the mini-batches
+    ///
+    pub fn with_weights(data: (&'a Tensor, &'a Tensor), weights: Option<&'a Tensor>, batch_size: u64) -> BatchIterator<'a> {
         // Check that both tensors have the same number of samples
         assert_eq!(data.0.dims().get()[3], data.1.dims().get()[3]);
         let num_samples = data.0.dims().get()[3];
@@ -30,8 +44,13 @@ impl<'a> BatchIterator<'a> {
             (num_samples, 1)
         };
 
+        if let Some(weights) = weights {
+            assert_eq!(weights.dims().get()[3], num_samples);
+        }
+
         BatchIterator {
             data,
+            weights,
             num_samples,
             batch_size,
             batch: 0,
@@ -46,7 +65,7 @@ impl<'a> BatchIterator<'a> {
 }
 
 impl<'a> std::iter::Iterator for BatchIterator<'a> {
-    type Item = (Tensor, Tensor);
+    type Item = (Tensor, Tensor, Option<Tensor>);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.batch < self.num_batches {
@@ -61,12 +80,88 @@ impl<'a> std::iter::Iterator for BatchIterator<'a> {
             let seqs = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(lb as f64, ub as f64, 1.0)];
             let mini_batch_x = index(&self.data.0, seqs);
             let mini_batch_y = index(&self.data.1, seqs);
+            let mini_batch_weights = self.weights.map(|weights| index(weights, seqs));
 
             self.batch += 1;
 
-            Some((mini_batch_x, mini_batch_y))
+            Some((mini_batch_x, mini_batch_y, mini_batch_weights))
         } else {
             None
         }
     }
-}
\ No newline at end of file
+}
+
+/// Iterator over the mini-batches of sparse samples and their labels.
+pub(crate) struct SparseBatchIterator<'a> {
+    data: (&'a SparseTensor, &'a Tensor),
+    weights: Option<&'a Tensor>,
+    indices: Vec<u64>,
+    batch_size: u64,
+    batch: u64,
+    num_batches: u64
+}
+
+impl<'a> SparseBatchIterator<'a> {
+
+    /// Creates a batch iterator of given size for the sparse samples and their labels.
+    ///
+    /// # Arguments
+    /// * `data` - tuple of reference to the sparse samples and the labels.
+    /// * `weights` - reference to the weights of the samples, if any.
+    /// * `batch_size` - size of the mini-batches
+    /// * `shuffle` - whether the samples are shuffled before the mini-batches are created.
+    ///
+    pub fn new(data: (&'a SparseTensor, &'a Tensor), weights: Option<&'a Tensor>, batch_size: u64, shuffle: bool) -> SparseBatchIterator<'a> {
+        assert_eq!(data.0.batch_size(), data.1.dims().get()[3]);
+        let num_samples = data.0.batch_size();
+        if let Some(weights) = weights {
+            assert_eq!(weights.dims().get()[3], num_samples);
+        }
+
+        let mut indices: Vec<u64> = (0..num_samples).collect();
+        if shuffle {
+            indices.shuffle(&mut thread_rng());
+        }
+
+        let batch_size = std::cmp::min(batch_size, num_samples);
+        let num_batches = (num_samples as f64 / batch_size as f64).ceil() as u64;
+
+        SparseBatchIterator {
+            data,
+            weights,
+            indices,
+            batch_size,
+            batch: 0,
+            num_batches
+        }
+    }
+
+    /// Returns the number of batches that the iterator will produce.
+    pub(crate) fn num_batches(&self) -> u64 {
+        self.num_batches
+    }
+}
+
+impl<'a> std::iter::Iterator for SparseBatchIterator<'a> {
+    type Item = (SparseTensor, Tensor, Option<Tensor>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch < self.num_batches {
+            let lb = (self.batch * self.batch_size) as usize;
+            let ub = std::cmp::min(lb + self.batch_size as usize, self.indices.len());
+            let indices = &self.indices[lb..ub];
+
+            // Create mini-batches
+            let indices_arr = Array::new(indices, Dim4::new(&[indices.len() as u64, 1, 1, 1]));
+            let mini_batch_x = self.data.0.rows(indices);
+            let mini_batch_y = lookup(self.data.1, &indices_arr, 3);
+            let mini_batch_weights = self.weights.map(|weights| lookup(weights, &indices_arr, 3));
+
+            self.batch += 1;
+
+            Some((mini_batch_x, mini_batch_y, mini_batch_weights))
+        } else {
+            None
+        }
+    }
+}
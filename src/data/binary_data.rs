@@ -0,0 +1,352 @@
+//! Compact binary format to save and reload data sets without parsing the original files.
+//!
+//! A binary file starts with a header containing the size of the primitive type, the scalings, the classes, and the
+//! dimensions of the tensors of the data set. The values of the tensors follow as raw little-endian blocks in the
+//! column-major order of ArrayFire. The blocks are aligned on 8 bytes such that they can be uploaded to the device
+//! directly from a memory map of the file.
+use arrayfire::*;
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::{DataSet, DataSetError, Scaling};
+use crate::tensor::*;
+
+/// Identifies the binary data set files.
+const MAGIC: &[u8; 8] = b"NEURODS\0";
+
+/// Version of the format.
+const VERSION: u32 = 1;
+
+/// Number of tensors described in the header: the samples and labels of the training, validation, and test sets, the
+/// sample weights, and the parameters of the scalings of the inputs and outputs.
+const NUM_BLOCKS: usize = 11;
+
+/// Size of the element of the tensors in bytes.
+const ELEMENT_SIZE: usize = std::mem::size_of::<PrimitiveType>();
+
+/// Saves a data set in the binary format.
+pub(crate) fn save<D: DataSet + ?Sized>(data: &D, path: &Path) -> Result<(), DataSetError> {
+    if data.batch_generator().is_some() || data.x_train_sparse().is_some() {
+        return Err(DataSetError::DataNotInMemory);
+    }
+
+    let x_stats = data.x_train_stats().as_ref();
+    let y_stats = data.y_train_stats().as_ref();
+    let blocks: [Option<&Tensor>; NUM_BLOCKS] = [
+        Some(data.x_train()),
+        Some(data.y_train()),
+        data.x_valid(),
+        data.y_valid(),
+        data.x_test(),
+        data.y_test(),
+        data.sample_weights(),
+        x_stats.map(|(_, c1, _)| c1),
+        x_stats.map(|(_, _, c2)| c2),
+        y_stats.map(|(_, c1, _)| c1),
+        y_stats.map(|(_, _, c2)| c2),
+    ];
+
+    let mut header: Vec<u8> = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(ELEMENT_SIZE as u32).to_le_bytes());
+    header.push(scaling_code(x_stats.map(|(scaling, _, _)| *scaling)));
+    header.push(scaling_code(y_stats.map(|(scaling, _, _)| *scaling)));
+    header.extend_from_slice(&[0; 6]);
+
+    let classes = data.classes().unwrap_or_default();
+    header.extend_from_slice(&(classes.len() as u64).to_le_bytes());
+    for class in &classes {
+        header.extend_from_slice(&(class.len() as u64).to_le_bytes());
+        header.extend_from_slice(class.as_bytes());
+    }
+
+    // The dimensions of a missing tensor are zeros
+    for block in &blocks {
+        let dims = block.map_or([0; 4], |tensor| *tensor.dims().get());
+        for dim in &dims {
+            header.extend_from_slice(&dim.to_le_bytes());
+        }
+    }
+    header.resize(aligned(header.len()), 0);
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header)?;
+    for tensor in blocks.iter().flatten() {
+        let mut values = vec![0 as PrimitiveType; tensor.elements()];
+        tensor.host(&mut values);
+        for value in &values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        let padding = aligned(values.len() * ELEMENT_SIZE) - values.len() * ELEMENT_SIZE;
+        writer.write_all(&[0; 8][..padding])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Rounds the number of bytes up to a multiple of 8.
+fn aligned(num_bytes: usize) -> usize {
+    (num_bytes + 7) & !7
+}
+
+/// Encodes the type of scaling.
+fn scaling_code(scaling: Option<Scaling>) -> u8 {
+    match scaling {
+        None => 0,
+        Some(Scaling::Normalized) => 1,
+        Some(Scaling::Standardized) => 2,
+    }
+}
+
+/// Decodes the type of scaling.
+fn scaling_from_code(code: u8) -> Result<Option<Scaling>, DataSetError> {
+    match code {
+        0 => Ok(None),
+        1 => Ok(Some(Scaling::Normalized)),
+        2 => Ok(Some(Scaling::Standardized)),
+        _ => Err(DataSetError::InvalidBinaryFile),
+    }
+}
+
+/// Reads the content of a memory-mapped binary file.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Returns the next bytes of the file.
+    fn take(&mut self, num_bytes: usize) -> Result<&'a [u8], DataSetError> {
+        let end = self.position.checked_add(num_bytes).filter(|&end| end <= self.bytes.len()).ok_or(DataSetError::InvalidBinaryFile)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, DataSetError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DataSetError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    /// Uploads the next block of values to the device.
+    ///
+    /// On little-endian platforms, the values are uploaded directly from the memory map.
+    fn read_tensor(&mut self, dims: [u64; 4]) -> Result<Tensor, DataSetError> {
+        // The dimensions come from the file, a corrupt header must not overflow the size of the block
+        let num_bytes = dims.iter().try_fold(ELEMENT_SIZE as u64, |size, &dim| size.checked_mul(dim))
+            .and_then(|num_bytes| usize::try_from(num_bytes).ok())
+            .filter(|&num_bytes| num_bytes <= self.bytes.len())
+            .ok_or(DataSetError::InvalidBinaryFile)?;
+        let num_elements = num_bytes / ELEMENT_SIZE;
+        let bytes = self.take(aligned(num_bytes))?;
+        let bytes = &bytes[..num_bytes];
+
+        // The blocks are aligned in the file and the memory map is aligned on a page
+        let (prefix, values, _) = unsafe { bytes.align_to::<PrimitiveType>() };
+        if cfg!(target_endian = "little") && prefix.is_empty() {
+            Ok(Tensor::new(&values[..num_elements], Dim4::new(&dims)))
+        } else {
+            let values: Vec<PrimitiveType> = bytes.chunks_exact(ELEMENT_SIZE)
+                .map(|value| PrimitiveType::from_le_bytes(value.try_into().unwrap()))
+                .collect();
+            Ok(Tensor::new(&values[..], Dim4::new(&dims)))
+        }
+    }
+}
+
+/// Data set loaded from the binary format.
+///
+/// The data set is saved with [DataSet::save_binary](trait.DataSet.html#method.save_binary), for instance after
+/// parsing a large csv file or decoding a directory of images once. The file is memory-mapped when it is loaded, such
+/// that the values are uploaded to the device without being parsed or copied into an intermediate buffer. The
+/// classes, the sample weights, and the parameters of the scalings are preserved.
+pub struct BinaryDataSet {
+    input_shape: Dim,
+    output_shape: Dim,
+    num_train_samples: u64,
+    num_valid_samples: u64,
+    x_train: Tensor,
+    y_train: Tensor,
+    x_valid: Option<Tensor>,
+    y_valid: Option<Tensor>,
+    x_test: Option<Tensor>,
+    y_test: Option<Tensor>,
+    sample_weights: Option<Tensor>,
+    classes: Option<Vec<String>>,
+    x_train_stats: Option<(Scaling, Tensor, Tensor)>,
+    y_train_stats: Option<(Scaling, Tensor, Tensor)>,
+}
+
+impl BinaryDataSet {
+    /// Loads a data set saved with [DataSet::save_binary](trait.DataSet.html#method.save_binary).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file is not a binary data set or if it was saved with a different primitive type.
+    pub fn load_binary(path: &Path) -> Result<BinaryDataSet, DataSetError> {
+        let file = File::open(path)?;
+        // The file is assumed not to be modified while it is mapped
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        let mut reader = Reader { bytes: &mmap, position: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC || reader.read_u32()? != VERSION || reader.read_u32()? != ELEMENT_SIZE as u32 {
+            return Err(DataSetError::InvalidBinaryFile);
+        }
+        let scalings = reader.take(8)?;
+        let (x_scaling, y_scaling) = (scaling_from_code(scalings[0])?, scaling_from_code(scalings[1])?);
+
+        let num_classes = reader.read_u64()?;
+        let mut classes = Vec::new();
+        for _ in 0..num_classes {
+            let length = reader.read_u64()? as usize;
+            let class = std::str::from_utf8(reader.take(length)?).map_err(|_| DataSetError::InvalidBinaryFile)?;
+            classes.push(class.to_string());
+        }
+
+        let mut dims = [[0u64; 4]; NUM_BLOCKS];
+        for block_dims in dims.iter_mut() {
+            for dim in block_dims.iter_mut() {
+                *dim = reader.read_u64()?;
+            }
+        }
+        reader.position = aligned(reader.position);
+
+        let mut blocks = Vec::with_capacity(NUM_BLOCKS);
+        for block_dims in &dims {
+            let block = if block_dims.iter().product::<u64>() == 0 { None } else { Some(reader.read_tensor(*block_dims)?) };
+            blocks.push(block);
+        }
+        let mut blocks = blocks.into_iter();
+        let mut next_block = || blocks.next().unwrap();
+
+        let x_train = next_block().ok_or(DataSetError::InvalidBinaryFile)?;
+        let y_train = next_block().ok_or(DataSetError::InvalidBinaryFile)?;
+        let (x_valid, y_valid, x_test, y_test, sample_weights) = (next_block(), next_block(), next_block(), next_block(), next_block());
+        let x_train_stats = match (x_scaling, next_block(), next_block()) {
+            (Some(scaling), Some(c1), Some(c2)) => Some((scaling, c1, c2)),
+            _ => None,
+        };
+        let y_train_stats = match (y_scaling, next_block(), next_block()) {
+            (Some(scaling), Some(c1), Some(c2)) => Some((scaling, c1, c2)),
+            _ => None,
+        };
+
+        let x_dims = x_train.dims();
+        let y_dims = y_train.dims();
+        Ok(BinaryDataSet {
+            input_shape: Dim4::new(&[x_dims[0], x_dims[1], x_dims[2], 1]),
+            output_shape: Dim4::new(&[y_dims[0], y_dims[1], y_dims[2], 1]),
+            num_train_samples: x_train.batch_size(),
+            num_valid_samples: x_valid.as_ref().map_or(0, |x_valid| x_valid.batch_size()),
+            x_train,
+            y_train,
+            x_valid,
+            y_valid,
+            x_test,
+            y_test,
+            sample_weights,
+            classes: if classes.is_empty() { None } else { Some(classes) },
+            x_train_stats,
+            y_train_stats,
+        })
+    }
+}
+
+impl DataSet for BinaryDataSet {
+    fn input_shape(&self) -> Dim { self.input_shape }
+
+    fn output_shape(&self) -> Dim { self.output_shape }
+
+    fn num_train_samples(&self) -> u64 { self.num_train_samples }
+
+    fn num_valid_samples(&self) -> u64 { self.num_valid_samples }
+
+    fn classes(&self) -> Option<Vec<String>> { self.classes.clone() }
+
+    fn x_train(&self) -> &Tensor { &self.x_train }
+
+    fn y_train(&self) -> &Tensor { &self.y_train }
+
+    fn x_valid(&self) -> Option<&Tensor> { self.x_valid.as_ref() }
+
+    fn sample_weights(&self) -> Option<&Tensor> { self.sample_weights.as_ref() }
+
+    fn y_valid(&self) -> Option<&Tensor> { self.y_valid.as_ref() }
+
+    fn x_test(&self) -> Option<&Tensor> { self.x_test.as_ref() }
+
+    fn y_test(&self) -> Option<&Tensor> { self.y_test.as_ref() }
+
+    fn x_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> { &self.x_train_stats }
+
+    fn y_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> { &self.y_train_stats }
+}
+
+impl fmt::Display for BinaryDataSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=======")?;
+        writeln!(f, "Dataset")?;
+        writeln!(f, "=======")?;
+        writeln!(f, "Input shape: [{} {} {}]", self.input_shape[0], self.input_shape[1], self.input_shape[2])?;
+        writeln!(f, "Output shape: [{} {} {}]", self.output_shape[0], self.output_shape[1], self.output_shape[2])?;
+        writeln!(f, "Number of training samples: {}", self.num_train_samples)?;
+        writeln!(f, "Number of validation samples: {}", self.num_valid_samples)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::data::{BinaryDataSet, DataSet, DataSetError, TabularDataSet};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_binary_data_set() {
+        let x = Tensor::new(&[1., 2., 3., 4., 5., 6.], Dim4::new(&[2, 1, 1, 3]));
+        let y = Tensor::new(&[0., 1., 0.], Dim4::new(&[1, 1, 1, 3]));
+        let x_valid = Tensor::new(&[7., 8.], Dim4::new(&[2, 1, 1, 1]));
+        let y_valid = Tensor::new(&[1.], Dim4::new(&[1, 1, 1, 1]));
+        let mut data = TabularDataSet::from_tensor(x, y, Some(x_valid), Some(y_valid), None, None).unwrap();
+        data.standardize_input();
+
+        let path = std::env::temp_dir().join("neuro_test_binary_data_set.bin");
+        data.save_binary(&path).unwrap();
+        let loaded = BinaryDataSet::load_binary(&path).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.input_shape(), data.input_shape());
+        assert_eq!(loaded.output_shape(), data.output_shape());
+        assert_eq!((loaded.num_train_samples(), loaded.num_valid_samples()), (3, 1));
+        assert!(loaded.x_test().is_none());
+        let equal = |a: &Tensor, b: &Tensor| a.dims() == b.dims() && sum_all(&neq(a, b, false)).0 == 0.;
+        assert!(equal(loaded.x_train(), data.x_train()));
+        assert!(equal(loaded.y_train(), data.y_train()));
+        assert!(equal(loaded.x_valid().unwrap(), data.x_valid().unwrap()));
+        let (scaling, c1, c2) = loaded.x_train_stats().as_ref().unwrap();
+        let (expected_scaling, expected_c1, expected_c2) = data.x_train_stats().as_ref().unwrap();
+        assert_eq!(scaling, expected_scaling);
+        assert!(equal(c1, expected_c1) && equal(c2, expected_c2));
+
+        let invalid_path = std::env::temp_dir().join("neuro_test_binary_data_set_invalid.bin");
+        std::fs::write(&invalid_path, b"not a data set").unwrap();
+        assert!(matches!(BinaryDataSet::load_binary(&invalid_path), Err(DataSetError::InvalidBinaryFile)));
+
+        // Dimensions whose product overflows are rejected
+        let dims: Vec<u8> = [2u64, 1, 1, 3].iter().flat_map(|dim| dim.to_le_bytes().to_vec()).collect();
+        let position = bytes.windows(dims.len()).position(|window| window == &dims[..]).unwrap();
+        let mut corrupted = bytes.clone();
+        corrupted[position..position + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&invalid_path, &corrupted).unwrap();
+        assert!(matches!(BinaryDataSet::load_binary(&invalid_path), Err(DataSetError::InvalidBinaryFile)));
+        std::fs::remove_file(&invalid_path).unwrap();
+    }
+}
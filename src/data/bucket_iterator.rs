@@ -0,0 +1,127 @@
+use arrayfire::*;
+use rand::seq::SliceRandom;
+use rand::thread_rng;
+
+use crate::tensor::*;
+
+/// Iterator creating mini-batches of variable-length sequences grouped by length.
+///
+/// The sequences are sorted by length and split in mini-batches, such that each mini-batch contains sequences of
+/// similar lengths. The sequences of a mini-batch are padded to the length of the longest one, instead of the length of
+/// the longest sequence of the data set. The order of the mini-batches is shuffled. The padded timesteps can be
+/// skipped with a [Masking](../layers/struct.Masking.html) layer using the same padding value.
+pub struct BucketIterator<'a> {
+    sequences: &'a [Tensor],
+    labels: &'a [Tensor],
+    batches: Vec<Vec<usize>>,
+    pad_value: PrimitiveType,
+    timestep_labels: bool,
+    batch: usize,
+}
+
+impl<'a> BucketIterator<'a> {
+
+    /// Creates a bucket iterator.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequences` - The sequences, each with dimensions [timesteps, features, 1, 1].
+    /// * `labels` - The labels of the sequences.
+    /// * `batch_size` - The maximum number of sequences in a mini-batch.
+    /// * `pad_value` - The value used to pad the sequences.
+    /// * `timestep_labels` - If true, the labels have one entry per timestep, with dimensions [timesteps, outputs, 1, 1],
+    /// and are padded with zeros. Otherwise, all the labels must have the same dimensions.
+    pub fn new(sequences: &'a [Tensor],
+               labels: &'a [Tensor],
+               batch_size: u64,
+               pad_value: PrimitiveType,
+               timestep_labels: bool
+    ) -> BucketIterator<'a> {
+        assert_eq!(sequences.len(), labels.len(), "There must be one label per sequence.");
+        assert!(batch_size > 0, "The batch size must be positive.");
+
+        // Sort by length, the ties are broken randomly
+        let mut indices: Vec<usize> = (0..sequences.len()).collect();
+        indices.shuffle(&mut thread_rng());
+        indices.sort_by_key(|&i| sequences[i].dims().get()[0]);
+
+        let mut batches: Vec<Vec<usize>> = indices.chunks(batch_size as usize).map(|chunk| chunk.to_vec()).collect();
+        batches.shuffle(&mut thread_rng());
+
+        BucketIterator {
+            sequences,
+            labels,
+            batches,
+            pad_value,
+            timestep_labels,
+            batch: 0,
+        }
+    }
+
+    /// Returns the number of batches that the iterator will produce.
+    pub fn num_batches(&self) -> usize {
+        self.batches.len()
+    }
+
+    /// Pads a sequence along the first dimension.
+    fn pad(tensor: &Tensor, length: u64, value: PrimitiveType) -> Tensor {
+        let dims = tensor.dims();
+        if dims[0] == length {
+            return tensor.copy();
+        }
+        let padding = constant(value, Dim4::new(&[length - dims[0], dims[1], dims[2], dims[3]]));
+        join(0, tensor, &padding)
+    }
+}
+
+impl<'a> std::iter::Iterator for BucketIterator<'a> {
+    type Item = (Tensor, Tensor);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let indices = self.batches.get(self.batch)?;
+        let max_length = indices.iter().map(|&i| self.sequences[i].dims().get()[0]).max().unwrap_or(0);
+
+        let mut mini_batch_x: Option<Tensor> = None;
+        let mut mini_batch_y: Option<Tensor> = None;
+        for &i in indices {
+            let x = Self::pad(&self.sequences[i], max_length, self.pad_value);
+            let y = if self.timestep_labels {
+                Self::pad(&self.labels[i], max_length, 0.)
+            } else {
+                self.labels[i].copy()
+            };
+            mini_batch_x = Some(match mini_batch_x { Some(batch) => join(3, &batch, &x), None => x });
+            mini_batch_y = Some(match mini_batch_y { Some(batch) => join(3, &batch, &y), None => y });
+        }
+
+        self.batch += 1;
+        Some((mini_batch_x?, mini_batch_y?))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::data::BucketIterator;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_bucket_iterator() {
+        let lengths = [3, 1, 2, 1];
+        let sequences: Vec<Tensor> = lengths.iter().map(|&length| Tensor::ones(Dim4::new(&[length, 2, 1, 1]))).collect();
+        let labels: Vec<Tensor> = lengths.iter().map(|_| Tensor::ones(Dim4::new(&[1, 1, 1, 1]))).collect();
+
+        let iterator = BucketIterator::new(&sequences[..], &labels[..], 2, -1., false);
+        assert_eq!(iterator.num_batches(), 2);
+
+        // The two shortest sequences are grouped together
+        let mut lengths: Vec<u64> = iterator.map(|(x, y)| {
+            assert_eq!(x.batch_size(), 2);
+            assert_eq!(y.batch_size(), 2);
+            x.dims().get()[0]
+        }).collect();
+        lengths.sort();
+        assert_eq!(lengths, vec![1, 3]);
+    }
+}
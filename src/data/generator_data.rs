@@ -0,0 +1,168 @@
+//! Data set generating the training mini-batches on the fly.
+use arrayfire::*;
+use std::fmt;
+
+use super::{DataSet, Scaling};
+use crate::tensor::*;
+
+/// Data set whose training mini-batches are generated by a closure.
+///
+/// The closure receives the index of a mini-batch and returns the input samples and the labels of that mini-batch,
+/// stacked along the fourth dimension. This allows the training data to be synthesized on the fly (procedural
+/// augmentation, simulators, ...) without materializing the full data set. The order of the mini-batches is shuffled
+/// at the beginning of each epoch.
+///
+/// The `batch_size` argument of [fit](../models/struct.Network.html#method.fit) is ignored for the training set and
+/// only used to evaluate the validation set.
+pub struct GeneratorDataSet<F>
+    where F: Fn(u64) -> (Tensor, Tensor)
+{
+    generator: F,
+    num_batches: u64,
+    input_shape: Dim,
+    output_shape: Dim,
+    num_train_samples: u64,
+    num_valid_samples: u64,
+    empty: Tensor,
+    x_valid: Option<Tensor>,
+    y_valid: Option<Tensor>,
+    x_train_stats: Option<(Scaling, Tensor, Tensor)>,
+    y_train_stats: Option<(Scaling, Tensor, Tensor)>,
+}
+
+impl<F> GeneratorDataSet<F>
+    where F: Fn(u64) -> (Tensor, Tensor)
+{
+    /// Creates a data set from a closure generating the training mini-batches.
+    ///
+    /// The first mini-batch is generated once to determine the shapes of the samples and of the labels.
+    ///
+    /// # Arguments
+    ///
+    /// * `generator` - The closure returning the samples and the labels of the mini-batch with the given index.
+    /// * `num_batches` - The number of mini-batches in an epoch. The indices passed to the closure range from 0 to num_batches - 1.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of batches is zero or if the samples and labels of the first mini-batch have different batch sizes.
+    pub fn new(generator: F, num_batches: u64) -> GeneratorDataSet<F> {
+        assert!(num_batches > 0, "The number of batches must be greater than zero.");
+
+        let (x, y) = generator(0);
+        assert_eq!(x.batch_size(), y.batch_size(), "The samples and the labels must have the same batch size.");
+        let input_shape = Dim4::new(&[x.dims()[0], x.dims()[1], x.dims()[2], 1]);
+        let output_shape = Dim4::new(&[y.dims()[0], y.dims()[1], y.dims()[2], 1]);
+
+        GeneratorDataSet {
+            generator,
+            num_batches,
+            input_shape,
+            output_shape,
+            num_train_samples: num_batches * x.batch_size(),
+            num_valid_samples: 0,
+            empty: Tensor::new_empty_tensor(),
+            x_valid: None,
+            y_valid: None,
+            x_train_stats: None,
+            y_train_stats: None,
+        }
+    }
+
+    /// Sets the validation samples and labels.
+    ///
+    /// The samples must be stacked along the fourth dimension.
+    pub fn set_validation_data(&mut self, x_valid: Tensor, y_valid: Tensor) {
+        assert_eq!(x_valid.batch_size(), y_valid.batch_size(), "The samples and the labels must have the same batch size.");
+        self.num_valid_samples = x_valid.batch_size();
+        self.x_valid = Some(x_valid);
+        self.y_valid = Some(y_valid);
+    }
+}
+
+impl<F> DataSet for GeneratorDataSet<F>
+    where F: Fn(u64) -> (Tensor, Tensor)
+{
+    fn input_shape(&self) -> Dim { self.input_shape }
+
+    fn output_shape(&self) -> Dim { self.output_shape }
+
+    /// Returns the number of samples in an epoch, assuming that all mini-batches have the size of the first one.
+    fn num_train_samples(&self) -> u64 { self.num_train_samples }
+
+    fn num_valid_samples(&self) -> u64 { self.num_valid_samples }
+
+    /// Returns an empty tensor since the training samples are generated on the fly.
+    fn x_train(&self) -> &Tensor {
+        &self.empty
+    }
+
+    /// Returns an empty tensor since the training labels are generated on the fly.
+    fn y_train(&self) -> &Tensor {
+        &self.empty
+    }
+
+    fn x_valid(&self) -> Option<&Tensor> {
+        self.x_valid.as_ref()
+    }
+
+    fn y_valid(&self) -> Option<&Tensor> {
+        self.y_valid.as_ref()
+    }
+
+    fn x_test(&self) -> Option<&Tensor> { None }
+
+    fn y_test(&self) -> Option<&Tensor> { None }
+
+    fn batch_generator(&self) -> Option<(u64, &dyn Fn(u64) -> (Tensor, Tensor))> {
+        Some((self.num_batches, &self.generator))
+    }
+
+    fn x_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
+        &self.x_train_stats
+    }
+
+    fn y_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
+        &self.y_train_stats
+    }
+}
+
+impl<F> fmt::Display for GeneratorDataSet<F>
+    where F: Fn(u64) -> (Tensor, Tensor)
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=======")?;
+        writeln!(f, "Dataset")?;
+        writeln!(f, "=======")?;
+        writeln!(f, "Samples shape: [{} {} {}]", self.input_shape.get()[0], self.input_shape.get()[1], self.input_shape.get()[2],)?;
+        writeln!(f, "Labels shape: [{} {} {}]", self.output_shape.get()[0], self.output_shape.get()[1], self.output_shape.get()[2])?;
+        writeln!(f, "Number of training batches: {}", self.num_batches)?;
+        writeln!(f, "Number of validation samples: {}", self.num_valid_samples)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::data::{DataSet, GeneratorDataSet};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_generator_data_set() {
+        let data = GeneratorDataSet::new(|batch| {
+            let x = constant(batch as PrimitiveType, Dim4::new(&[3, 2, 1, 4]));
+            let y = constant(2. * batch as PrimitiveType, Dim4::new(&[1, 1, 1, 4]));
+            (x, y)
+        }, 5);
+        assert_eq!(data.input_shape().get(), &[3, 2, 1, 1]);
+        assert_eq!(data.output_shape().get(), &[1, 1, 1, 1]);
+        assert_eq!(data.num_train_samples(), 20);
+
+        let (num_batches, generator) = data.batch_generator().unwrap();
+        assert_eq!(num_batches, 5);
+        let (_, y) = generator(3);
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        y.host(&mut output);
+        assert_eq!(output, [6.; 4]);
+    }
+}
@@ -0,0 +1,105 @@
+//! Splits of the samples keeping the samples of a group in the same set.
+//!
+//! When several samples come from the same patient, user, or session, splitting them between the training and
+//! validation sets leaks information and overestimates the performance of the model on new groups.
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::collections::HashMap;
+
+use crate::tensor::*;
+
+/// Lists the indices of the samples of each group, in the order of first appearance of the groups.
+fn group_members(groups: &[PrimitiveType]) -> Vec<Vec<u64>> {
+    let mut positions: HashMap<u64, usize> = HashMap::new();
+    let mut members: Vec<Vec<u64>> = Vec::new();
+    for (i, &group) in groups.iter().enumerate() {
+        let position = *positions.entry((group as f64).to_bits()).or_insert_with(|| {
+            members.push(Vec::new());
+            members.len() - 1
+        });
+        members[position].push(i as u64);
+    }
+    members
+}
+
+/// Returns the number of distinct groups.
+pub(crate) fn num_groups(groups: &[PrimitiveType]) -> usize {
+    group_members(groups).len()
+}
+
+/// Randomly assigns whole groups to the validation set until it contains a fraction `valid_frac` of the samples.
+///
+/// # Return value
+///
+/// The sorted indices of the training and validation samples.
+pub(crate) fn group_shuffle_split<R: Rng + ?Sized>(groups: &[PrimitiveType], valid_frac: f64, rng: &mut R) -> (Vec<u64>, Vec<u64>) {
+    let mut members = group_members(groups);
+    members.shuffle(rng);
+
+    let num_valid_samples = (valid_frac * groups.len() as f64).floor() as usize;
+    let mut train_indices = Vec::new();
+    let mut valid_indices = Vec::new();
+    for group in members {
+        if valid_indices.len() < num_valid_samples {
+            valid_indices.extend(group);
+        } else {
+            train_indices.extend(group);
+        }
+    }
+    train_indices.sort_unstable();
+    valid_indices.sort_unstable();
+    (train_indices, valid_indices)
+}
+
+/// Assigns the groups to `num_folds` folds with balanced numbers of samples.
+///
+/// The groups are assigned from the largest to the smallest to the fold containing the fewest samples, such that the
+/// assignment is deterministic.
+///
+/// # Return value
+///
+/// The sorted indices of the samples of each fold.
+pub(crate) fn group_k_fold(groups: &[PrimitiveType], num_folds: usize) -> Vec<Vec<u64>> {
+    let mut members = group_members(groups);
+    // The sort is stable, hence groups of the same size keep their order of appearance
+    members.sort_by(|a, b| b.len().cmp(&a.len()));
+
+    let mut folds: Vec<Vec<u64>> = vec![Vec::new(); num_folds];
+    for group in members {
+        let smallest = (0..num_folds).min_by_key(|&fold| folds[fold].len()).unwrap();
+        folds[smallest].extend(group);
+    }
+    for fold in folds.iter_mut() {
+        fold.sort_unstable();
+    }
+    folds
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::data::groups::*;
+
+    #[test]
+    fn test_group_shuffle_split() {
+        let groups = [1., 1., 2., 3., 3., 3., 4., 2., 5., 5.];
+        let (train, valid) = group_shuffle_split(&groups, 0.3, &mut StdRng::seed_from_u64(0));
+        assert_eq!(train.len() + valid.len(), groups.len());
+        assert!(valid.len() >= 3);
+        // No group is split between the sets
+        for &i in &train {
+            assert!(valid.iter().all(|&j| groups[i as usize] != groups[j as usize]));
+        }
+    }
+
+    #[test]
+    fn test_group_k_fold() {
+        let groups = [1., 1., 2., 3., 3., 3., 4., 2., 5., 5.];
+        assert_eq!(num_groups(&groups), 5);
+        let folds = group_k_fold(&groups, 3);
+        // Groups 3 (3 samples), 1, 2, 5 (2 samples) and 4 (1 sample) are assigned from the largest
+        assert_eq!(folds, vec![vec![3, 4, 5], vec![0, 1, 8, 9], vec![2, 6, 7]]);
+    }
+}
@@ -5,11 +5,13 @@ use image;
 use image::DynamicImage;
 use walkdir::{DirEntry, WalkDir};
 use rand::{thread_rng, Rng};
+use rayon::prelude::*;
 use std::fmt;
 use std::fs;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::io::Write;
+use std::str::FromStr;
 
 use super::{Scaling, DataSet, DataSetError};
 use crate::errors::*;
@@ -35,6 +37,10 @@ pub struct ImageDataSet {
 }
 
 impl ImageDataSet {
+
+    /// Number of images decoded before being uploaded to the device.
+    const UPLOAD_CHUNK_SIZE: usize = 1024;
+
     /// Constructs an ImageDataSet from a directory tree.
     ///
     /// The images must be in folders named after the corresponding class in a *train* top-level directory.
@@ -155,13 +161,9 @@ impl ImageDataSet {
         let num_classes = walker.filter_entry(|e| !Self::is_hidden(e)).count();
         let mut classes = Vec::<String>::with_capacity(num_classes);
 
-        let mut x_vec: Vec<PrimitiveType> = Vec::new();
-        let mut y_vec: Vec<PrimitiveType> = Vec::new();
-
-        // Iterate through the subdirectories and load the images
+        // Iterate through the subdirectories and list the images
+        let mut images = Vec::<(PathBuf, usize)>::new();
         let mut class_id: usize = 0;
-        let mut num_channels = 0;
-        let mut num_images = 0;
         for class in fs::read_dir(&path)? {
             let class = class?;
             if class.path().is_dir() {
@@ -169,37 +171,59 @@ impl ImageDataSet {
                 // Store name of the class
                 classes.push(class.path().file_name().unwrap().to_str().unwrap().to_string());
 
-                // Load the images
                 for image in fs::read_dir(&class.path())? {
-                    let dir_entry = image?;
-                    let image = Self::load_image(dir_entry.path().as_path(), size, image_ops)?;
-
-                    let label = if one_hot_encode {
-                        Self::one_hot_encode(class_id, num_classes)
-                    } else {
-                        vec![class_id as PrimitiveType]
-                    };
-
-                    x_vec.extend(image.0);
-                    y_vec.extend(label);
-
-                    num_channels = image.1;
-                    num_images += 1;
+                    images.push((image?.path(), class_id));
                 }
                 class_id += 1;
             }
         }
+        let num_images = images.len();
 
-        let mut x = Tensor::new(&x_vec[..], Dim::new(&[num_channels as u64, size.1 as u64, size.0 as u64, num_images as u64]));
-        //x = reorder(&x, Dim::new(&[2, 1, 0, 3]));
-        x = reorder_v2(&x, 2, 1, Some(vec![0, 3]));
+        // Decode the images in parallel and upload them to the device in chunks, which are copied in place in the
+        // tensor holding all the images
+        let mut x: Option<Tensor> = None;
+        let mut y_vec: Vec<PrimitiveType> = Vec::new();
+        let mut num_channels = None;
+        for (chunk_index, chunk) in images.chunks(Self::UPLOAD_CHUNK_SIZE).enumerate() {
+            let decoded = chunk.par_iter()
+                .map(|(path, _)| Self::load_image(path.as_path(), size, image_ops))
+                .collect::<Result<Vec<(Vec<PrimitiveType>, u8)>, DataSetError>>()?;
+
+            let mut x_vec: Vec<PrimitiveType> = Vec::with_capacity(decoded.iter().map(|image| image.0.len()).sum());
+            for (image, (_, class_id)) in decoded.into_iter().zip(chunk.iter()) {
+                match num_channels {
+                    Some(n) if n != image.1 => return Err(DataSetError::DifferentNumbersOfChannels),
+                    _ => num_channels = Some(image.1),
+                }
+                x_vec.extend(image.0);
+
+                let label = if one_hot_encode {
+                    Self::one_hot_encode(*class_id, num_classes)
+                } else {
+                    vec![*class_id as PrimitiveType]
+                };
+                y_vec.extend(label);
+            }
+
+            let mut x_chunk = Tensor::new(&x_vec[..], Dim::new(&[num_channels.unwrap_or(0) as u64, size.1 as u64, size.0 as u64, chunk.len() as u64]));
+            x_chunk = reorder_v2(&x_chunk, 2, 1, Some(vec![0, 3]));
+            let x = x.get_or_insert_with(|| {
+                let dims = x_chunk.dims();
+                Tensor::zeros(Dim::new(&[dims[0], dims[1], dims[2], num_images as u64]))
+            });
+            let first = chunk_index * Self::UPLOAD_CHUNK_SIZE;
+            let seqs = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(first as f64, (first + chunk.len() - 1) as f64, 1.0)];
+            assign_seq(x, seqs, &x_chunk);
+        }
+
+        let mut x = x.unwrap_or_else(Tensor::new_empty_tensor);
         let mut y = if one_hot_encode {
             Tensor::new(&y_vec[..], Dim::new(&[num_classes as u64, 1, 1, num_images as u64]))
         } else {
             Tensor::new(&y_vec[..], Dim::new(&[1, 1, 1, num_images as u64]))
         };
 
-        Tensor::shuffle_mut(&mut x, &mut y);
+        Tensor::shuffle_mut(&mut [&mut x, &mut y], &mut thread_rng());
         Ok((x, y, classes))
     }
 
@@ -210,7 +234,7 @@ impl ImageDataSet {
     ///
     /// * `class_id` - The unique identifier of the class.
     /// * `num_classes` - The number of classes present in the dataset.
-    fn one_hot_encode(class_id: usize, num_classes: usize) -> Vec<PrimitiveType> {
+    pub(crate) fn one_hot_encode(class_id: usize, num_classes: usize) -> Vec<PrimitiveType> {
         if num_classes < 3 {
             let mut ohe = vec![0.; 1];
             ohe[0] = class_id as PrimitiveType;
@@ -228,7 +252,7 @@ impl ImageDataSet {
     /// # Return values
     ///
     /// Tuple containing the training samples, training labels, validation samples, and validation labels.
-    fn split_data(x: Tensor, y: Tensor, valid_frac: f64) -> (Tensor, Tensor, Option<Tensor>, Option<Tensor>) {
+    pub(crate) fn split_data(x: Tensor, y: Tensor, valid_frac: f64) -> (Tensor, Tensor, Option<Tensor>, Option<Tensor>) {
         let num_samples = x.dims().get()[3];
         let num_valid_samples = (valid_frac * num_samples as f64).floor() as u64;
         let num_train_samples = num_samples - num_valid_samples;
@@ -242,6 +266,77 @@ impl ImageDataSet {
         (x_train, y_train, Some(x_valid), Some(y_valid))
     }
 
+    /// Saves the preprocessed samples and labels in an HDF5 cache file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the cache file.
+    fn save_cache(&self, path: &Path) -> Result<(), Error> {
+        let file = hdf5::File::create(path)?;
+
+        let tensors = [("x_train", Some(&self.x_train)), ("y_train", Some(&self.y_train)),
+                       ("x_valid", self.x_valid.as_ref()), ("y_valid", self.y_valid.as_ref()),
+                       ("x_test", self.x_test.as_ref()), ("y_test", self.y_test.as_ref())];
+        for (name, tensor) in tensors.iter() {
+            if let Some(tensor) = tensor {
+                let ds = file.new_dataset::<H5Tensor>().create(name, 1)?;
+                ds.write(&[H5Tensor::from(*tensor)])?;
+            }
+        }
+
+        let classes = file.new_dataset::<hdf5::types::VarLenUnicode>().create("classes", self.classes.len())?;
+        let names: Vec<hdf5::types::VarLenUnicode> = self.classes.iter().map(|class| hdf5::types::VarLenUnicode::from_str(class).unwrap()).collect();
+        classes.write(&names[..])?;
+
+        Ok(())
+    }
+
+    /// Loads the preprocessed samples and labels from an HDF5 cache file.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path to the cache file.
+    /// * `image_size` - The height and width of the images.
+    /// * `image_ops` - The collection of operations that were applied on the images.
+    fn from_cache(path: &Path, image_size: (u32, u32), image_ops: ImageOps) -> Result<ImageDataSet, Error> {
+        let file = hdf5::File::open(path)?;
+        let _ = hdf5::silence_errors();
+        let read_tensor = |name: &str| file.dataset(name).and_then(|ds| ds.read_raw::<H5Tensor>()).ok().map(|values| Tensor::from(&values[0]));
+
+        let x_train = file.dataset("x_train").and_then(|ds| ds.read_raw::<H5Tensor>()).map(|values| Tensor::from(&values[0]))?;
+        let y_train = file.dataset("y_train").and_then(|ds| ds.read_raw::<H5Tensor>()).map(|values| Tensor::from(&values[0]))?;
+        let x_valid = read_tensor("x_valid");
+        let y_valid = read_tensor("y_valid");
+        let x_test = read_tensor("x_test");
+        let y_test = read_tensor("y_test");
+        let classes = file.dataset("classes")
+            .and_then(|ds| ds.read_raw::<hdf5::types::VarLenUnicode>())?
+            .iter()
+            .map(|entry| String::from(entry.as_str()))
+            .collect::<Vec<String>>();
+
+        let num_valid_samples = match &x_valid {
+            Some(x) => x.dims().get()[3],
+            None => 0
+        };
+
+        Ok(ImageDataSet {
+            input_shape: x_train.dims(),
+            output_shape: y_train.dims(),
+            image_size,
+            image_ops,
+            num_train_samples: x_train.dims().get()[3],
+            num_valid_samples,
+            classes,
+            x_train,
+            y_train,
+            x_valid,
+            y_valid,
+            x_test,
+            y_test,
+        })
+    }
+
     /// Filters out hidden directories (typically .DS_Store on macOS).
     ///
     /// The filtering is performed by testing if the directory name starts by '.'.
@@ -327,6 +422,11 @@ impl ImageDataSet {
     pub fn image_ops(&self) -> &ImageOps {
         &self.image_ops
     }
+
+    /// Returns the height and width to which the images are resized.
+    pub fn image_size(&self) -> (u32, u32) {
+        self.image_size
+    }
 }
 
 impl DataSet for ImageDataSet {
@@ -435,6 +535,11 @@ impl ImageOps {
         }
     }
 
+    /// Returns the factor applied to each pixel of the images, if any.
+    pub(crate) fn scale_factor(&self) -> Option<PrimitiveType> {
+        self.scale
+    }
+
     fn process(&self, image: &mut DynamicImage) -> Vec<PrimitiveType> {
         self.rotate(image);
         self.hflip(image);
@@ -492,6 +597,7 @@ pub struct ImageDataSetBuilder {
     valid_frac: Option<f64>,
     one_hot_encode: bool,
     image_ops: ImageOps,
+    cache_path: Option<PathBuf>,
 }
 
 impl ImageDataSetBuilder {
@@ -550,6 +656,7 @@ impl ImageDataSetBuilder {
             valid_frac: None,
             one_hot_encode: false,
             image_ops: ImageOps::default(),
+            cache_path: None,
         }
     }
 
@@ -581,11 +688,36 @@ impl ImageDataSetBuilder {
                 })
             }, */
             Source::Dir => {
-                ImageDataSet::from_dir(self.path, self.image_size, self.one_hot_encode, self.valid_frac, self.image_ops)
+                if let Some(cache_path) = &self.cache_path {
+                    if cache_path.exists() {
+                        return ImageDataSet::from_cache(cache_path, self.image_size, self.image_ops);
+                    }
+                }
+
+                let data = ImageDataSet::from_dir(self.path, self.image_size, self.one_hot_encode, self.valid_frac, self.image_ops)?;
+                if let Some(cache_path) = &self.cache_path {
+                    data.save_cache(cache_path)?;
+                }
+                Ok(data)
             }
         }
     }
 
+    /// Caches the preprocessed images in an HDF5 file at the given path.
+    ///
+    /// The first time the data set is built, the decoded, resized, and scaled images are saved in the cache file
+    /// together with the labels and the split into training, validation, and test sets. The subsequent builds read the
+    /// tensors from the cache file instead of decoding the images again. The cache must be deleted whenever the images
+    /// or the options of the builder change. Since the random transformations are applied when the images are decoded,
+    /// the cached images keep the transformations drawn during the first build.
+    ///
+    /// The cached tensors are read in full rather than memory-mapped, because they are uploaded to the device memory
+    /// as a whole anyway.
+    pub fn cache(mut self, path: &Path) -> ImageDataSetBuilder {
+        self.cache_path = Some(path.to_path_buf());
+        self
+    }
+
     /// Flips the images horizontally with the given probability.
     pub fn hflip(mut self, prob: f64) -> ImageDataSetBuilder {
         if prob < 0. || prob > 1. {
@@ -634,4 +766,49 @@ impl ImageDataSetBuilder {
         self.image_ops.scale = Some(factor);
         self
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::data::{DataSet, ImageDataSetBuilder};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_cache() {
+        let root = std::env::temp_dir().join("neuro_test_image_cache");
+        let cache_path = std::env::temp_dir().join("neuro_test_image_cache.h5");
+        let _ = fs::remove_dir_all(&root);
+        let _ = fs::remove_file(&cache_path);
+        for (class, value) in [("black", 0u8), ("white", 255u8)].iter() {
+            let class_dir = root.join("train").join(class);
+            fs::create_dir_all(&class_dir).unwrap();
+            for i in 0..2 {
+                image::RgbImage::from_pixel(4, 4, image::Rgb([*value, *value, *value])).save(class_dir.join(format!("{}.png", i))).unwrap();
+            }
+        }
+        let path: &'static std::path::Path = Box::leak(root.clone().into_boxed_path());
+
+        let data = ImageDataSetBuilder::from_dir(path, (4, 4)).cache(&cache_path).build().unwrap();
+        assert!(cache_path.exists());
+
+        // The second build reads the cache, even without the images
+        fs::remove_dir_all(&root).unwrap();
+        let cached = ImageDataSetBuilder::from_dir(path, (4, 4)).cache(&cache_path).build().unwrap();
+        fs::remove_file(&cache_path).unwrap();
+        assert_eq!(cached.num_train_samples(), 4);
+        assert_eq!(cached.classes(), data.classes());
+
+        let mut expected = [0 as PrimitiveType; 192];
+        data.x_train().host(&mut expected);
+        let mut values = [0 as PrimitiveType; 192];
+        cached.x_train().host(&mut values);
+        assert_eq!(values, expected);
+        let mut expected_labels = [0 as PrimitiveType; 4];
+        data.y_train().host(&mut expected_labels);
+        let mut labels = [0 as PrimitiveType; 4];
+        cached.y_train().host(&mut labels);
+        assert_eq!(labels, expected_labels);
+    }
+}
@@ -1,19 +1,39 @@
 
 //! Simple interfaces to import data.
+use arrayfire::*;
 use std::fmt;
 use std::io;
+use std::path::Path;
 
 use crate::tensor::*;
 
-pub(crate) use self::batch_iterator::BatchIterator;
+pub use self::audio_data::{AudioDataSet, AudioFeatures};
+pub use self::binary_data::BinaryDataSet;
+pub(crate) use self::batch_iterator::{BatchIterator, SparseBatchIterator};
+pub use self::bucket_iterator::BucketIterator;
+pub use self::generator_data::GeneratorDataSet;
 pub use self::image_data::ImageDataSet;
 pub use self::image_data::ImageDataSetBuilder;
 pub use self::image_data::ImageOps;
+pub use self::streaming_data::{BatchedDataSet, StreamingDataSet};
+pub use self::summary::DataSetSummary;
 pub use self::tabular_data::TabularDataSet;
+pub use self::transforms::{FeatureTransform, FittedTransform};
 
+mod audio_data;
+mod binary_data;
 mod batch_iterator;
+mod bucket_iterator;
+mod generator_data;
+mod groups;
 mod image_data;
+mod oversampling;
+pub mod spectral;
+mod streaming_data;
+mod summary;
+pub mod synthetic;
 mod tabular_data;
+mod transforms;
 
 /// Errors that may be raised by data sets methods.
 #[derive(Debug)]
@@ -26,8 +46,20 @@ pub enum DataSetError {
     ValidPathDoesNotExist,
     ImageFormatNotSupported,
     InvalidImagePath,
+    InvalidAudioFile,
+    InvalidManifest,
+    EmptyManifest,
     InvalidValidationFraction,
+    InvalidTransform,
+    InvalidValue(u64),
+    TransformAfterScaling,
     DifferentNumbersOfChannels,
+    MissingTestSet,
+    MissingValidationSet,
+    InvalidBinaryFile,
+    DataNotInMemory,
+    InvalidNumberOfFolds,
+    InvalidOversampling,
 }
 
 /// Types of data.
@@ -48,8 +80,20 @@ impl fmt::Display for DataSetError {
             DataSetError::ValidPathDoesNotExist => write!(f, "The root directory does not contain a 'valid' subfolder."),
             DataSetError::ImageFormatNotSupported => write!(f, "The image format is not supported."),
             DataSetError::InvalidImagePath => write!(f, "The path could not be opened as an image."),
+            DataSetError::InvalidAudioFile => write!(f, "The path could not be opened as a WAV file."),
+            DataSetError::InvalidManifest => write!(f, "Each row of the manifest must contain a path and a label."),
+            DataSetError::EmptyManifest => write!(f, "The manifest does not list any clip."),
             DataSetError::InvalidValidationFraction => write!(f, "The validation fraction is incorrect. It must be between 0 and 1."),
+            DataSetError::InvalidTransform => write!(f, "The transformed column does not exist or the number of buckets is zero."),
+            DataSetError::InvalidValue(sample) => write!(f, "The sample {} contains a value that is not a number.", sample),
+            DataSetError::TransformAfterScaling => write!(f, "The features must be transformed before they are scaled."),
             DataSetError::DifferentNumbersOfChannels => write!(f, "The directory contains images with different numbers of channels."),
+            DataSetError::MissingTestSet => write!(f, "The data set does not contain any test samples."),
+            DataSetError::MissingValidationSet => write!(f, "The data set does not contain any validation samples."),
+            DataSetError::InvalidBinaryFile => write!(f, "The file is not a binary data set or was saved with a different primitive type."),
+            DataSetError::DataNotInMemory => write!(f, "The training samples are generated on the fly or stored in a sparse format."),
+            DataSetError::InvalidNumberOfFolds => write!(f, "The number of folds must be between 2 and the number of groups."),
+            DataSetError::InvalidOversampling => write!(f, "The oversampling requires dense samples without head labels."),
         }
     }
 }
@@ -61,10 +105,83 @@ impl std::convert::From<io::Error> for DataSetError {
 }
 
 /// Defines the type of scaling that has been performed.
-#[derive(Debug)]
+#[derive(hdf5::H5Type, Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
 pub enum Scaling {
-    Normalized,
-    Standardized,
+    Normalized = 0,
+    Standardized = 1,
+}
+
+/// Relative tolerance below which the range or standard deviation of a feature is considered null.
+const SCALE_TOLERANCE: PrimitiveType = 1e-6;
+
+impl Scaling {
+    /// Scales the values with the parameters computed on the training set.
+    ///
+    /// The constant features are only shifted, see [constant_features](#method.constant_features).
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values to scale.
+    /// * `c1` - The minimum value for a normalization or the mean for a standardization.
+    /// * `c2` - The maximum value for a normalization or the standard deviation for a standardization.
+    pub fn apply(self, values: &Tensor, c1: &Tensor, c2: &Tensor) -> Tensor {
+        let scale = self.safe_scale(c1, c2);
+        match self {
+            Scaling::Normalized => div(&sub(values, c2, true), &scale, true),
+            Scaling::Standardized => div(&sub(values, c1, true), &scale, true),
+        }
+    }
+
+    /// Reverts the scaling of the values.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The scaled values.
+    /// * `c1` - The minimum value for a normalization or the mean for a standardization.
+    /// * `c2` - The maximum value for a normalization or the standard deviation for a standardization.
+    pub fn invert(self, values: &Tensor, c1: &Tensor, c2: &Tensor) -> Tensor {
+        let scale = self.safe_scale(c1, c2);
+        match self {
+            Scaling::Normalized => add(&mul(values, &scale, true), c2, true),
+            Scaling::Standardized => add(&mul(values, &scale, true), c1, true),
+        }
+    }
+
+    /// Returns the indices of the constant features, whose range or standard deviation is null.
+    ///
+    /// Dividing by a null scale would fill these features with NaN, hence their scale is replaced by one. The indices
+    /// refer to the features flattened in column-major order.
+    ///
+    /// # Arguments
+    ///
+    /// * `c1` - The minimum value for a normalization or the mean for a standardization.
+    /// * `c2` - The maximum value for a normalization or the standard deviation for a standardization.
+    pub fn constant_features(self, c1: &Tensor, c2: &Tensor) -> Vec<u64> {
+        let is_constant = self.is_constant(c1, c2);
+        let mut is_constant_host = vec![false; is_constant.elements()];
+        is_constant.host(&mut is_constant_host);
+        is_constant_host.iter().enumerate().filter(|(_, &constant)| constant).map(|(i, _)| i as u64).collect()
+    }
+
+    /// Computes the divisor of the scaling, which is the range or the standard deviation of the features.
+    fn scale(self, c1: &Tensor, c2: &Tensor) -> Tensor {
+        match self {
+            Scaling::Normalized => sub(c2, c1, true),
+            Scaling::Standardized => c2.copy(),
+        }
+    }
+
+    /// Flags the features whose scale is negligible compared to their magnitude.
+    fn is_constant(self, c1: &Tensor, c2: &Tensor) -> Array<bool> {
+        let magnitude = add(&maxof(&abs(c1), &abs(c2), true), &(1 as PrimitiveType), true);
+        le(&abs(&self.scale(c1, c2)), &mul(&magnitude, &SCALE_TOLERANCE, true), true)
+    }
+
+    /// Computes the divisor of the scaling where the scale of the constant features is replaced by one.
+    fn safe_scale(self, c1: &Tensor, c2: &Tensor) -> Tensor {
+        selectl(1., &self.is_constant(c1, c2), &self.scale(c1, c2))
+    }
 }
 
 
@@ -94,6 +211,31 @@ pub trait DataSet {
     /// Returns a reference to the validation samples.
     fn x_valid(&self) -> Option<&Tensor>;
 
+    /// Returns the number of training mini-batches and the closure generating them, if the mini-batches are generated on the fly.
+    fn batch_generator(&self) -> Option<(u64, &dyn Fn(u64) -> (Tensor, Tensor))> { None }
+
+    /// Returns a reference to the weights of the training samples, if any.
+    ///
+    /// The weights have dimensions [1, 1, 1, num_train_samples] and scale the contribution of each sample to the loss.
+    fn sample_weights(&self) -> Option<&Tensor> { None }
+
+    /// Returns the standard deviation of the Gaussian noise added to the training samples of each mini-batch, if any.
+    ///
+    /// The noise is drawn anew for every mini-batch and is not applied to the validation and test samples.
+    fn input_noise(&self) -> Option<PrimitiveType> { None }
+
+    /// Returns the training labels of the output heads of the network, in the order in which the heads were added.
+    ///
+    /// The auxiliary heads are trained on the labels of the network and don't have labels. The labels are empty if the
+    /// network has no heads.
+    fn y_train_heads(&self) -> &[Tensor] { &[] }
+
+    /// Returns a reference to the sparse training samples, if the samples are stored in a sparse format.
+    fn x_train_sparse(&self) -> Option<&SparseTensor> { None }
+
+    /// Returns a reference to the sparse validation samples, if the samples are stored in a sparse format.
+    fn x_valid_sparse(&self) -> Option<&SparseTensor> { None }
+
     /// Returns a reference to the validation labels.
     fn y_valid(&self) -> Option<&Tensor>;
 
@@ -118,4 +260,66 @@ pub trait DataSet {
     /// * Standardization: (Scaling::Standardized, mean, standard deviation)
     ///
     fn y_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)>;
-}
\ No newline at end of file
+
+    /// Returns the transforms fitted on the training features, in the order in which they are applied.
+    ///
+    /// The transforms are applied to the raw features before the scaling.
+    fn transforms(&self) -> &[FittedTransform] { &[] }
+
+    /// Computes summary statistics of the training set.
+    ///
+    /// The summary contains the minimum, maximum, mean, standard deviation, and number of missing values of each
+    /// feature, as well as the distribution of the classes for one-hot encoded labels. It can be printed to sanity
+    /// check the data before training.
+    fn describe(&self) -> DataSetSummary {
+        DataSetSummary::new(self.x_train(), self.y_train(), self.classes())
+    }
+
+    /// Saves the data set in a compact binary format.
+    ///
+    /// The data set can be reloaded with [BinaryDataSet::load_binary](struct.BinaryDataSet.html#method.load_binary),
+    /// which skips the parsing of the original csv files or images. The training, validation, and test sets, the sample
+    /// weights, the classes, and the parameters of the scalings are saved. The transforms and the labels of the heads
+    /// are not saved.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the training samples are generated on the fly or sparse, or if the file cannot be written.
+    fn save_binary(&self, path: &Path) -> Result<(), DataSetError> {
+        binary_data::save(self, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::assert_approx_eq;
+    use crate::data::{DataSet, Scaling, TabularDataSet};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_constant_features() {
+        // The second feature is constant
+        let x = Tensor::new(&[1., 5., 2., 3., 5., 4., 5., 5., 9.], Dim4::new(&[3, 1, 1, 3]));
+        let y = Tensor::new(&[1., 2., 3.], Dim4::new(&[1, 1, 1, 3]));
+
+        for scaling in &[Scaling::Normalized, Scaling::Standardized] {
+            let mut data = TabularDataSet::from_tensor(x.copy(), y.copy(), None, None, None, None).unwrap();
+            let constant_features = match scaling {
+                Scaling::Normalized => data.normalize_input(),
+                Scaling::Standardized => data.standardize_input(),
+            };
+            assert_eq!(constant_features, vec![1]);
+
+            let mut scaled = [0 as PrimitiveType; 9];
+            data.x_train().host(&mut scaled);
+            assert!(scaled.iter().all(|value| value.is_finite()));
+            assert_approx_eq!([scaled[1], scaled[4], scaled[7]], [0., 0., 0.]);
+
+            let (scaling, c1, c2) = data.x_train_stats().as_ref().unwrap();
+            let mut restored = [0 as PrimitiveType; 9];
+            scaling.invert(data.x_train(), c1, c2).host(&mut restored);
+            assert_approx_eq!(restored, [1., 5., 2., 3., 5., 4., 5., 5., 9.]);
+        }
+    }
+}
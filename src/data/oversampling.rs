@@ -0,0 +1,148 @@
+//! Oversampling of the minority classes with SMOTE.
+//!
+//! The Synthetic Minority Over-sampling Technique (Chawla et al., 2002) creates samples of a minority class by
+//! interpolating between a sample and one of its nearest neighbors of the same class. Unlike duplicating samples, the
+//! interpolation smooths the decision region of the minority class. The distances and the interpolations are computed
+//! on the device.
+use arrayfire::*;
+use rand::Rng;
+
+use crate::backend::{Backend, DefaultBackend};
+use crate::tensor::*;
+
+/// Creates synthetic samples of the minority classes until all classes have as many samples as the majority class.
+///
+/// # Arguments
+///
+/// * `x` - The samples, stacked along the fourth dimension.
+/// * `y` - The one-hot encoded labels, or the binary labels 0 and 1 of a single output, stacked along the fourth
+/// dimension.
+/// * `k_neighbors` - The number of nearest neighbors among which the interpolated neighbor is drawn.
+/// * `rng` - The random number generator.
+///
+/// # Return value
+///
+/// The synthetic samples and their labels, or `None` if the classes are already balanced.
+pub(crate) fn smote<R: Rng + ?Sized>(x: &Tensor, y: &Tensor, k_neighbors: u64, rng: &mut R) -> Option<(Tensor, Tensor)> {
+    let mut classes = vec![0u32; y.batch_size() as usize];
+    let num_classes = if y.dims()[0] == 1 {
+        // A single output holds the binary labels of two classes
+        gt(y, &(0.5 as PrimitiveType), false).cast::<u32>().host(&mut classes);
+        2
+    } else {
+        imax(y, 0).1.host(&mut classes);
+        y.dims()[0] as usize
+    };
+
+    let mut members: Vec<Vec<u32>> = vec![Vec::new(); num_classes];
+    for (i, &class) in classes.iter().enumerate() {
+        members[class as usize].push(i as u32);
+    }
+    let majority_count = members.iter().map(|samples| samples.len()).max().unwrap_or(0);
+
+    let mut synthetic: Option<(Tensor, Tensor)> = None;
+    for samples in members.iter().filter(|samples| !samples.is_empty() && samples.len() < majority_count) {
+        let indices = Array::new(&samples[..], Dim4::new(&[samples.len() as u64, 1, 1, 1]));
+        let x_class = lookup(x, &indices, 3);
+        let y_class = lookup(y, &indices, 3);
+        let (x_new, y_new) = interpolate(&x_class, &y_class, majority_count - samples.len(), k_neighbors, rng);
+        synthetic = match synthetic {
+            Some((x_all, y_all)) => Some((join(3, &x_all, &x_new), join(3, &y_all, &y_new))),
+            None => Some((x_new, y_new)),
+        };
+    }
+    synthetic
+}
+
+/// Creates samples by interpolating between the samples of a class and their nearest neighbors.
+fn interpolate<R: Rng + ?Sized>(x: &Tensor, y: &Tensor, num_new_samples: usize, k_neighbors: u64, rng: &mut R) -> (Tensor, Tensor) {
+    let num_samples = x.batch_size();
+    let dims = x.dims();
+    let num_features = dims[0] * dims[1] * dims[2];
+
+    // A single sample has no neighbor and is duplicated
+    let k = k_neighbors.min(num_samples - 1);
+    let mut neighbors = vec![0u32; (num_samples * k) as usize];
+    if k > 0 {
+        // Squared Euclidean distances between the samples, excluding each sample from its own neighbors
+        let matrix = moddims(x, Dim4::new(&[num_features, num_samples, 1, 1]));
+        let squared_norms = sum(&mul(&matrix, &matrix, false), 0);
+        let gram = DefaultBackend::matmul(&matrix, &matrix, true, false);
+        let mut distances = sub(&add(&transpose(&squared_norms, false), &squared_norms, true), &(gram * 2 as PrimitiveType), false);
+        distances = add(&distances, &diag_create(&constant(PrimitiveType::INFINITY, Dim4::new(&[num_samples, 1, 1, 1])), 0), false);
+
+        let nearest = sort_index(&distances, 1, true).1;
+        index(&nearest, &[Seq::default(), Seq::new(0., (k - 1) as f64, 1.)]).host(&mut neighbors);
+    }
+
+    let mut bases = Vec::with_capacity(num_new_samples);
+    let mut partners = Vec::with_capacity(num_new_samples);
+    let mut gaps = Vec::with_capacity(num_new_samples);
+    for s in 0..num_new_samples {
+        let base = (s as u64 % num_samples) as u32;
+        bases.push(base);
+        if k > 0 {
+            // The neighbors are stored in column-major order: the r-th neighbor of sample i is at r * num_samples + i
+            let rank = rng.gen_range(0, k);
+            partners.push(neighbors[(rank * num_samples + base as u64) as usize]);
+            gaps.push(rng.gen::<PrimitiveType>());
+        } else {
+            partners.push(base);
+            gaps.push(0.);
+        }
+    }
+
+    let new_dims = Dim4::new(&[num_new_samples as u64, 1, 1, 1]);
+    let base_samples = lookup(x, &Array::new(&bases[..], new_dims), 3);
+    let partner_samples = lookup(x, &Array::new(&partners[..], new_dims), 3);
+    let gaps = Tensor::new(&gaps[..], Dim4::new(&[1, 1, 1, num_new_samples as u64]));
+    let x_new = add(&base_samples, &mul(&gaps, &sub(&partner_samples, &base_samples, false), true), false);
+    let y_new = lookup(y, &Array::new(&bases[..], new_dims), 3);
+    (x_new, y_new)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::data::oversampling::smote;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_smote() {
+        // Four samples of the first class and two samples of the second class on a line
+        let x = Tensor::new(&[0., 1., 2., 3., 10., 11.], Dim4::new(&[1, 1, 1, 6]));
+        let y = Tensor::new(&[1., 0., 1., 0., 1., 0., 1., 0., 0., 1., 0., 1.], Dim4::new(&[2, 1, 1, 6]));
+        let (x_new, y_new) = smote(&x, &y, 5, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_eq!(x_new.batch_size(), 2);
+
+        // The synthetic samples belong to the second class and lie between its samples
+        let mut x_host = [0 as PrimitiveType; 2];
+        let mut y_host = [0 as PrimitiveType; 4];
+        x_new.host(&mut x_host);
+        y_new.host(&mut y_host);
+        assert!(x_host.iter().all(|&value| (10. ..=11.).contains(&value)));
+        assert_eq!(y_host, [0., 1., 0., 1.]);
+
+        // Balanced classes are not oversampled
+        assert!(smote(&x, &Tensor::new(&[1., 0., 0., 1., 1., 0., 0., 1., 1., 0., 0., 1.], Dim4::new(&[2, 1, 1, 6])), 5, &mut StdRng::seed_from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn test_smote_binary_labels() {
+        // The single output holds the label 1 for the two samples of the minority class
+        let x = Tensor::new(&[0 as PrimitiveType, 1., 2., 3., 10., 11.], Dim4::new(&[1, 1, 1, 6]));
+        let y = Tensor::new(&[0 as PrimitiveType, 0., 0., 0., 1., 1.], Dim4::new(&[1, 1, 1, 6]));
+        let (x_new, y_new) = smote(&x, &y, 5, &mut StdRng::seed_from_u64(0)).unwrap();
+        assert_eq!(y_new.dims().get(), &[1, 1, 1, 2]);
+
+        let mut x_host = [0 as PrimitiveType; 2];
+        let mut y_host = [0 as PrimitiveType; 2];
+        x_new.host(&mut x_host);
+        y_new.host(&mut y_host);
+        assert!(x_host.iter().all(|&value| (10. ..=11.).contains(&value)));
+        assert_eq!(y_host, [1., 1.]);
+    }
+}
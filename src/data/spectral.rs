@@ -0,0 +1,209 @@
+//! Spectral preprocessing of signals.
+//!
+//! The signals are stored in tensors of shape [length, 1, 1, batch size]. The spectrograms have the frequencies along
+//! the first dimension and the frames along the second dimension, such that they can be fed to convolutional networks
+//! like single-channel images. All the operations run on the device with ArrayFire's FFT.
+use arrayfire::*;
+use std::f64::consts::PI;
+
+use crate::tensor::*;
+
+/// Windows applied to the frames before the Fourier transform.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Window {
+    Rectangular,
+    Hann,
+    Hamming,
+}
+
+impl Window {
+    /// Returns the coefficients of the periodic window of the given length, in a tensor of shape [length, 1, 1, 1].
+    pub fn coefficients(self, length: u64) -> Tensor {
+        let coefficients: Vec<PrimitiveType> = (0..length).map(|n| {
+            let cosine = (2. * PI * n as f64 / length as f64).cos();
+            let value = match self {
+                Window::Rectangular => 1.,
+                Window::Hann => 0.5 - 0.5 * cosine,
+                Window::Hamming => 0.54 - 0.46 * cosine,
+            };
+            value as PrimitiveType
+        }).collect();
+        Tensor::new(&coefficients[..], Dim::new(&[length, 1, 1, 1]))
+    }
+}
+
+/// Slices the signals into overlapping frames.
+///
+/// # Arguments
+///
+/// * `signal` - The signals, of shape [length, 1, 1, batch size].
+/// * `frame_length` - The number of values in each frame.
+/// * `hop_length` - The number of values between the beginnings of two successive frames.
+///
+/// # Return value
+///
+/// Tensor of shape [frame_length, number of frames, 1, batch size]. The values at the end of the signals that don't
+/// fill a complete frame are dropped.
+///
+/// # Panics
+///
+/// Panics if the signals are shorter than a frame or if the hop length is zero.
+pub fn frame(signal: &Tensor, frame_length: u64, hop_length: u64) -> Tensor {
+    let length = signal.dims()[0];
+    assert!(frame_length > 0 && frame_length <= length, "The frame length must be between 1 and the length of the signals.");
+    assert!(hop_length > 0, "The hop length must be greater than zero.");
+
+    let num_frames = 1 + (length - frame_length) / hop_length;
+    let indices: Vec<u64> = (0..num_frames).flat_map(|i| (0..frame_length).map(move |j| i * hop_length + j)).collect();
+    let indices = Array::new(&indices[..], Dim4::new(&[frame_length * num_frames, 1, 1, 1]));
+
+    let batch_size = signal.batch_size();
+    let signal = moddims(signal, Dim4::new(&[length, batch_size, 1, 1]));
+    moddims(&lookup(&signal, &indices, 0), Dim4::new(&[frame_length, num_frames, 1, batch_size]))
+}
+
+/// Computes the short-time Fourier transform of the signals.
+///
+/// # Return value
+///
+/// Complex tensor of shape [frame_length / 2 + 1, number of frames, 1, batch size] containing the non-negative
+/// frequencies of each frame.
+pub fn stft(signal: &Tensor, frame_length: u64, hop_length: u64, window: Window) -> ComplexTensor {
+    let frames = mul(&frame(signal, frame_length, hop_length), &window.coefficients(frame_length), true);
+    let transform = fft(&frames, 1., frame_length as i64);
+    index(&transform, &[Seq::new(0., (frame_length / 2) as f64, 1.), Seq::default(), Seq::default(), Seq::default()])
+}
+
+/// Computes the spectrogram of the signals.
+///
+/// # Arguments
+///
+/// * `signal` - The signals, of shape [length, 1, 1, batch size].
+/// * `frame_length` - The number of values in each frame, which sets the resolution in frequency.
+/// * `hop_length` - The number of values between the beginnings of two successive frames.
+/// * `window` - The window applied to each frame.
+/// * `power` - The exponent applied to the magnitude: 1 for a magnitude spectrogram, 2 for a power spectrogram.
+///
+/// # Return value
+///
+/// Tensor of shape [frame_length / 2 + 1, number of frames, 1, batch size].
+pub fn spectrogram(signal: &Tensor, frame_length: u64, hop_length: u64, window: Window, power: PrimitiveType) -> Tensor {
+    let magnitude: Tensor = abs(&stft(signal, frame_length, hop_length, window));
+    if (power - 1.).abs() < PrimitiveType::EPSILON {
+        magnitude
+    } else {
+        pow(&magnitude, &power, false)
+    }
+}
+
+/// Converts a frequency in hertz to the mel scale.
+fn hz_to_mel(frequency: f64) -> f64 {
+    2595. * (1. + frequency / 700.).log10()
+}
+
+/// Converts a frequency on the mel scale to hertz.
+fn mel_to_hz(mel: f64) -> f64 {
+    700. * (10f64.powf(mel / 2595.) - 1.)
+}
+
+/// Creates a bank of triangular filters equally spaced on the mel scale.
+///
+/// # Arguments
+///
+/// * `num_mels` - The number of filters.
+/// * `frame_length` - The frame length of the spectrograms the filters are applied to.
+/// * `sample_rate` - The sampling rate of the signals in hertz.
+/// * `min_frequency` - The lowest frequency of the filters in hertz.
+/// * `max_frequency` - The highest frequency of the filters in hertz.
+///
+/// # Return value
+///
+/// Tensor of shape [num_mels, frame_length / 2 + 1, 1, 1] whose rows are the filters.
+pub fn mel_filterbank(num_mels: u64, frame_length: u64, sample_rate: f64, min_frequency: f64, max_frequency: f64) -> Tensor {
+    let num_bins = frame_length / 2 + 1;
+    let (min_mel, max_mel) = (hz_to_mel(min_frequency), hz_to_mel(max_frequency));
+    let edges: Vec<f64> = (0..num_mels + 2)
+        .map(|i| mel_to_hz(min_mel + (max_mel - min_mel) * i as f64 / (num_mels + 1) as f64))
+        .collect();
+
+    // The filters are stored in column-major order
+    let mut filters = vec![0 as PrimitiveType; (num_mels * num_bins) as usize];
+    for bin in 0..num_bins {
+        let frequency = bin as f64 * sample_rate / frame_length as f64;
+        for mel in 0..num_mels as usize {
+            let (lower, center, upper) = (edges[mel], edges[mel + 1], edges[mel + 2]);
+            let weight = if frequency > lower && frequency <= center {
+                (frequency - lower) / (center - lower)
+            } else if frequency > center && frequency < upper {
+                (upper - frequency) / (upper - center)
+            } else {
+                0.
+            };
+            filters[bin as usize * num_mels as usize + mel] = weight as PrimitiveType;
+        }
+    }
+    Tensor::new(&filters[..], Dim::new(&[num_mels, num_bins, 1, 1]))
+}
+
+/// Computes the mel spectrogram of the signals.
+///
+/// The power spectrogram of the signals, computed with a Hann window, is projected on a mel filterbank spanning the
+/// frequencies from 0 to the Nyquist frequency. The logarithm of the mel spectrogram is typically taken before feeding
+/// it to a network.
+///
+/// # Return value
+///
+/// Tensor of shape [num_mels, number of frames, 1, batch size].
+pub fn mel_spectrogram(signal: &Tensor, sample_rate: f64, frame_length: u64, hop_length: u64, num_mels: u64) -> Tensor {
+    let power_spectrogram = spectrogram(signal, frame_length, hop_length, Window::Hann, 2.);
+    let dims = power_spectrogram.dims();
+    let filters = mel_filterbank(num_mels, frame_length, sample_rate, 0., sample_rate / 2.);
+    let flat_spectrogram = moddims(&power_spectrogram, Dim4::new(&[dims[0], dims[1] * dims[3], 1, 1]));
+    moddims(&matmul(&filters, &flat_spectrogram, MatProp::NONE, MatProp::NONE), Dim4::new(&[num_mels, dims[1], 1, dims[3]]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::assert_approx_eq;
+    use crate::data::spectral::*;
+
+    #[test]
+    fn test_frame() {
+        let signal = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 10., 11., 12., 13., 14., 15., 16.], Dim4::new(&[7, 1, 1, 2]));
+        let frames = frame(&signal, 3, 2);
+        assert_eq!(frames.dims().get(), &[3, 3, 1, 2]);
+        let mut output = [0 as PrimitiveType; 18];
+        frames.host(&mut output);
+        assert_approx_eq!(output, [0., 1., 2., 2., 3., 4., 4., 5., 6., 10., 11., 12., 12., 13., 14., 14., 15., 16.]);
+    }
+
+    #[test]
+    fn test_spectrogram() {
+        // Cosine with 2 periods over the frame
+        let values: Vec<PrimitiveType> = (0..16).map(|n| (2. * std::f64::consts::PI * 2. * n as f64 / 16.).cos() as PrimitiveType).collect();
+        let signal = Tensor::new(&values[..], Dim4::new(&[16, 1, 1, 1]));
+        let magnitude = spectrogram(&signal, 16, 16, crate::data::spectral::Window::Rectangular, 1.);
+        assert_eq!(magnitude.dims().get(), &[9, 1, 1, 1]);
+        let mut output = [0 as PrimitiveType; 9];
+        magnitude.host(&mut output);
+        let expected = [0., 0., 8., 0., 0., 0., 0., 0., 0.];
+        for (value, expected) in output.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_mel_filterbank() {
+        let filters = mel_filterbank(4, 32, 8000., 0., 4000.);
+        assert_eq!(filters.dims().get(), &[4, 17, 1, 1]);
+        let mut output = [0 as PrimitiveType; 68];
+        filters.host(&mut output);
+        assert!(output.iter().all(|&weight| (0. ..=1.).contains(&weight)));
+        // Each filter has a non-zero response
+        for mel in 0..4 {
+            assert!((0..17).map(|bin| output[bin * 4 + mel]).sum::<PrimitiveType>() > 0.);
+        }
+    }
+}
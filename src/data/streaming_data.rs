@@ -0,0 +1,229 @@
+//! Data set streaming the training mini-batches from a user-defined source.
+use arrayfire::*;
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use super::{DataSet, Scaling};
+use crate::tensor::*;
+
+/// Defines a source of training mini-batches.
+///
+/// Implementing this trait allows the training data to be read from a stream, augmented or synthesized batch by batch
+/// without holding the full training set in memory. The source is consumed by the networks through a
+/// [StreamingDataSet](struct.StreamingDataSet.html).
+pub trait BatchedDataSet {
+    /// Returns the number of mini-batches in an epoch.
+    fn num_batches(&self) -> u64;
+
+    /// Returns the samples and the labels of the next mini-batch, stacked along the fourth dimension.
+    fn next_batch(&mut self) -> (Tensor, Tensor);
+
+    /// Prepares the source for a new epoch, for instance by rewinding a file or reshuffling the samples.
+    ///
+    /// The method is called before the first mini-batch of every epoch except the first one.
+    fn reset(&mut self) {}
+}
+
+/// State shared between the data set and the closure generating the mini-batches.
+struct Stream<B: BatchedDataSet> {
+    source: B,
+    first_batch: Option<(Tensor, Tensor)>,
+    count: u64,
+}
+
+impl<B: BatchedDataSet> Stream<B> {
+    /// Returns the next mini-batch, resetting the source at the beginning of each epoch.
+    fn next_batch(&mut self) -> (Tensor, Tensor) {
+        let num_batches = self.source.num_batches();
+        if self.count > 0 && self.count % num_batches == 0 {
+            self.source.reset();
+        }
+        self.count += 1;
+        match self.first_batch.take() {
+            Some(batch) => batch,
+            None => self.source.next_batch(),
+        }
+    }
+}
+
+/// Data set whose training mini-batches are pulled from a [BatchedDataSet](trait.BatchedDataSet.html).
+///
+/// The mini-batches are consumed in the order in which the source produces them, hence the source is responsible for
+/// shuffling the samples if needed. The `batch_size` argument of [fit](../models/struct.Network.html#method.fit) is
+/// ignored for the training set and only used to evaluate the validation set.
+pub struct StreamingDataSet<B: BatchedDataSet> {
+    stream: Rc<RefCell<Stream<B>>>,
+    generator: Box<dyn Fn(u64) -> (Tensor, Tensor)>,
+    num_batches: u64,
+    input_shape: Dim,
+    output_shape: Dim,
+    num_train_samples: u64,
+    num_valid_samples: u64,
+    empty: Tensor,
+    x_valid: Option<Tensor>,
+    y_valid: Option<Tensor>,
+    x_train_stats: Option<(Scaling, Tensor, Tensor)>,
+    y_train_stats: Option<(Scaling, Tensor, Tensor)>,
+}
+
+impl<B: BatchedDataSet + 'static> StreamingDataSet<B> {
+    /// Creates a data set from a source of mini-batches.
+    ///
+    /// The first mini-batch is pulled once to determine the shapes of the samples and of the labels. It is then used
+    /// as the first training mini-batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of batches is zero or if the samples and labels of the first mini-batch have different batch sizes.
+    pub fn new(mut source: B) -> StreamingDataSet<B> {
+        let num_batches = source.num_batches();
+        assert!(num_batches > 0, "The number of batches must be greater than zero.");
+
+        let (x, y) = source.next_batch();
+        assert_eq!(x.batch_size(), y.batch_size(), "The samples and the labels must have the same batch size.");
+        let input_shape = Dim4::new(&[x.dims()[0], x.dims()[1], x.dims()[2], 1]);
+        let output_shape = Dim4::new(&[y.dims()[0], y.dims()[1], y.dims()[2], 1]);
+        let num_train_samples = num_batches * x.batch_size();
+
+        let stream = Rc::new(RefCell::new(Stream { source, first_batch: Some((x, y)), count: 0 }));
+        let generator_stream = Rc::clone(&stream);
+        StreamingDataSet {
+            stream,
+            generator: Box::new(move |_| generator_stream.borrow_mut().next_batch()),
+            num_batches,
+            input_shape,
+            output_shape,
+            num_train_samples,
+            num_valid_samples: 0,
+            empty: Tensor::new_empty_tensor(),
+            x_valid: None,
+            y_valid: None,
+            x_train_stats: None,
+            y_train_stats: None,
+        }
+    }
+
+    /// Sets the validation samples and labels.
+    ///
+    /// The samples must be stacked along the fourth dimension.
+    pub fn set_validation_data(&mut self, x_valid: Tensor, y_valid: Tensor) {
+        assert_eq!(x_valid.batch_size(), y_valid.batch_size(), "The samples and the labels must have the same batch size.");
+        self.num_valid_samples = x_valid.batch_size();
+        self.x_valid = Some(x_valid);
+        self.y_valid = Some(y_valid);
+    }
+
+    /// Pulls the next training mini-batch from the source.
+    pub fn next_batch(&mut self) -> (Tensor, Tensor) {
+        self.stream.borrow_mut().next_batch()
+    }
+}
+
+impl<B: BatchedDataSet> DataSet for StreamingDataSet<B> {
+    fn input_shape(&self) -> Dim { self.input_shape }
+
+    fn output_shape(&self) -> Dim { self.output_shape }
+
+    /// Returns the number of samples in an epoch, assuming that all mini-batches have the size of the first one.
+    fn num_train_samples(&self) -> u64 { self.num_train_samples }
+
+    fn num_valid_samples(&self) -> u64 { self.num_valid_samples }
+
+    /// Returns an empty tensor since the training samples are streamed.
+    fn x_train(&self) -> &Tensor {
+        &self.empty
+    }
+
+    /// Returns an empty tensor since the training labels are streamed.
+    fn y_train(&self) -> &Tensor {
+        &self.empty
+    }
+
+    fn x_valid(&self) -> Option<&Tensor> {
+        self.x_valid.as_ref()
+    }
+
+    fn y_valid(&self) -> Option<&Tensor> {
+        self.y_valid.as_ref()
+    }
+
+    fn x_test(&self) -> Option<&Tensor> { None }
+
+    fn y_test(&self) -> Option<&Tensor> { None }
+
+    fn batch_generator(&self) -> Option<(u64, &dyn Fn(u64) -> (Tensor, Tensor))> {
+        Some((self.num_batches, self.generator.as_ref()))
+    }
+
+    fn x_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
+        &self.x_train_stats
+    }
+
+    fn y_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
+        &self.y_train_stats
+    }
+}
+
+impl<B: BatchedDataSet> fmt::Display for StreamingDataSet<B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "=======")?;
+        writeln!(f, "Dataset")?;
+        writeln!(f, "=======")?;
+        writeln!(f, "Samples shape: [{} {} {}]", self.input_shape.get()[0], self.input_shape.get()[1], self.input_shape.get()[2],)?;
+        writeln!(f, "Labels shape: [{} {} {}]", self.output_shape.get()[0], self.output_shape.get()[1], self.output_shape.get()[2])?;
+        writeln!(f, "Number of training batches: {}", self.num_batches)?;
+        writeln!(f, "Number of validation samples: {}", self.num_valid_samples)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::data::{BatchedDataSet, DataSet, StreamingDataSet};
+    use crate::tensor::*;
+
+    /// Source counting the batches and the epochs.
+    struct Counter {
+        batch: u64,
+        epoch: u64,
+    }
+
+    impl BatchedDataSet for Counter {
+        fn num_batches(&self) -> u64 { 2 }
+
+        fn next_batch(&mut self) -> (Tensor, Tensor) {
+            self.batch += 1;
+            let x = constant(self.batch as PrimitiveType, Dim4::new(&[3, 1, 1, 4]));
+            let y = constant(self.epoch as PrimitiveType, Dim4::new(&[1, 1, 1, 4]));
+            (x, y)
+        }
+
+        fn reset(&mut self) {
+            self.epoch += 1;
+        }
+    }
+
+    #[test]
+    fn test_streaming_data_set() {
+        let data = StreamingDataSet::new(Counter { batch: 0, epoch: 0 });
+        assert_eq!(data.input_shape().get(), &[3, 1, 1, 1]);
+        assert_eq!(data.output_shape().get(), &[1, 1, 1, 1]);
+        assert_eq!(data.num_train_samples(), 8);
+
+        let (num_batches, generator) = data.batch_generator().unwrap();
+        assert_eq!(num_batches, 2);
+        let mut x_output: [PrimitiveType; 3] = [0.; 3];
+        let mut y_output: [PrimitiveType; 1] = [0.; 1];
+        let batches: Vec<(PrimitiveType, PrimitiveType)> = (0..4).map(|i| {
+            let (x, y) = generator(i);
+            index(&x, &[Seq::default(), Seq::default(), Seq::default(), Seq::new(0., 0., 1.)]).host(&mut x_output);
+            index(&y, &[Seq::default(), Seq::default(), Seq::default(), Seq::new(0., 0., 1.)]).host(&mut y_output);
+            (x_output[0], y_output[0])
+        }).collect();
+
+        // The first batch pulled to infer the shapes is served first and the source is reset after each epoch
+        assert_eq!(batches, vec![(1., 0.), (2., 0.), (3., 1.), (4., 1.)]);
+    }
+}
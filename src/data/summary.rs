@@ -0,0 +1,133 @@
+//! Summary statistics of a data set.
+use arrayfire::*;
+use std::fmt;
+
+use crate::tensor::*;
+
+/// Statistics of the training set used to sanity check the data before training.
+///
+/// A summary is created with [DataSet::describe](trait.DataSet.html#method.describe). The statistics of the
+/// features ignore the missing values (NaN).
+#[derive(Debug, Clone)]
+pub struct DataSetSummary {
+    /// The number of training samples.
+    pub num_samples: u64,
+    /// The minimum value of each feature.
+    pub min: Vec<PrimitiveType>,
+    /// The maximum value of each feature.
+    pub max: Vec<PrimitiveType>,
+    /// The mean of each feature.
+    pub mean: Vec<PrimitiveType>,
+    /// The standard deviation of each feature.
+    pub std: Vec<PrimitiveType>,
+    /// The number of missing values of each feature.
+    pub missing: Vec<u64>,
+    /// The name, if any, and the number of samples of each class for one-hot encoded labels.
+    pub class_distribution: Option<Vec<(Option<String>, u64)>>,
+}
+
+impl DataSetSummary {
+    /// Computes the summary of the samples and labels.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The samples, stacked along the fourth dimension.
+    /// * `y` - The labels, stacked along the fourth dimension.
+    /// * `classes` - The names of the classes, if any.
+    pub(crate) fn new(x: &Tensor, y: &Tensor, classes: Option<Vec<String>>) -> DataSetSummary {
+        let num_samples = x.batch_size();
+        let num_features = x.dims()[0] * x.dims()[1] * x.dims()[2];
+
+        let (min, max, mean, std, missing) = if x.elements() > 0 {
+            let x = moddims(x, Dim4::new(&[num_features, num_samples, 1, 1]));
+            let missing_values = isnan(&x);
+            let valid_values = sub(&(1.0 as PrimitiveType), &missing_values.cast::<PrimitiveType>(), false);
+            let num_valid = sum(&valid_values, 1);
+            let values = select(&constant(0 as PrimitiveType, x.dims()), &missing_values, &x);
+
+            let mean_values = div(&sum(&values, 1), &num_valid, false);
+            let deviations = mul(&sub(&values, &mean_values, true), &valid_values, false);
+            let std_values = sqrt(&div(&sum(&mul(&deviations, &deviations, false), 1), &num_valid, false));
+            let missing_counts = sub(&(num_samples as PrimitiveType), &num_valid, false);
+
+            (Self::to_vec(&min(&x, 1)), Self::to_vec(&max(&x, 1)), Self::to_vec(&mean_values), Self::to_vec(&std_values),
+             Self::to_vec(&missing_counts).iter().map(|&count| count as u64).collect())
+        } else {
+            (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new())
+        };
+
+        // Count the samples of each class for one-hot encoded labels
+        let class_distribution = if y.dims()[0] > 1 && y.dims()[1] == 1 && y.dims()[2] == 1 && y.elements() > 0 {
+            let counts = Self::to_vec(&sum(y, 3));
+            Some(counts.iter().enumerate().map(|(i, &count)| {
+                let name = classes.as_ref().and_then(|classes| classes.get(i).cloned());
+                (name, count.round() as u64)
+            }).collect())
+        } else {
+            None
+        };
+
+        DataSetSummary {
+            num_samples,
+            min,
+            max,
+            mean,
+            std,
+            missing,
+            class_distribution,
+        }
+    }
+
+    /// Copies the values of a tensor to the host.
+    fn to_vec(tensor: &Tensor) -> Vec<PrimitiveType> {
+        let mut buffer = vec![0 as PrimitiveType; tensor.elements()];
+        tensor.host(&mut buffer);
+        buffer
+    }
+}
+
+impl fmt::Display for DataSetSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Number of training samples: {}", self.num_samples)?;
+        writeln!(f, "Feature \t Min \t\t Max \t\t Mean \t\t Std \t\t Missing")?;
+        writeln!(f, "-----------------------------------------------------------------------------------")?;
+        for i in 0..self.mean.len() {
+            writeln!(f, "{} \t\t {:.4} \t {:.4} \t {:.4} \t {:.4} \t {}", i, self.min[i], self.max[i], self.mean[i], self.std[i], self.missing[i])?;
+        }
+
+        if let Some(class_distribution) = &self.class_distribution {
+            writeln!(f)?;
+            writeln!(f, "Class \t\t Samples \t Fraction")?;
+            writeln!(f, "-----------------------------------------")?;
+            for (i, (name, count)) in class_distribution.iter().enumerate() {
+                let name = name.clone().unwrap_or_else(|| i.to_string());
+                writeln!(f, "{} \t\t {} \t\t {:.4}", name, count, *count as f64 / self.num_samples as f64)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::assert_approx_eq;
+    use crate::data::DataSetSummary;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_summary() {
+        let x = Tensor::new(&[1., 2., 3., PrimitiveType::NAN, 5., -4.], Dim4::new(&[2, 1, 1, 3]));
+        let y = Tensor::new(&[1., 0., 0., 1., 1., 0.], Dim4::new(&[2, 1, 1, 3]));
+        let summary = DataSetSummary::new(&x, &y, Some(vec![String::from("cat"), String::from("dog")]));
+
+        assert_eq!(summary.num_samples, 3);
+        assert_approx_eq!(summary.min, [1., -4.]);
+        assert_approx_eq!(summary.max, [5., 2.]);
+        assert_approx_eq!(summary.mean, [3., -1.]);
+        assert_approx_eq!(summary.std, [1.6329932, 3.]);
+        assert_eq!(summary.missing, vec![0, 1]);
+        assert_eq!(summary.class_distribution, Some(vec![(Some(String::from("cat")), 2), (Some(String::from("dog")), 1)]));
+    }
+}
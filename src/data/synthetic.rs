@@ -0,0 +1,215 @@
+//! Synthetic data sets.
+//!
+//! The generators create small tabular data sets with known structure, for instance to run the examples without
+//! downloading data or to sanity check a new layer. The classification labels are one-hot encoded and the classes are
+//! balanced. The samples are drawn from the given random number generator, such that a seeded generator makes the data
+//! sets reproducible, and are shuffled before being split into training and validation sets.
+use arrayfire::*;
+use rand::Rng;
+use std::f64::consts::PI;
+
+use super::TabularDataSet;
+use crate::tensor::*;
+
+/// Creates two interleaving half circles in two dimensions.
+///
+/// # Arguments
+///
+/// * `num_samples` - The total number of samples.
+/// * `noise` - The standard deviation of the Gaussian noise added to the samples.
+/// * `valid_frac` - The fraction of the samples used for validation.
+/// * `rng` - The random number generator.
+pub fn two_moons<R: Rng + ?Sized>(num_samples: u64, noise: PrimitiveType, valid_frac: f64, rng: &mut R) -> TabularDataSet {
+    let mut x = Vec::with_capacity(2 * num_samples as usize);
+    let mut classes = Vec::with_capacity(num_samples as usize);
+    for i in 0..num_samples {
+        let angle = rng.gen::<f64>() * PI;
+        let (x1, x2) = if i % 2 == 0 { (angle.cos(), angle.sin()) } else { (1. - angle.cos(), 0.5 - angle.sin()) };
+        x.push(x1 as PrimitiveType + noise * standard_normal(rng));
+        x.push(x2 as PrimitiveType + noise * standard_normal(rng));
+        classes.push(i % 2);
+    }
+    classification_data_set(x, &classes, 2, valid_frac, rng)
+}
+
+/// Creates a large circle containing a smaller circle in two dimensions.
+///
+/// # Arguments
+///
+/// * `num_samples` - The total number of samples.
+/// * `noise` - The standard deviation of the Gaussian noise added to the samples.
+/// * `factor` - The ratio between the radius of the inner circle and the radius of the outer circle.
+/// * `valid_frac` - The fraction of the samples used for validation.
+/// * `rng` - The random number generator.
+///
+/// # Panics
+///
+/// Panics if the factor is not between 0 and 1.
+pub fn circles<R: Rng + ?Sized>(num_samples: u64, noise: PrimitiveType, factor: PrimitiveType, valid_frac: f64, rng: &mut R) -> TabularDataSet {
+    assert!(factor > 0. && factor < 1., "The factor must be between 0 and 1.");
+    let mut x = Vec::with_capacity(2 * num_samples as usize);
+    let mut classes = Vec::with_capacity(num_samples as usize);
+    for i in 0..num_samples {
+        let angle = rng.gen::<f64>() * 2. * PI;
+        let radius = if i % 2 == 0 { 1. } else { factor };
+        x.push(radius * angle.cos() as PrimitiveType + noise * standard_normal(rng));
+        x.push(radius * angle.sin() as PrimitiveType + noise * standard_normal(rng));
+        classes.push(i % 2);
+    }
+    classification_data_set(x, &classes, 2, valid_frac, rng)
+}
+
+/// Creates isotropic Gaussian blobs, one per class.
+///
+/// The centers of the blobs are drawn uniformly within [-10, 10] in each dimension.
+///
+/// # Arguments
+///
+/// * `num_samples` - The total number of samples.
+/// * `num_features` - The number of features.
+/// * `num_classes` - The number of classes.
+/// * `std` - The standard deviation of the blobs.
+/// * `valid_frac` - The fraction of the samples used for validation.
+/// * `rng` - The random number generator.
+pub fn blobs<R: Rng + ?Sized>(num_samples: u64,
+                              num_features: u64,
+                              num_classes: u64,
+                              std: PrimitiveType,
+                              valid_frac: f64,
+                              rng: &mut R,
+) -> TabularDataSet {
+    assert!(num_classes > 0, "The number of classes must be greater than zero.");
+    let centers: Vec<PrimitiveType> = (0..num_classes * num_features).map(|_| rng.gen::<PrimitiveType>() * 20. - 10.).collect();
+    let mut x = Vec::with_capacity((num_features * num_samples) as usize);
+    let mut classes = Vec::with_capacity(num_samples as usize);
+    for i in 0..num_samples {
+        let class = i % num_classes;
+        let center = &centers[(class * num_features) as usize..((class + 1) * num_features) as usize];
+        for &coordinate in center {
+            x.push(coordinate + std * standard_normal(rng));
+        }
+        classes.push(class);
+    }
+    classification_data_set(x, &classes, num_classes, valid_frac, rng)
+}
+
+/// Creates interleaving spirals in two dimensions, one per class.
+///
+/// # Arguments
+///
+/// * `num_samples` - The total number of samples.
+/// * `num_classes` - The number of spirals.
+/// * `noise` - The standard deviation of the Gaussian noise added to the angles.
+/// * `valid_frac` - The fraction of the samples used for validation.
+/// * `rng` - The random number generator.
+pub fn spirals<R: Rng + ?Sized>(num_samples: u64, num_classes: u64, noise: PrimitiveType, valid_frac: f64, rng: &mut R) -> TabularDataSet {
+    assert!(num_classes > 0, "The number of classes must be greater than zero.");
+    let mut x = Vec::with_capacity(2 * num_samples as usize);
+    let mut classes = Vec::with_capacity(num_samples as usize);
+    for i in 0..num_samples {
+        let class = i % num_classes;
+        // The radius grows from the center to 1 while the angle increases by 4 radians
+        let radius = rng.gen::<PrimitiveType>();
+        let angle = 4. * (class as PrimitiveType + radius) + noise * standard_normal(rng);
+        x.push(radius * angle.sin());
+        x.push(radius * angle.cos());
+        classes.push(class);
+    }
+    classification_data_set(x, &classes, num_classes, valid_frac, rng)
+}
+
+/// Creates a linear regression problem with Gaussian features and noise.
+///
+/// The labels are a linear combination of the features, whose coefficients are drawn uniformly within [-1, 1], plus a
+/// bias and Gaussian noise. The mean squared error of the best linear model is thus the variance of the noise.
+///
+/// # Arguments
+///
+/// * `num_samples` - The total number of samples.
+/// * `num_features` - The number of features.
+/// * `noise` - The standard deviation of the noise added to the labels.
+/// * `valid_frac` - The fraction of the samples used for validation.
+/// * `rng` - The random number generator.
+pub fn linear_regression<R: Rng + ?Sized>(num_samples: u64, num_features: u64, noise: PrimitiveType, valid_frac: f64, rng: &mut R) -> TabularDataSet {
+    let coefficients: Vec<PrimitiveType> = (0..=num_features).map(|_| rng.gen::<PrimitiveType>() * 2. - 1.).collect();
+    let mut x = Vec::with_capacity((num_features * num_samples) as usize);
+    let mut y = Vec::with_capacity(num_samples as usize);
+    for _ in 0..num_samples {
+        let mut label = coefficients[num_features as usize] + noise * standard_normal(rng);
+        for &coefficient in &coefficients[..num_features as usize] {
+            let feature = standard_normal(rng);
+            label += coefficient * feature;
+            x.push(feature);
+        }
+        y.push(label);
+    }
+    data_set(x, y, num_features, 1, valid_frac, rng)
+}
+
+/// Draws a value from the standard normal distribution with the Box-Muller transform.
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> PrimitiveType {
+    let u1 = 1. - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    ((-2. * u1.ln()).sqrt() * (2. * PI * u2).cos()) as PrimitiveType
+}
+
+/// Creates a classification data set with one-hot encoded labels.
+fn classification_data_set<R: Rng + ?Sized>(x: Vec<PrimitiveType>, classes: &[u64], num_classes: u64, valid_frac: f64, rng: &mut R) -> TabularDataSet {
+    let mut y = vec![0 as PrimitiveType; classes.len() * num_classes as usize];
+    for (i, &class) in classes.iter().enumerate() {
+        y[i * num_classes as usize + class as usize] = 1.;
+    }
+    let num_features = x.len() as u64 / classes.len().max(1) as u64;
+    data_set(x, y, num_features, num_classes, valid_frac, rng)
+}
+
+/// Shuffles the samples and splits them into training and validation sets.
+fn data_set<R: Rng + ?Sized>(x: Vec<PrimitiveType>,
+                             y: Vec<PrimitiveType>,
+                             num_features: u64,
+                             num_outputs: u64,
+                             valid_frac: f64,
+                             rng: &mut R,
+) -> TabularDataSet {
+    let num_samples = y.len() as u64 / num_outputs;
+    let mut x = Tensor::new(&x[..], Dim4::new(&[num_features, 1, 1, num_samples]));
+    let mut y = Tensor::new(&y[..], Dim4::new(&[num_outputs, 1, 1, num_samples]));
+    Tensor::shuffle_mut(&mut [&mut x, &mut y], rng);
+    TabularDataSet::split(x, y, None, valid_frac, false)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::data::DataSet;
+    use crate::data::synthetic::*;
+
+    #[test]
+    fn test_synthetic_data_sets() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let data_sets = vec![
+            (two_moons(100, 0.1, 0.2, &mut rng), 2, 2),
+            (circles(100, 0.1, 0.5, 0.2, &mut rng), 2, 2),
+            (blobs(100, 3, 4, 1., 0.2, &mut rng), 3, 4),
+            (spirals(100, 3, 0.1, 0.2, &mut rng), 2, 3),
+            (linear_regression(100, 5, 0.1, 0.2, &mut rng), 5, 1),
+        ];
+        for (data, num_features, num_outputs) in data_sets {
+            assert_eq!(data.input_shape().get(), &[num_features, 1, 1, 1]);
+            assert_eq!(data.output_shape().get(), &[num_outputs, 1, 1, 1]);
+            assert_eq!((data.num_train_samples(), data.num_valid_samples()), (80, 20));
+            if num_outputs > 1 {
+                // The labels are one-hot encoded
+                assert_eq!(sum_all(data.y_train()).0, 80.);
+            }
+        }
+
+        // The data sets are reproducible
+        let first = two_moons(10, 0.1, 0.2, &mut StdRng::seed_from_u64(1));
+        let second = two_moons(10, 0.1, 0.2, &mut StdRng::seed_from_u64(1));
+        assert_eq!(sum_all(&neq(first.x_train(), second.x_train(), false)).0, 0.);
+    }
+}
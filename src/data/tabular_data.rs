@@ -2,10 +2,14 @@
 //! Helper methods to work with tabular data sets.
 use arrayfire::*;
 use csv;
+use rand::thread_rng;
+use rayon::prelude::*;
 use std::fmt;
 use std::path::Path;
 
-use super::{DataSet, DataSetError, Scaling, IO};
+use super::{DataSet, DataSetError, FeatureTransform, FittedTransform, Scaling, IO};
+use super::groups::{group_k_fold, group_shuffle_split, num_groups};
+use super::oversampling::smote;
 use crate::errors::*;
 use crate::tensor::*;
 
@@ -21,12 +25,21 @@ pub struct TabularDataSet {
     y_valid: Option<Tensor>,
     x_test: Option<Tensor>,
     y_test: Option<Tensor>,
+    x_train_sparse: Option<SparseTensor>,
+    x_valid_sparse: Option<SparseTensor>,
+    sample_weights: Option<Tensor>,
+    input_noise: Option<PrimitiveType>,
+    y_train_heads: Vec<Tensor>,
+    transforms: Vec<FittedTransform>,
     x_train_stats: Option<(Scaling, Tensor, Tensor)>,
     y_train_stats: Option<(Scaling, Tensor, Tensor)>,
 }
 
 impl TabularDataSet {
 
+    /// Number of csv records parsed before being uploaded to the device.
+    const UPLOAD_CHUNK_SIZE: usize = 65536;
+
     /// Creates a TabularDataSet from a set of csv files.
     ///
     /// The data are shuffled before being split into training and validation sets.
@@ -42,44 +55,325 @@ impl TabularDataSet {
                     valid_frac: f64,
                     header: bool
     ) -> Result<TabularDataSet, Error> {
-        let (in_shape, num_in_samples, in_values) = TabularDataSet::load_data_from_path(&inputs, header)?;
-        let (out_shape, num_out_samples, out_values) = TabularDataSet::load_data_from_path(&outputs, header)?;
+        TabularDataSet::from_csv_with_options(inputs, outputs, valid_frac, header, true)
+    }
+
+    /// Creates a TabularDataSet from a set of csv files, optionally without shuffling the data.
+    ///
+    /// If the data are not shuffled, the split is chronological: the first samples of the files are used for training
+    /// and the last ones for validation. This prevents future data from leaking into the training set for time-ordered
+    /// data sets.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The path to the csv file containing the input features.
+    /// * `outputs` - The path to the csv file containing the output labels.
+    /// * `valid_frac` - The fraction of the data used for validation.
+    /// * `header` - Flag indicating whether the files have a header.
+    /// * `shuffle` - Flag indicating whether the data are shuffled before being split into training and validation sets.
+    pub fn from_csv_with_options(inputs: &Path,
+                                 outputs: &Path,
+                                 valid_frac: f64,
+                                 header: bool,
+                                 shuffle: bool
+    ) -> Result<TabularDataSet, Error> {
+        let (_, num_in_samples, x) = TabularDataSet::load_data_from_path(&inputs, header)?;
+        let (_, num_out_samples, y) = TabularDataSet::load_data_from_path(&outputs, header)?;
 
         if num_in_samples != num_out_samples {
             Err(std::convert::From::from(DataSetError::DimensionMismatch))
         } else {
-            let num_samples = num_in_samples;
-
-            let mut x = Tensor::new(&in_values[..], Dim4::new(&[in_shape, 1, 1, num_samples]));
-            let mut y = Tensor::new(&out_values[..],  Dim4::new(&[out_shape, 1, 1, num_samples]));
-
-            Tensor::shuffle_mut(&mut x, &mut y);
-
-            // Compute number of samples in training set and validation set
-            let num_valid_samples = (valid_frac * num_samples as f64).floor() as u64;
-            let num_train_samples = num_samples - num_valid_samples;
-            let seqs_train = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(0.0, (num_train_samples - 1) as f64, 1.0)];
-            let seqs_valid = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(num_train_samples as f64, (num_samples - 1) as f64, 1.0)];
-            let x_train = index(&x, seqs_train);
-            let x_valid = index(&x, seqs_valid);
-            let y_train = index(&y, seqs_train);
-            let y_valid = index(&y, seqs_valid);
-
-            // Create the data set
-            Ok(TabularDataSet {
-                num_train_samples,
-                num_valid_samples,
-                input_shape: Dim4::new(&[in_shape, 1, 1, 1]),
-                output_shape: Dim4::new(&[out_shape, 1, 1, 1]),
-                x_train,
-                y_train,
-                x_valid: Some(x_valid),
-                y_valid: Some(y_valid),
-                x_test: None,
-                y_test: None,
-                x_train_stats: None,
-                y_train_stats: None,
-            })
+            Ok(TabularDataSet::split(x, y, None, valid_frac, shuffle))
+        }
+    }
+
+    /// Creates a TabularDataSet from a set of csv files where one column of the input file contains the weight of each sample.
+    ///
+    /// The weights scale the contribution of each sample to the training loss, for instance for survey-weighted or
+    /// importance-weighted regression. The weight column is removed from the input features. The data are shuffled
+    /// before being split into training and validation sets and the validation samples are not weighted.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The path to the csv file containing the input features and the weights.
+    /// * `outputs` - The path to the csv file containing the output labels.
+    /// * `weight_column` - The index of the column containing the weights in the input file.
+    /// * `valid_frac` - The fraction of the data used for validation.
+    /// * `header` - Flag indicating whether the files have a header.
+    pub fn from_csv_with_weights(inputs: &Path,
+                                 outputs: &Path,
+                                 weight_column: usize,
+                                 valid_frac: f64,
+                                 header: bool
+    ) -> Result<TabularDataSet, Error> {
+        let (in_shape, num_in_samples, in_values) = TabularDataSet::load_data_from_path(&inputs, header)?;
+        let (_, num_out_samples, y) = TabularDataSet::load_data_from_path(&outputs, header)?;
+
+        if num_in_samples != num_out_samples || weight_column as u64 >= in_shape || in_shape < 2 {
+            Err(std::convert::From::from(DataSetError::DimensionMismatch))
+        } else {
+            // Separate the weights from the input features
+            let (x, weights) = TabularDataSet::remove_column(&in_values, weight_column);
+            Ok(TabularDataSet::split(x, y, Some(weights), valid_frac, true))
+        }
+    }
+
+    /// Creates a TabularDataSet from a set of csv files where one column of the input file contains the group of each sample.
+    ///
+    /// All the samples of a group (patient, user, session, ...) are placed in the same set, see
+    /// [from_tensor_with_groups](#method.from_tensor_with_groups). The group column is removed from the input features.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The path to the csv file containing the input features and the groups.
+    /// * `outputs` - The path to the csv file containing the output labels.
+    /// * `group_column` - The index of the column containing the groups in the input file.
+    /// * `valid_frac` - The fraction of the data used for validation.
+    /// * `header` - Flag indicating whether the files have a header.
+    pub fn from_csv_with_groups(inputs: &Path,
+                                outputs: &Path,
+                                group_column: usize,
+                                valid_frac: f64,
+                                header: bool
+    ) -> Result<TabularDataSet, Error> {
+        let (in_shape, num_in_samples, in_values) = TabularDataSet::load_data_from_path(&inputs, header)?;
+        let (_, num_out_samples, y) = TabularDataSet::load_data_from_path(&outputs, header)?;
+
+        if num_in_samples != num_out_samples || group_column as u64 >= in_shape || in_shape < 2 {
+            return Err(std::convert::From::from(DataSetError::DimensionMismatch));
+        }
+        let (x, groups) = TabularDataSet::remove_column(&in_values, group_column);
+        TabularDataSet::from_tensor_with_groups(x, y, &groups, valid_frac)
+    }
+
+    /// Creates a TabularDataSet from Tensors, keeping all the samples of a group in the same set.
+    ///
+    /// The groups are shuffled and whole groups are moved to the validation set until it contains at least a fraction
+    /// `valid_frac` of the samples. This prevents the samples of a group from leaking between the training and
+    /// validation sets.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The samples, stacked along the fourth dimension.
+    /// * `y` - The labels, stacked along the fourth dimension.
+    /// * `groups` - The group of each sample, with dimensions [1, 1, 1, number of samples].
+    /// * `valid_frac` - The fraction of the data used for validation.
+    pub fn from_tensor_with_groups(x: Tensor, y: Tensor, groups: &Tensor, valid_frac: f64) -> Result<TabularDataSet, Error> {
+        if !(0. ..1.).contains(&valid_frac) {
+            return Err(Error::from(DataSetError::InvalidValidationFraction));
+        }
+        let groups = TabularDataSet::host_groups(&x, &y, groups)?;
+
+        let (train_indices, valid_indices) = group_shuffle_split(&groups, valid_frac, &mut thread_rng());
+        if train_indices.is_empty() {
+            return Err(Error::from(DataSetError::InvalidValidationFraction));
+        }
+        let (x_valid, y_valid) = if valid_indices.is_empty() {
+            (None, None)
+        } else {
+            (Some(TabularDataSet::select_samples(&x, &valid_indices)), Some(TabularDataSet::select_samples(&y, &valid_indices)))
+        };
+        TabularDataSet::from_tensor(TabularDataSet::select_samples(&x, &train_indices), TabularDataSet::select_samples(&y, &train_indices), x_valid, y_valid, None, None)
+    }
+
+    /// Splits the samples into folds for a group k-fold cross-validation.
+    ///
+    /// The groups are distributed among the folds such that the folds have similar numbers of samples and all the
+    /// samples of a group belong to the same fold. The i-th returned data set is validated on the i-th fold and trained
+    /// on the other folds.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The samples, stacked along the fourth dimension.
+    /// * `y` - The labels, stacked along the fourth dimension.
+    /// * `groups` - The group of each sample, with dimensions [1, 1, 1, number of samples].
+    /// * `num_folds` - The number of folds, between 2 and the number of groups.
+    pub fn group_k_fold(x: &Tensor, y: &Tensor, groups: &Tensor, num_folds: usize) -> Result<Vec<TabularDataSet>, Error> {
+        let groups = TabularDataSet::host_groups(x, y, groups)?;
+        if num_folds < 2 || num_folds > num_groups(&groups) {
+            return Err(Error::from(DataSetError::InvalidNumberOfFolds));
+        }
+
+        let folds = group_k_fold(&groups, num_folds);
+        folds.iter().enumerate().map(|(k, valid_indices)| {
+            let train_indices: Vec<u64> = folds.iter().enumerate().filter(|&(i, _)| i != k).flat_map(|(_, fold)| fold.iter().copied()).collect();
+            TabularDataSet::from_tensor(TabularDataSet::select_samples(x, &train_indices),
+                                        TabularDataSet::select_samples(y, &train_indices),
+                                        Some(TabularDataSet::select_samples(x, valid_indices)),
+                                        Some(TabularDataSet::select_samples(y, valid_indices)),
+                                        None,
+                                        None)
+        }).collect()
+    }
+
+    /// Copies the groups to the host after checking that there is one group per sample.
+    fn host_groups(x: &Tensor, y: &Tensor, groups: &Tensor) -> Result<Vec<PrimitiveType>, Error> {
+        if x.batch_size() != y.batch_size() || groups.elements() as u64 != x.batch_size() {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        let mut groups_host = vec![0 as PrimitiveType; groups.elements()];
+        groups.host(&mut groups_host);
+        Ok(groups_host)
+    }
+
+    /// Selects the samples with the given indices.
+    fn select_samples(values: &Tensor, indices: &[u64]) -> Tensor {
+        let indices: Vec<u32> = indices.iter().map(|&i| i as u32).collect();
+        lookup(values, &Array::new(&indices[..], Dim4::new(&[indices.len() as u64, 1, 1, 1])), 3)
+    }
+
+    /// Separates a column from the other features.
+    ///
+    /// # Return value
+    ///
+    /// The remaining features and the values of the column, with dimensions [1, 1, 1, number of samples].
+    fn remove_column(values: &Tensor, column: usize) -> (Tensor, Tensor) {
+        let num_features = values.dims()[0];
+        let feature_columns: Vec<u32> = (0..num_features as u32).filter(|&i| i != column as u32).collect();
+        let feature_columns = Array::new(&feature_columns[..], Dim4::new(&[num_features - 1, 1, 1, 1]));
+        let column_seqs = [Seq::new(column as f64, column as f64, 1.0), Seq::default(), Seq::default(), Seq::default()];
+        (lookup(values, &feature_columns, 0), index(values, &column_seqs))
+    }
+
+    /// Attaches a test set loaded from a set of csv files.
+    ///
+    /// The test samples are not shuffled. If the features or labels have already been scaled, the test set is scaled
+    /// with the values computed on the training set. The transforms of the features, if any, are applied before the scaling.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The path to the csv file containing the input features of the test set.
+    /// * `outputs` - The path to the csv file containing the output labels of the test set.
+    /// * `header` - Flag indicating whether the files have a header.
+    pub fn with_test_csv(mut self,
+                         inputs: &Path,
+                         outputs: &Path,
+                         header: bool
+    ) -> Result<TabularDataSet, Error> {
+        let (in_shape, num_in_samples, mut x_test) = TabularDataSet::load_data_from_path(&inputs, header)?;
+        let (out_shape, num_out_samples, mut y_test) = TabularDataSet::load_data_from_path(&outputs, header)?;
+
+        let num_raw_features = self.transforms.first().map_or(self.input_shape[0], |transform| transform.num_input_features());
+        if num_in_samples != num_out_samples || in_shape != num_raw_features || out_shape != self.output_shape[0] {
+            return Err(std::convert::From::from(DataSetError::DimensionMismatch));
+        }
+
+        for transform in &self.transforms {
+            x_test = transform.apply(&x_test);
+        }
+        if let Some((scaling, c1, c2)) = &self.x_train_stats {
+            x_test = scaling.apply(&x_test, c1, c2);
+        }
+        if let Some((scaling, c1, c2)) = &self.y_train_stats {
+            y_test = scaling.apply(&y_test, c1, c2);
+        }
+
+        self.x_test = Some(x_test);
+        self.y_test = Some(y_test);
+        Ok(self)
+    }
+
+    /// Balances the classes of the training set by oversampling the minority classes with SMOTE.
+    ///
+    /// Synthetic samples of each minority class are created by interpolating between a sample and one of its
+    /// `k_neighbors` nearest neighbors of the same class, until all classes have as many training samples as the
+    /// majority class. The validation and test sets are not modified. The weight of the synthetic samples is one. A
+    /// single output is considered as the binary labels 0 and 1 of two classes.
+    ///
+    /// # Arguments
+    ///
+    /// * `k_neighbors` - The number of nearest neighbors among which the interpolated neighbor is drawn.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the samples are sparse, or if the data set contains labels for the heads.
+    pub fn with_oversampling(mut self, k_neighbors: u64) -> Result<TabularDataSet, Error> {
+        if self.x_train_sparse.is_some() || !self.y_train_heads.is_empty() {
+            return Err(Error::from(DataSetError::InvalidOversampling));
+        }
+
+        if let Some((x_new, y_new)) = smote(&self.x_train, &self.y_train, k_neighbors, &mut thread_rng()) {
+            let num_new_samples = x_new.batch_size();
+            self.x_train = join(3, &self.x_train, &x_new);
+            self.y_train = join(3, &self.y_train, &y_new);
+            self.sample_weights = self.sample_weights.take().map(|weights| join(3, &weights, &Tensor::ones(Dim4::new(&[1, 1, 1, num_new_samples]))));
+            self.num_train_samples += num_new_samples;
+        }
+        Ok(self)
+    }
+
+    /// Adds Gaussian noise to the training samples of each mini-batch.
+    ///
+    /// The corruption is a cheap regularizer for small networks trained on small tabular data sets. A new noise is drawn
+    /// for every mini-batch, and the validation and test samples are left untouched. The noise is not applied to sparse
+    /// samples, and the input corruption of the
+    /// [training configuration](../training/struct.FitConfig.html#method.input_corruption), if any, takes precedence.
+    ///
+    /// # Arguments
+    ///
+    /// * `stddev` - The standard deviation of the noise, in the units of the samples after scaling.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stddev` is negative.
+    pub fn with_input_noise(mut self, stddev: PrimitiveType) -> TabularDataSet {
+        assert!(stddev >= 0., "The standard deviation must be positive.");
+        self.input_noise = Some(stddev);
+        self
+    }
+
+    /// Splits the samples into training and validation sets.
+    ///
+    /// If the samples are not shuffled, the last samples are used for validation. The weights of the samples, if any,
+    /// are only kept for the training set.
+    pub(crate) fn split(x: Tensor, y: Tensor, weights: Option<Tensor>, valid_frac: f64, shuffle: bool) -> TabularDataSet {
+        let num_samples = x.batch_size();
+        let in_shape = x.dims()[0];
+        let out_shape = y.dims()[0];
+
+        let (x, y, weights) = match (weights, shuffle) {
+            (Some(weights), true) => {
+                let (x, y, weights) = Tensor::shuffle_weighted(&x, &y, &weights);
+                (x, y, Some(weights))
+            },
+            (None, true) => {
+                let (x, y) = Tensor::shuffle(&x, &y);
+                (x, y, None)
+            },
+            (weights, false) => (x, y, weights),
+        };
+
+        // Compute number of samples in training set and validation set
+        let num_valid_samples = (valid_frac * num_samples as f64).floor() as u64;
+        let num_train_samples = num_samples - num_valid_samples;
+        let seqs_train = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(0.0, (num_train_samples - 1) as f64, 1.0)];
+        let seqs_valid = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(num_train_samples as f64, (num_samples - 1) as f64, 1.0)];
+        let x_train = index(&x, seqs_train);
+        let x_valid = index(&x, seqs_valid);
+        let y_train = index(&y, seqs_train);
+        let y_valid = index(&y, seqs_valid);
+        let sample_weights = weights.map(|weights| index(&weights, seqs_train));
+
+        // Create the data set
+        TabularDataSet {
+            num_train_samples,
+            num_valid_samples,
+            input_shape: Dim4::new(&[in_shape, 1, 1, 1]),
+            output_shape: Dim4::new(&[out_shape, 1, 1, 1]),
+            x_train,
+            y_train,
+            x_valid: Some(x_valid),
+            y_valid: Some(y_valid),
+            x_test: None,
+            y_test: None,
+            x_train_sparse: None,
+            x_valid_sparse: None,
+            sample_weights,
+            input_noise: None,
+            y_train_heads: Vec::new(),
+            transforms: Vec::new(),
+            x_train_stats: None,
+            y_train_stats: None,
         }
     }
 
@@ -111,38 +405,183 @@ impl TabularDataSet {
             y_valid,
             x_test,
             y_test,
+            x_train_sparse: None,
+            x_valid_sparse: None,
+            sample_weights: None,
+            input_noise: None,
+            y_train_heads: Vec::new(),
+            transforms: Vec::new(),
             x_train_stats: None,
             y_train_stats: None,
         })
     }
 
-    /// Loads the content of a csv file into a vector of floats.
+    /// Creates a TabularDataSet from unlabeled samples, whose labels are the samples themselves.
+    ///
+    /// The data set trains autoencoders, for instance to pretrain a network on unlabeled data with
+    /// [FitConfig::input_corruption](../training/struct.FitConfig.html#method.input_corruption). The samples can be
+    /// tabular data or images and must be stacked along the fourth dimension.
+    pub fn from_unlabeled(x_train: Tensor, x_valid: Option<Tensor>) -> Result<TabularDataSet, Error> {
+        if let Some(x_valid) = &x_valid {
+            if x_valid.dims().get()[..3] != x_train.dims().get()[..3] {
+                return Err(Error::from(DataSetError::DimensionMismatch));
+            }
+        }
+        let mut data = TabularDataSet::from_tensor(x_train.copy(), x_train, x_valid.as_ref().map(|x| x.copy()), x_valid, None, None)?;
+        data.input_shape = Dim4::new(&[data.x_train.dims()[0], data.x_train.dims()[1], data.x_train.dims()[2], 1]);
+        data.output_shape = data.input_shape;
+        Ok(data)
+    }
+
+    /// Creates a TabularDataSet from sparse samples.
+    ///
+    /// The samples are kept in a sparse format during training, which avoids densifying data sets with a large number of
+    /// features such as bags of words or one-hot encoded features. The first layer of the network must be a
+    /// [Dense](../layers/struct.Dense.html) layer. The labels must be stacked along the fourth dimension.
+    pub fn from_sparse(x_train: SparseTensor,
+                       y_train: Tensor,
+                       x_valid: Option<SparseTensor>,
+                       y_valid: Option<Tensor>
+    ) -> Result<TabularDataSet, Error> {
+        if x_train.batch_size() != y_train.dims()[3] {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        let num_valid_samples = match (&x_valid, &y_valid) {
+            (Some(x), Some(y)) if x.batch_size() != y.dims()[3] => return Err(Error::from(DataSetError::DimensionMismatch)),
+            (Some(x), Some(_)) => x.batch_size(),
+            (None, None) => 0,
+            _ => return Err(Error::from(DataSetError::DimensionMismatch)),
+        };
+        let num_outputs = y_train.dims()[0];
+        Ok(TabularDataSet {
+            num_train_samples: x_train.batch_size(),
+            num_valid_samples,
+            input_shape: Dim4::new(&[x_train.num_features(), 1, 1, 1]),
+            output_shape: Dim4::new(&[num_outputs, 1, 1, 1]),
+            x_train: Tensor::new_empty_tensor(),
+            y_train,
+            x_valid: None,
+            y_valid,
+            x_test: None,
+            y_test: None,
+            x_train_sparse: Some(x_train),
+            x_valid_sparse: x_valid,
+            sample_weights: None,
+            input_noise: None,
+            y_train_heads: Vec::new(),
+            transforms: Vec::new(),
+            x_train_stats: None,
+            y_train_stats: None,
+        })
+    }
+
+    /// Sets the weights of the training samples.
+    ///
+    /// The weights scale the contribution of each sample to the training loss. The tensor must contain one weight per
+    /// training sample.
+    pub fn set_sample_weights(&mut self, weights: &Tensor) -> Result<(), Error> {
+        if weights.elements() as u64 != self.num_train_samples {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        self.sample_weights = Some(moddims(weights, Dim4::new(&[1, 1, 1, self.num_train_samples])));
+        Ok(())
+    }
+
+    /// Adds the training labels of an output head of the network.
+    ///
+    /// The labels must be added in the order in which the heads were added to the network, skipping the auxiliary
+    /// heads, and stacked along the fourth dimension.
+    pub fn add_head_labels(&mut self, y_train: Tensor) -> Result<(), Error> {
+        if y_train.dims()[3] != self.num_train_samples {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        self.y_train_heads.push(y_train);
+        Ok(())
+    }
+
+    /// Fits a transform of the features on the training set and applies it to the training, validation, and test sets.
+    ///
+    /// The transforms can be chained and are kept by the data set, such that a [Pipeline](../pipeline/struct.Pipeline.html)
+    /// applies them to raw inputs at inference time. The features must be transformed before they are scaled, and
+    /// sparse samples can't be transformed.
+    pub fn transform(&mut self, transform: FeatureTransform) -> Result<(), Error> {
+        if self.x_train_stats.is_some() {
+            return Err(Error::from(DataSetError::TransformAfterScaling));
+        }
+        if self.x_train_sparse.is_some() {
+            return Err(Error::from(DataSetError::InvalidTransform));
+        }
+
+        let (transform, x_train) = FittedTransform::fit_transform(transform, &self.x_train, &self.y_train)?;
+        self.x_train = x_train;
+        self.x_valid = self.x_valid.as_ref().map(|x| transform.apply(x));
+        self.x_test = self.x_test.as_ref().map(|x| transform.apply(x));
+        self.input_shape = Dim4::new(&[transform.num_output_features(), 1, 1, 1]);
+        self.transforms.push(transform);
+        Ok(())
+    }
+
+    /// Loads the content of a csv file into a tensor.
+    ///
+    /// The file is read twice: the first pass counts the records such that the tensor is allocated once on the device.
+    /// The second pass parses the records in parallel by chunks of `UPLOAD_CHUNK_SIZE` records, and each chunk is
+    /// uploaded to the device before the next one is parsed. The host memory is thus bounded by the size of a chunk.
     ///
     /// # Return value
     ///
-    /// Returns a tuple containing the number of features, the number of samples, and a vector containing the values.
-    fn load_data_from_path(path: &Path, header: bool) -> Result<(u64, u64, Vec<PrimitiveType>), DataSetError> {
-        //let reader = csv::Reader::from_path(path);
-        let reader = csv::ReaderBuilder::new().has_headers(header).from_path(path);
-        match reader {
-            Ok(mut rdr) => {
-                let mut values = Vec::<PrimitiveType>::new();
-                let mut input_shape = 0;
-                for (i, result) in rdr.records().enumerate() {
-                    let record = result.unwrap();
-                    if i == 0 {
-                        input_shape = record.len() as u64;
-                    }
-                    for entry in record.iter() {
-                        values.push((*entry).parse::<PrimitiveType>().unwrap());
-                    }
-                }
+    /// Returns a tuple containing the number of features, the number of samples, and a tensor of dimensions
+    /// [number of features, 1, 1, number of samples] containing the values.
+    ///
+    /// # Errors
+    ///
+    /// Returns `DataSetError::InvalidValue` with the index of the sample if a value is not a number.
+    fn load_data_from_path(path: &Path, header: bool) -> Result<(u64, u64, Tensor), DataSetError> {
+        // Count the records and the features
+        let mut reader = csv::ReaderBuilder::new().has_headers(header).from_path(path).map_err(DataSetError::Csv)?;
+        let mut record = csv::ByteRecord::new();
+        let mut num_samples = 0;
+        let mut num_features = 0;
+        while reader.read_byte_record(&mut record).map_err(DataSetError::Csv)? {
+            num_features = record.len();
+            num_samples += 1;
+        }
+        if num_samples == 0 {
+            return Ok((0, 0, Tensor::new_empty_tensor()));
+        }
 
-                let num_samples = values.len() as u64 / input_shape;
-                Ok((input_shape, num_samples, values))
-            },
-            Err(e) => Err(DataSetError::Csv(e))
+        let mut values = Array::new_empty(Dim4::new(&[num_features as u64, 1, 1, num_samples]));
+        let mut reader = csv::ReaderBuilder::new().has_headers(header).from_path(path).map_err(DataSetError::Csv)?;
+        let max_chunk_size = std::cmp::min(Self::UPLOAD_CHUNK_SIZE, num_samples as usize);
+        let mut records = vec![csv::ByteRecord::new(); max_chunk_size];
+        let mut buffer = vec![0 as PrimitiveType; max_chunk_size * num_features];
+        let mut offset = 0;
+        loop {
+            // Read a chunk of records
+            let mut chunk_size = 0;
+            while chunk_size < max_chunk_size && reader.read_byte_record(&mut records[chunk_size]).map_err(DataSetError::Csv)? {
+                chunk_size += 1;
+            }
+            if chunk_size == 0 {
+                break;
+            }
+
+            // Parse the chunk in parallel and upload it
+            let chunk_values = &mut buffer[..chunk_size * num_features];
+            chunk_values.par_chunks_mut(num_features).zip(records[..chunk_size].par_iter()).enumerate().try_for_each(|(index, (sample, record))| {
+                for (value, entry) in sample.iter_mut().zip(record.iter()) {
+                    *value = std::str::from_utf8(entry).ok()
+                        .and_then(|entry| entry.trim().parse::<PrimitiveType>().ok())
+                        .ok_or(DataSetError::InvalidValue((offset + index) as u64))?;
+                }
+                Ok::<(), DataSetError>(())
+            })?;
+            let slab = Tensor::new(chunk_values, Dim4::new(&[num_features as u64, 1, 1, chunk_size as u64]));
+            let seqs = [Seq::default(), Seq::default(), Seq::default(), Seq::new(offset as f64, (offset + chunk_size - 1) as f64, 1.0)];
+            assign_seq(&mut values, &seqs, &slab);
+            offset += chunk_size;
         }
+
+        Ok((num_features as u64, num_samples, values))
     }
 
     /// Normalizes the features of the training, validation, and test (if any) sets.
@@ -151,8 +590,16 @@ impl TabularDataSet {
     /// validation, and test sets. After normalization, the distribution of the features in the training
     /// sets is a uniform distribution within 0 and 1 (assuming that the features originally come from a uniform
     /// distribution).
-    pub fn normalize_input(&mut self) {
-        self.x_train_stats = Some(self.normalize(IO::Input));
+    ///
+    /// The constant features are only shifted since their range is null.
+    ///
+    /// # Return value
+    ///
+    /// The indices of the constant features.
+    pub fn normalize_input(&mut self) -> Vec<u64> {
+        let (stats, constant_features) = self.scale(IO::Input, Scaling::Normalized);
+        self.x_train_stats = Some(stats);
+        constant_features
     }
 
     /// Standardizes the features of the training, validation, and test (if any) sets.
@@ -161,8 +608,16 @@ impl TabularDataSet {
     /// validation, and test sets. After standardization, the distribution of the features in the training set
     /// is a normal distribution with a mean of 0 and standard deviation 1 (assuming that the features originally come
     /// from a Gaussian distribution).
-    pub fn standardize_input(&mut self) {
-        self.x_train_stats = Some(self.standardize(IO::Input));
+    ///
+    /// The constant features are only centered since their standard deviation is null.
+    ///
+    /// # Return value
+    ///
+    /// The indices of the constant features.
+    pub fn standardize_input(&mut self) -> Vec<u64> {
+        let (stats, constant_features) = self.scale(IO::Input, Scaling::Standardized);
+        self.x_train_stats = Some(stats);
+        constant_features
     }
 
     /// Normalizes the labels of the training, validation, and test (if any) sets.
@@ -171,9 +626,13 @@ impl TabularDataSet {
     /// validation, and test sets. After normalization, the distribution of the labels in the training
     /// sets is a uniform distribution within 0 and 1 (assuming that the labels originally come from a uniform
     /// distribution).
-    pub fn normalize_output(&mut self) {
-
-        self.y_train_stats = Some(self.normalize(IO::Output));
+    ///
+    /// # Return value
+    ///
+    /// The indices of the constant labels.
+    pub fn normalize_output(&mut self) -> Vec<u64> {
+        let (stats, constant_labels) = self.scale(IO::Output, Scaling::Normalized);
+        self.y_train_stats = Some(stats);
 
         /*
         let y_max = max(&self.y_train, 3);
@@ -193,6 +652,8 @@ impl TabularDataSet {
         // Save normalization parameters
         self.y_train_stats = Some((Scaling::Normalized, y_min, y_max));
         */
+
+        constant_labels
     }
 
 
@@ -202,9 +663,13 @@ impl TabularDataSet {
     /// validation, and test sets. After standardization, the distribution of the labels in the training set
     /// is a normal distribution with a mean of 0 and standard deviation 1 (assuming that the labels originally come
     /// from a Gaussian distribution).
-    pub fn standardize_output(&mut self) {
-
-        self.y_train_stats = Some(self.standardize(IO::Output));
+    ///
+    /// # Return value
+    ///
+    /// The indices of the constant labels.
+    pub fn standardize_output(&mut self) -> Vec<u64> {
+        let (stats, constant_labels) = self.scale(IO::Output, Scaling::Standardized);
+        self.y_train_stats = Some(stats);
 
         /*
         let y_mean = mean(&self.y_train, 3);
@@ -224,6 +689,8 @@ impl TabularDataSet {
         // Save standardization parameters
         self.y_train_stats = Some((Scaling::Standardized, y_mean, y_std));
         */
+
+        constant_labels
     }
 
     /// Selects the input or output values.
@@ -254,53 +721,35 @@ impl TabularDataSet {
         }
     }
 
-    /// Standardizes the inputs or outputs.
+    /// Scales the inputs or outputs with the parameters computed on the training set.
     ///
     /// # Arguments
     ///
-    /// * `io` - The IO variant indicating if the inputs or outputs are standardized.
-    fn standardize(&mut self, io: IO) -> (Scaling, Tensor, Tensor) {
-        let (train_values, valid_values, test_values) = self.select_io(io);
-
-        let mean_value = mean(train_values, 3);
-        let standard_deviation = stdev(train_values, 3);
-
-        // Standardize the training, validation, and test sets.
-        *train_values = div(&sub(train_values, &mean_value, true), &standard_deviation, true);
-        if let Some(valid_values) = valid_values {
-            *valid_values = div(&sub(valid_values, &mean_value, true), &standard_deviation, true);
-        }
-
-        if let Some(test_values) = test_values {
-            *test_values = div(&sub(test_values, &mean_value, true), &standard_deviation, true);
-        }
-
-        // Return standardization parameters
-        (Scaling::Standardized, mean_value, standard_deviation)
-    }
-
-    /// Normalizes the inputs or outputs.
+    /// * `io` - The IO variant indicating if the inputs or outputs are scaled.
+    /// * `scaling` - The type of scaling.
     ///
-    /// # Arguments
+    /// # Return value
     ///
-    /// * `io` - The IO variant indicating if the inputs or outputs are normalized.
-    fn normalize(&mut self, io: IO) -> (Scaling, Tensor, Tensor) {
+    /// The scaling parameters and the indices of the constant features.
+    fn scale(&mut self, io: IO, scaling: Scaling) -> ((Scaling, Tensor, Tensor), Vec<u64>) {
         let (train_values, valid_values, test_values) = self.select_io(io);
 
-        let max_values = max(train_values, 3);
-        let min_values = min(train_values, 3);
+        let (c1, c2) = match scaling {
+            Scaling::Normalized => (min(train_values, 3), max(train_values, 3)),
+            Scaling::Standardized => (mean(train_values, 3), stdev_v2(train_values, VarianceBias::POPULATION, 3)),
+        };
 
-        // Normalize y_train, y_valid, and y_test
-        *train_values = div(&sub(train_values, &max_values, true), &sub(&max_values, &min_values, true), true);
+        // Scale the training, validation, and test sets
+        *train_values = scaling.apply(train_values, &c1, &c2);
         if let Some(valid_values) = valid_values {
-            *valid_values = div(&sub(valid_values, &max_values, true), &sub(&max_values, &min_values, true), true);
+            *valid_values = scaling.apply(valid_values, &c1, &c2);
         }
         if let Some(test_values) = test_values {
-            *test_values = div(&sub(test_values, &max_values, true), &sub(&max_values, &min_values, true), true);
+            *test_values = scaling.apply(test_values, &c1, &c2);
         }
 
-        // Save normalization parameters
-        (Scaling::Normalized, min_values, max_values)
+        let constant_features = scaling.constant_features(&c1, &c2);
+        ((scaling, c1, c2), constant_features)
     }
 }
 
@@ -335,6 +784,26 @@ impl DataSet for TabularDataSet {
         }
     }
 
+    fn sample_weights(&self) -> Option<&Tensor> {
+        self.sample_weights.as_ref()
+    }
+
+    fn input_noise(&self) -> Option<PrimitiveType> {
+        self.input_noise
+    }
+
+    fn y_train_heads(&self) -> &[Tensor] {
+        &self.y_train_heads
+    }
+
+    fn x_train_sparse(&self) -> Option<&SparseTensor> {
+        self.x_train_sparse.as_ref()
+    }
+
+    fn x_valid_sparse(&self) -> Option<&SparseTensor> {
+        self.x_valid_sparse.as_ref()
+    }
+
     fn x_test(&self) -> Option<&Tensor> {
         match &self.x_test {
             Some(values) => Some(values),
@@ -356,6 +825,9 @@ impl DataSet for TabularDataSet {
     fn y_train_stats(&self) -> &Option<(Scaling, Tensor, Tensor)> {
         &self.y_train_stats
     }
+    fn transforms(&self) -> &[FittedTransform] {
+        &self.transforms
+    }
 }
 
 impl fmt::Display for TabularDataSet {
@@ -389,4 +861,144 @@ impl fmt::Display for TabularDataSet {
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use crate::data::{DataSet, DataSetError, TabularDataSet};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_from_csv_with_weights() {
+        let inputs = std::env::temp_dir().join("neuro_test_from_csv_with_weights_inputs.csv");
+        let outputs = std::env::temp_dir().join("neuro_test_from_csv_with_weights_outputs.csv");
+        let mut inputs_file = std::fs::File::create(&inputs).unwrap();
+        let mut outputs_file = std::fs::File::create(&outputs).unwrap();
+        for i in 0..6 {
+            writeln!(inputs_file, "{},{},{}", i, 10 * i + 1, -i).unwrap();
+            writeln!(outputs_file, "{}", 2 * i).unwrap();
+        }
+        drop(inputs_file);
+        drop(outputs_file);
+
+        let data = TabularDataSet::from_csv_with_weights(&inputs, &outputs, 1, 0., false).unwrap();
+        std::fs::remove_file(&inputs).unwrap();
+        std::fs::remove_file(&outputs).unwrap();
+        assert_eq!(data.input_shape()[0], 2);
+        let weights = data.sample_weights().unwrap();
+        assert_eq!(weights.dims().get(), &[1, 1, 1, 6]);
+
+        // The weight column is removed from the features and the weights follow their samples through the shuffling
+        let mut x = [0 as PrimitiveType; 12];
+        data.x_train().host(&mut x);
+        let mut y = [0 as PrimitiveType; 6];
+        data.y_train().host(&mut y);
+        let mut w = [0 as PrimitiveType; 6];
+        weights.host(&mut w);
+        for j in 0..6 {
+            assert_eq!(x[2 * j + 1], -x[2 * j]);
+            assert_eq!(y[j], 2. * x[2 * j]);
+            assert_eq!(w[j], 10. * x[2 * j] + 1.);
+        }
+    }
+
+    #[test]
+    fn test_chronological_split() {
+        let inputs = std::env::temp_dir().join("neuro_test_chronological_split_inputs.csv");
+        let outputs = std::env::temp_dir().join("neuro_test_chronological_split_outputs.csv");
+        let mut inputs_file = std::fs::File::create(&inputs).unwrap();
+        let mut outputs_file = std::fs::File::create(&outputs).unwrap();
+        for i in 0..10 {
+            writeln!(inputs_file, "{}", i).unwrap();
+            writeln!(outputs_file, "{}", -i).unwrap();
+        }
+        drop(inputs_file);
+        drop(outputs_file);
+
+        let data = TabularDataSet::from_csv_with_options(&inputs, &outputs, 0.3, false, false).unwrap();
+        std::fs::remove_file(&inputs).unwrap();
+        std::fs::remove_file(&outputs).unwrap();
+
+        // The first samples are used for training and the last ones for validation, in their original order
+        let mut x_train = [0 as PrimitiveType; 7];
+        data.x_train().host(&mut x_train);
+        assert_eq!(x_train, [0., 1., 2., 3., 4., 5., 6.]);
+        let mut x_valid = [0 as PrimitiveType; 3];
+        data.x_valid().unwrap().host(&mut x_valid);
+        assert_eq!(x_valid, [7., 8., 9.]);
+        let mut y_valid = [0 as PrimitiveType; 3];
+        data.y_valid().unwrap().host(&mut y_valid);
+        assert_eq!(y_valid, [-7., -8., -9.]);
+    }
+
+    #[test]
+    fn test_with_test_csv() {
+        let dir = std::env::temp_dir();
+        let paths: Vec<_> = ["train_inputs", "train_outputs", "test_inputs", "test_outputs"].iter()
+            .map(|name| dir.join(format!("neuro_test_with_test_csv_{}.csv", name)))
+            .collect();
+        let contents = ["0\n2\n4\n", "1\n1\n1\n", "1\n8\n", "0\n1\n"];
+        for (path, content) in paths.iter().zip(contents.iter()) {
+            std::fs::write(path, content).unwrap();
+        }
+
+        let mut data = TabularDataSet::from_csv_with_options(&paths[0], &paths[1], 0.34, false, false).unwrap();
+        data.normalize_input();
+        let data = data.with_test_csv(&paths[2], &paths[3], false).unwrap();
+        let mismatch = TabularDataSet::from_csv_with_options(&paths[0], &paths[1], 0.34, false, false).unwrap()
+            .with_test_csv(&paths[2], &paths[0], false);
+        for path in paths.iter() {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        // The test features are scaled with the minimum and maximum of the training features
+        let mut x_test = [0 as PrimitiveType; 2];
+        data.x_test().unwrap().host(&mut x_test);
+        assert_eq!(x_test, [0.5, 4.]);
+        let mut y_test = [0 as PrimitiveType; 2];
+        data.y_test().unwrap().host(&mut y_test);
+        assert_eq!(y_test, [0., 1.]);
+
+        assert!(mismatch.is_err());
+    }
+
+    #[test]
+    fn test_with_oversampling_binary_labels() {
+        let x = Tensor::new(&[0 as PrimitiveType, 1., 2., 3., 10., 11.], Dim::new(&[1, 1, 1, 6]));
+        let y = Tensor::new(&[0 as PrimitiveType, 0., 0., 0., 1., 1.], Dim::new(&[1, 1, 1, 6]));
+        let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap().with_oversampling(3).unwrap();
+
+        // The minority class of the single output is oversampled until both classes have four samples
+        assert_eq!(data.num_train_samples(), 8);
+        let mut y_train = [0 as PrimitiveType; 8];
+        data.y_train().host(&mut y_train);
+        assert_eq!(y_train.iter().filter(|&&label| label == 1.).count(), 4);
+    }
+
+    #[test]
+    fn test_load_data_from_path() {
+        let path = std::env::temp_dir().join("neuro_test_load_data_from_path.csv");
+        let mut file = std::fs::File::create(&path).unwrap();
+        writeln!(file, "a,b,c").unwrap();
+        for i in 0..5 {
+            writeln!(file, "{},{}, {}", i, 10 * i, -i).unwrap();
+        }
+        drop(file);
+
+        let (num_features, num_samples, values) = TabularDataSet::load_data_from_path(&path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((num_features, num_samples), (3, 5));
+        assert_eq!(values.dims().get(), &[3, 1, 1, 5]);
+        let mut output = [0 as PrimitiveType; 15];
+        values.host(&mut output);
+        assert_eq!(output, [0., 0., 0., 1., 10., -1., 2., 20., -2., 3., 30., -3., 4., 40., -4.]);
+
+        // A value that is not a number is reported with the index of its sample
+        std::fs::write(&path, "1,2\n3,x\n").unwrap();
+        let result = TabularDataSet::load_data_from_path(&path, false);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(DataSetError::InvalidValue(1))));
+    }
+}
@@ -0,0 +1,621 @@
+//! Transforms of the features of tabular data.
+//!
+//! The transforms are fitted on the training set with [TabularDataSet::transform](struct.TabularDataSet.html#method.transform)
+//! and applied to the validation and test sets with the same parameters. The fitted transforms are kept by the data set
+//! and saved with the [Pipeline](../pipeline/struct.Pipeline.html), such that the raw features can be transformed at
+//! inference time.
+use arrayfire::*;
+use std::collections::HashMap;
+
+use super::DataSetError;
+use crate::io::{read_scalar, write_scalar};
+use crate::layers::Projection;
+use crate::tensor::*;
+
+/// Transforms applied to the features of tabular data.
+///
+/// The columns are the indices of the features along the first dimension, at the time the transform is applied.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FeatureTransform {
+    /// Replaces the values of a column by log(1 + x), which compresses heavy-tailed features.
+    Log { column: u64 },
+    /// Replaces the values of a column by the index of their quantile bucket, from 0 to `num_buckets - 1`. The
+    /// boundaries of the buckets are the quantiles of the training values.
+    QuantileBuckets { column: u64, num_buckets: u64 },
+    /// Replaces a categorical column by `num_buckets` columns one hot encoding the hash of its values, which bounds the
+    /// number of features created by high-cardinality categories. Distinct categories may share a bucket.
+    Hash { column: u64, num_buckets: u64 },
+    /// Appends the powers of the features up to the given degree and the products of all the pairs of features, which
+    /// lets small networks pick up interactions between the features. The degree must be at least 2.
+    Polynomial { degree: u32 },
+    /// Projects the features on their `num_components` principal components, computed from the covariance of the
+    /// training features. If `whiten` is set, the components are also scaled to unit variance.
+    Pca { num_components: u64, whiten: bool },
+    /// Replaces a categorical column by the mean of the labels of each category, with one column per output. The means
+    /// are smoothed towards the mean of all the labels with the weight `smoothing`, which is expressed as a number of
+    /// samples. The training samples are encoded with the means computed on the other `num_folds - 1` folds, such that
+    /// their own labels don't leak into their features. The unseen categories are encoded with the mean of all labels.
+    TargetEncoding { column: u64, num_folds: u64, smoothing: PrimitiveType },
+}
+
+impl FeatureTransform {
+    /// Returns the identifier of the transform used in the HDF5 files.
+    fn id(&self) -> u8 {
+        match self {
+            FeatureTransform::Log { .. } => 0,
+            FeatureTransform::QuantileBuckets { .. } => 1,
+            FeatureTransform::Hash { .. } => 2,
+            FeatureTransform::Polynomial { .. } => 3,
+            FeatureTransform::Pca { .. } => 4,
+            FeatureTransform::TargetEncoding { .. } => 5,
+        }
+    }
+
+    /// Returns the column transformed, if the transform applies to a single column.
+    fn column(&self) -> Option<u64> {
+        match *self {
+            FeatureTransform::Log { column } => Some(column),
+            FeatureTransform::QuantileBuckets { column, .. } => Some(column),
+            FeatureTransform::Hash { column, .. } => Some(column),
+            FeatureTransform::TargetEncoding { column, .. } => Some(column),
+            FeatureTransform::Polynomial { .. } | FeatureTransform::Pca { .. } => None,
+        }
+    }
+}
+
+
+/// Transform of the features fitted on a training set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FittedTransform {
+    transform: FeatureTransform,
+    num_features: u64,
+    boundaries: Vec<PrimitiveType>,
+    mean: Vec<PrimitiveType>,
+    projection: Vec<PrimitiveType>,
+    categories: Vec<PrimitiveType>,
+    encodings: Vec<PrimitiveType>,
+    prior: Vec<PrimitiveType>,
+}
+
+impl FittedTransform {
+    /// Fits the transform on the training samples, stacked along the fourth dimension.
+    ///
+    /// The transforms of a [TabularDataSet](struct.TabularDataSet.html) are fitted with
+    /// [TabularDataSet::transform](struct.TabularDataSet.html#method.transform). The target encoding requires the
+    /// labels and must be fitted with [fit_transform](#method.fit_transform).
+    pub fn fit(transform: FeatureTransform, x: &Tensor) -> Result<FittedTransform, DataSetError> {
+        Self::fit_with_labels(transform, x, None)
+    }
+
+    /// Fits the transform on the training samples and labels, and returns the transformed training samples.
+    ///
+    /// The training samples are transformed like any other samples, except for the target encoding which encodes each
+    /// fold with the statistics of the other folds.
+    pub fn fit_transform(transform: FeatureTransform, x: &Tensor, y: &Tensor) -> Result<(FittedTransform, Tensor), DataSetError> {
+        let fitted = Self::fit_with_labels(transform, x, Some(y))?;
+        let transformed = match transform {
+            FeatureTransform::TargetEncoding { column, num_folds, smoothing } => {
+                let values = host_values(&column_values(x, column));
+                let encoded = out_of_fold_encoding(&values, &host_values(y), fitted.prior.len(), num_folds, smoothing);
+                let encoded = Tensor::new(&encoded[..], Dim::new(&[fitted.prior.len() as u64, 1, 1, x.batch_size()]));
+                replace_column(x, column, &encoded)
+            },
+            _ => fitted.apply(x),
+        };
+        Ok((fitted, transformed))
+    }
+
+    /// Fits the transform on the training samples and, if given, the training labels.
+    fn fit_with_labels(transform: FeatureTransform, x: &Tensor, y: Option<&Tensor>) -> Result<FittedTransform, DataSetError> {
+        let num_features = x.dims()[0];
+        let valid = match transform {
+            FeatureTransform::Log { column } => column < num_features,
+            FeatureTransform::QuantileBuckets { column, num_buckets } => column < num_features && num_buckets > 0,
+            FeatureTransform::Hash { column, num_buckets } => column < num_features && num_buckets > 0,
+            FeatureTransform::Polynomial { degree } => degree >= 2,
+            FeatureTransform::Pca { num_components, .. } => num_components > 0 && num_components <= num_features,
+            FeatureTransform::TargetEncoding { column, num_folds, smoothing } => {
+                column < num_features && num_folds >= 2 && num_folds <= x.batch_size() && smoothing >= 0.
+                    && y.map_or(false, |y| y.batch_size() == x.batch_size())
+            },
+        };
+        if !valid || x.dims()[1] != 1 || x.dims()[2] != 1 {
+            return Err(DataSetError::InvalidTransform);
+        }
+
+        let boundaries = match transform {
+            FeatureTransform::QuantileBuckets { column, num_buckets } => {
+                let mut values: Vec<PrimitiveType> = host_values(&column_values(x, column)).into_iter().filter(|value| !value.is_nan()).collect();
+                values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let mut boundaries: Vec<PrimitiveType> = if values.is_empty() {
+                    Vec::new()
+                } else {
+                    (1..num_buckets).map(|i| values[(i as usize * values.len() / num_buckets as usize).min(values.len() - 1)]).collect()
+                };
+                boundaries.dedup();
+                boundaries
+            },
+            _ => Vec::new(),
+        };
+
+        let (mean, projection) = match transform {
+            FeatureTransform::Pca { num_components, whiten } => principal_components(x, num_components, whiten),
+            _ => (Vec::new(), Vec::new()),
+        };
+
+        let (categories, encodings, prior) = match (transform, y) {
+            (FeatureTransform::TargetEncoding { column, smoothing, .. }, Some(y)) => {
+                target_encoding(&host_values(&column_values(x, column)), &host_values(y), y.dims()[0] as usize, smoothing)
+            },
+            _ => (Vec::new(), Vec::new(), Vec::new()),
+        };
+
+        Ok(FittedTransform { transform, num_features, boundaries, mean, projection, categories, encodings, prior })
+    }
+
+    /// Returns the transform.
+    pub fn transform(&self) -> FeatureTransform {
+        self.transform
+    }
+
+    /// Returns the boundaries of the buckets of a quantile bucketization.
+    pub fn boundaries(&self) -> &[PrimitiveType] {
+        &self.boundaries
+    }
+
+    /// Returns the mean of the training features and the projection matrix of a PCA, of shape
+    /// [number of components, number of features].
+    ///
+    /// The rows of the projection matrix are the principal components, scaled to unit variance if the components are
+    /// whitened.
+    pub fn principal_components(&self) -> Option<(Tensor, Tensor)> {
+        match self.transform {
+            FeatureTransform::Pca { num_components, .. } => Some((
+                Tensor::new(&self.mean[..], Dim::new(&[self.num_features, 1, 1, 1])),
+                Tensor::new(&self.projection[..], Dim::new(&[num_components, self.num_features, 1, 1])),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Converts a PCA into a fixed [Projection](../layers/struct.Projection.html) layer, such that the projection can be
+    /// computed as the first layer of a network instead of preprocessing the samples.
+    ///
+    /// Returns `None` for the other transforms.
+    pub fn to_layer(&self) -> Option<Box<Projection>> {
+        let (mean, projection) = self.principal_components()?;
+        let biases = mul(&matmul(&projection, &mean, MatProp::NONE, MatProp::NONE), &(-1 as PrimitiveType), false);
+        Some(Projection::new(projection, biases))
+    }
+
+    /// Returns the number of features of the samples the transform is applied to.
+    pub fn num_input_features(&self) -> u64 {
+        self.num_features
+    }
+
+    /// Returns the number of features of the transformed samples.
+    pub fn num_output_features(&self) -> u64 {
+        match self.transform {
+            FeatureTransform::Hash { num_buckets, .. } => self.num_features - 1 + num_buckets,
+            FeatureTransform::Polynomial { degree } => {
+                self.num_features * degree as u64 + self.num_features * (self.num_features - 1) / 2
+            },
+            FeatureTransform::Pca { num_components, .. } => num_components,
+            FeatureTransform::TargetEncoding { .. } => self.num_features - 1 + self.prior.len() as u64,
+            _ => self.num_features,
+        }
+    }
+
+    /// Applies the transform to samples stacked along the fourth dimension.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of features of the samples doesn't match the training set.
+    pub fn apply(&self, x: &Tensor) -> Tensor {
+        assert_eq!(x.dims()[0], self.num_features, "The number of features doesn't match the training set.");
+        if let FeatureTransform::Polynomial { degree } = self.transform {
+            return polynomial_features(x, degree);
+        }
+        if let Some((mean, projection)) = self.principal_components() {
+            let batch_size = x.batch_size();
+            let centered = sub(&moddims(x, Dim::new(&[self.num_features, batch_size, 1, 1])), &mean, true);
+            let projected = matmul(&projection, &centered, MatProp::NONE, MatProp::NONE);
+            return moddims(&projected, Dim::new(&[projection.dims()[0], 1, 1, batch_size]));
+        }
+        let column = self.transform.column().expect("The transform applies to a single column.");
+        let values = column_values(x, column);
+        let transformed = match self.transform {
+            FeatureTransform::Log { .. } => log1p(&values),
+            FeatureTransform::QuantileBuckets { .. } => {
+                if self.boundaries.is_empty() {
+                    constant(0 as PrimitiveType, values.dims())
+                } else {
+                    let boundaries = Tensor::new(&self.boundaries[..], Dim::new(&[1, self.boundaries.len() as u64, 1, 1]));
+                    sum(&ge(&values, &boundaries, true).cast::<PrimitiveType>(), 1)
+                }
+            },
+            FeatureTransform::Polynomial { .. } | FeatureTransform::Pca { .. } => unreachable!(),
+            FeatureTransform::Hash { num_buckets, .. } => {
+                let batch_size = x.batch_size();
+                let mut one_hot = vec![0 as PrimitiveType; (num_buckets * batch_size) as usize];
+                for (i, value) in host_values(&values).into_iter().enumerate() {
+                    one_hot[i * num_buckets as usize + (hash(value) % num_buckets) as usize] = 1.;
+                }
+                Tensor::new(&one_hot[..], Dim::new(&[num_buckets, 1, 1, batch_size]))
+            },
+            FeatureTransform::TargetEncoding { .. } => {
+                let num_outputs = self.prior.len();
+                let categories: HashMap<u64, usize> = self.categories.iter().enumerate().map(|(i, &value)| (category_key(value), i)).collect();
+                let encoded: Vec<PrimitiveType> = host_values(&values).into_iter().flat_map(|value| {
+                    let encoding = match categories.get(&category_key(value)) {
+                        Some(&category) => &self.encodings[category * num_outputs..(category + 1) * num_outputs],
+                        None => &self.prior[..],
+                    };
+                    encoding.to_vec()
+                }).collect();
+                Tensor::new(&encoded[..], Dim::new(&[num_outputs as u64, 1, 1, x.batch_size()]))
+            },
+        };
+        replace_column(x, column, &transformed)
+    }
+
+    /// Saves the transform in the group.
+    pub(crate) fn save(&self, group: &hdf5::Group, index: usize) -> hdf5::Result<()> {
+        let transform = group.create_group(&format!("transform_{}", index))?;
+
+        let kind = transform.new_dataset::<u8>().create("kind", 1)?;
+        write_scalar(&kind, &self.transform.id());
+
+        if let Some(column) = self.transform.column() {
+            let column_ds = transform.new_dataset::<u64>().create("column", 1)?;
+            write_scalar(&column_ds, &column);
+        }
+        if let FeatureTransform::Polynomial { degree } = self.transform {
+            let degree_ds = transform.new_dataset::<u32>().create("degree", 1)?;
+            write_scalar(&degree_ds, &degree);
+        }
+
+        let num_buckets = match self.transform {
+            FeatureTransform::QuantileBuckets { num_buckets, .. } | FeatureTransform::Hash { num_buckets, .. } => num_buckets,
+            FeatureTransform::Pca { num_components, .. } => num_components,
+            FeatureTransform::TargetEncoding { num_folds, .. } => num_folds,
+            _ => 0,
+        };
+        let num_buckets_ds = transform.new_dataset::<u64>().create("num_buckets", 1)?;
+        write_scalar(&num_buckets_ds, &num_buckets);
+
+        let num_features = transform.new_dataset::<u64>().create("num_features", 1)?;
+        write_scalar(&num_features, &self.num_features);
+
+        if !self.boundaries.is_empty() {
+            let boundaries = transform.new_dataset::<PrimitiveType>().create("boundaries", self.boundaries.len())?;
+            boundaries.write(&self.boundaries[..])?;
+        }
+        if let FeatureTransform::Pca { whiten, .. } = self.transform {
+            let whiten_ds = transform.new_dataset::<bool>().create("whiten", 1)?;
+            write_scalar(&whiten_ds, &whiten);
+            let mean = transform.new_dataset::<PrimitiveType>().create("mean", self.mean.len())?;
+            mean.write(&self.mean[..])?;
+            let projection = transform.new_dataset::<PrimitiveType>().create("projection", self.projection.len())?;
+            projection.write(&self.projection[..])?;
+        }
+        if let FeatureTransform::TargetEncoding { smoothing, .. } = self.transform {
+            let smoothing_ds = transform.new_dataset::<PrimitiveType>().create("smoothing", 1)?;
+            write_scalar(&smoothing_ds, &smoothing);
+            let categories = transform.new_dataset::<PrimitiveType>().create("categories", self.categories.len())?;
+            categories.write(&self.categories[..])?;
+            let encodings = transform.new_dataset::<PrimitiveType>().create("encodings", self.encodings.len())?;
+            encodings.write(&self.encodings[..])?;
+            let prior = transform.new_dataset::<PrimitiveType>().create("prior", self.prior.len())?;
+            prior.write(&self.prior[..])?;
+        }
+        Ok(())
+    }
+
+    /// Loads a transform saved with [save](#method.save).
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> FittedTransform {
+        let kind = group.dataset("kind").map(|ds| read_scalar::<u8>(&ds)).expect("Could not retrieve the kind of transform.");
+        let column = group.dataset("column").map(|ds| read_scalar::<u64>(&ds)).unwrap_or(0);
+        let degree = group.dataset("degree").map(|ds| read_scalar::<u32>(&ds)).unwrap_or(0);
+        let num_buckets = group.dataset("num_buckets").map(|ds| read_scalar::<u64>(&ds)).expect("Could not retrieve the number of buckets.");
+        let num_features = group.dataset("num_features").map(|ds| read_scalar::<u64>(&ds)).expect("Could not retrieve the number of features.");
+        let boundaries = group.dataset("boundaries").and_then(|ds| ds.read_raw::<PrimitiveType>()).unwrap_or_default();
+        let whiten = group.dataset("whiten").map(|ds| read_scalar::<bool>(&ds)).unwrap_or(false);
+        let mean = group.dataset("mean").and_then(|ds| ds.read_raw::<PrimitiveType>()).unwrap_or_default();
+        let projection = group.dataset("projection").and_then(|ds| ds.read_raw::<PrimitiveType>()).unwrap_or_default();
+        let smoothing = group.dataset("smoothing").map(|ds| read_scalar::<PrimitiveType>(&ds)).unwrap_or(0.);
+        let categories = group.dataset("categories").and_then(|ds| ds.read_raw::<PrimitiveType>()).unwrap_or_default();
+        let encodings = group.dataset("encodings").and_then(|ds| ds.read_raw::<PrimitiveType>()).unwrap_or_default();
+        let prior = group.dataset("prior").and_then(|ds| ds.read_raw::<PrimitiveType>()).unwrap_or_default();
+
+        let transform = match kind {
+            0 => FeatureTransform::Log { column },
+            1 => FeatureTransform::QuantileBuckets { column, num_buckets },
+            2 => FeatureTransform::Hash { column, num_buckets },
+            3 => FeatureTransform::Polynomial { degree },
+            4 => FeatureTransform::Pca { num_components: num_buckets, whiten },
+            5 => FeatureTransform::TargetEncoding { column, num_folds: num_buckets, smoothing },
+            _ => panic!("Unknown transform."),
+        };
+        FittedTransform { transform, num_features, boundaries, mean, projection, categories, encodings, prior }
+    }
+}
+
+/// Returns the values of a column, in a tensor of shape [1, 1, 1, batch size].
+fn column_values(x: &Tensor, column: u64) -> Tensor {
+    index(x, &[Seq::new(column as f64, column as f64, 1.), Seq::default(), Seq::default(), Seq::default()])
+}
+
+/// Computes the mean of the features and the projection on their principal components.
+///
+/// The principal components are the eigenvectors of the covariance matrix of the features, obtained with a singular
+/// value decomposition since the matrix is symmetric positive semi-definite. The singular values are the variances
+/// along the components.
+fn principal_components(x: &Tensor, num_components: u64, whiten: bool) -> (Vec<PrimitiveType>, Vec<PrimitiveType>) {
+    let num_features = x.dims()[0];
+    let batch_size = x.batch_size();
+    let samples = moddims(x, Dim::new(&[num_features, batch_size, 1, 1]));
+    let mean = mean(&samples, 1);
+    let centered = sub(&samples, &mean, true);
+    let covariance = div(&matmul(&centered, &centered, MatProp::NONE, MatProp::TRANS), &(batch_size.max(2) as PrimitiveType - 1.), false);
+
+    let (u, s, _) = svd(&covariance);
+    let components = Seq::new(0., (num_components - 1) as f64, 1.);
+    let mut projection = transpose(&index(&u, &[Seq::default(), components]), false);
+    if whiten {
+        let variances = index(&s, &[components]);
+        projection = div(&projection, &sqrt(&add(&variances, &(1e-8 as PrimitiveType), false)), true);
+    }
+    (host_values(&mean), host_values(&projection))
+}
+
+/// Returns the key identifying a category, such that NaN values form a category.
+fn category_key(value: PrimitiveType) -> u64 {
+    (value as f64).to_bits()
+}
+
+/// Returns the mean of the labels of a category, smoothed towards the prior with a weight of `smoothing` samples.
+fn smoothed_mean(sum: PrimitiveType, count: usize, prior: PrimitiveType, smoothing: PrimitiveType) -> PrimitiveType {
+    if count as PrimitiveType + smoothing > 0. {
+        (sum + smoothing * prior) / (count as PrimitiveType + smoothing)
+    } else {
+        prior
+    }
+}
+
+/// Computes the target encoding of a categorical column.
+///
+/// # Return value
+///
+/// The categories in order of appearance, the smoothed means of the labels of each category (stored category by
+/// category) and the means of all the labels.
+fn target_encoding(values: &[PrimitiveType], labels: &[PrimitiveType], num_outputs: usize, smoothing: PrimitiveType)
+    -> (Vec<PrimitiveType>, Vec<PrimitiveType>, Vec<PrimitiveType>)
+{
+    let mut categories: Vec<PrimitiveType> = Vec::new();
+    let mut indices: HashMap<u64, usize> = HashMap::new();
+    let mut sums: Vec<PrimitiveType> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    let mut prior = vec![0 as PrimitiveType; num_outputs];
+
+    for (&value, sample_labels) in values.iter().zip(labels.chunks(num_outputs)) {
+        let category = *indices.entry(category_key(value)).or_insert_with(|| {
+            categories.push(value);
+            sums.extend(std::iter::repeat(0.).take(num_outputs));
+            counts.push(0);
+            counts.len() - 1
+        });
+        counts[category] += 1;
+        for (output, &label) in sample_labels.iter().enumerate() {
+            sums[category * num_outputs + output] += label;
+            prior[output] += label;
+        }
+    }
+    prior.iter_mut().for_each(|value| *value /= values.len() as PrimitiveType);
+
+    let encodings = counts.iter().enumerate()
+        .flat_map(|(category, &count)| {
+            let sums = &sums;
+            let prior = &prior;
+            (0..num_outputs).map(move |output| smoothed_mean(sums[category * num_outputs + output], count, prior[output], smoothing))
+        })
+        .collect();
+    (categories, encodings, prior)
+}
+
+/// Encodes the training samples of each fold with the target encoding computed on the other folds.
+///
+/// The sample `i` belongs to the fold `i % num_folds`, which yields random folds since the training samples are
+/// shuffled. The encodings are stored sample by sample.
+fn out_of_fold_encoding(values: &[PrimitiveType], labels: &[PrimitiveType], num_outputs: usize, num_folds: u64, smoothing: PrimitiveType)
+    -> Vec<PrimitiveType>
+{
+    let num_folds = num_folds as usize;
+    let mut indices: HashMap<u64, usize> = HashMap::new();
+    let categories: Vec<usize> = values.iter().map(|&value| {
+        let num_categories = indices.len();
+        *indices.entry(category_key(value)).or_insert(num_categories)
+    }).collect();
+    let num_categories = indices.len();
+
+    // Sums and counts of the labels per category and fold, and per fold
+    let mut sums = vec![0 as PrimitiveType; num_categories * num_folds * num_outputs];
+    let mut counts = vec![0usize; num_categories * num_folds];
+    let mut fold_sums = vec![0 as PrimitiveType; num_folds * num_outputs];
+    let mut fold_counts = vec![0usize; num_folds];
+    for (i, (&category, sample_labels)) in categories.iter().zip(labels.chunks(num_outputs)).enumerate() {
+        let fold = i % num_folds;
+        counts[category * num_folds + fold] += 1;
+        fold_counts[fold] += 1;
+        for (output, &label) in sample_labels.iter().enumerate() {
+            sums[(category * num_folds + fold) * num_outputs + output] += label;
+            fold_sums[fold * num_outputs + output] += label;
+        }
+    }
+
+    let mut encoded = Vec::with_capacity(values.len() * num_outputs);
+    for (i, &category) in categories.iter().enumerate() {
+        let fold = i % num_folds;
+        let count = (0..num_folds).filter(|&f| f != fold).map(|f| counts[category * num_folds + f]).sum();
+        let num_samples = values.len() - fold_counts[fold];
+        for output in 0..num_outputs {
+            let other_folds = (0..num_folds).filter(|&f| f != fold);
+            let sum = other_folds.clone().map(|f| sums[(category * num_folds + f) * num_outputs + output]).sum();
+            let prior = other_folds.map(|f| fold_sums[f * num_outputs + output]).sum::<PrimitiveType>() / num_samples as PrimitiveType;
+            encoded.push(smoothed_mean(sum, count, prior, smoothing));
+        }
+    }
+    encoded
+}
+
+/// Appends the powers of the features from 2 to the degree and the pairwise products of the features.
+///
+/// The powers are ordered by degree, and the products of the features i and j follow the order (0, 1), (0, 2), ...,
+/// (1, 2), ...
+fn polynomial_features(x: &Tensor, degree: u32) -> Tensor {
+    let num_features = x.dims()[0];
+    let mut result = x.copy();
+    for power in 2..=degree {
+        result = join(0, &result, &pow(x, &(power as PrimitiveType), false));
+    }
+    if num_features > 1 {
+        let (first, second): (Vec<u32>, Vec<u32>) = (0..num_features as u32)
+            .flat_map(|i| (i + 1..num_features as u32).map(move |j| (i, j)))
+            .unzip();
+        let num_pairs = first.len() as u64;
+        let first = Array::new(&first[..], Dim4::new(&[num_pairs, 1, 1, 1]));
+        let second = Array::new(&second[..], Dim4::new(&[num_pairs, 1, 1, 1]));
+        let interactions = mul(&lookup(x, &first, 0), &lookup(x, &second, 0), false);
+        result = join(0, &result, &interactions);
+    }
+    result
+}
+
+/// Replaces a column by the rows of another tensor.
+fn replace_column(x: &Tensor, column: u64, values: &Tensor) -> Tensor {
+    let num_features = x.dims()[0];
+    let mut result = values.copy();
+    if column > 0 {
+        let before = index(x, &[Seq::new(0., (column - 1) as f64, 1.), Seq::default(), Seq::default(), Seq::default()]);
+        result = join(0, &before, &result);
+    }
+    if column + 1 < num_features {
+        let after = index(x, &[Seq::new((column + 1) as f64, (num_features - 1) as f64, 1.), Seq::default(), Seq::default(), Seq::default()]);
+        result = join(0, &result, &after);
+    }
+    result
+}
+
+/// Copies the values of a tensor to the host.
+fn host_values(tensor: &Tensor) -> Vec<PrimitiveType> {
+    let mut values = vec![0 as PrimitiveType; tensor.elements()];
+    tensor.host(&mut values);
+    values
+}
+
+/// Hashes a value with the finalizer of SplitMix64, such that the buckets don't depend on the primitive type.
+fn hash(value: PrimitiveType) -> u64 {
+    let mut z = (value as f64).to_bits().wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::assert_approx_eq;
+    use crate::data::transforms::*;
+    use crate::layers::Layer;
+
+    #[test]
+    fn test_feature_transforms() {
+        let x = Tensor::new(&[1., 0., 2., 10., 3., 20., 4., 10.], Dim4::new(&[2, 1, 1, 4]));
+
+        let log = FittedTransform::fit(FeatureTransform::Log { column: 1 }, &x).unwrap();
+        let mut output = [0 as PrimitiveType; 8];
+        log.apply(&x).host(&mut output);
+        assert_approx_eq!(output, [1., 0., 2., (11 as PrimitiveType).ln(), 3., (21 as PrimitiveType).ln(), 4., (11 as PrimitiveType).ln()]);
+
+        let buckets = FittedTransform::fit(FeatureTransform::QuantileBuckets { column: 0, num_buckets: 2 }, &x).unwrap();
+        assert_approx_eq!(buckets.boundaries(), [3.]);
+        buckets.apply(&x).host(&mut output);
+        assert_approx_eq!(output, [0., 0., 0., 10., 1., 20., 1., 10.]);
+
+        let hashing = FittedTransform::fit(FeatureTransform::Hash { column: 1, num_buckets: 3 }, &x).unwrap();
+        assert_eq!(hashing.num_output_features(), 4);
+        let hashed = hashing.apply(&x);
+        assert_eq!(hashed.dims().get(), &[4, 1, 1, 4]);
+        let mut output = [0 as PrimitiveType; 16];
+        hashed.host(&mut output);
+        // Each sample has a single active bucket and the samples with the same category share it
+        for sample in output.chunks(4) {
+            assert_approx_eq!([sample[1] + sample[2] + sample[3]], [1.]);
+        }
+        assert_eq!(output[5..8], output[13..16]);
+
+        assert!(FittedTransform::fit(FeatureTransform::Log { column: 2 }, &x).is_err());
+    }
+
+    #[test]
+    fn test_polynomial_features() {
+        let x = Tensor::new(&[1., 2., 3., -1., 0., 2.], Dim4::new(&[3, 1, 1, 2]));
+        let polynomial = FittedTransform::fit(FeatureTransform::Polynomial { degree: 3 }, &x).unwrap();
+        assert_eq!(polynomial.num_output_features(), 12);
+        let expanded = polynomial.apply(&x);
+        assert_eq!(expanded.dims().get(), &[12, 1, 1, 2]);
+        let mut output = [0 as PrimitiveType; 24];
+        expanded.host(&mut output);
+        assert_approx_eq!(output, [1., 2., 3., 1., 4., 9., 1., 8., 27., 2., 3., 6.,
+                                   -1., 0., 2., 1., 0., 4., -1., 0., 8., 0., -2., 0.]);
+
+        assert!(FittedTransform::fit(FeatureTransform::Polynomial { degree: 1 }, &x).is_err());
+    }
+
+    #[test]
+    fn test_pca() {
+        // Samples along the line y = 2x, whose first principal component is (1, 2) / sqrt(5)
+        let x = Tensor::new(&[-2., -4., -1., -2., 0., 0., 1., 2., 2., 4.], Dim4::new(&[2, 1, 1, 5]));
+        let pca = FittedTransform::fit(FeatureTransform::Pca { num_components: 1, whiten: false }, &x).unwrap();
+        let projected = pca.apply(&x);
+        assert_eq!(projected.dims().get(), &[1, 1, 1, 5]);
+        let mut output = [0 as PrimitiveType; 5];
+        projected.host(&mut output);
+        // The sign of the component is arbitrary
+        let norm = (5 as PrimitiveType).sqrt();
+        let sign = output[4].signum();
+        for (value, expected) in output.iter().zip([-2., -1., 0., 1., 2.].iter()) {
+            assert!((value - sign * expected * norm).abs() < 1e-4);
+        }
+
+        // The projection layer computes the same values
+        let layer = pca.to_layer().unwrap();
+        let mut layer_output = [0 as PrimitiveType; 5];
+        layer.compute_activation(&x).host(&mut layer_output);
+        for (value, expected) in layer_output.iter().zip(output.iter()) {
+            assert!((value - expected).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_target_encoding() {
+        let x = Tensor::new(&[7., 7., 8., 8., 7., 7., 8., 8.], Dim4::new(&[1, 1, 1, 8]));
+        let y = Tensor::new(&[1., 0., 0., 0., 1., 1., 0., 1.], Dim4::new(&[1, 1, 1, 8]));
+        let transform = FeatureTransform::TargetEncoding { column: 0, num_folds: 2, smoothing: 0. };
+        let (encoder, x_train) = FittedTransform::fit_transform(transform, &x, &y).unwrap();
+
+        // The training samples are encoded with the labels of the other fold only
+        let mut output = [0 as PrimitiveType; 8];
+        x_train.host(&mut output);
+        assert_approx_eq!(output, [0.5, 1., 0.5, 0., 0.5, 1., 0.5, 0.]);
+
+        // The other samples are encoded with all the labels, and the unseen categories with the mean of the labels
+        let x_test = Tensor::new(&[7., 8., 9.], Dim4::new(&[1, 1, 1, 3]));
+        let mut output = [0 as PrimitiveType; 3];
+        encoder.apply(&x_test).host(&mut output);
+        assert_approx_eq!(output, [0.75, 0.25, 0.5]);
+
+        assert!(FittedTransform::fit(transform, &x).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! Monitoring of the memory of the device.
+//!
+//! ArrayFire keeps the buffers released by the tensors in a cache in order to reuse them for subsequent allocations.
+//! The functions of this module report how much memory is allocated on the active device and allow to limit the size
+//! of the cache.
+use arrayfire::*;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Peak memory in use recorded since the last reset.
+static PEAK_MEMORY: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximum number of bytes kept in the cache of unused buffers (0 if unlimited).
+static MAX_CACHED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Memory usage of the active device.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MemoryInfo {
+    /// Number of bytes allocated by ArrayFire, including the cached buffers.
+    pub allocated_bytes: usize,
+    /// Number of buffers allocated by ArrayFire, including the cached buffers.
+    pub allocated_buffers: usize,
+    /// Number of bytes used by live tensors.
+    pub locked_bytes: usize,
+    /// Number of buffers used by live tensors.
+    pub locked_buffers: usize,
+}
+
+impl MemoryInfo {
+    /// Returns the number of bytes held in the cache of unused buffers.
+    pub fn cached_bytes(&self) -> usize {
+        self.allocated_bytes.saturating_sub(self.locked_bytes)
+    }
+}
+
+impl fmt::Display for MemoryInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "allocated: {} ({} buffers), in use: {} ({} buffers), cached: {}",
+               format_bytes(self.allocated_bytes),
+               self.allocated_buffers,
+               format_bytes(self.locked_bytes),
+               self.locked_buffers,
+               format_bytes(self.cached_bytes()))
+    }
+}
+
+/// Returns the memory usage of the active device.
+pub fn memory_info() -> MemoryInfo {
+    let (allocated_bytes, allocated_buffers, locked_bytes, locked_buffers) = device_mem_info();
+    MemoryInfo {
+        allocated_bytes,
+        allocated_buffers,
+        locked_bytes,
+        locked_buffers,
+    }
+}
+
+/// Returns the peak number of bytes allocated on the device since the last call to
+/// [reset_peak_memory](fn.reset_peak_memory.html).
+///
+/// The peak is updated after each mini-batch during the training.
+pub fn peak_memory() -> usize {
+    PEAK_MEMORY.load(Ordering::Relaxed)
+}
+
+/// Resets the peak memory.
+pub fn reset_peak_memory() {
+    PEAK_MEMORY.store(0, Ordering::Relaxed);
+}
+
+/// Limits the number of bytes kept in the cache of unused buffers.
+///
+/// When the limit is exceeded, the unused buffers are released after the current mini-batch. Setting a limit reduces
+/// the risk of running out of memory on the device at the cost of more allocations. Use `None` to remove the limit.
+pub fn set_max_cached_bytes(limit: Option<usize>) {
+    MAX_CACHED_BYTES.store(limit.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Releases the unused buffers held in the cache.
+pub fn free_cached_memory() {
+    device_gc();
+}
+
+/// Records the current memory usage and releases the cached buffers if their size exceeds the limit.
+pub(crate) fn track_memory() {
+    let info = memory_info();
+    PEAK_MEMORY.fetch_max(info.allocated_bytes, Ordering::Relaxed);
+
+    let max_cached_bytes = MAX_CACHED_BYTES.load(Ordering::Relaxed);
+    if max_cached_bytes > 0 && info.cached_bytes() > max_cached_bytes {
+        device_gc();
+    }
+}
+
+/// Formats a number of bytes in a human readable form.
+pub(crate) fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::device::format_bytes;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512.0 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+}
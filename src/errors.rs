@@ -6,25 +6,81 @@ use crate::data;
 
 #[derive(Debug)]
 pub enum Error {
+    ChecksumMismatch(String),
+    CommunicationError(String),
     DataSetError(data::DataSetError),
+    DownloadError(String),
     HDF5Error(hdf5::Error),
+    HeadLabelsMismatch,
+    IncompatibleLoss(String),
+    InputShapeMismatch,
+    InvalidAxis(usize),
+    InvalidCompressionLevel(u8),
+    InvalidDeltas(String),
+    InvalidFitConfig(String),
     InvalidInputShape,
+    InvalidLabels(String),
+    InvalidLayerIndex(usize),
+    InvalidLayerShape(usize, String),
     InvalidOutputShape,
+    InvalidTensorBytes(String),
+    InvalidWeightSharing,
+    LayerMismatch(usize),
+    MaskShapeMismatch,
+    NoBestParameters,
+    NoCheckpoint,
+    NoIntegrationStep,
     NoLayer,
+    NoTensor,
+    OutputShapeMismatch,
+    ParameterShapeMismatch,
+    ServingError(String),
+    SparseInputNotSupported,
+    TensorShapeMismatch(String),
+    UnknownHyperparameter(String),
     UnknownLayer,
     UnknownOptimizer,
+    UnsupportedLayer(String),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::ChecksumMismatch(ref url) => write!(f, "The checksum of the file downloaded from {} doesn't match the expected checksum.", url),
+            Error::CommunicationError(ref err) => write!(f, "CommunicationError: {}", err),
             Error::DataSetError(ref err) => write!(f, "DataSetError: {}", err),
+            Error::DownloadError(ref err) => write!(f, "DownloadError: {}", err),
             Error::HDF5Error(ref err) => write!(f, "HDF5Error: {}", err),
+            Error::HeadLabelsMismatch => write!(f, "The number of head labels of the data set does not match the number of heads of the network."),
+            Error::IncompatibleLoss(ref reason) => write!(f, "The loss function is incompatible with the output of the network: {}", reason),
+            Error::InputShapeMismatch => write!(f, "The shape of the samples does not match the input shape of the network."),
+            Error::InvalidAxis(axis) => write!(f, "The tensors have four dimensions, numbered from 0 to 3, but the dimension {} was given.", axis),
+            Error::InvalidCompressionLevel(level) => write!(f, "The compression level must be between 1 and 9, got {}.", level),
+            Error::InvalidDeltas(ref reason) => write!(f, "The parameter deltas can't be aggregated: {}.", reason),
+            Error::InvalidFitConfig(ref err) => write!(f, "InvalidFitConfig: {}", err),
             Error::InvalidInputShape => write!(f, "The input shape of the network must be a slice with 1, 2, or 3 elements."),
+            Error::InvalidLabels(ref reason) => write!(f, "The labels are invalid: {}.", reason),
+            Error::InvalidLayerIndex(index) => write!(f, "The network doesn't contain any layer at index {}.", index),
+            Error::InvalidLayerShape(index, ref name) => write!(f, "The output shape of the layer {} ({}) is invalid. The layer is incompatible with the shape of its input.", index, name),
             Error::InvalidOutputShape => write!(f, "The output shape of the network is invalid."),
+            Error::InvalidTensorBytes(ref reason) => write!(f, "The bytes don't contain a valid tensor: {}.", reason),
+            Error::InvalidWeightSharing => write!(f, "The weights cannot be shared. The layers must be distinct, have weights, and the dimensions of the weights must match."),
+            Error::LayerMismatch(index) => write!(f, "The layer {} doesn't match the corresponding layer of the saved model.", index),
+            Error::MaskShapeMismatch => write!(f, "The masked timesteps must be along the first axis of the output of the network."),
+            Error::NoBestParameters => write!(f, "No best parameters have been recorded. The training must be configured with a monitor."),
+            Error::NoCheckpoint => write!(f, "At least one checkpoint must be given."),
+            Error::NoIntegrationStep => write!(f, "The number of integration steps must be greater than zero."),
             Error::NoLayer => write!(f, "The network doesn't contain any layer."),
+            Error::NoTensor => write!(f, "At least one tensor must be given."),
+            Error::OutputShapeMismatch => write!(f, "The shape of the labels does not match the output shape of the network."),
+            Error::ParameterShapeMismatch => write!(f, "The tensors don't match the shapes of the trainable parameters of the network."),
+            Error::ServingError(ref err) => write!(f, "ServingError: {}", err),
+            Error::SparseInputNotSupported => write!(f, "The first layer of the network doesn't accept sparse inputs."),
+            Error::TensorShapeMismatch(ref reason) => write!(f, "The shapes of the tensors don't match: {}.", reason),
+            Error::UnknownHyperparameter(ref name) => write!(f, "The optimizer doesn't have any hyperparameter named {}.", name),
             Error::UnknownLayer => write!(f, "The type of layer is unknown."),
             Error::UnknownOptimizer => write!(f, "The type of optimizer is unknown."),
+            Error::UnsupportedLayer(ref name) => write!(f, "The layer {} is not supported by the CPU backend.", name),
         }
     }
 }
@@ -8,17 +8,17 @@ use crate::tensor::*;
 ///
 /// # Arguments
 ///
-/// * `file` - The file in which  the group is created.
+/// * `parent` - The file or the group in which the group is created.
 /// * `group_name` - The name of the group.
-pub(crate) fn create_group(file: &hdf5::File, group_name: &str) -> hdf5::Group {
+pub(crate) fn create_group(parent: &hdf5::Group, group_name: &str) -> hdf5::Group {
     let name = CString::new(group_name).unwrap();
     unsafe {
         let lcpl = hdf5_sys::h5p::H5Pcreate(*H5P_CLS_LINK_CREATE);
         let gcpl = hdf5_sys::h5p::H5Pcreate(*H5P_CLS_GROUP_CREATE);
         hdf5_sys::h5p::H5Pset_link_creation_order(gcpl, H5P_CRT_ORDER_TRACKED | H5P_CRT_ORDER_INDEXED);
-        hdf5_sys::h5g::H5Gcreate2(file.id(), name.as_ptr(), lcpl, gcpl, H5P_DEFAULT);
+        hdf5_sys::h5g::H5Gcreate2(parent.id(), name.as_ptr(), lcpl, gcpl, H5P_DEFAULT);
     }
-    file.group(group_name).expect("Could not create the group.")
+    parent.group(group_name).expect("Could not create the group.")
 
 }
 
@@ -78,4 +78,147 @@ pub(crate) fn save_vec_tensor(group: &hdf5::Group,
     let ds = group.new_dataset::<H5Tensor>().create(name, slice.len())?;
     ds.write(values.as_slice())?;
     Ok(())
-}
\ No newline at end of file
+}
+/// Returns the precision of the tensors stored in the file containing the group.
+///
+/// Files without any precision flag store single-precision tensors.
+fn storage_precision(group: &hdf5::Group) -> StoragePrecision {
+    group.file()
+        .and_then(|file| file.dataset("precision"))
+        .map(|ds| read_scalar::<StoragePrecision>(&ds))
+        .unwrap_or(StoragePrecision::Single)
+}
+
+/// Returns the gzip compression level of the tensors stored in the file containing the group, if any.
+fn storage_compression(group: &hdf5::Group) -> Option<u8> {
+    group.file()
+        .and_then(|file| file.dataset("compression"))
+        .map(|ds| read_scalar::<u8>(&ds))
+        .ok()
+        .filter(|&level| level > 0)
+}
+
+/// Writes the values of a tensor in a flat dataset, compressed with gzip if a level is given.
+fn write_values<T: hdf5::H5Type>(group: &hdf5::Group, name: &str, values: &[T], compression: Option<u8>) -> hdf5::Result<()> {
+    let mut builder = group.new_dataset::<T>();
+    if let Some(level) = compression {
+        builder.gzip(level).chunk(std::cmp::max(values.len(), 1));
+    }
+    let dataset = builder.create(name, values.len())?;
+    dataset.write(values)
+}
+
+/// Writes a tensor in a dataset of the group with the precision and compression of the file.
+///
+/// Uncompressed single-precision tensors are stored as a `H5Tensor`. Otherwise, the values are stored as a flat array,
+/// in the order of the host buffer, and the dimensions of the tensor are stored in the dataset `{name}_dims`. Variable
+/// length data cannot be compressed by HDF5, hence the flat layout of the compressed tensors.
+pub(crate) fn write_tensor(group: &hdf5::Group, name: &str, tensor: &Tensor) -> hdf5::Result<()> {
+    let compression = storage_compression(group);
+    let precision = storage_precision(group);
+    if precision == StoragePrecision::Single && compression.is_none() {
+        let dataset = group.new_dataset::<H5Tensor>().create(name, 1)?;
+        return dataset.write(&[H5Tensor::from(tensor)]);
+    }
+
+    let mut buffer = vec![0 as PrimitiveType; tensor.elements()];
+    tensor.host(&mut buffer);
+    match precision {
+        StoragePrecision::Single => write_values(group, name, &buffer[..], compression)?,
+        StoragePrecision::Half => {
+            let values: Vec<u16> = buffer.iter().map(|&value| f32_to_f16(value as f32)).collect();
+            write_values(group, name, &values[..], compression)?
+        },
+    }
+    let dims = group.new_dataset::<[u64; 4]>().create(&format!("{}_dims", name), 1)?;
+    dims.write(&[*tensor.dims().get()])
+}
+
+/// Reads a tensor written by [write_tensor](fn.write_tensor.html).
+pub(crate) fn read_tensor(group: &hdf5::Group, name: &str) -> hdf5::Result<Tensor> {
+    let dataset = group.dataset(name)?;
+    let precision = storage_precision(group);
+    if precision == StoragePrecision::Single && storage_compression(group).is_none() {
+        return dataset.read_raw::<H5Tensor>().map(|values| Tensor::from(&values[0]));
+    }
+
+    let dims = group.dataset(&format!("{}_dims", name))?.read_raw::<[u64; 4]>()?;
+    let values: Vec<PrimitiveType> = match precision {
+        StoragePrecision::Single => dataset.read_raw::<PrimitiveType>()?,
+        StoragePrecision::Half => dataset.read_raw::<u16>()?.iter().map(|&value| f16_to_f32(value) as PrimitiveType).collect(),
+    };
+    Ok(Tensor::new(&values[..], Dim::new(&dims[0])))
+}
+
+/// Converts a single-precision value to the bits of the nearest half-precision value, rounding ties to even.
+pub(crate) fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x007f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x0200 } else { 0 };
+    }
+
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        // Overflow to infinity
+        return sign | 0x7c00;
+    }
+
+    // The rounding bit is set and either the sticky bits or the last kept bit are set
+    let round = |value: u32, shift: u32| {
+        let round_bit = 1 << (shift - 1);
+        let rounded = value >> shift;
+        if value & round_bit != 0 && value & (3 * round_bit - 1) != 0 { rounded + 1 } else { rounded }
+    };
+
+    if half_exponent <= 0 {
+        // Subnormal values and underflow to zero
+        if half_exponent < -10 {
+            return sign;
+        }
+        return sign | round(mantissa | 0x0080_0000, (14 - half_exponent) as u32) as u16;
+    }
+    // A carry of the rounding propagates to the exponent
+    sign | (((half_exponent as u32) << 10) + round(mantissa, 13)) as u16
+}
+
+/// Converts the bits of a half-precision value to a single-precision value.
+pub(crate) fn f16_to_f32(half: u16) -> f32 {
+    let negative = half & 0x8000 != 0;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x03ff) as u32;
+    let sign = if negative { 0x8000_0000 } else { 0 };
+
+    match exponent {
+        0 => {
+            // Zero and subnormal values
+            let value = mantissa as f32 / 16_777_216.;
+            if negative { -value } else { value }
+        },
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::io::{f16_to_f32, f32_to_f16};
+
+    #[test]
+    fn test_half_precision_conversion() {
+        let values: [f32; 8] = [0., -2., 0.5, 65504., 1e-7, 3.14159, 1e6, -0.1];
+        let halves: Vec<u16> = values.iter().map(|&value| f32_to_f16(value)).collect();
+        assert_eq!(halves, vec![0x0000, 0xc000, 0x3800, 0x7bff, 0x0002, 0x4248, 0x7c00, 0xae66]);
+
+        let restored: Vec<f32> = halves.iter().map(|&half| f16_to_f32(half)).collect();
+        assert_eq!(restored[..4], values[..4]);
+        assert!((restored[5] - 3.140625).abs() < 1e-7);
+        assert_eq!(restored[6], f32::INFINITY);
+        assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+    }
+}
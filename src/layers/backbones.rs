@@ -0,0 +1,55 @@
+//! Pre-configured feature extractors for images.
+//!
+//! A backbone turns the images into feature maps, on top of which a head, for instance one of the
+//! [heads](../heads/index.html), computes the outputs. The layers are returned in order and added with
+//! [Network::add_all](../../models/struct.Network.html#method.add_all).
+use super::{Conv2D, Layer, MaxPool2D, Padding};
+
+/// Creates a simple convolutional backbone.
+///
+/// Each block stacks two 3x3 convolutions with a ReLU activation, followed by a 2x2 max pooling which halves the height
+/// and width of the feature maps. The first block has 32 filters and each following block doubles the number of
+/// filters. The images must therefore be at least 2^depth pixels high and wide.
+///
+/// # Arguments
+///
+/// * `depth` - The number of blocks.
+///
+/// # Panics
+///
+/// Panics if the depth is zero.
+pub fn simple_cnn(depth: u64) -> Vec<Box<dyn Layer>> {
+    assert!(depth > 0, "The depth must be greater than zero.");
+    let mut layers: Vec<Box<dyn Layer>> = Vec::with_capacity(3 * depth as usize);
+    for block in 0..depth {
+        let num_filters = 32 << block;
+        layers.push(Conv2D::new(num_filters, (3, 3), (1, 1), Padding::Same));
+        layers.push(Conv2D::new(num_filters, (3, 3), (1, 1), Padding::Same));
+        layers.push(MaxPool2D::new((2, 2)));
+    }
+    layers
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::layers::backbones::simple_cnn;
+    use crate::layers::heads::classification;
+    use crate::losses::SoftmaxCrossEntropy;
+    use crate::models::NetworkBuilder;
+    use crate::optimizers::SGD;
+
+    #[test]
+    fn test_simple_cnn_with_classification_head() {
+        assert_eq!(simple_cnn(2).len(), 6);
+        assert_eq!(classification(10, 0.).len(), 2);
+
+        let nn = NetworkBuilder::new(Dim4::new(&[8, 8, 3, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1))
+            .add_all(simple_cnn(2))
+            .add_all(classification(10, 0.5))
+            .build()
+            .unwrap();
+        assert_eq!(nn.output_shape().get(), &[10, 1, 1, 1]);
+    }
+}
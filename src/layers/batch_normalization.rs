@@ -3,14 +3,22 @@
 use arrayfire::*;
 use std::fmt;
 
+use crate::cpu::{CpuLayer, CpuTensor};
 use crate::errors::Error;
-use crate::io::{write_scalar, read_scalar};
+use crate::io::{read_scalar, read_tensor, write_scalar, write_tensor};
 use crate::tensor::*;
 use super::Layer;
 
 /// Defines a batch normalization layer.
+///
+/// The statistics are computed for each index along the feature axis, over all the other axes of the mini-batch.
+/// By default, the feature axis is the channels axis (2) for images and the first axis (0) for one-dimensional
+/// samples.
+#[derive(Clone)]
 pub struct BatchNorm {
-    follow_conv2d: bool,
+    axis: Option<usize>,
+    frozen_statistics: bool,
+    training: bool,
     mb_mean: Tensor,
     mb_variance: Tensor,
     mean: Tensor,
@@ -35,7 +43,9 @@ impl BatchNorm {
     /// used for numerical stability to 1e-5.
     pub fn new() -> Box<BatchNorm> {
         Box::new(BatchNorm {
-            follow_conv2d: false,
+            axis: None,
+            frozen_statistics: false,
+            training: true,
             mb_mean: Tensor::new_empty_tensor(),
             mb_variance: Tensor::new_empty_tensor(),
             mean: Tensor::new_empty_tensor(),
@@ -51,7 +61,9 @@ impl BatchNorm {
         })
     }
 
-    /// Creates a batch normalization layers with the given momentum.
+    /// Creates a batch normalization layers with the given momentum and epsilon value.
+    ///
+    /// The running averages are updated as `running = momentum * running + (1 - momentum) * mini_batch`.
     ///
     /// # Arguments
     ///
@@ -59,7 +71,9 @@ impl BatchNorm {
     /// * `eps` - A small constant used for numerical stability.
     pub fn with_param(momentum: PrimitiveType, eps: PrimitiveType) -> Box<BatchNorm> {
         Box::new(BatchNorm {
-            follow_conv2d: false,
+            axis: None,
+            frozen_statistics: false,
+            training: true,
             mb_mean: Tensor::new_empty_tensor(),
             mb_variance: Tensor::new_empty_tensor(),
             mean: Tensor::new_empty_tensor(),
@@ -75,6 +89,26 @@ impl BatchNorm {
         })
     }
 
+    /// Sets the feature axis along which the statistics are computed.
+    ///
+    /// Use 0 for the outputs of dense layers, with dimensions [units, 1, 1, batch_size], and 2 for the outputs of
+    /// convolutional layers, with dimensions [height, width, channels, batch_size]. The axis must be set before the
+    /// layer is added to a network.
+    pub fn set_axis(&mut self, axis: usize) {
+        assert!(axis < 3, "The axis must be 0, 1, or 2.");
+        self.axis = Some(axis);
+    }
+
+    /// Returns the momentum used by the running averages.
+    pub fn momentum(&self) -> PrimitiveType {
+        self.momentum
+    }
+
+    /// Returns the small constant used for numerical stability.
+    pub fn eps(&self) -> PrimitiveType {
+        self.eps
+    }
+
     /// Returns the current estimate of the dataset mean.
     pub fn mean(&self) -> Tensor {
         self.mean.copy()
@@ -88,33 +122,80 @@ impl BatchNorm {
     /// Creates a BatchNorm layer from an HDF5 group.
     pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Self> {
         let _ = hdf5::silence_errors();
-        let follow_conv2d = group.dataset("follow_conv2d").and_then(|ds| Ok(read_scalar::<bool>(&ds))).expect("Could not retrieve follow_conv2d.");
-        let mb_mean = group.dataset("mb_mean").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the mini-batch mean.");
-        let mb_variance = group.dataset("mb_variance").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the mini-batch variance.");
-        let mean = group.dataset("mean").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the mean.");
-        let variance = group.dataset("variance").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the variance.");
-        let gamma = group.dataset("gamma").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the gamma values.");
-        let beta = group.dataset("beta").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the beta values.");
+        // Older files only store whether the statistics were computed along the channels
+        let axis = group.dataset("axis").and_then(|ds| Ok(read_scalar::<u64>(&ds) as usize))
+            .or_else(|_| group.dataset("follow_conv2d").and_then(|ds| Ok(if read_scalar::<bool>(&ds) { 2 } else { 0 })))
+            .expect("Could not retrieve the axis.");
+        let frozen_statistics = group.dataset("frozen_statistics").and_then(|ds| Ok(read_scalar::<bool>(&ds))).unwrap_or(false);
+        let mb_mean = read_tensor(group, "mb_mean").expect("Could not retrieve the mini-batch mean.");
+        let mb_variance = read_tensor(group, "mb_variance").expect("Could not retrieve the mini-batch variance.");
+        let mean = read_tensor(group, "mean").expect("Could not retrieve the mean.");
+        let variance = read_tensor(group, "variance").expect("Could not retrieve the variance.");
+        let gamma = read_tensor(group, "gamma").expect("Could not retrieve the gamma values.");
+        let beta = read_tensor(group, "beta").expect("Could not retrieve the beta values.");
         let momentum = group.dataset("momentum").and_then(|ds| Ok(read_scalar::<PrimitiveType>(&ds))).expect("Could not retrieve the momentum.");
         let eps = group.dataset("eps").and_then(|ds| Ok(read_scalar::<PrimitiveType>(&ds))).expect("Could not retrieve the epsilon value.");
         let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
 
         Box::new(BatchNorm {
-            follow_conv2d,
-            mb_mean: Tensor::from(&mb_mean[0]),
-            mb_variance: Tensor::from(&mb_variance[0]),
-            mean: Tensor::from(&mean[0]),
-            variance: Tensor::from(&variance[0]),
+            axis: Some(axis),
+            frozen_statistics,
+            training: true,
+            mb_mean,
+            mb_variance,
+            mean,
+            variance,
             normalized_input: Tensor::new_empty_tensor(),
-            gamma: Tensor::from(&gamma[0]),
+            gamma,
             dgamma: Tensor::new_empty_tensor(),
-            beta: Tensor::from(&beta[0]),
+            beta,
             dbeta: Tensor::new_empty_tensor(),
             momentum,
             eps,
             output_shape: Dim::new(&output_shape[0]),
         })
     }
+
+    /// Freezes or unfreezes the running mean and variance.
+    ///
+    /// When the statistics are frozen, the layer normalizes the mini-batches with the running averages during the
+    /// training and doesn't update them. Gamma and beta are still trained. This is typically used to fine-tune a
+    /// pre-trained network on a small data set.
+    pub fn set_frozen_statistics(&mut self, frozen: bool) {
+        self.frozen_statistics = frozen;
+    }
+
+    /// Returns true if the mini-batches are normalized with the running averages.
+    fn uses_running_statistics(&self) -> bool {
+        self.frozen_statistics || !self.training
+    }
+
+    /// Returns the axis along which the features are stored.
+    fn feature_axis(&self) -> usize {
+        self.axis.unwrap_or(2)
+    }
+
+    /// Computes the mean over all axes except the feature axis.
+    fn reduce_mean(&self, tensor: &Tensor) -> Tensor {
+        let mut reduced = tensor.clone();
+        for axis in (0..4).filter(|&axis| axis != self.feature_axis()) {
+            if reduced.dims().get()[axis] > 1 {
+                reduced = mean(&reduced, axis as i64);
+            }
+        }
+        reduced
+    }
+
+    /// Computes the sum over all axes except the feature axis.
+    fn reduce_sum(&self, tensor: &Tensor) -> Tensor {
+        let mut reduced = tensor.clone();
+        for axis in (0..4).filter(|&axis| axis != self.feature_axis()) {
+            if reduced.dims().get()[axis] > 1 {
+                reduced = sum(&reduced, axis as i32);
+            }
+        }
+        reduced
+    }
 }
 
 impl Layer for BatchNorm {
@@ -122,21 +203,24 @@ impl Layer for BatchNorm {
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
     fn initialize_parameters(&mut self, input_shape: Dim4) {
-        // If the previous layer has channels, the batch normalization is done along the channels
-        if input_shape[2] != 0 { self.follow_conv2d = true; }
-        if self.follow_conv2d {
-            let num_channels = input_shape.get()[2];
-            self.gamma = Tensor::ones(Dim4::new(&[1, 1, num_channels, 1]));
-            self.beta = Tensor::zeros(Dim4::new(&[1, 1, num_channels, 1]));
-            self.mean = Tensor::zeros(Dim4::new(&[1, 1, num_channels, 1]));
-            self.variance = Tensor::ones(Dim4::new(&[1, 1, num_channels, 1]));
-        } else {
-            self.gamma = Tensor::ones(input_shape);
-            self.beta = Tensor::zeros(input_shape);
-            self.mean = Tensor::zeros(input_shape);
-            self.variance = Tensor::ones(input_shape);
+        // If the previous layer outputs images, the batch normalization is done along the channels
+        if self.axis.is_none() {
+            let is_image = input_shape.get()[1] > 1 || input_shape.get()[2] > 1;
+            self.axis = Some(if is_image { 2 } else { 0 });
         }
+
+        let mut dims = [1, 1, 1, 1];
+        dims[self.feature_axis()] = input_shape.get()[self.feature_axis()];
+        let dims = Dim4::new(&dims);
+        self.gamma = Tensor::ones(dims);
+        self.beta = Tensor::zeros(dims);
+        self.mean = Tensor::zeros(dims);
+        self.variance = Tensor::ones(dims);
         self.output_shape = input_shape;
     }
 
@@ -145,21 +229,17 @@ impl Layer for BatchNorm {
     }
 
     fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
-        // Compute mini-batch mean and variance
-        if self.follow_conv2d {
-            //let mut flat = reorder(&input, Dim4::new(&[0, 1, 3, 2]));
-            let mut flat = reorder_v2(&input, 0, 1, Some(vec![3, 2]));
-            flat = moddims(&flat, Dim4::new(&[flat.elements() as u64 / flat.dims().get()[3], flat.dims().get()[3], 1, 1]));
-            let mean = mean(&flat, 0);
-            let var = var(&flat, false, 0);
-            //self.mb_mean = reorder(&mean, Dim4::new(&[0, 2, 1, 3]));
-            self.mb_mean = reorder_v2(&mean, 0, 2, Some(vec![1, 3]));
-            //self.mb_variance = reorder(&var, Dim4::new(&[0, 2, 1, 3]));
-            self.mb_variance = reorder_v2(&var, 0, 2, Some(vec![1, 3]));
-        } else {
-            self.mb_mean = input.reduce(Reduction::MeanBatches);
-            self.mb_variance = var(input, false, 3);
+        // Normalize with the running averages, which are left untouched
+        if self.uses_running_statistics() {
+            self.normalized_input = div(&sub(input, &self.mean, true), &sqrt(&add(&self.variance, &self.eps, true)), true);
+            self.normalized_input.eval();
+            return add(&mul(&self.gamma, &self.normalized_input, true), &self.beta, true);
         }
+
+        // Compute mini-batch mean and variance
+        self.mb_mean = self.reduce_mean(input);
+        let centered = sub(input, &self.mb_mean, true);
+        self.mb_variance = self.reduce_mean(&mul(&centered, &centered, false));
         self.mb_mean.eval();
         self.mb_variance.eval();
 
@@ -170,43 +250,31 @@ impl Layer for BatchNorm {
         self.variance.eval();
 
         // Cache the normalized input for backprop
-        self.normalized_input = div(&sub(input, &self.mb_mean, true), &sqrt(&add(&self.mb_variance, &self.eps, true)), true);
+        self.normalized_input = div(&centered, &sqrt(&add(&self.mb_variance, &self.eps, true)), true);
         self.normalized_input.eval();
 
         add(&mul(&self.gamma, &self.normalized_input, true), &self.beta, true)
     }
 
     fn compute_dactivation_mut(&mut self, dz: &Tensor) -> Tensor {
-        if self.follow_conv2d {
-            self.dgamma = sum(&sum(&sum(&mul(dz, &self.normalized_input, true), 3), 1), 0);
-            self.dbeta = sum(&sum(&sum(dz, 3), 1), 0);
-        } else {
-            self.dgamma = sum(&mul(dz, &self.normalized_input, true), 3);
-            self.dbeta = sum(dz, 3);
-        }
-
-        // Compute the derivative of the loss wrt the variance
-        // c1 corresponds to: input - mb_mean
-        let c1 = mul(&self.normalized_input, &sqrt(&add(&self.mb_variance, &self.eps, true)), true);
-        // c2 corresponds to: sqrt(variance + eps)
-        let c2 = sqrt(&add(&self.mb_variance, &self.eps, true));
-        let fac = mul(&div(&self.gamma, &(-2.0 as PrimitiveType), true), &pow(&c2, &(-3.0 as PrimitiveType), true), true);
-        let dmb_variance = mul(&sum(&mul(dz, &c1, true), 3), &fac, true);
-
-        // Compute the derivative of the loss wrt the mean
-        let term1 = mul(&sum(&dz, 3), &sub(&(0.0 as PrimitiveType), &div(&self.gamma, &c2, true), true), true);
-        let term2 = mul(&dmb_variance, &mean(&mul(&(-2.0 as PrimitiveType), &c1, true), 3), true);
-        let dmb_mean = add(&term1, &term2, true);
+        self.dgamma = self.reduce_sum(&mul(dz, &self.normalized_input, true));
+        self.dbeta = self.reduce_sum(dz);
 
         // Compute the derivative of the loss wrt the normalized input
         let dnormalized_input = mul(dz, &self.gamma, true);
 
-        // Compute and return the derivative of the loss wrt the input
-        let term1 = mul(&dnormalized_input, &div(&(1.0 as PrimitiveType), &sqrt(&self.variance), true), true);
-        let m = self.normalized_input.dims()[3] as PrimitiveType;
-        let term2 = mul(&dmb_variance, &mul(&(2.0 as PrimitiveType / m), &c1, true), true);
-        let term3 = div(&dmb_mean, &m, true);
-        add(&term1, &add(&term2, &term3, true), true)
+        // The running averages are constants when the statistics are frozen
+        if self.uses_running_statistics() {
+            return div(&dnormalized_input, &sqrt(&add(&self.variance, &self.eps, true)), true);
+        }
+
+        // Compute and return the derivative of the loss wrt the input, accounting for the dependency of the
+        // mini-batch mean and variance on the input
+        let inv_std = div(&(1.0 as PrimitiveType), &sqrt(&add(&self.mb_variance, &self.eps, true)), true);
+        let mean_dnormalized = self.reduce_mean(&dnormalized_input);
+        let mean_dnormalized_input = self.reduce_mean(&mul(&dnormalized_input, &self.normalized_input, false));
+        let centered = sub(&sub(&dnormalized_input, &mean_dnormalized, true), &mul(&self.normalized_input, &mean_dnormalized_input, true), false);
+        mul(&centered, &inv_std, true)
     }
 
     fn output_shape(&self) -> Dim4 {
@@ -221,33 +289,39 @@ impl Layer for BatchNorm {
         Some((vec![&mut self.gamma, &mut self.beta], vec![&self.dgamma, &self.dbeta]))
     }
 
+    fn gradients_mut(&mut self) -> Option<Vec<&mut Tensor>> {
+        Some(vec![&mut self.dgamma, &mut self.dbeta])
+    }
+
+    fn affine_transformation(&self) -> Option<(Tensor, Tensor)> {
+        let scale = div(&self.gamma, &sqrt(&add(&self.variance, &self.eps, true)), false);
+        let shift = sub(&self.beta, &mul(&self.mean, &scale, false), false);
+        Some((scale, shift))
+    }
+
 
 
     fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
         let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
         let batch_norm = group.create_group(&group_name)?;
 
-        let follow_conv2d = batch_norm.new_dataset::<bool>().create("follow_conv2d", 1)?;
-        write_scalar(&follow_conv2d, &self.follow_conv2d);
-        //follow_conv2d.write(&[self.follow_conv2d])?;
+        let axis = batch_norm.new_dataset::<u64>().create("axis", 1)?;
+        write_scalar(&axis, &(self.feature_axis() as u64));
 
-        let mb_mean = batch_norm.new_dataset::<H5Tensor>().create("mb_mean", 1)?;
-        mb_mean.write(&[H5Tensor::from(&self.mb_mean)])?;
+        let frozen_statistics = batch_norm.new_dataset::<bool>().create("frozen_statistics", 1)?;
+        write_scalar(&frozen_statistics, &self.frozen_statistics);
 
-        let mb_variance = batch_norm.new_dataset::<H5Tensor>().create("mb_variance", 1)?;
-        mb_variance.write(&[H5Tensor::from(&self.mb_variance)])?;
+        write_tensor(&batch_norm, "mb_mean", &self.mb_mean)?;
 
-        let mean = batch_norm.new_dataset::<H5Tensor>().create("mean", 1)?;
-        mean.write(&[H5Tensor::from(&self.mean)])?;
+        write_tensor(&batch_norm, "mb_variance", &self.mb_variance)?;
 
-        let variance = batch_norm.new_dataset::<H5Tensor>().create("variance", 1)?;
-        variance.write(&[H5Tensor::from(&self.variance)])?;
+        write_tensor(&batch_norm, "mean", &self.mean)?;
 
-        let gamma = batch_norm.new_dataset::<H5Tensor>().create("gamma", 1)?;
-        gamma.write(&[H5Tensor::from(&self.gamma)])?;
+        write_tensor(&batch_norm, "variance", &self.variance)?;
 
-        let beta = batch_norm.new_dataset::<H5Tensor>().create("beta", 1)?;
-        beta.write(&[H5Tensor::from(&self.beta)])?;
+        write_tensor(&batch_norm, "gamma", &self.gamma)?;
+
+        write_tensor(&batch_norm, "beta", &self.beta)?;
 
         let momentum = batch_norm.new_dataset::<PrimitiveType>().create("momentum", 1)?;
         write_scalar(&momentum, &self.momentum);
@@ -263,6 +337,24 @@ impl Layer for BatchNorm {
         Ok(())
     }
 
+    fn set_frozen_statistics(&mut self, frozen: bool) {
+        BatchNorm::set_frozen_statistics(self, frozen);
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::BatchNorm {
+            gamma: CpuTensor::from(&self.gamma),
+            beta: CpuTensor::from(&self.beta),
+            mean: CpuTensor::from(&self.mean),
+            variance: CpuTensor::from(&self.variance),
+            eps: self.eps as f32,
+        })
+    }
+
 
 }
 
@@ -270,4 +362,69 @@ impl fmt::Display for BatchNorm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} \t {}", Self::NAME, self.gamma.elements() + self.beta.elements())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::layers::{BatchNorm, Layer};
+    use crate::assert_approx_eq;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_batchnorm_running_statistics() {
+        let mut layer = BatchNorm::with_param(0.5, 0.);
+        layer.initialize_parameters(Dim4::new(&[2, 1, 1, 1]));
+
+        // Two samples with two features each
+        let input = Tensor::new(&[1., 2., 3., 6.], Dim4::new(&[2, 1, 1, 2]));
+        let layer_output = layer.compute_activation_mut(&input);
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        layer_output.host(&mut output);
+        assert_approx_eq!(output, [-1., -1., 1., 1.]);
+
+        let mut mean: [PrimitiveType; 2] = [0.; 2];
+        layer.mean().host(&mut mean);
+        assert_approx_eq!(mean, [1., 2.]);
+
+        // The running averages are not updated when the statistics are frozen
+        layer.set_frozen_statistics(true);
+        let _ = layer.compute_activation_mut(&input);
+        layer.mean().host(&mut mean);
+        assert_approx_eq!(mean, [1., 2.]);
+    }
+
+    #[test]
+    fn test_batchnorm_backward() {
+        let input_values: [PrimitiveType; 6] = [0.5, -1., 2., 1.5, -0.3, 0.8];
+        let dz_values: [PrimitiveType; 6] = [0.2, -0.4, 1., 0.3, -0.7, 0.5];
+        let dims = Dim4::new(&[2, 1, 1, 3]);
+        let dz = Tensor::new(&dz_values, dims);
+
+        for &frozen in [false, true].iter() {
+            let new_layer = || {
+                let mut layer = BatchNorm::with_param(0.5, 1e-3);
+                layer.initialize_parameters(Dim4::new(&[2, 1, 1, 1]));
+                layer.set_frozen_statistics(frozen);
+                layer
+            };
+            let mut layer = new_layer();
+            let _ = layer.compute_activation_mut(&Tensor::new(&input_values, dims));
+            let mut dinput: [PrimitiveType; 6] = [0.; 6];
+            layer.compute_dactivation_mut(&dz).host(&mut dinput);
+
+            // Compare with the finite differences of sum(dz * output) wrt the input
+            let eps = 1e-2;
+            for i in 0..6 {
+                let objective = |shift: PrimitiveType| {
+                    let mut values = input_values;
+                    values[i] += shift;
+                    let output = new_layer().compute_activation_mut(&Tensor::new(&values, dims));
+                    sum_all(&mul(&output, &dz, false)).0 as PrimitiveType
+                };
+                let numerical = (objective(eps) - objective(-eps)) / (2. * eps);
+                assert!((numerical - dinput[i]).abs() < 1e-2, "input {}: {} != {}", i, numerical, dinput[i]);
+            }
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Complex-valued dense layer
+use arrayfire::*;
+use std::convert::TryInto;
+use std::fmt;
+
+use crate::activations::*;
+use crate::errors::Error;
+use crate::io::{read_tensor, write_tensor};
+use crate::layers::*;
+use crate::initializers::*;
+
+
+/// Defines a dense layer with complex weights operating on complex inputs.
+///
+/// The complex inputs and outputs are packed in real tensors with the real and imaginary parts along the third
+/// dimension, see [pack_complex](../tensor/fn.pack_complex.html). An input of shape [n, 1, 2] holds n complex values
+/// and the output has the shape [units, 1, 2]. The weights and biases are stored packed as well, such that they are
+/// trained by the optimizers as real parameters.
+///
+/// The activation is applied separately to the real and imaginary parts of the linear activation.
+#[derive(Clone)]
+pub struct ComplexDense
+{
+    units: u64,
+    activation: Activation,
+    weights: Tensor,
+    dweights: Tensor,
+    biases: Tensor,
+    dbiases: Tensor,
+    input_shape: Dim,
+    output_shape: Dim,
+    linear_activation: Option<Tensor>,
+    previous_input: Option<ComplexTensor>,
+    weights_initializer: Initializer,
+    biases_initializer: Initializer,
+    regularizer: Option<Regularizer>,
+}
+
+impl ComplexDense
+{
+    pub(crate) const NAME: &'static str = "ComplexDense";
+
+    /// Creates a complex dense layer with given number of units and activation function.
+    ///
+    /// By default, the weights are initialized with a HeNormal initializer and the biases with a Zeros initializer.
+    pub fn new(units: u64, activation: Activation) -> Box<ComplexDense> {
+        ComplexDense::with_param(units, activation, Initializer::HeNormal, Initializer::Zeros)
+    }
+
+    /// Creates a complex dense layer with the given parameters.
+    ///
+    /// The real and imaginary parts of the weights are initialized independently, with the fan-in and fan-out of
+    /// the equivalent real layer.
+    pub fn with_param(units: u64,
+                      activation: Activation,
+                      weights_initializer: Initializer,
+                      biases_initializer: Initializer
+    ) -> Box<ComplexDense> {
+        Box::new(ComplexDense {
+            units,
+            activation,
+            weights: Tensor::new_empty_tensor(),
+            dweights: Tensor::new_empty_tensor(),
+            biases: Tensor::new_empty_tensor(),
+            dbiases: Tensor::new_empty_tensor(),
+            input_shape: Dim::new(&[0, 0, 0, 0]),
+            output_shape: Dim::new(&[units, 1, 2, 1]),
+            linear_activation: None,
+            previous_input: None,
+            weights_initializer,
+            biases_initializer,
+            regularizer: None,
+        })
+    }
+
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Self> {
+        let _ = hdf5::silence_errors();
+        let units = group.dataset("units").and_then(|ds| ds.read_raw::<u64>()).expect("Could not retrieve the number of units.");
+        let activation: Vec<u8> = group.dataset("activation").and_then(|ds| ds.read_raw::<u8>()).expect("Could not retrieve the activation.");
+        let weights = read_tensor(group, "weights").expect("Could not retrieve the weights.");
+        let biases = read_tensor(group, "biases").expect("Could not retrieve the biases.");
+        let input_shape = group.dataset("input_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the input shape.");
+        let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
+        let regularizer = Regularizer::from_hdf5_group(group);
+        let weights_initializer = group.dataset("weights_initializer").and_then(|ds| ds.read_raw::<H5Initializer>()).expect("Could not retrieve the weights initializer.");
+        let biases_initializer = group.dataset("biases_initializer").and_then(|ds| ds.read_raw::<H5Initializer>()).expect("Could not retrieve the biases initializer.");
+
+        Box::new(Self {
+            units: units[0],
+            activation: activation[0].try_into().expect("Could not create activation variant."),
+            weights,
+            dweights: Tensor::new_empty_tensor(),
+            biases,
+            dbiases: Tensor::new_empty_tensor(),
+            input_shape: Dim::new(&(input_shape[0])),
+            output_shape: Dim::new(&(output_shape[0])),
+            linear_activation: None,
+            previous_input: None,
+            weights_initializer: Initializer::from(&weights_initializer[0]),
+            biases_initializer: Initializer::from(&biases_initializer[0]),
+            regularizer,
+        })
+    }
+
+    /// Returns the number of complex values of the input of the layer.
+    fn fan_in(&self) -> u64 {
+        self.input_shape[0] * self.input_shape[1]
+    }
+
+    /// Unpacks the input into a complex matrix with one column per sample.
+    fn complex_input(&self, input: &Tensor) -> ComplexTensor {
+        moddims(&unpack_complex(input), Dim4::new(&[self.fan_in(), input.batch_size(), 1, 1]))
+    }
+
+    /// Computes the packed linear activation from the complex input matrix.
+    fn compute_linear_activation(&self, input: &ComplexTensor) -> Tensor {
+        let batch_size = input.dims()[1];
+        let product = matmul(&unpack_complex(&self.weights), input, MatProp::NONE, MatProp::NONE);
+        let linear_activation = add(&product, &unpack_complex(&self.biases), true);
+        pack_complex(&moddims(&linear_activation, Dim4::new(&[self.units, 1, 1, batch_size])))
+    }
+}
+
+impl Layer for ComplexDense
+{
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn initialize_parameters(&mut self, input_shape: Dim) {
+        assert_eq!(input_shape[2], 2, "The input of a complex dense layer must hold the real and imaginary parts along the third dimension.");
+        self.input_shape = input_shape;
+        let fan_in = 2 * self.fan_in();
+        let fan_out = 2 * self.units;
+        self.weights = self.weights_initializer.new_tensor(Dim::new(&[self.units, self.fan_in(), 2, 1]), fan_in, fan_out);
+        self.biases = self.biases_initializer.new_tensor(Dim::new(&[self.units, 1, 2, 1]), fan_in, fan_out);
+    }
+
+    fn compute_activation(&self, input: &Tensor) -> Tensor {
+        let linear_activation = self.compute_linear_activation(&self.complex_input(input));
+        self.activation.eval(&linear_activation)
+    }
+
+    fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
+        let complex_input = self.complex_input(input);
+        let linear_activation = self.compute_linear_activation(&complex_input);
+        let nonlinear_activation = self.activation.eval(&linear_activation);
+        eval_tensors(&[&linear_activation, &nonlinear_activation]);
+
+        self.previous_input = Some(complex_input);
+        self.linear_activation = Some(linear_activation);
+        nonlinear_activation
+    }
+
+    /// Computes the gradients of the real-valued loss with respect to the real and imaginary parts of the parameters.
+    ///
+    /// With g the packed gradient of the linear activation, the gradient of the weights is g·x^H and the gradient of
+    /// the input is W^H·g, where ^H denotes the conjugate transpose.
+    fn compute_dactivation_mut(&mut self, input: &Tensor) -> Tensor {
+        let linear_activation = self.linear_activation.as_ref().expect("The linear activations z have not been computed!");
+        let previous_input = self.previous_input.as_ref().expect("The previous activations have not been computed!");
+        let batch_size = input.batch_size();
+
+        let linear_activation_grad = mul(input, &self.activation.grad(linear_activation), true);
+        let grad = moddims(&unpack_complex(&linear_activation_grad), Dim4::new(&[self.units, batch_size, 1, 1]));
+
+        let dweights = matmul(&grad, previous_input, MatProp::NONE, MatProp::CTRANS);
+        self.dweights = pack_complex(&dweights) / batch_size as PrimitiveType;
+        if let Some(regularizer) = self.regularizer { self.dweights += regularizer.grad(&self.weights) }
+        self.dbiases = pack_complex(&sum(&grad, 1)) / batch_size as PrimitiveType;
+
+        let dinput = matmul(&unpack_complex(&self.weights), &grad, MatProp::CTRANS, MatProp::NONE);
+        pack_complex(&moddims(&dinput, Dim4::new(&[self.input_shape[0], self.input_shape[1], 1, batch_size])))
+    }
+
+    fn output_shape(&self) -> Dim4 {
+        self.output_shape
+    }
+
+    fn parameters(&self) -> Option<Vec<&Tensor>> {
+        Some(vec![&self.weights, &self.biases])
+    }
+
+    fn parameters_mut(&mut self) -> Option<(Vec<&mut Tensor>, Vec<&Tensor>)> {
+        Some((vec![&mut self.weights, &mut self.biases], vec![&self.dweights, &self.dbiases]))
+    }
+
+    fn gradients_mut(&mut self) -> Option<Vec<&mut Tensor>> {
+        Some(vec![&mut self.dweights, &mut self.dbiases])
+    }
+
+    fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
+        let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
+        let complex_dense = group.create_group(&group_name)?;
+
+        let units = complex_dense.new_dataset::<u64>().create("units", 1)?;
+        units.write(&[self.units])?;
+
+        let activation = complex_dense.new_dataset::<Activation>().create("activation", 1)?;
+        activation.write(&[self.activation])?;
+
+        write_tensor(&complex_dense, "weights", &self.weights)?;
+
+        write_tensor(&complex_dense, "biases", &self.biases)?;
+
+        let input_shape = complex_dense.new_dataset::<[u64; 4]>().create("input_shape", 1)?;
+        input_shape.write(&[*self.input_shape.get()])?;
+
+        let output_shape = complex_dense.new_dataset::<[u64; 4]>().create("output_shape", 1)?;
+        output_shape.write(&[*self.output_shape.get()])?;
+
+        let weights_initializer = complex_dense.new_dataset::<H5Initializer>().create("weights_initializer", 1)?;
+        self.weights_initializer.save(&weights_initializer)?;
+
+        let biases_initializer = complex_dense.new_dataset::<H5Initializer>().create("biases_initializer", 1)?;
+        self.biases_initializer.save(&biases_initializer)?;
+
+        Ok(())
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(self.activation)
+    }
+
+    fn set_regularizer(&mut self, regularizer: Option<Regularizer>) {
+        self.regularizer = regularizer;
+    }
+
+    fn print(&self) {
+        println!("Number of parameters: {}", self.weights.elements() + self.biases.elements());
+    }
+}
+
+impl fmt::Display for ComplexDense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \t {} \t\t [{}, {}, {}]", Self::NAME, self.weights.elements() + self.biases.elements(), self.output_shape[0], self.output_shape[1], self.output_shape[2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layers::{ComplexDense, Layer};
+    use crate::activations::Activation;
+    use crate::initializers::Initializer;
+    use crate::tensor::*;
+    use crate::assert_approx_eq;
+
+    fn create_test_layer() -> ComplexDense {
+        // W = [1 + 2i, -1 + 0i], b = 1 - i
+        let mut layer = ComplexDense::with_param(1, Activation::Linear, Initializer::Zeros, Initializer::Zeros);
+        layer.initialize_parameters(Dim::new(&[2, 1, 2, 1]));
+        layer.weights = Tensor::new(&[1., -1., 2., 0.], Dim::new(&[1, 2, 2, 1]));
+        layer.biases = Tensor::new(&[1., -1.], Dim::new(&[1, 1, 2, 1]));
+        *layer
+    }
+
+    #[test]
+    fn test_complex_dense_forward_backward() {
+        let mut layer = create_test_layer();
+
+        // x = [i, 2 - i]
+        let input = Tensor::new(&[0., 2., 1., -1.], Dim::new(&[2, 1, 2, 1]));
+        let layer_output = layer.compute_activation_mut(&input);
+        let mut output: [PrimitiveType; 2] = [0.; 2];
+        layer_output.host(&mut output);
+        // (1 + 2i)i - (2 - i) + 1 - i = -3 + i
+        assert_approx_eq!(output, [-3., 1.]);
+
+        // g = 1 + i
+        let input_backward = Tensor::new(&[1., 1.], Dim::new(&[1, 1, 2, 1]));
+        let dinput = layer.compute_dactivation_mut(&input_backward);
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        dinput.host(&mut output);
+        // W^H g = [(1 - 2i)(1 + i), -(1 + i)] = [3 - i, -1 - i]
+        assert_approx_eq!(output, [3., -1., -1., -1.]);
+
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        layer.dweights.host(&mut output);
+        // g x^H = [(1 + i)(-i), (1 + i)(2 + i)] = [1 - i, 1 + 3i]
+        assert_approx_eq!(output, [1., 1., -1., 3.]);
+    }
+}
@@ -4,7 +4,10 @@ use std::convert::{TryFrom};
 use std::fmt;
 
 use crate::activations::*;
+use crate::backend::{Backend, DefaultBackend, conv_output_size};
+use crate::cpu::{CpuActivation, CpuLayer, CpuTensor};
 use crate::errors::Error;
+use crate::io::{read_tensor, write_tensor};
 use crate::initializers::*;
 use crate::regularizers::*;
 use crate::tensor::*;
@@ -15,11 +18,16 @@ use super::Layer;
 /// * Same: a same convolution is such that the dimensions of the output of the convolution is the
 /// same as the dimensions of the input, provided a stride of 1.
 /// * Valid: a valid convolution is such that the kernel is moved as long as the shift results in a valid convolution operation. No padding is applied.
+/// * Explicit: the number of zeros added to the top, right, bottom, and left of the inputs is given by the user.
+/// * Causal: the first dimension of the inputs is treated as time and all the padding along it is added at the top,
+/// such that an output never depends on future timesteps. The second dimension is padded as with `Same`.
 #[derive(hdf5::H5Type, Debug, Copy, Clone, PartialEq)]
 #[repr(u8)]
 pub enum Padding {
     Same = 0,
     Valid = 1,
+    Explicit = 2,
+    Causal = 3,
 }
 
 impl TryFrom<u8> for Padding {
@@ -29,19 +37,69 @@ impl TryFrom<u8> for Padding {
         match v {
             x if x == Padding::Same as u8 => Ok(Padding::Same),
             x if x == Padding::Valid as u8 => Ok(Padding::Valid),
+            x if x == Padding::Explicit as u8 => Ok(Padding::Explicit),
+            x if x == Padding::Causal as u8 => Ok(Padding::Causal),
             _ => Err(()),
         }
     }
 }
 
+/// Computes the padding such that the output has dimensions h_out x w_out.
+///
+/// When the total padding along a dimension is odd, the extra row is added at the bottom and the extra column on
+/// the right. The padding is returned in the order top, right, bottom, left.
+pub(crate) fn same_padding_size(height: u64,
+                                width: u64,
+                                h_out: u64,
+                                w_out: u64,
+                                kernel_size: (u64, u64),
+                                stride: (u64, u64)
+) -> (u64, u64, u64, u64) {
+    let pad_along_h = ((h_out - 1) * stride.0 + kernel_size.0).saturating_sub(height);
+    let pad_along_w = ((w_out - 1) * stride.1 + kernel_size.1).saturating_sub(width);
+    (pad_along_h / 2, (pad_along_w + 1) / 2, (pad_along_h + 1) / 2, pad_along_w / 2)
+}
+
+/// Computes the padding such that the output at a given row only depends on the current and previous rows.
+///
+/// The first dimension is padded at the top with kernel_height - 1 rows, which yields an output height of
+/// ceil(height / stride). The second dimension is padded as with `Padding::Same`.
+pub(crate) fn causal_padding_size(height: u64,
+                                  width: u64,
+                                  w_out: u64,
+                                  kernel_size: (u64, u64),
+                                  stride: (u64, u64)
+) -> (u64, u64, u64, u64) {
+    let (_, pad_right, _, pad_left) = same_padding_size(height, width, 1, w_out, kernel_size, stride);
+    (kernel_size.0 - 1, pad_right, 0, pad_left)
+}
+
+
+/// Defines the algorithm used to compute the convolutions.
+///
+/// * Im2Col: the patches of the images are rearranged into columns and the convolution is computed with a matrix
+/// multiplication. This is fast but the columns take kernel_height * kernel_width times the memory of the images.
+/// * Native: the convolution and its gradients are computed with the dedicated ArrayFire functions, which require
+/// much less memory.
+#[derive(hdf5::H5Type, Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum ConvAlgorithm {
+    Im2Col = 0,
+    Native = 1,
+}
+
 
 /// Defines a 2D convolution layer.
+#[derive(Clone)]
 pub struct Conv2D {
     activation: Activation,
     kernel_size: (u64, u64),
     stride: (u64, u64),
+    dilation: (u64, u64),
+    groups: u64,
     padding: Padding,
     padding_size: (u64, u64, u64, u64), // top, right, bottom, left
+    algorithm: ConvAlgorithm,
     num_filters: u64,
     input_shape: Dim,
     output_shape: Dim,
@@ -81,8 +139,11 @@ impl Conv2D {
             activation: Activation::ReLU,
             kernel_size,
             stride,
+            dilation: (1, 1),
+            groups: 1,
             padding,
             padding_size: (0, 0, 0, 0),
+            algorithm: ConvAlgorithm::Im2Col,
             num_filters,
             input_shape: Dim::new(&[0, 0, 0, 0]),
             output_shape: Dim::new(&[0, 0, 0, 0]),
@@ -126,8 +187,11 @@ impl Conv2D {
             activation,
             kernel_size,
             stride,
+            dilation: (1, 1),
+            groups: 1,
             padding,
             padding_size: (0, 0, 0, 0),
+            algorithm: ConvAlgorithm::Im2Col,
             num_filters,
             input_shape: Dim::new(&[0, 0, 0, 0]),
             output_shape: Dim::new(&[0, 0, 0, 0]),
@@ -153,23 +217,29 @@ impl Conv2D {
         let num_filters = group.dataset("num_filters").and_then(|ds| ds.read_raw::<u64>()).expect("Could not retrieve the number of filters.");
         let input_shape = group.dataset("input_shape").and_then(|value| value.read_raw::<[u64; 4]>()).expect("Could not retrieve the input shape.");
         let output_shape = group.dataset("output_shape").and_then(|value| value.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
-        let weights = group.dataset("weights").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the weights.");
-        let biases = group.dataset("biases").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the biases.");
+        let weights = read_tensor(group, "weights").expect("Could not retrieve the weights.");
+        let biases = read_tensor(group, "biases").expect("Could not retrieve the biases.");
         let weights_initializer = group.dataset("weights_initializer").and_then(|ds| ds.read_raw::<H5Initializer>()).expect("Could not retrieve the weights initializer.");
         let biases_initializer = group.dataset("biases_initializer").and_then(|ds| ds.read_raw::<H5Initializer>()).expect("Could not retrieve the biases initializer.");
         let regularizer = Regularizer::from_hdf5_group(group);
+        let dilation = group.dataset("dilation").and_then(|ds| ds.read_raw::<[u64; 2]>()).map(|dilation| (dilation[0][0], dilation[0][1])).unwrap_or((1, 1));
+        let groups = group.dataset("groups").and_then(|ds| ds.read_raw::<u64>()).map(|groups| groups[0]).unwrap_or(1);
+        let algorithm = group.dataset("algorithm").and_then(|ds| ds.read_raw::<ConvAlgorithm>()).map(|algorithm| algorithm[0]).unwrap_or(ConvAlgorithm::Im2Col);
 
         Box::new(Conv2D {
             activation: activation[0],
             kernel_size: (kernel_size[0][0], kernel_size[0][1]),
             stride: (stride[0][0], stride[0][1]),
+            dilation,
+            groups,
             padding: padding[0],
             padding_size: (padding_size[0][0], padding_size[0][1], padding_size[0][2], padding_size[0][3]),
+            algorithm,
             num_filters: num_filters[0],
             input_shape: Dim::new(&input_shape[0]),
             output_shape: Dim::new(&output_shape[0]),
-            weights: Tensor::from(&weights[0]),
-            biases: Tensor::from(&biases[0]),
+            weights,
+            biases,
             dweights: Tensor::new_empty_tensor(),
             dbiases: Tensor::new_empty_tensor(),
             linear_activation: None,
@@ -181,6 +251,159 @@ impl Conv2D {
         })
     }
 
+    /// Sets the algorithm used to compute the convolutions.
+    ///
+    /// By default, the Im2Col algorithm is used.
+    pub fn set_algorithm(&mut self, algorithm: ConvAlgorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Sets the number of zeros added to the top, right, bottom, and left of the inputs.
+    ///
+    /// The padding of the layer is changed to `Padding::Explicit`. It must be set before the layer is added to a network.
+    pub fn set_explicit_padding(&mut self, padding_size: (u64, u64, u64, u64)) {
+        self.padding = Padding::Explicit;
+        self.padding_size = padding_size;
+    }
+
+    /// Sets the vertical and horizontal dilation rates of the kernels.
+    ///
+    /// A dilation rate d inserts d - 1 zeros between the entries of the kernels, which expands the receptive field
+    /// without increasing the number of parameters. The dilation must be set before the layer is added to a network.
+    /// By default, the dilation rates are (1, 1).
+    pub fn set_dilation(&mut self, dilation: (u64, u64)) {
+        assert!(dilation.0 > 0 && dilation.1 > 0, "The dilation rates must be positive.");
+        self.dilation = dilation;
+    }
+
+    /// Sets the number of groups in which the input channels and the filters are split.
+    ///
+    /// Each group of filters is only connected to the corresponding group of input channels, which divides the number
+    /// of parameters by the number of groups. The number of input channels and the number of filters must both be
+    /// divisible by the number of groups. The groups must be set before the layer is added to a network. By default,
+    /// a single group is used.
+    pub fn set_groups(&mut self, groups: u64) {
+        assert!(groups > 0, "The number of groups must be positive.");
+        assert_eq!(self.num_filters % groups, 0, "The number of filters must be divisible by the number of groups.");
+        self.groups = groups;
+    }
+
+    /// Returns the sequences selecting the rows and the columns of the weights of a group in the full weights.
+    fn group_seqs(&self, group: u64) -> [Seq<f64>; 2] {
+        let filters_per_group = self.num_filters / self.groups;
+        let group_size = self.weights.dims().get()[1];
+        [Seq::new((group * filters_per_group) as f64, ((group + 1) * filters_per_group - 1) as f64, 1.0),
+         Seq::new((group * group_size) as f64, ((group + 1) * group_size - 1) as f64, 1.0)]
+    }
+
+    /// Returns the weights connecting all the filters to all the input channels.
+    ///
+    /// The weights of the groups are placed on the block diagonal and the other entries are zeros.
+    fn full_weights(&self) -> Tensor {
+        if self.groups == 1 {
+            return self.weights.clone();
+        }
+        let group_size = self.weights.dims().get()[1];
+        let mut weights = constant(0 as PrimitiveType, Dim4::new(&[self.num_filters, group_size * self.groups, 1, 1]));
+        for group in 0..self.groups {
+            let seqs = self.group_seqs(group);
+            let group_weights = index(&self.weights, &[seqs[0], Seq::default()]);
+            assign_seq(&mut weights, &seqs, &group_weights);
+        }
+        weights
+    }
+
+    /// Extracts the gradient of the weights from the gradient of the full weights.
+    fn ungroup_gradient(&self, full_grad: &Tensor) -> Tensor {
+        if self.groups == 1 {
+            return full_grad.clone();
+        }
+        let mut grad = index(full_grad, &self.group_seqs(0));
+        for group in 1..self.groups {
+            grad = join(0, &grad, &index(full_grad, &self.group_seqs(group)));
+        }
+        grad
+    }
+
+    /// Returns the height and width of the dilated kernels.
+    fn dilated_kernel_size(&self) -> (u64, u64) {
+        (self.dilation.0 * (self.kernel_size.0 - 1) + 1, self.dilation.1 * (self.kernel_size.1 - 1) + 1)
+    }
+
+    /// Returns the sequences selecting the entries of the original kernels in the dilated kernels.
+    fn dilation_seqs(&self) -> [Seq<f64>; 4] {
+        let (height, width) = self.dilated_kernel_size();
+        [Seq::default(), Seq::new(0.0, (height - 1) as f64, self.dilation.0 as f64), Seq::new(0.0, (width - 1) as f64, self.dilation.1 as f64), Seq::default()]
+    }
+
+    /// Returns the weights of the dilated kernels, where zeros are inserted between the entries of the kernels.
+    fn dilated_weights(&self) -> Tensor {
+        if self.dilation == (1, 1) {
+            return self.full_weights();
+        }
+        let num_channels = self.input_shape.get()[2];
+        let (height, width) = self.dilated_kernel_size();
+        let weights = moddims(&self.full_weights(), Dim4::new(&[self.num_filters, self.kernel_size.0, self.kernel_size.1, num_channels]));
+        let mut dilated = constant(0 as PrimitiveType, Dim4::new(&[self.num_filters, height, width, num_channels]));
+        assign_seq(&mut dilated, &self.dilation_seqs(), &weights);
+        moddims(&dilated, Dim4::new(&[self.num_filters, height * width * num_channels, 1, 1]))
+    }
+
+    /// Extracts the gradient of the weights from the gradient of the dilated weights.
+    fn undilate_gradient(&self, dilated_grad: &Tensor) -> Tensor {
+        if self.dilation == (1, 1) {
+            return self.ungroup_gradient(dilated_grad);
+        }
+        let num_channels = self.input_shape.get()[2];
+        let (height, width) = self.dilated_kernel_size();
+        let grad = moddims(dilated_grad, Dim4::new(&[self.num_filters, height, width, num_channels]));
+        let full_grad = moddims(&index(&grad, &self.dilation_seqs()), Dim4::new(&[self.num_filters, self.kernel_size.0 * self.kernel_size.1 * num_channels, 1, 1]));
+        self.ungroup_gradient(&full_grad)
+    }
+
+    /// Computes the convolution with the ArrayFire convolution function.
+    fn compute_convolution_native(&self, input: &Tensor) -> Tensor {
+        let padded = DefaultBackend::pad(input, self.padding_size);
+        let conv = convolve2_nn(&padded, &self.native_filters(), Dim4::new(&[self.stride.0, self.stride.1, 1, 1]), Dim4::new(&[0, 0, 1, 1]), Dim4::new(&[self.dilation.0, self.dilation.1, 1, 1]));
+        add(&conv, &moddims(&self.biases, Dim4::new(&[1, 1, self.num_filters, 1])), true)
+    }
+
+    /// Rearranges the weights into filters with dimensions [kernel_height, kernel_width, channels, num_filters].
+    fn native_filters(&self) -> Tensor {
+        let num_channels = self.input_shape.get()[2];
+        let mut filters = moddims(&self.full_weights(), Dim4::new(&[self.num_filters, self.kernel_size.0, self.kernel_size.1, num_channels]));
+        filters = reorder_v2(&filters, 1, 2, Some(vec![3, 0]));
+
+        // convolve2_nn computes a convolution: the kernels are flipped to compute the cross-correlation
+        flip(&flip(&filters, 0), 1)
+    }
+
+    /// Computes the gradients with the ArrayFire convolution functions and returns the gradient of the input.
+    fn compute_gradients_native(&mut self, linear_activation: &Tensor, linear_activation_grad: &Tensor) -> Tensor {
+        let batch_size = linear_activation_grad.dims().get()[3];
+        let previous_activation = self.previous_activation.as_ref().expect("The previous activations have not been computed!");
+        let padded = DefaultBackend::pad(previous_activation, self.padding_size);
+        let filters = self.native_filters();
+        let stride = Dim4::new(&[self.stride.0, self.stride.1, 1, 1]);
+        let padding = Dim4::new(&[0, 0, 1, 1]);
+        let dilation = Dim4::new(&[self.dilation.0, self.dilation.1, 1, 1]);
+
+        let bias_grad = sum(&sum(&sum(linear_activation_grad, 0), 1), 3);
+        self.dbiases = moddims(&bias_grad, Dim4::new(&[self.num_filters, 1, 1, 1])) / batch_size;
+
+        // Rearrange the gradient of the filters to match the layout of the weights
+        let mut filters_grad = convolve2_gradient_nn(linear_activation_grad, &padded, &filters, linear_activation, stride, padding, dilation, ConvGradientType::FILTER);
+        filters_grad = flip(&flip(&filters_grad, 0), 1);
+        filters_grad = reorder_v2(&filters_grad, 3, 0, Some(vec![1, 2]));
+        let num_channels = self.input_shape.get()[2];
+        filters_grad = moddims(&filters_grad, Dim4::new(&[self.num_filters, self.kernel_size.0 * self.kernel_size.1 * num_channels, 1, 1]));
+        self.dweights = self.ungroup_gradient(&filters_grad) / batch_size;
+        if let Some(regularizer) = self.regularizer { self.dweights += regularizer.grad(&self.weights) }
+
+        let input_grad = convolve2_gradient_nn(linear_activation_grad, &padded, &filters, linear_activation, stride, padding, dilation, ConvGradientType::DATA);
+        self.remove_padding(&input_grad)
+    }
+
     /// Computes the convolution.
     fn compute_convolution(&self, input: &Tensor) -> (Tensor, Tensor) {
         let batch_size = input.dims().get()[3];
@@ -188,17 +411,11 @@ impl Conv2D {
         let h_out = self.output_shape.get()[0];
         let w_out = self.output_shape.get()[1];
 
-        // Pad input if necessary
-        let padded = self.pad_input(&input);
-
-        // Transform input into column array
-        let input_values = match &padded {
-            Some(p) => self.img_to_col(&p),
-            None => self.img_to_col(input)
-        };
+        // Transform the padded input into column array
+        let input_values = DefaultBackend::im2col(input, self.dilated_kernel_size(), self.stride, self.padding_size);
 
         // Compute the convolution and add biases
-        let mut conv = add(&matmul(&self.weights, &input_values, MatProp::NONE, MatProp::NONE), &self.biases, true);
+        let mut conv = add(&DefaultBackend::matmul(&self.dilated_weights(), &input_values, false, false), &self.biases, true);
 
         // Reshape to have each mini-batch on the last dimension
         conv = moddims(&conv, Dim4::new(&[self.num_filters, h_out * w_out, 1, batch_size]));
@@ -212,68 +429,18 @@ impl Conv2D {
     fn compute_padding_size(&mut self, height: u64, width: u64, h_out: u64, w_out: u64) {
         match self.padding {
             Padding::Same => {
-                let pad_along_h = std::cmp::max((h_out - 1) * self.stride.0 + self.kernel_size.0 - height, 0);
-                let pad_along_w = std::cmp::max((w_out - 1) * self.stride.1 + self.kernel_size.1 - width, 0);
-                if pad_along_h != 0 {
-                    if pad_along_h % 2 == 0 {
-                        self.padding_size.0 = pad_along_h / 2;
-                        self.padding_size.2 = pad_along_h / 2;
-                    } else {
-                        self.padding_size.0 = (pad_along_h - 1) / 2;
-                        self.padding_size.2 = (pad_along_h + 1) / 2;
-                    }
-                }
-                if pad_along_w != 0 {
-                    if pad_along_w % 2 == 0 {
-                        self.padding_size.1 = pad_along_w / 2;
-                        self.padding_size.3 = pad_along_w / 2;
-                    } else {
-                        self.padding_size.1 = (pad_along_w + 1) / 2;
-                        self.padding_size.3 = (pad_along_w - 1) / 2;
-                    }
-                }
-            },
-            Padding::Valid => {}
-        }
-    }
-
-    /// Applies the padding to the layer's inputs.
-    fn pad_input(&self, input: &Tensor) -> Option<Tensor> {
-        let height = input.dims().get()[0];
-        let width = input.dims().get()[1];
-        let num_channels = input.dims().get()[2];
-        let mb_size = input.dims().get()[3];
-
-        // Create padded input
-        match self.padding {
-            Padding::Same => {
-                let pad_top = constant(0.0 as PrimitiveType, Dim4::new(&[self.padding_size.0, width, num_channels, mb_size]));
-                let pad_right = constant(0.0 as PrimitiveType, Dim4::new(&[height + self.padding_size.0, self.padding_size.1, num_channels, mb_size]));
-                let pad_bottom = constant(0.0 as PrimitiveType, Dim4::new(&[self.padding_size.2, width + self.padding_size.1, num_channels, mb_size]));
-                let pad_left = constant(0.0 as PrimitiveType, Dim4::new(&[height + self.padding_size.0 + self.padding_size.2, self.padding_size.3, num_channels, mb_size]));
-                let mut padded = join(0, &pad_top, input);
-                padded = join(1, &padded, &pad_right);
-                padded = join(0, &padded, &pad_bottom);
-                padded = join(1, &pad_left, &padded);
-                Some(padded)
+                self.padding_size = same_padding_size(height, width, h_out, w_out, self.dilated_kernel_size(), self.stride);
             },
             Padding::Valid => {
-                None
-            }
+                self.padding_size = (0, 0, 0, 0);
+            },
+            Padding::Explicit => {},
+            Padding::Causal => {
+                self.padding_size = causal_padding_size(height, width, w_out, self.dilated_kernel_size(), self.stride);
+            },
         }
     }
 
-    /// Converts the image into a columns representation.
-    ///
-    /// This is done for computation speed but there is a memory cost.
-    fn img_to_col(&self, input: &Tensor) -> Tensor {
-        let num_channels = input.dims().get()[2];
-        let mut col = unwrap(input, self.kernel_size.0 as i64, self.kernel_size.1 as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true);
-        //col = reorder(&col, Dim4::new(&[0, 2, 1, 3]));
-        col = reorder_v2(&col, 0, 2, Some(vec![1, 3]));
-        moddims(&col, Dim4::new(&[col.dims().get()[0] * num_channels, col.elements() as u64/(col.dims().get()[0] * num_channels), 1, 1]))
-    }
-
     /// Transforms a columns representation of an image into an image with dimensions height x width x channels.
     fn col_to_img(&self, input: &Tensor) -> Tensor {
         let num_channels = self.input_shape.get()[2];
@@ -281,18 +448,25 @@ impl Conv2D {
         let w_out = self.output_shape.get()[1];
         let num_cols = h_out * w_out;
         let batch_size = input.dims().get()[1] / num_cols;
-        let height_padded = (h_out - 1) * self.stride.0 + self.kernel_size.0;
-        let width_padded = (w_out - 1) * self.stride.1 + self.kernel_size.1;
+        let (kernel_height, kernel_width) = self.dilated_kernel_size();
+        let height_padded = (h_out - 1) * self.stride.0 + kernel_height;
+        let width_padded = (w_out - 1) * self.stride.1 + kernel_width;
 
         let mut img = moddims(&input, Dim4::new(&[input.dims().get()[0], h_out*w_out, 1, batch_size]));
         //img = reorder(&img, Dim4::new(&[1, 0, 2, 3]));
         img = reorder_v2(&img, 1, 0, Some(vec![2, 3]));
-        img = moddims(&img, Dim4::new(&[img.dims().get()[0], self.kernel_size.0 * self.kernel_size.1, num_channels, batch_size]));
+        img = moddims(&img, Dim4::new(&[img.dims().get()[0], kernel_height * kernel_width, num_channels, batch_size]));
         img = transpose(&img, false);
-        img = wrap(&img, height_padded as i64, width_padded as i64, self.kernel_size.0 as i64, self.kernel_size.1 as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true);
+        img = wrap(&img, height_padded as i64, width_padded as i64, kernel_height as i64, kernel_width as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true);
 
-        // Remove padding
-        index(&img, &[Seq::new(self.padding_size.0 as f32, (height_padded - self.padding_size.2 - 1) as f32, 1.0), Seq::new(self.padding_size.3 as f32, (width_padded - self.padding_size.1 - 1) as f32, 1.0), Seq::default(), Seq::default()])
+        self.remove_padding(&img)
+    }
+
+    /// Removes the padding added to the images.
+    fn remove_padding(&self, img: &Tensor) -> Tensor {
+        let height_padded = img.dims().get()[0];
+        let width_padded = img.dims().get()[1];
+        index(img, &[Seq::new(self.padding_size.0 as f32, (height_padded - self.padding_size.2 - 1) as f32, 1.0), Seq::new(self.padding_size.3 as f32, (width_padded - self.padding_size.1 - 1) as f32, 1.0), Seq::default(), Seq::default()])
     }
 }
 
@@ -301,6 +475,27 @@ impl Layer for Conv2D {
         Self::NAME
     }
 
+    fn accepts_input_shape(&self, input_shape: Dim4) -> bool {
+        let height = input_shape.get()[0];
+        let width = input_shape.get()[1];
+        let num_channels = input_shape.get()[2];
+        if num_channels % self.groups != 0 { return false; }
+
+        let (kernel_height, kernel_width) = self.dilated_kernel_size();
+        match self.padding {
+            Padding::Valid => height >= kernel_height && width >= kernel_width,
+            Padding::Explicit => {
+                height + self.padding_size.0 + self.padding_size.2 >= kernel_height
+                    && width + self.padding_size.1 + self.padding_size.3 >= kernel_width
+            },
+            Padding::Same | Padding::Causal => true,
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
     fn initialize_parameters(&mut self, input_shape: Dim4) {
         let height = input_shape.get()[0];
         let width = input_shape.get()[1];
@@ -308,41 +503,56 @@ impl Layer for Conv2D {
 
         // Compute output dimensions and padding size
         let (h_out, w_out) = match self.padding {
-            Padding::Same => {
+            Padding::Same | Padding::Causal => {
                 ((height as f64 / self.stride.0 as f64).ceil() as u64, (width as f64 / self.stride.1 as f64).ceil() as u64)
             },
             Padding::Valid => {
-                ((((height - self.kernel_size.0 + 1) as f64) / self.stride.0 as f64).ceil() as u64, (((width - self.kernel_size.1 + 1) as f64) / self.stride.1 as f64).ceil() as u64)
+                let (kernel_height, kernel_width) = self.dilated_kernel_size();
+                ((((height - kernel_height + 1) as f64) / self.stride.0 as f64).ceil() as u64, (((width - kernel_width + 1) as f64) / self.stride.1 as f64).ceil() as u64)
+            },
+            Padding::Explicit => {
+                let (kernel_height, kernel_width) = self.dilated_kernel_size();
+                (conv_output_size(height, kernel_height, self.stride.0, self.padding_size.0 + self.padding_size.2),
+                 conv_output_size(width, kernel_width, self.stride.1, self.padding_size.1 + self.padding_size.3))
             }
         };
         self.compute_padding_size(height, width, h_out, w_out);
 
+        assert_eq!(num_channels % self.groups, 0, "The number of input channels must be divisible by the number of groups.");
         let receptive_field = self.kernel_size.0 * self.kernel_size.1;
-        let fan_in = receptive_field * num_channels;
-        let fan_out = receptive_field * self.num_filters;
+        let channels_per_group = num_channels / self.groups;
+        let fan_in = receptive_field * channels_per_group;
+        let fan_out = receptive_field * self.num_filters / self.groups;
         self.output_shape = Dim4::new(&[h_out, w_out, self.num_filters, 1]);
         self.input_shape = input_shape;
 
         // Initialize weights and biases
-        self.weights = self.weights_initializer.new_tensor(Dim4::new(&[self.num_filters, receptive_field * num_channels, 1, 1]), fan_in, fan_out);
+        self.weights = self.weights_initializer.new_tensor(Dim4::new(&[self.num_filters, receptive_field * channels_per_group, 1, 1]), fan_in, fan_out);
         self.biases = self.biases_initializer.new_tensor(Dim4::new(&[self.num_filters, 1, 1, 1]), fan_in, fan_out);
     }
 
     fn compute_activation(&self, input: &Tensor) -> Tensor {
-        let (linear_activation, _) = self.compute_convolution(input);
-        linear_activation.eval();
-        let nonlinear_activation = self.activation.eval(&linear_activation);
-        nonlinear_activation.eval();
-        nonlinear_activation
+        let linear_activation = match self.algorithm {
+            ConvAlgorithm::Im2Col => DefaultBackend::conv2d(input, &self.dilated_weights(), &self.biases, self.dilated_kernel_size(), self.stride, self.padding_size),
+            ConvAlgorithm::Native => self.compute_convolution_native(input),
+        };
+        self.activation.eval(&linear_activation)
     }
 
     fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
-        let (linear_activation, reshaped_input) = self.compute_convolution(input);
-        linear_activation.eval();
-        reshaped_input.eval();
-        self.reshaped_input = reshaped_input;
+        let linear_activation = match self.algorithm {
+            ConvAlgorithm::Im2Col => {
+                let (linear_activation, reshaped_input) = self.compute_convolution(input);
+                reshaped_input.eval();
+                self.reshaped_input = reshaped_input;
+                linear_activation
+            },
+            ConvAlgorithm::Native => self.compute_convolution_native(input),
+        };
 
+        // The bias addition and the activation are fused in a single kernel
         let nonlinear_activation = self.activation.eval(&linear_activation);
+        eval_tensors(&[&linear_activation, &nonlinear_activation]);
 
         self.linear_activation = Some(linear_activation);
         self.previous_activation = Some(input.copy());
@@ -355,17 +565,22 @@ impl Layer for Conv2D {
         match &self.linear_activation {
             Some(linear_activation) => {
                 let mut linear_activation_grad = mul(input, &self.activation.grad(linear_activation), true);
+                if self.algorithm == ConvAlgorithm::Native {
+                    let linear_activation = linear_activation.clone();
+                    return self.compute_gradients_native(&linear_activation, &linear_activation_grad);
+                }
+
                 //linear_activation_grad = reorder(&linear_activation_grad, Dim4::new(&[2, 0, 1, 3]));
                 linear_activation_grad = reorder_v2(&linear_activation_grad, 2, 0, Some(vec![1, 3]));
                 linear_activation_grad = moddims(&linear_activation_grad, Dim4::new(&[self.num_filters, linear_activation_grad.elements() as u64 / self.num_filters, 1, 1]));
 
                 self.dbiases = sum(&linear_activation_grad, 1) / input.dims().get()[3];
 
-                let weights_grad = matmul(&linear_activation_grad, &self.reshaped_input, MatProp::NONE, MatProp::TRANS);
-                self.dweights = weights_grad / input.dims().get()[3];
+                let weights_grad = DefaultBackend::matmul(&linear_activation_grad, &self.reshaped_input, false, true);
+                self.dweights = self.undilate_gradient(&weights_grad) / input.dims().get()[3];
                 if let Some(regularizer) = self.regularizer {  self.dweights += regularizer.grad(&self.weights) }
 
-                let input_grad = matmul(&self.weights, &linear_activation_grad, MatProp::TRANS, MatProp::NONE);
+                let input_grad = DefaultBackend::matmul(&self.dilated_weights(), &linear_activation_grad, true, false);
                 self.col_to_img(&input_grad)
             },
             None => panic!("The linear activations have not been computed!"),
@@ -386,6 +601,22 @@ impl Layer for Conv2D {
         Some((vec![&mut self.weights, &mut self.biases], vec![&self.dweights, &self.dbiases]))
     }
 
+    fn gradients_mut(&mut self) -> Option<Vec<&mut Tensor>> {
+        Some(vec![&mut self.dweights, &mut self.dbiases])
+    }
+
+    fn fold_affine_transformation(&mut self, scale: &Tensor, shift: &Tensor) -> bool {
+        // The transformation must be applied along the channels
+        if !matches!(self.activation, Activation::Linear) || scale.dims().get() != &[1, 1, self.num_filters, 1] {
+            return false;
+        }
+        let dims = Dim4::new(&[self.num_filters, 1, 1, 1]);
+        let (scale, shift) = (moddims(scale, dims), moddims(shift, dims));
+        self.weights = mul(&self.weights, &scale, true);
+        self.biases = add(&mul(&self.biases, &scale, false), &shift, false);
+        true
+    }
+
 
     fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
         let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
@@ -400,12 +631,21 @@ impl Layer for Conv2D {
         let stride = conv2d.new_dataset::<[u64; 2]>().create("stride", 1)?;
         stride.write(&[[self.stride.0, self.stride.1]])?;
 
+        let dilation = conv2d.new_dataset::<[u64; 2]>().create("dilation", 1)?;
+        dilation.write(&[[self.dilation.0, self.dilation.1]])?;
+
+        let groups = conv2d.new_dataset::<u64>().create("groups", 1)?;
+        groups.write(&[self.groups])?;
+
         let padding = conv2d.new_dataset::<Padding>().create("padding", 1)?;
         padding.write(&[self.padding])?;
 
         let padding_size = conv2d.new_dataset::<[u64; 4]>().create("padding_size", 1)?;
         padding_size.write(&[[self.padding_size.0, self.padding_size.1, self.padding_size.2, self.padding_size.3]])?;
 
+        let algorithm = conv2d.new_dataset::<ConvAlgorithm>().create("algorithm", 1)?;
+        algorithm.write(&[self.algorithm])?;
+
         let num_filters = conv2d.new_dataset::<u64>().create("num_filters", 1)?;
         num_filters.write(&[self.num_filters])?;
 
@@ -415,11 +655,9 @@ impl Layer for Conv2D {
         let output_shape = conv2d.new_dataset::<[u64; 4]>().create("output_shape", 1)?;
         output_shape.write(&[*self.output_shape.get()])?;
 
-        let weights = conv2d.new_dataset::<H5Tensor>().create("weights", 1)?;
-        weights.write(&[ H5Tensor::from(&self.weights) ])?;
+        write_tensor(&conv2d, "weights", &self.weights)?;
 
-        let biases = conv2d.new_dataset::<H5Tensor>().create("biases", 1)?;
-        biases.write(&[ H5Tensor::from(&self.biases) ])?;
+        write_tensor(&conv2d, "biases", &self.biases)?;
 
         let weights_initializer = conv2d.new_dataset::<H5Initializer>().create("weights_initializer", 1)?;
         let biases_initializer = conv2d.new_dataset::<H5Initializer>().create("biases_initializer", 1)?;
@@ -430,6 +668,21 @@ impl Layer for Conv2D {
         Ok(())
     }
 
+    fn activation(&self) -> Option<Activation> {
+        Some(self.activation)
+    }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::Conv2D {
+            activation: CpuActivation::from(self.activation),
+            kernel_size: self.dilated_kernel_size(),
+            stride: self.stride,
+            padding_size: self.padding_size,
+            weights: CpuTensor::from(&self.dilated_weights()),
+            biases: CpuTensor::from(&self.biases),
+        })
+    }
+
 
     fn set_regularizer(&mut self, regularizer: Option<Regularizer>) {
         self.regularizer = regularizer;
@@ -447,7 +700,7 @@ impl fmt::Display for Conv2D {
 #[cfg(test)]
 mod tests {
     use crate::layers::{Conv2D, Layer};
-    use crate::layers::Padding;
+    use crate::layers::{ConvAlgorithm, Padding};
     use crate::activations::Activation;
     use crate::initializers::Initializer;
     use crate::tensor::*;
@@ -460,8 +713,11 @@ mod tests {
             activation: Activation::Linear,
             kernel_size: (2, 2),
             stride: (1, 1),
+            dilation: (1, 1),
+            groups: 1,
             padding: Padding::Valid,
             padding_size: (0, 0, 0, 0), // top, right, bottom, left
+            algorithm: ConvAlgorithm::Im2Col,
             num_filters: 2,
             input_shape: Dim::new(&[3, 3, 3, 1]),
             output_shape: Dim::new(&[2, 2, 2, 1]),
@@ -498,6 +754,23 @@ mod tests {
         assert_approx_eq!(output, expected_output);
     }
 
+    #[test]
+    fn test_conv2d_causal_padding() {
+        let mut layer = Conv2D::with_param(1, (2, 1), (1, 1), Padding::Causal, Activation::Linear, Initializer::Ones, Initializer::Zeros);
+        layer.initialize_parameters(Dim::new(&[4, 1, 1, 1]));
+        assert_eq!(layer.padding_size, (1, 0, 0, 0));
+        assert_eq!(layer.output_shape().get(), &[4, 1, 1, 1]);
+
+        // Each output is the sum of the current and previous timesteps
+        let input = Tensor::new(&[1., 2., 3., 4.], Dim::new(&[4, 1, 1, 1]));
+        let layer_output = layer.compute_activation(&input);
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        layer_output.host(&mut output);
+        let expected_output: [PrimitiveType; 4] = [1., 3., 5., 7.];
+
+        assert_approx_eq!(output, expected_output);
+    }
+
     #[test]
     fn test_conv2d_input_gradient() {
         let mut layer = create_test_layer();
@@ -559,4 +832,105 @@ mod tests {
 
         assert_approx_eq!(output, expected_output);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_conv2d_native_algorithm() {
+        let images = create_test_images();
+        let input_vec = [1., -4., -2., 1., -1., -3., 1., 2., -2., 1., 3., -1., 4., -1., 2., -4.];
+        let input = Tensor::new(&input_vec, Dim::new(&[2, 2, 2, 2]));
+
+        let mut im2col_layer = create_test_layer();
+        let mut native_layer = create_test_layer();
+        native_layer.set_algorithm(ConvAlgorithm::Native);
+        let max_difference = |a: &Tensor, b: &Tensor| max_all(&abs(&(a - b))).0;
+
+        // Both algorithms compute the same activations and gradients
+        let im2col_output = im2col_layer.compute_activation_mut(&images);
+        let native_output = native_layer.compute_activation_mut(&images);
+        assert!(max_difference(&im2col_output, &native_output) < 1e-4);
+        assert!(max_difference(&im2col_layer.compute_activation(&images), &native_layer.compute_activation(&images)) < 1e-4);
+
+        let im2col_input_grad = im2col_layer.compute_dactivation_mut(&input);
+        let native_input_grad = native_layer.compute_dactivation_mut(&input);
+        assert!(max_difference(&im2col_input_grad, &native_input_grad) < 1e-4);
+        assert!(max_difference(&im2col_layer.dweights, &native_layer.dweights) < 1e-4);
+        assert!(max_difference(&im2col_layer.dbiases, &native_layer.dbiases) < 1e-4);
+    }
+
+    #[test]
+    fn test_conv2d_dilation() {
+        let images = create_test_images();
+        let max_difference = |a: &Tensor, b: &Tensor| max_all(&abs(&(a - b))).0;
+
+        let mut dilated_layer = create_test_layer();
+        let weights = dilated_layer.weights.copy();
+        dilated_layer.set_dilation((2, 2));
+        dilated_layer.initialize_parameters(Dim::new(&[3, 3, 3, 1]));
+        dilated_layer.weights = weights;
+        assert_eq!(dilated_layer.output_shape().get(), &[1, 1, 2, 1]);
+
+        // A 2x2 kernel with a dilation rate of 2 is equivalent to a 3x3 kernel with zeros between its entries
+        let mut layer = create_test_layer();
+        layer.kernel_size = (3, 3);
+        layer.initialize_parameters(Dim::new(&[3, 3, 3, 1]));
+        layer.weights = dilated_layer.dilated_weights();
+        let mut dilated_weights: [PrimitiveType; 54] = [0.; 54];
+        layer.weights.host(&mut dilated_weights);
+        assert_eq!(dilated_weights[..6], [1., 1., 0., 0., 1., 2.]);
+
+        let output = dilated_layer.compute_activation_mut(&images);
+        let expected_output = layer.compute_activation_mut(&images);
+        assert_eq!(output.dims().get(), &[1, 1, 2, 2]);
+        assert!(max_difference(&output, &expected_output) < 1e-4);
+
+        // Only the entries of the original kernels receive a gradient
+        let input = Tensor::new(&[1., -2., 3., -1.], Dim::new(&[1, 1, 2, 2]));
+        let input_grad = dilated_layer.compute_dactivation_mut(&input);
+        let expected_input_grad = layer.compute_dactivation_mut(&input);
+        assert!(max_difference(&input_grad, &expected_input_grad) < 1e-4);
+        assert_eq!(dilated_layer.dweights.dims(), dilated_layer.weights.dims());
+        assert!(max_difference(&dilated_layer.dweights, &dilated_layer.undilate_gradient(&layer.dweights)) < 1e-4);
+
+        // The native algorithm supports the dilation as well
+        dilated_layer.set_algorithm(ConvAlgorithm::Native);
+        assert!(max_difference(&dilated_layer.compute_activation(&images), &expected_output) < 1e-4);
+    }
+
+    #[test]
+    fn test_conv2d_groups() {
+        let max_difference = |a: &Tensor, b: &Tensor| max_all(&abs(&(a - b))).0;
+        let channel = |tensor: &Tensor, channel: u64| index(tensor, &[Seq::default(), Seq::default(), Seq::new(channel as f64, channel as f64, 1.), Seq::default()]);
+        let filter = |tensor: &Tensor, filter: u64| index(tensor, &[Seq::new(filter as f64, filter as f64, 1.), Seq::default(), Seq::default(), Seq::default()]);
+        let images = index(&create_test_images(), &[Seq::default(), Seq::default(), Seq::new(0., 1., 1.), Seq::default()]);
+        let input_vec = [1., -4., -2., 1., -1., -3., 1., 2., -2., 1., 3., -1., 4., -1., 2., -4.];
+        let input = Tensor::new(&input_vec, Dim::new(&[2, 2, 2, 2]));
+
+        let mut layer = create_test_layer();
+        layer.set_groups(2);
+        layer.initialize_parameters(Dim::new(&[3, 3, 2, 1]));
+        assert_eq!(layer.weights.dims().get(), &[2, 4, 1, 1]);
+        let output = layer.compute_activation_mut(&images);
+        let input_grad = layer.compute_dactivation_mut(&input);
+        assert_eq!(layer.dweights.dims(), layer.weights.dims());
+
+        // Each filter is only connected to its group of input channels
+        for group in 0..2 {
+            let mut group_layer = create_test_layer();
+            group_layer.num_filters = 1;
+            group_layer.initialize_parameters(Dim::new(&[3, 3, 1, 1]));
+            group_layer.weights = filter(&layer.weights, group);
+            group_layer.biases = filter(&layer.biases, group);
+
+            let group_output = group_layer.compute_activation_mut(&channel(&images, group));
+            assert!(max_difference(&channel(&output, group), &group_output) < 1e-4);
+            let group_input_grad = group_layer.compute_dactivation_mut(&channel(&input, group));
+            assert!(max_difference(&channel(&input_grad, group), &group_input_grad) < 1e-4);
+            assert!(max_difference(&filter(&layer.dweights, group), &group_layer.dweights) < 1e-4);
+        }
+
+        // The native algorithm supports the groups as well
+        let expected_output = layer.compute_activation(&images);
+        layer.set_algorithm(ConvAlgorithm::Native);
+        assert!(max_difference(&layer.compute_activation(&images), &expected_output) < 1e-4);
+    }
+}
@@ -4,7 +4,10 @@ use std::convert::TryInto;
 use std::fmt;
 
 use crate::activations::*;
+use crate::backend::{Backend, DefaultBackend};
+use crate::cpu::{CpuActivation, CpuLayer, CpuTensor};
 use crate::errors::Error;
+use crate::io::{read_tensor, write_tensor};
 use crate::layers::*;
 use crate::initializers::*;
 use crate::regularizers::*;
@@ -12,6 +15,7 @@ use crate::tensor::*;
 
 
 /// Defines a dense (or fully connected) layer.
+#[derive(Clone)]
 pub struct Dense
 {
     units: u64,
@@ -24,6 +28,7 @@ pub struct Dense
     output_shape: Dim,
     linear_activation: Option<Tensor>,
     previous_input: Option<Tensor>,
+    sparse_input: Option<SparseTensor>,
     weights_initializer: Initializer,
     biases_initializer: Initializer,
     regularizer: Option<Regularizer>,
@@ -50,6 +55,7 @@ impl Dense
             output_shape: Dim::new(&[units, 1, 1, 1]),
             linear_activation: None,
             previous_input: None,
+            sparse_input: None,
             weights_initializer: Initializer::HeNormal,
             biases_initializer: Initializer::Zeros,
             regularizer: None,
@@ -73,6 +79,7 @@ impl Dense
             output_shape: Dim::new(&[units, 1, 1, 1]),
             linear_activation: None,
             previous_input: None,
+            sparse_input: None,
             weights_initializer,
             biases_initializer,
             regularizer: None,
@@ -83,8 +90,8 @@ impl Dense
         let _ = hdf5::silence_errors();
         let units = group.dataset("units").and_then(|ds| ds.read_raw::<u64>()).expect("Could not retrieve the number of units.");
         let activation: Vec<u8> = group.dataset("activation").and_then(|ds| ds.read_raw::<u8>()).expect("Could not retrieve the activation.");
-        let weights = group.dataset("weights").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the weights.");
-        let biases = group.dataset("biases").and_then(|ds| ds.read_raw::<H5Tensor>()).expect("Could not retrieve the biases.");
+        let weights = read_tensor(group, "weights").expect("Could not retrieve the weights.");
+        let biases = read_tensor(group, "biases").expect("Could not retrieve the biases.");
         let input_shape = group.dataset("input_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the input shape.");
         let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
         let regularizer = Regularizer::from_hdf5_group(group);
@@ -94,14 +101,15 @@ impl Dense
         Box::new(Self {
             units: units[0],
             activation: activation[0].try_into().expect("Could not create activation variant."),
-            weights: Tensor::from(&weights[0]),
+            weights,
             dweights: Tensor::new_empty_tensor(),
-            biases: Tensor::from(&biases[0]),
+            biases,
             dbiases: Tensor::new_empty_tensor(),
             input_shape: Dim::new(&(input_shape[0])),
             output_shape: Dim::new(&(output_shape[0])),
             linear_activation: None,
             previous_input: None,
+            sparse_input: None,
             weights_initializer: Initializer::from(&weights_initializer[0]),
             biases_initializer: Initializer::from(&biases_initializer[0]),
             regularizer,
@@ -109,12 +117,30 @@ impl Dense
     }
 }
 
+impl Dense
+{
+    /// Computes the linear activation for sparse inputs.
+    ///
+    /// ArrayFire only supports sparse matrices on the left-hand side of a product, such that the transpose of the
+    /// linear activation is computed.
+    fn compute_linear_activation_sparse(&self, input: &SparseTensor) -> Tensor {
+        assert_eq!(input.num_features(), self.weights.dims().get()[1], "The number of features does not match the input shape of the layer.");
+        let product = matmul(input.csr(), &transpose(&self.weights, false), MatProp::NONE, MatProp::NONE);
+        let linear_activation = moddims(&transpose(&product, false), Dim4::new(&[self.units, 1, 1, input.batch_size()]));
+        add(&linear_activation, &self.biases, true)
+    }
+}
+
 impl Layer for Dense
 {
     fn name(&self) -> &str {
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
     fn initialize_parameters(&mut self, input_shape: Dim) {
         let fan_in = input_shape.get()[0] * input_shape.get()[1] * input_shape.get()[2];
         let fan_out = self.units;
@@ -124,16 +150,36 @@ impl Layer for Dense
     }
 
     fn compute_activation(&self, input: &Tensor) -> Tensor {
-        let linear_activation = add(&matmul(&self.weights, &input, MatProp::NONE, MatProp::NONE), &self.biases, true);
+        let linear_activation = add(&DefaultBackend::matmul(&self.weights, &input, false, false), &self.biases, true);
         self.activation.eval(&linear_activation)
     }
 
+    fn compute_activation_sparse(&self, input: &SparseTensor) -> Option<Tensor> {
+        Some(self.activation.eval(&self.compute_linear_activation_sparse(input)))
+    }
+
+    fn compute_activation_sparse_mut(&mut self, input: &SparseTensor) -> Option<Tensor> {
+        let linear_activation = self.compute_linear_activation_sparse(input);
+        let nonlinear_activation = self.activation.eval(&linear_activation);
+        eval_tensors(&[&linear_activation, &nonlinear_activation]);
+
+        self.previous_input = None;
+        self.sparse_input = Some(input.clone());
+        self.linear_activation = Some(linear_activation);
+
+        Some(nonlinear_activation)
+    }
+
     fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
-        let linear_activation = add(&matmul(&self.weights, input, MatProp::NONE, MatProp::NONE), &self.biases, true);
+        let linear_activation = add(&DefaultBackend::matmul(&self.weights, input, false, false), &self.biases, true);
         let nonlinear_activation = self.activation.eval(&linear_activation);
 
+        // The bias addition and the activation are fused in a single kernel
+        eval_tensors(&[&linear_activation, &nonlinear_activation]);
+
         // Save input and linear activation for efficient backprop
         self.previous_input = Some(input.clone());
+        self.sparse_input = None;
         self.linear_activation = Some(linear_activation);
 
         // Return the non linear activation
@@ -145,16 +191,27 @@ impl Layer for Dense
         match &self.linear_activation {
             Some(linear_activation) => {
                 let linear_activation_grad = mul(input, &self.activation.grad(linear_activation), true);
+
+                // The gradient wrt the sparse inputs is not computed since the layer is the first of the network
+                if let Some(sparse_input) = &self.sparse_input {
+                    let batch_size = sparse_input.batch_size();
+                    let grad = transpose(&moddims(&linear_activation_grad, Dim4::new(&[self.units, batch_size, 1, 1])), false);
+                    self.dweights = transpose(&matmul(sparse_input.csr(), &grad, MatProp::TRANS, MatProp::NONE), false) / batch_size as PrimitiveType;
+                    if let Some(regularizer) = self.regularizer { self.dweights += regularizer.grad(&self.weights) }
+                    self.dbiases = linear_activation_grad.reduce(Reduction::MeanBatches);
+                    return Tensor::new_empty_tensor();
+                }
+
                 match &mut self.previous_input {
                     Some(previous_input) => {
-                        self.dweights = matmul(&linear_activation_grad, previous_input, MatProp::NONE, MatProp::TRANS).reduce(Reduction::MeanBatches);
+                        self.dweights = DefaultBackend::matmul(&linear_activation_grad, previous_input, false, true).reduce(Reduction::MeanBatches);
                         if let Some(regularizer) = self.regularizer { self.dweights += regularizer.grad(&self.weights) }
                         self.dbiases = linear_activation_grad.reduce(Reduction::MeanBatches);
                     },
                     None => panic!("The previous activations have not been computed!"),
                 }
                 //matmul(&self.weights, &linear_activation_grad, MatProp::TRANS, MatProp::NONE).reshape(Dim4::new(&[self.input_shape[0], self.input_shape[1], self.input_shape[2], input.batch_size()]))
-                matmul(&self.weights, &linear_activation_grad, MatProp::TRANS, MatProp::NONE)
+                DefaultBackend::matmul(&self.weights, &linear_activation_grad, true, false)
             },
             None => panic!("The linear activations z have not been computed!"),
         }
@@ -174,6 +231,20 @@ impl Layer for Dense
         Some((vec![&mut self.weights, &mut self.biases], vec![&self.dweights, &self.dbiases]))
     }
 
+    fn gradients_mut(&mut self) -> Option<Vec<&mut Tensor>> {
+        Some(vec![&mut self.dweights, &mut self.dbiases])
+    }
+
+    fn fold_affine_transformation(&mut self, scale: &Tensor, shift: &Tensor) -> bool {
+        // The transformation must be applied along the units
+        if !matches!(self.activation, Activation::Linear) || scale.dims().get() != &[self.units, 1, 1, 1] {
+            return false;
+        }
+        self.weights = mul(&self.weights, scale, true);
+        self.biases = add(&mul(&self.biases, scale, false), shift, false);
+        true
+    }
+
 
     fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
         let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
@@ -185,11 +256,9 @@ impl Layer for Dense
         let activation = dense.new_dataset::<Activation>().create("activation", 1)?;
         activation.write(&[self.activation])?;
 
-        let weights = dense.new_dataset::<H5Tensor>().create("weights", 1)?;
-        weights.write(&[H5Tensor::from(&self.weights)])?;
+        write_tensor(&dense, "weights", &self.weights)?;
 
-        let biases = dense.new_dataset::<H5Tensor>().create("biases", 1)?;
-        biases.write(&[H5Tensor::from(&self.biases)])?;
+        write_tensor(&dense, "biases", &self.biases)?;
 
         let input_shape = dense.new_dataset::<[u64; 4]>().create("input_shape", 1)?;
         input_shape.write(&[*self.input_shape.get()])?;
@@ -206,6 +275,18 @@ impl Layer for Dense
         Ok(())
     }
 
+    fn activation(&self) -> Option<Activation> {
+        Some(self.activation)
+    }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::Dense {
+            activation: CpuActivation::from(self.activation),
+            weights: CpuTensor::from(&self.weights),
+            biases: CpuTensor::from(&self.biases),
+        })
+    }
+
     fn set_regularizer(&mut self, regularizer: Option<Regularizer>) {
         self.regularizer = regularizer;
     }
@@ -244,6 +325,7 @@ mod tests {
             output_shape: Dim::new(&[2, 1, 1, 1]),
             linear_activation: None,
             previous_input: None,
+            sparse_input: None,
             weights_initializer: Initializer::HeUniform,
             biases_initializer: Initializer::Zeros,
             regularizer: None,
@@ -314,4 +396,25 @@ mod tests {
 
         assert_approx_eq!(output, expected_output);
     }
+
+    #[test]
+    fn test_dense_sparse_input() {
+        let mut layer = create_test_layer();
+        let input_forward = SparseTensor::from_dense(&Tensor::new(&[-2., 0., 4., 0., -1., 0.], Dim::new(&[3, 1, 1, 2])));
+        assert_eq!(input_forward.nnz(), 3);
+
+        let layer_output = layer.compute_activation_sparse_mut(&input_forward).unwrap();
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        layer_output.host(&mut output);
+        let expected_output: [PrimitiveType; 4] = [-28., 3., -3., -4.];
+        assert_approx_eq!(output, expected_output);
+
+        // The gradients match the ones computed with the dense inputs
+        let input_backward = Tensor::new(&[1., -2., -1., 3.], Dim::new(&[2, 1, 1, 2]));
+        let _ = layer.compute_dactivation_mut(&input_backward);
+        let mut output: [PrimitiveType; 6] = [0.; 6];
+        layer.dweights.host(&mut output);
+        let expected_output: [PrimitiveType; 6] = [-1., 2., 0.5, -1.5, 2., -4.];
+        assert_approx_eq!(output, expected_output);
+    }
 }
\ No newline at end of file
@@ -1,11 +1,11 @@
 //! Dropout layer
 use arrayfire::*;
-use rand::prelude::*;
 use std::fmt;
 
+use crate::cpu::CpuLayer;
 use crate::errors::Error;
 use crate::io::{write_scalar, read_scalar};
-use crate::layers::Layer;
+use crate::layers::{Layer, LayerRandomEngine};
 use crate::tensor::*;
 
 /// Defines a dropout layer.
@@ -13,8 +13,9 @@ pub struct Dropout {
     drop_rate: f64,
     output_shape: Dim,
     grad: Tensor,
-    random_engine: RandomEngine,
-    scaling_factor: PrimitiveType
+    random_engine: LayerRandomEngine,
+    scaling_factor: PrimitiveType,
+    training: bool,
 }
 
 impl Dropout {
@@ -36,24 +37,21 @@ impl Dropout {
             panic!("The drop rate is invalid.");
         }
 
-        let mut rng = rand::thread_rng();
-        let seed: u64 = rng.gen();
-        let random_engine = RandomEngine::new(RandomEngineType::PHILOX_4X32_10, Some(seed));
-
         let scaling_factor = 1. / (1. - drop_rate) as PrimitiveType;
 
         Box::new(Dropout {
             drop_rate,
             output_shape: Dim4::new(&[0, 0, 0, 0]),
             grad: Tensor::new_empty_tensor(),
-            random_engine,
+            random_engine: LayerRandomEngine::new(),
             scaling_factor,
+            training: true,
         })
     }
 
     /// Generates a binomial mask to let some values pass through the layer.
-    fn generate_binomial_mask(&self, dims: Dim4) -> Tensor {
-        let random_values = random_uniform::<f64>(dims, &self.random_engine);
+    fn generate_binomial_mask(&mut self, dims: Dim4) -> Tensor {
+        let random_values = random_uniform::<f64>(dims, self.random_engine.get_mut());
         let cond = gt(&random_values, &self.drop_rate, true);
         cond.cast()
     }
@@ -63,18 +61,15 @@ impl Dropout {
         let drop_rate = group.dataset("drop_rate").and_then(|ds| Ok(read_scalar::<f64>(&ds))).expect("Could not retrieve the drop rate.");
         let output_shape = group.dataset("output_shape").and_then(|value| value.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
 
-        let mut rng = rand::thread_rng();
-        let seed: u64 = rng.gen();
-        let random_engine = RandomEngine::new(RandomEngineType::PHILOX_4X32_10, Some(seed));
-
         let scaling_factor = 1. / (1. - drop_rate) as PrimitiveType;
 
         Box::new(Self {
             drop_rate,
             output_shape: Dim::new(&(output_shape[0])),
             grad: Tensor::new_empty_tensor(),
-            random_engine,
+            random_engine: LayerRandomEngine::new(),
             scaling_factor,
+            training: true,
         })
     }
 }
@@ -84,6 +79,10 @@ impl Layer for Dropout {
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
     fn initialize_parameters(&mut self, input_shape: Dim4) {
         self.output_shape = input_shape;
     }
@@ -92,7 +91,17 @@ impl Layer for Dropout {
         prev_activation.copy()
     }
 
+    fn compute_activation_stochastic(&self, prev_activation: &Tensor) -> Tensor {
+        // The layer is shared, so the mask is drawn from the default engine of ArrayFire instead of the layer's engine
+        let mask: Tensor = gt(&randu::<f64>(prev_activation.dims()), &self.drop_rate, true).cast();
+        &(prev_activation * &mask) * self.scaling_factor
+    }
+
     fn compute_activation_mut(&mut self, prev_activation: &Tensor) -> Tensor {
+        if !self.training {
+            return prev_activation.copy();
+        }
+
         let mask = self.generate_binomial_mask(prev_activation.dims());
         let output = prev_activation * &mask;
         self.grad = mask;
@@ -102,6 +111,9 @@ impl Layer for Dropout {
     }
 
     fn compute_dactivation_mut(&mut self, dz: &Tensor) -> Tensor {
+        if !self.training {
+            return dz.copy();
+        }
         &self.grad * dz * self.scaling_factor
     }
 
@@ -122,10 +134,60 @@ impl Layer for Dropout {
 
         Ok(())
     }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::Identity)
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.random_engine.set_seed(seed);
+    }
+}
+
+impl Clone for Dropout {
+    /// Clones the layer with a new random engine, such that the clones draw different masks.
+    fn clone(&self) -> Dropout {
+        Dropout {
+            drop_rate: self.drop_rate,
+            output_shape: self.output_shape,
+            grad: self.grad.clone(),
+            random_engine: LayerRandomEngine::new(),
+            scaling_factor: self.scaling_factor,
+            training: self.training,
+        }
+    }
 }
 
 impl fmt::Display for Dropout {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} \t 0  \t\t [{}, {}, {}]", Self::NAME, self.output_shape[0], self.output_shape[1], self.output_shape[2])
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::layers::{Dropout, Layer};
+    use crate::assert_approx_eq;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_dropout_inference_mode() {
+        let mut layer = Dropout::new(0.5);
+        layer.initialize_parameters(Dim4::new(&[4, 1, 1, 1]));
+        layer.set_training(false);
+
+        let input = Tensor::new(&[1., -2., 3., 4.], Dim4::new(&[4, 1, 1, 1]));
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        layer.compute_activation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [1., -2., 3., 4.]);
+
+        layer.compute_dactivation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [1., -2., 3., 4.]);
+    }
+}
@@ -1,10 +1,12 @@
 use hdf5::Group;
 use std::fmt;
 
+use crate::cpu::CpuLayer;
 use crate::errors::Error;
 use crate::layers::Layer;
 use crate::tensor::*;
 
+#[derive(Clone)]
 pub struct Flatten {
     input_shape: Dim,
     output_shape: Dim,
@@ -37,6 +39,10 @@ impl Layer for Flatten {
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
     fn initialize_parameters(&mut self, input_shape: Dim) {
         self.input_shape = input_shape;
         self.output_shape = Dim::new(&[input_shape.get()[0] * input_shape.get()[1] * input_shape.get()[2], 1, 1, 1]);
@@ -70,6 +76,10 @@ impl Layer for Flatten {
 
         Ok(())
     }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::Flatten)
+    }
 }
 
 impl fmt::Display for Flatten {
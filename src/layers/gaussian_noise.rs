@@ -0,0 +1,175 @@
+//! Gaussian noise layer
+use arrayfire::*;
+use std::fmt;
+
+use crate::cpu::CpuLayer;
+use crate::errors::Error;
+use crate::io::{write_scalar, read_scalar};
+use crate::layers::{Layer, LayerRandomEngine};
+use crate::tensor::*;
+
+/// Defines a layer adding zero-mean Gaussian noise to its inputs during training.
+///
+/// The noise regularizes the network, which is especially useful for small networks trained on small tabular data sets.
+/// At inference, the layer lets the values through unchanged.
+pub struct GaussianNoise {
+    stddev: PrimitiveType,
+    output_shape: Dim,
+    random_engine: LayerRandomEngine,
+    training: bool,
+}
+
+impl GaussianNoise {
+
+    pub(crate) const NAME: &'static str = "GaussianNoise";
+
+    /// Creates a Gaussian noise layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `stddev` - The standard deviation of the noise.
+    ///
+    /// # Panics
+    ///
+    /// The method panics if `stddev` is negative.
+    pub fn new(stddev: PrimitiveType) -> Box<GaussianNoise> {
+        assert!(stddev >= 0., "The standard deviation must be positive.");
+
+        Box::new(GaussianNoise {
+            stddev,
+            output_shape: Dim4::new(&[0, 0, 0, 0]),
+            random_engine: LayerRandomEngine::new(),
+            training: true,
+        })
+    }
+
+    /// Adds the noise to the input.
+    fn add_noise(&mut self, input: &Tensor) -> Tensor {
+        let noise = random_normal::<PrimitiveType>(input.dims(), self.random_engine.get_mut());
+        input + &(noise * self.stddev)
+    }
+
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Self> {
+        let _ = hdf5::silence_errors();
+        let stddev = group.dataset("stddev").and_then(|ds| Ok(read_scalar::<PrimitiveType>(&ds))).expect("Could not retrieve the standard deviation.");
+        let output_shape = group.dataset("output_shape").and_then(|value| value.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
+
+        Box::new(Self {
+            stddev,
+            output_shape: Dim::new(&(output_shape[0])),
+            random_engine: LayerRandomEngine::new(),
+            training: true,
+        })
+    }
+}
+
+impl Layer for GaussianNoise {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn initialize_parameters(&mut self, input_shape: Dim4) {
+        self.output_shape = input_shape;
+    }
+
+    fn compute_activation(&self, prev_activation: &Tensor) -> Tensor {
+        prev_activation.copy()
+    }
+
+    fn compute_activation_stochastic(&self, prev_activation: &Tensor) -> Tensor {
+        // The layer is shared, so the noise is drawn from the default engine of ArrayFire instead of the layer's engine
+        prev_activation + &(randn::<PrimitiveType>(prev_activation.dims()) * self.stddev)
+    }
+
+    fn compute_activation_mut(&mut self, prev_activation: &Tensor) -> Tensor {
+        if !self.training {
+            return prev_activation.copy();
+        }
+        self.add_noise(prev_activation)
+    }
+
+    fn compute_dactivation_mut(&mut self, dz: &Tensor) -> Tensor {
+        // The noise is additive, hence the derivative is the identity
+        dz.copy()
+    }
+
+    fn output_shape(&self) -> Dim4 {
+        self.output_shape
+    }
+
+    fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
+        let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
+        let gaussian_noise = group.create_group(&group_name)?;
+
+        let stddev = gaussian_noise.new_dataset::<PrimitiveType>().create("stddev", 1)?;
+        write_scalar(&stddev, &self.stddev);
+
+        let output_shape = gaussian_noise.new_dataset::<[u64; 4]>().create("output_shape", 1)?;
+        output_shape.write(&[*self.output_shape.get()])?;
+
+        Ok(())
+    }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::Identity)
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.random_engine.set_seed(seed);
+    }
+}
+
+impl Clone for GaussianNoise {
+    /// Clones the layer with a new random engine, such that the clones draw different noises.
+    fn clone(&self) -> GaussianNoise {
+        GaussianNoise {
+            stddev: self.stddev,
+            output_shape: self.output_shape,
+            random_engine: LayerRandomEngine::new(),
+            training: self.training,
+        }
+    }
+}
+
+impl fmt::Display for GaussianNoise {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \t 0  \t\t [{}, {}, {}]", Self::NAME, self.output_shape[0], self.output_shape[1], self.output_shape[2])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::layers::{GaussianNoise, Layer};
+    use crate::assert_approx_eq;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_gaussian_noise() {
+        let mut layer = GaussianNoise::new(0.1);
+        layer.initialize_parameters(Dim4::new(&[4, 1, 1, 1]));
+        let input = Tensor::new(&[1., -2., 3., 4.], Dim4::new(&[4, 1, 1, 1]));
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+
+        // The noise is only added in training mode and doesn't change the derivatives
+        let noisy = layer.compute_activation_mut(&input);
+        assert!(sum_all(&neq(&noisy, &input, false)).0 > 0.);
+        layer.compute_dactivation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [1., -2., 3., 4.]);
+
+        layer.compute_activation(&input).host(&mut output);
+        assert_approx_eq!(output, [1., -2., 3., 4.]);
+        layer.set_training(false);
+        layer.compute_activation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [1., -2., 3., 4.]);
+    }
+}
@@ -0,0 +1,123 @@
+//! Global average pooling layer
+use arrayfire::*;
+use hdf5::Group;
+use std::fmt;
+
+use crate::errors::Error;
+use crate::layers::Layer;
+use crate::tensor::*;
+
+/// Defines a 2D global average pooling layer.
+///
+/// The layer averages each channel of the images over their height and width. The output is flat, with one value per
+/// channel, such that it can be fed directly to a [Dense](struct.Dense.html) layer. Unlike a flatten layer, the number
+/// of outputs doesn't depend on the size of the images, which keeps the classification heads small.
+#[derive(Clone)]
+pub struct GlobalAvgPool2D {
+    input_shape: Dim,
+    output_shape: Dim,
+}
+
+impl GlobalAvgPool2D {
+    pub(crate) const NAME: &'static str = "GlobalAvgPool2D";
+
+    /// Creates a 2D global average pooling layer.
+    pub fn new() -> Box<GlobalAvgPool2D> {
+        Box::new(GlobalAvgPool2D {
+            input_shape: Dim::new(&[0, 0, 0, 0]),
+            output_shape: Dim::new(&[0, 0, 0, 0]),
+        })
+    }
+
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<GlobalAvgPool2D> {
+        let input_shape = group.dataset("input_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the input shape.");
+        let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
+
+        Box::new(GlobalAvgPool2D {
+            input_shape: Dim::new(&input_shape[0]),
+            output_shape: Dim::new(&output_shape[0]),
+        })
+    }
+}
+
+impl Layer for GlobalAvgPool2D {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn initialize_parameters(&mut self, input_shape: Dim) {
+        self.input_shape = input_shape;
+        self.output_shape = Dim::new(&[input_shape.get()[2], 1, 1, 1]);
+    }
+
+    fn compute_activation(&self, input: &Tensor) -> Tensor {
+        let dims = input.dims();
+        let num_pixels = (dims[0] * dims[1]) as PrimitiveType;
+        let pooled = sum(&sum(input, 0), 1) / num_pixels;
+        moddims(&pooled, Dim::new(&[dims[2], 1, 1, dims[3]]))
+    }
+
+    fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
+        self.compute_activation(input)
+    }
+
+    fn compute_dactivation_mut(&mut self, input: &Tensor) -> Tensor {
+        // Each pixel receives an equal share of the gradient of its channel
+        let (height, width) = (self.input_shape.get()[0], self.input_shape.get()[1]);
+        let dpooled = moddims(input, Dim::new(&[1, 1, self.input_shape.get()[2], input.dims()[3]]));
+        tile(&dpooled, Dim::new(&[height, width, 1, 1])) / (height * width) as PrimitiveType
+    }
+
+    fn output_shape(&self) -> Dim {
+        self.output_shape
+    }
+
+    fn save(&self, group: &Group, layer_number: usize) -> Result<(), Error> {
+        let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
+        let pooling = group.create_group(&group_name)?;
+
+        let input_shape = pooling.new_dataset::<[u64; 4]>().create("input_shape", 1)?;
+        input_shape.write(&[*self.input_shape.get()])?;
+
+        let output_shape = pooling.new_dataset::<[u64; 4]>().create("output_shape", 1)?;
+        output_shape.write(&[*self.output_shape.get()])?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for GlobalAvgPool2D {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \t 0 \t\t [{}, {}, {}]", Self::NAME, self.output_shape[0], self.output_shape[1], self.output_shape[2])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::assert_approx_eq;
+    use crate::layers::{GlobalAvgPool2D, Layer};
+    use crate::tensor::*;
+
+    #[test]
+    fn test_global_avg_pool() {
+        let mut layer = GlobalAvgPool2D::new();
+        layer.initialize_parameters(Dim4::new(&[2, 2, 2, 1]));
+        assert_eq!(layer.output_shape().get(), &[2, 1, 1, 1]);
+
+        let input = Tensor::new(&[1., 2., 3., 4., 0., 0., 4., 8.], Dim4::new(&[2, 2, 2, 1]));
+        let mut output: [PrimitiveType; 2] = [0.; 2];
+        layer.compute_activation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [2.5, 3.]);
+
+        let dz = Tensor::new(&[4., 8.], Dim4::new(&[2, 1, 1, 1]));
+        let mut dinput: [PrimitiveType; 8] = [0.; 8];
+        layer.compute_dactivation_mut(&dz).host(&mut dinput);
+        assert_approx_eq!(dinput, [1., 1., 1., 1., 2., 2., 2., 2.]);
+    }
+}
@@ -0,0 +1,26 @@
+//! Pre-configured output stacks placed on top of a backbone.
+//!
+//! A head turns the features computed by a backbone, for instance one of the [backbones](../backbones/index.html),
+//! into the outputs of the network. The layers are returned in order and added with
+//! [Network::add_all](../../models/struct.Network.html#method.add_all).
+use super::{Dense, Dropout, GlobalAvgPool2D, Layer};
+use crate::activations::Activation;
+
+/// Creates a classification head for images.
+///
+/// The feature maps are averaged over the height and width of the images, regularized with dropout, and mapped to the
+/// logits of the classes by a dense layer with a linear activation, as expected by the
+/// [SoftmaxCrossEntropy](../../losses/struct.SoftmaxCrossEntropy.html) loss.
+///
+/// # Arguments
+///
+/// * `num_classes` - The number of classes.
+/// * `dropout` - The drop rate applied to the pooled features. No dropout layer is added if the rate is zero.
+pub fn classification(num_classes: u64, dropout: f64) -> Vec<Box<dyn Layer>> {
+    let mut layers: Vec<Box<dyn Layer>> = vec![GlobalAvgPool2D::new()];
+    if dropout > 0. {
+        layers.push(Dropout::new(dropout));
+    }
+    layers.push(Dense::new(num_classes, Activation::Linear));
+    layers
+}
@@ -0,0 +1,168 @@
+//! Masking layer
+use arrayfire::*;
+use std::fmt;
+
+use crate::errors::Error;
+use crate::io::{write_scalar, read_scalar};
+use crate::layers::Layer;
+use crate::tensor::*;
+
+/// Defines a masking layer skipping the padded timesteps of sequences.
+///
+/// The sequences are stored with the timesteps along the first dimension and the features along the second and third
+/// dimensions. A timestep is masked if all its features are equal to the mask value. The masked timesteps are set to
+/// zero and the mask is propagated to the loss, such that the padded timesteps don't contribute to the loss nor to the
+/// gradients.
+#[derive(Clone)]
+pub struct Masking {
+    mask_value: PrimitiveType,
+    output_shape: Dim,
+    mask: Tensor,
+}
+
+impl Masking {
+
+    pub(crate) const NAME: &'static str = "Masking";
+
+    /// Creates a masking layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `mask_value` - The value used to pad the sequences.
+    pub fn new(mask_value: PrimitiveType) -> Box<Masking> {
+        Box::new(Masking {
+            mask_value,
+            output_shape: Dim::new(&[0, 0, 0, 0]),
+            mask: Tensor::new_empty_tensor(),
+        })
+    }
+
+    /// Creates a Masking layer from an HDF5 group.
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Masking> {
+        let _ = hdf5::silence_errors();
+        let mask_value = group.dataset("mask_value").and_then(|ds| Ok(read_scalar::<PrimitiveType>(&ds))).expect("Could not retrieve the mask value.");
+        let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
+
+        Box::new(Masking {
+            mask_value,
+            output_shape: Dim::new(&output_shape[0]),
+            mask: Tensor::new_empty_tensor(),
+        })
+    }
+
+    /// Computes the mask of the timesteps whose features are all equal to the mask value.
+    fn timestep_mask(&self, input: &Tensor) -> Tensor {
+        let padded = all_true(&all_true(&eq(input, &self.mask_value, true), 1), 2);
+        sub(&(1.0 as PrimitiveType), &padded.cast::<PrimitiveType>(), true)
+    }
+}
+
+impl Layer for Masking {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn initialize_parameters(&mut self, input_shape: Dim4) {
+        self.output_shape = input_shape;
+    }
+
+    fn compute_activation(&self, input: &Tensor) -> Tensor {
+        mul(input, &self.timestep_mask(input), true)
+    }
+
+    fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
+        self.mask = self.timestep_mask(input);
+        mul(input, &self.mask, true)
+    }
+
+    fn compute_dactivation_mut(&mut self, dz: &Tensor) -> Tensor {
+        mul(dz, &self.mask, true)
+    }
+
+    fn compute_mask(&self, input: &Tensor) -> Option<Tensor> {
+        Some(self.timestep_mask(input))
+    }
+
+    fn mask_timesteps(&self) -> Option<u64> {
+        Some(self.output_shape[0])
+    }
+
+    fn output_shape(&self) -> Dim4 {
+        self.output_shape
+    }
+
+    fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
+        let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
+        let masking = group.create_group(&group_name)?;
+
+        let mask_value = masking.new_dataset::<PrimitiveType>().create("mask_value", 1)?;
+        write_scalar(&mask_value, &self.mask_value);
+
+        let output_shape = masking.new_dataset::<[u64; 4]>().create("output_shape", 1)?;
+        output_shape.write(&[*self.output_shape.get()])?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Masking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \t 0 \t\t [{}, {}, {}]", Self::NAME, self.output_shape[0], self.output_shape[1], self.output_shape[2])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::errors::Error;
+    use crate::layers::{Flatten, Layer, Masking};
+    use crate::losses::MeanSquaredError;
+    use crate::models::Network;
+    use crate::optimizers::SGD;
+    use crate::assert_approx_eq;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_masking_forward() {
+        // Two sequences of 3 timesteps with 2 features, the last timestep of the first sequence is padded
+        let input = Tensor::new(&[1., 2., -1., 3., 4., -1., 5., 0., 6., 7., 0., 8.], Dim4::new(&[3, 2, 1, 2]));
+
+        let mut layer = Masking::new(-1.);
+        layer.initialize_parameters(Dim4::new(&[3, 2, 1, 1]));
+
+        let mut mask: [PrimitiveType; 6] = [0.; 6];
+        layer.compute_mask(&input).unwrap().host(&mut mask);
+        assert_approx_eq!(mask, [1., 1., 0., 1., 1., 1.]);
+
+        let mut output: [PrimitiveType; 12] = [0.; 12];
+        layer.compute_activation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [1., 2., 0., 3., 4., 0., 5., 0., 6., 7., 0., 8.]);
+    }
+
+    #[test]
+    fn test_masked_loss() {
+        let input = Tensor::new(&[1., 2., -1., 3., 4., -1., 5., 0., 6., 7., 0., 8.], Dim4::new(&[3, 2, 1, 2]));
+        let labels = Tensor::new(&[0., 0., 5., 0., 0., 5., 0., 0., 0., 0., 0., 0.], Dim4::new(&[3, 2, 1, 2]));
+
+        // The padded timestep doesn't contribute to the loss, which is averaged over the 5 valid timesteps
+        let mut nn = Network::new(Dim4::new(&[3, 2, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Masking::new(-1.));
+        let loss = nn.test_on_batch(&input, &labels).unwrap();
+        assert_approx_eq!([loss], [204. / 2. * 6. / 5.]);
+
+        // The mask can't be applied once the timesteps are flattened
+        let mut nn = Network::new(Dim4::new(&[3, 2, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Masking::new(-1.));
+        nn.add(Flatten::new());
+        let flat_labels = Tensor::new(&[0.; 12], Dim4::new(&[6, 1, 1, 2]));
+        match nn.test_on_batch(&input, &flat_labels) {
+            Err(Error::MaskShapeMismatch) => {},
+            _ => panic!("The mask shape mismatch was not detected."),
+        }
+    }
+}
@@ -2,14 +2,20 @@
 use arrayfire::*;
 use std::fmt;
 
+use crate::backend::{DefaultBackend, conv_output_size};
+use crate::cpu::CpuLayer;
 use crate::errors::Error;
 use crate::layers::Layer;
 use crate::tensor::*;
+use super::conv2d::{Padding, causal_padding_size, same_padding_size};
 
 /// Defines a 2D max pooling layer.
+#[derive(Clone)]
 pub struct MaxPool2D {
     pool_size: (u64, u64),
     stride: (u64, u64),
+    padding: Padding,
+    padding_size: (u64, u64, u64, u64), // top, right, bottom, left
     input_shape: Dim,
     output_shape: Dim,
     row_indices: Array<i32>,
@@ -31,6 +37,8 @@ impl MaxPool2D {
         Box::new(MaxPool2D {
             pool_size,
             stride: pool_size,
+            padding: Padding::Valid,
+            padding_size: (0, 0, 0, 0),
             input_shape: Dim::new(&[0, 0, 0, 0]),
             output_shape: Dim::new(&[0, 0, 0, 0]),
             row_indices: Array::new(&[0], Dim4::new(&[1, 1, 1, 1])),
@@ -49,6 +57,8 @@ impl MaxPool2D {
         Box::new(MaxPool2D {
             pool_size,
             stride,
+            padding: Padding::Valid,
+            padding_size: (0, 0, 0, 0),
             input_shape: Dim::new(&[0, 0, 0, 0]),
             output_shape: Dim::new(&[0, 0, 0, 0]),
             row_indices: Array::new(&[0], Dim4::new(&[1, 1, 1, 1])),
@@ -62,10 +72,14 @@ impl MaxPool2D {
         let stride = group.dataset("stride").and_then(|ds| ds.read_raw::<[u64; 2]>()).expect("Could not retrieve the stride.");
         let input_shape = group.dataset("input_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the input shape.");
         let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
+        let padding = group.dataset("padding").and_then(|ds| ds.read_raw::<Padding>()).map(|padding| padding[0]).unwrap_or(Padding::Valid);
+        let padding_size = group.dataset("padding_size").and_then(|ds| ds.read_raw::<[u64; 4]>()).map(|size| (size[0][0], size[0][1], size[0][2], size[0][3])).unwrap_or((0, 0, 0, 0));
 
         Box::new(MaxPool2D {
             pool_size: (pool_size[0][0], pool_size[0][1]),
             stride: (stride[0][0], stride[0][1]),
+            padding,
+            padding_size,
             input_shape: Dim::new(&input_shape[0]),
             output_shape: Dim::new(&output_shape[0]),
             row_indices: Array::new(&[0], Dim4::new(&[1, 1, 1, 1])),
@@ -73,9 +87,29 @@ impl MaxPool2D {
         })
     }
 
+    /// Sets the padding applied to the inputs.
+    ///
+    /// With `Padding::Valid`, the rows and columns that don't fill a complete pooling window are discarded. With
+    /// `Padding::Same`, the inputs are padded such that every row and column is pooled. With `Padding::Causal`, the
+    /// pooling window only covers the current and previous rows. The padding must be set before the layer is added to
+    /// a network. By default, no padding is applied.
+    pub fn set_padding(&mut self, padding: Padding) {
+        self.padding = padding;
+    }
+
+    /// Sets the number of rows and columns added to the top, right, bottom, and left of the inputs.
+    ///
+    /// The padding of the layer is changed to `Padding::Explicit`. It must be set before the layer is added to a network.
+    pub fn set_explicit_padding(&mut self, padding_size: (u64, u64, u64, u64)) {
+        self.padding = Padding::Explicit;
+        self.padding_size = padding_size;
+    }
+
     /// Computes the maximum value in the pooling window.
     fn max_pool(&self, input: &Tensor) -> (Tensor, Array<i32>, Array<i32>) {
-        let cols = unwrap(input, self.pool_size.0 as i64, self.pool_size.1 as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true);
+        // The padded values never win the max
+        let padded = DefaultBackend::pad_with(input, self.padding_size, PrimitiveType::NEG_INFINITY);
+        let cols = unwrap(&padded, self.pool_size.0 as i64, self.pool_size.1 as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true);
         let cols_reshaped = moddims(&cols, Dim4::new(&[cols.dims().get()[0], cols.elements() as u64 / cols.dims().get()[0], 1, 1]));
 
         // Computes max values and indices
@@ -105,9 +139,49 @@ impl Layer for MaxPool2D {
         Self::NAME
     }
 
+    fn accepts_input_shape(&self, input_shape: Dim4) -> bool {
+        let height = input_shape.get()[0];
+        let width = input_shape.get()[1];
+        match self.padding {
+            Padding::Valid => height >= self.pool_size.0 && width >= self.pool_size.1,
+            Padding::Explicit => {
+                height + self.padding_size.0 + self.padding_size.2 >= self.pool_size.0
+                    && width + self.padding_size.1 + self.padding_size.3 >= self.pool_size.1
+            },
+            Padding::Same | Padding::Causal => true,
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
     fn initialize_parameters(&mut self, input_shape: Dim4) {
-        let output_height = ((input_shape.get()[0] - self.pool_size.0) as f64 / self.stride.0 as f64 + 1.).floor() as u64;
-        let output_width = ((input_shape.get()[1] - self.pool_size.1) as f64 / self.stride.1 as f64 + 1.).floor() as u64;
+        let height = input_shape.get()[0];
+        let width = input_shape.get()[1];
+        let (output_height, output_width) = match self.padding {
+            Padding::Same => {
+                let output_height = (height as f64 / self.stride.0 as f64).ceil() as u64;
+                let output_width = (width as f64 / self.stride.1 as f64).ceil() as u64;
+                self.padding_size = same_padding_size(height, width, output_height, output_width, self.pool_size, self.stride);
+                (output_height, output_width)
+            },
+            Padding::Valid => {
+                self.padding_size = (0, 0, 0, 0);
+                (((height - self.pool_size.0) as f64 / self.stride.0 as f64 + 1.).floor() as u64,
+                 ((width - self.pool_size.1) as f64 / self.stride.1 as f64 + 1.).floor() as u64)
+            },
+            Padding::Causal => {
+                let output_height = (height as f64 / self.stride.0 as f64).ceil() as u64;
+                let output_width = (width as f64 / self.stride.1 as f64).ceil() as u64;
+                self.padding_size = causal_padding_size(height, width, output_width, self.pool_size, self.stride);
+                (output_height, output_width)
+            },
+            Padding::Explicit => {
+                (conv_output_size(height, self.pool_size.0, self.stride.0, self.padding_size.0 + self.padding_size.2),
+                 conv_output_size(width, self.pool_size.1, self.stride.1, self.padding_size.1 + self.padding_size.3))
+            },
+        };
         self.input_shape = input_shape;
         self.output_shape = Dim4::new(&[output_height, output_width, input_shape.get()[2], input_shape.get()[3]]);
     }
@@ -132,7 +206,19 @@ impl Layer for MaxPool2D {
         let num_channels = self.input_shape.get()[2];
         let num_cols = dense.dims().get()[1] / (num_channels * batch_size);
         dense = moddims(&dense, Dim4::new(&[dense.dims().get()[0], num_cols, num_channels, batch_size]));
-        wrap(&dense, self.input_shape.get()[0] as i64, self.input_shape.get()[1] as i64, self.pool_size.0 as i64, self.pool_size.1 as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true)
+        let height = self.input_shape.get()[0];
+        let width = self.input_shape.get()[1];
+        let height_padded = height + self.padding_size.0 + self.padding_size.2;
+        let width_padded = width + self.padding_size.1 + self.padding_size.3;
+        let grad = wrap(&dense, height_padded as i64, width_padded as i64, self.pool_size.0 as i64, self.pool_size.1 as i64, self.stride.0 as i64, self.stride.1 as i64, 0, 0, true);
+
+        // Remove the padding
+        if self.padding_size == (0, 0, 0, 0) {
+            return grad;
+        }
+        let rows = Seq::new(self.padding_size.0 as f64, (self.padding_size.0 + height - 1) as f64, 1.0);
+        let cols = Seq::new(self.padding_size.3 as f64, (self.padding_size.3 + width - 1) as f64, 1.0);
+        index(&grad, &[rows, cols, Seq::default(), Seq::default()])
     }
 
     fn output_shape(&self) -> Dim {
@@ -150,6 +236,12 @@ impl Layer for MaxPool2D {
         let stride = max_pool.new_dataset::<[u64; 2]>().create("stride", 1)?;
         stride.write(&[[self.stride.0, self.stride.1]])?;
 
+        let padding = max_pool.new_dataset::<Padding>().create("padding", 1)?;
+        padding.write(&[self.padding])?;
+
+        let padding_size = max_pool.new_dataset::<[u64; 4]>().create("padding_size", 1)?;
+        padding_size.write(&[[self.padding_size.0, self.padding_size.1, self.padding_size.2, self.padding_size.3]])?;
+
         let input_shape = max_pool.new_dataset::<[u64; 4]>().create("input_shape", 1)?;
         input_shape.write(&[*self.input_shape.get()])?;
 
@@ -158,6 +250,15 @@ impl Layer for MaxPool2D {
 
         Ok(())
     }
+
+    fn to_cpu(&self) -> Option<CpuLayer> {
+        Some(CpuLayer::MaxPool2D {
+            pool_size: self.pool_size,
+            stride: self.stride,
+            padding_size: self.padding_size,
+            output_shape: *self.output_shape.get(),
+        })
+    }
 }
 
 
@@ -171,7 +272,7 @@ impl fmt::Display for MaxPool2D {
 #[cfg(test)]
 mod tests {
     use arrayfire::*;
-    use crate::layers::{MaxPool2D, Layer};
+    use crate::layers::{MaxPool2D, Layer, Padding};
     use crate::assert_approx_eq;
     use crate::tensor::*;
 
@@ -179,6 +280,8 @@ mod tests {
         MaxPool2D {
             pool_size: (2, 2),
             stride: (2, 2),
+            padding: Padding::Valid,
+            padding_size: (0, 0, 0, 0),
             input_shape: Dim::new(&[4, 4, 2, 1]),
             output_shape: Dim::new(&[2, 2, 2, 1]),
             row_indices: Array::new(&[0], Dim4::new(&[1, 1, 1, 1])),
@@ -217,4 +320,29 @@ mod tests {
         layer_output.host(&mut output);
         let expected_output: [PrimitiveType; 64] = [0., 0., 0., 0., -1., 0., 0., 2., 0., 0., 0., 0., 0., 3., 1., 0., 0., -2., 4., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., -1., 0., 1., 2., 0., 0., 1., 0., 0., 0., 0., 0., -3., 0., 1., 0., 0., 0., 0., 0., -2., 0., 0., 0., 0., 0., 0., 0., 0., 0., 4., 0., 1., 0., 0.];
     }
+
+    #[test]
+    fn test_maxpool2d_same_padding() {
+        let input = Tensor::new(&[1., 2., 3., 4., 5., 6., 7., 8., 9.], Dim4::new(&[3, 3, 1, 1]));
+
+        let mut layer = MaxPool2D::new((2, 2));
+        layer.set_padding(Padding::Same);
+        layer.initialize_parameters(Dim4::new(&[3, 3, 1, 1]));
+        assert_eq!(layer.output_shape().get(), &[2, 2, 1, 1]);
+
+        let layer_output = layer.compute_activation_mut(&input);
+        let mut output: [PrimitiveType; 4] = [0.; 4];
+        layer_output.host(&mut output);
+        let expected_output = [5., 6., 8., 9.];
+
+        assert_approx_eq!(expected_output, output);
+
+        let layer_output = layer.compute_dactivation_mut(&Tensor::new(&[1., 2., 3., 4.], Dim4::new(&[2, 2, 1, 1])));
+        assert_eq!(layer_output.dims().get(), &[3, 3, 1, 1]);
+        let mut output: [PrimitiveType; 9] = [0.; 9];
+        layer_output.host(&mut output);
+        let expected_output = [0., 0., 0., 0., 1., 2., 0., 3., 4.];
+
+        assert_approx_eq!(expected_output, output);
+    }
 }
@@ -1,53 +1,130 @@
 //! Collection of layers used to create neural networks.
 use arrayfire::*;
 
+use crate::activations::Activation;
+use crate::cpu::CpuLayer;
 use crate::errors::Error;
 use crate::regularizers::*;
 use crate::tensor::*;
 
 // Public re-exports
 pub use self::batch_normalization::BatchNorm;
+pub use self::complex_dense::ComplexDense;
 pub use self::conv2d::Conv2D;
+pub use self::conv2d::ConvAlgorithm;
 pub use self::conv2d::Padding;
 pub use self::dense::Dense;
 pub use self::dropout::Dropout;
 pub use self::flatten::Flatten;
+pub use self::gaussian_noise::GaussianNoise;
+pub use self::global_pooling::GlobalAvgPool2D;
+pub use self::masking::Masking;
 pub use self::max_pooling::MaxPool2D;
+pub use self::projection::Projection;
+pub use self::residual::Residual;
+
+pub mod backbones;
+pub mod heads;
 
 mod batch_normalization;
+mod complex_dense;
 mod conv2d;
 mod dense;
 mod dropout;
 mod flatten;
+mod gaussian_noise;
+mod global_pooling;
+mod masking;
 mod max_pooling;
+mod projection;
+mod residual;
 
 
 /// Public trait defining the behaviors of a layer.
-pub trait Layer: std::fmt::Display {
+///
+/// Layers must be `Send` and `Sync` such that a trained network can be shared across threads for inference.
+pub trait Layer: std::fmt::Display + Send + Sync {
     /// Returns the name of the layer.
     fn name(&self) -> &str;
 
+    /// Returns a copy of the layer, including its parameters and its internal state.
+    ///
+    /// The tensors of ArrayFire are copied on write, hence the copy and the original layer never see each other's
+    /// updates.
+    fn clone_box(&self) -> Box<dyn Layer>;
+
     /// Initializes the parameters of the layer.
     fn initialize_parameters(&mut self, input_shape: Dim4);
 
+    /// Returns whether the layer can be initialized with inputs of the given shape.
+    ///
+    /// The parameters must only be initialized if the input shape is accepted. By default, all the shapes are accepted.
+    fn accepts_input_shape(&self, _input_shape: Dim4) -> bool { true }
+
     /// Computes the activation of the layer during the forward pass.
     fn compute_activation(&self, input: &Tensor) -> Tensor;
 
+    /// Computes the activation of the layer with the random behaviors of the training, such as the dropout, for Monte
+    /// Carlo inference.
+    ///
+    /// The parameters and the running statistics are not modified. By default, the activation is computed as for
+    /// regular inference.
+    fn compute_activation_stochastic(&self, input: &Tensor) -> Tensor {
+        self.compute_activation(input)
+    }
+
+    /// Computes the mask of the timesteps from the input of the layer.
+    ///
+    /// The timesteps are along the first axis of the input. The mask has dimensions [timesteps, 1, 1, batch_size] and
+    /// contains 1 for the valid timesteps and 0 for the padded ones. By default, the layer doesn't produce any mask and
+    /// the mask of the previous layers is propagated.
+    fn compute_mask(&self, _input: &Tensor) -> Option<Tensor> { None }
+
+    /// Returns the number of timesteps of the mask computed by the layer, or `None` if the layer doesn't mask timesteps.
+    fn mask_timesteps(&self) -> Option<u64> { None }
+
     /// Computes the forward pass and stores intermediate values for efficient backpropagation.
     fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor;
 
+    /// Computes the activation of the layer for sparse inputs.
+    ///
+    /// Returns `None` if the layer doesn't accept sparse inputs.
+    fn compute_activation_sparse(&self, _input: &SparseTensor) -> Option<Tensor> { None }
+
+    /// Computes the forward pass for sparse inputs and stores intermediate values for efficient backpropagation.
+    ///
+    /// Returns `None` if the layer doesn't accept sparse inputs.
+    fn compute_activation_sparse_mut(&mut self, _input: &SparseTensor) -> Option<Tensor> { None }
+
     /// Computes the backward pass through the layer.
     fn compute_dactivation_mut(&mut self, input: &Tensor) -> Tensor;
 
     /// Returns the shape of the output.
     fn output_shape(&self) -> Dim;
 
+    /// Returns the activation function applied at the output of the layer, if any.
+    fn activation(&self) -> Option<Activation> { None }
+
     /// Returns the trainable parameters of the layer.
     fn parameters(&self) -> Option<Vec<&Tensor>> { None }
 
     /// Returns the trainable parameters of the layer and their derivatives.
     fn parameters_mut(&mut self) -> Option<(Vec<&mut Tensor>, Vec<&Tensor>)> { None }
 
+    /// Returns the derivatives of the trainable parameters, in the same order as the parameters.
+    fn gradients_mut(&mut self) -> Option<Vec<&mut Tensor>> { None }
+
+    /// Returns the scale and shift of the elementwise affine transformation computed by the layer at inference, if the
+    /// layer computes one.
+    fn affine_transformation(&self) -> Option<(Tensor, Tensor)> { None }
+
+    /// Folds an elementwise affine transformation of the outputs into the parameters of the layer.
+    ///
+    /// The scale and shift are given in the layout returned by
+    /// [affine_transformation](trait.Layer.html#method.affine_transformation). Returns `false` if the transformation
+    /// can't be folded, for instance because the activation of the layer is not linear.
+    fn fold_affine_transformation(&mut self, _scale: &Tensor, _shift: &Tensor) -> bool { false }
+
     /// Writes the parameters of the layer in the HDF5 group.
     ///
     /// # Arguments
@@ -56,10 +133,64 @@ pub trait Layer: std::fmt::Display {
     /// * `layer_number`: The position of the layer in the network.
     fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error>;
 
+    /// Converts the layer for the pure Rust CPU backend.
+    ///
+    /// Returns `None` if the layer is not supported by the CPU backend.
+    fn to_cpu(&self) -> Option<CpuLayer> { None }
+
     /// Sets the regularizer for the layer.
     fn set_regularizer(&mut self, _regularizer: Option<Regularizer>) {}
 
+    /// Freezes or unfreezes the running statistics of the layer, if it has any.
+    fn set_frozen_statistics(&mut self, _frozen: bool) {}
+
+    /// Switches the layer between the training and inference behaviors.
+    ///
+    /// This only affects `compute_activation_mut` and `compute_dactivation_mut`. In inference mode, the dropout
+    /// and noise layers let all the values through and the batch normalization layers use their running statistics.
+    fn set_training(&mut self, _training: bool) {}
+
+    /// Sets the seed of the random number generator of the layer, if it has one.
+    fn set_seed(&mut self, _seed: u64) {}
+
     /// Displays the properties of the layer.
     fn print(&self) {}
 }
 
+
+
+/// Random engine owned by a layer.
+///
+/// The ArrayFire `RandomEngine` is `Send` but not `Sync`, because drawing numbers through a shared reference advances
+/// its state. The wrapper only gives access to the engine through a mutable reference, such that a shared reference to
+/// the layer never reaches the engine and the layer can be shared across threads.
+pub(crate) struct LayerRandomEngine(RandomEngine);
+
+impl LayerRandomEngine {
+    /// Creates an engine with a random seed.
+    pub(crate) fn new() -> LayerRandomEngine {
+        let seed: u64 = rand::random();
+        LayerRandomEngine(RandomEngine::new(RandomEngineType::PHILOX_4X32_10, Some(seed)))
+    }
+
+    /// Returns the engine.
+    pub(crate) fn get_mut(&mut self) -> &mut RandomEngine {
+        &mut self.0
+    }
+
+    /// Sets the seed of the engine.
+    pub(crate) fn set_seed(&mut self, seed: u64) {
+        self.0.set_seed(seed);
+    }
+}
+
+// SAFETY: the engine is only reachable through `get_mut`, which requires exclusive access to the wrapper. Threads
+// sharing a reference to the wrapper therefore can't use the engine concurrently.
+unsafe impl Sync for LayerRandomEngine {}
+
+impl Clone for Box<dyn Layer> {
+    fn clone(&self) -> Box<dyn Layer> {
+        self.clone_box()
+    }
+}
+
@@ -0,0 +1,100 @@
+//! Projection layer
+use arrayfire::*;
+use std::fmt;
+
+use crate::backend::{Backend, DefaultBackend};
+use crate::errors::Error;
+use crate::io::{read_tensor, write_tensor};
+use crate::layers::Layer;
+use crate::tensor::*;
+
+/// Defines a fixed linear projection of the features.
+///
+/// The layer computes `weights * input + biases` like a [Dense](struct.Dense.html) layer without activation, but its
+/// parameters are not trained. It typically embeds a preprocessing step such as a PCA into the network, see
+/// [FittedTransform::to_layer](../data/struct.FittedTransform.html#method.to_layer).
+#[derive(Clone)]
+pub struct Projection {
+    weights: Tensor,
+    biases: Tensor,
+    output_shape: Dim,
+}
+
+impl Projection {
+
+    pub(crate) const NAME: &'static str = "Projection";
+
+    /// Creates a projection layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `weights` - The projection matrix, of shape [number of outputs, number of inputs].
+    /// * `biases` - The values added after the projection, of shape [number of outputs, 1].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the shapes of the weights and biases don't match.
+    pub fn new(weights: Tensor, biases: Tensor) -> Box<Projection> {
+        let units = weights.dims()[0];
+        assert_eq!(biases.dims().get(), &[units, 1, 1, 1], "The biases must have one value per output.");
+        Box::new(Projection {
+            weights,
+            biases,
+            output_shape: Dim::new(&[units, 1, 1, 1]),
+        })
+    }
+
+    /// Creates a Projection layer from an HDF5 group.
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Projection> {
+        let _ = hdf5::silence_errors();
+        let weights = read_tensor(group, "weights").expect("Could not retrieve the weights.");
+        let biases = read_tensor(group, "biases").expect("Could not retrieve the biases.");
+        Projection::new(weights, biases)
+    }
+}
+
+impl Layer for Projection {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn initialize_parameters(&mut self, input_shape: Dim4) {
+        assert_eq!(input_shape[0], self.weights.dims()[1], "The number of features doesn't match the projection.");
+    }
+
+    fn compute_activation(&self, input: &Tensor) -> Tensor {
+        add(&DefaultBackend::matmul(&self.weights, input, false, false), &self.biases, true)
+    }
+
+    fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
+        self.compute_activation(input)
+    }
+
+    fn compute_dactivation_mut(&mut self, dz: &Tensor) -> Tensor {
+        DefaultBackend::matmul(&self.weights, dz, true, false)
+    }
+
+    fn output_shape(&self) -> Dim4 {
+        self.output_shape
+    }
+
+    fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
+        let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
+        let projection = group.create_group(&group_name)?;
+
+        write_tensor(&projection, "weights", &self.weights)?;
+        write_tensor(&projection, "biases", &self.biases)?;
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \t 0 \t\t [{}, {}, {}]", Self::NAME, self.output_shape[0], self.output_shape[1], self.output_shape[2])
+    }
+}
@@ -0,0 +1,465 @@
+//! Residual block
+use arrayfire::*;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{RwLock, RwLockReadGuard};
+
+use crate::activations::Activation;
+use crate::errors::Error;
+use crate::io::{create_group, list_subgroups};
+use crate::layers::Layer;
+use crate::models::layer_from_hdf5_group;
+use crate::regularizers::Regularizer;
+use crate::tensor::*;
+
+/// Defines a residual block, which adds its input to the output of a stack of layers.
+///
+/// The block computes `activation(layers(x) + shortcut(x))`. The shortcut has no parameters: it is the identity when
+/// the stack preserves the shape of the input. Otherwise, the input is subsampled along the height and the width and
+/// padded with zeros along the channels to match the output, which is the shortcut of type A of He et al. (2015).
+///
+/// The optimizers update two tensors per layer, hence the parameters of the inner layers are exposed as the flattened
+/// concatenation of their first and of their second parameters. The inner layers read the updated values on their next
+/// forward pass. The block doesn't accept an input shape for which an inner layer exposes another number of parameter
+/// tensors or for which the shortcut can't match the shapes, in which case
+/// [NetworkBuilder::build](../models/struct.NetworkBuilder.html#method.build) returns an error.
+pub struct Residual {
+    layers: RwLock<Vec<Box<dyn Layer>>>,
+    activation: Activation,
+    weights: Tensor,
+    biases: Tensor,
+    dweights: Tensor,
+    dbiases: Tensor,
+    stale: AtomicBool,
+    input_shape: Dim,
+    output_shape: Dim,
+    linear_activation: Option<Tensor>,
+}
+
+impl Residual {
+    pub(crate) const NAME: &'static str = "Residual";
+
+    /// Creates a residual block.
+    ///
+    /// # Arguments
+    ///
+    /// * `layers` - The layers of the main path of the block.
+    /// * `activation` - The activation function applied after the addition of the shortcut.
+    pub fn new(layers: Vec<Box<dyn Layer>>, activation: Activation) -> Box<Residual> {
+        Box::new(Residual {
+            layers: RwLock::new(layers),
+            activation,
+            weights: Tensor::new_empty_tensor(),
+            biases: Tensor::new_empty_tensor(),
+            dweights: Tensor::new_empty_tensor(),
+            dbiases: Tensor::new_empty_tensor(),
+            stale: AtomicBool::new(false),
+            input_shape: Dim::new(&[0, 0, 0, 0]),
+            output_shape: Dim::new(&[0, 0, 0, 0]),
+            linear_activation: None,
+        })
+    }
+
+    pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Residual> {
+        let activation = group.dataset("activation").and_then(|ds| ds.read_raw::<Activation>()).expect("Could not retrieve the activation function.");
+        let input_shape = group.dataset("input_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the input shape.");
+        let output_shape = group.dataset("output_shape").and_then(|ds| ds.read_raw::<[u64; 4]>()).expect("Could not retrieve the output shape.");
+        let layers_group = group.group("layers").expect("Could not retrieve the layers.");
+
+        let mut layers = Vec::new();
+        for layer in list_subgroups(&layers_group) {
+            let layer_group = layers_group.group(&layer).expect("Could not retrieve the layer.");
+            let layer_type: Vec<&str> = layer.split('_').collect();
+            layers.push(layer_from_hdf5_group(layer_type[1], &layer_group).expect("Unknown layer."));
+        }
+
+        let (weights, biases) = flatten_parameters(&layers);
+        Box::new(Residual {
+            layers: RwLock::new(layers),
+            activation: activation[0],
+            dweights: constant(0 as PrimitiveType, weights.dims()),
+            dbiases: constant(0 as PrimitiveType, biases.dims()),
+            weights,
+            biases,
+            stale: AtomicBool::new(false),
+            input_shape: Dim::new(&input_shape[0]),
+            output_shape: Dim::new(&output_shape[0]),
+            linear_activation: None,
+        })
+    }
+
+    /// Returns the inner layers, after copying the latest parameters into them if needed.
+    fn synchronized_layers(&self) -> RwLockReadGuard<'_, Vec<Box<dyn Layer>>> {
+        if self.stale.load(Ordering::Acquire) {
+            let mut layers = self.layers.write().unwrap();
+            // Another thread may have copied the parameters while this one was waiting for the lock
+            if self.stale.load(Ordering::Acquire) {
+                unflatten_parameters(&mut layers, &self.weights, &self.biases);
+                self.stale.store(false, Ordering::Release);
+            }
+        }
+        self.layers.read().unwrap()
+    }
+
+    /// Returns the stride of the subsampling of the shortcut along the height and the width.
+    fn shortcut_stride(&self) -> (u64, u64) {
+        shortcut_stride(self.input_shape, self.output_shape)
+    }
+
+    /// Computes the shortcut, matching the shape of the input to the shape of the output.
+    fn shortcut(&self, input: &Tensor) -> Tensor {
+        if self.input_shape == self.output_shape {
+            return input.clone();
+        }
+
+        let (input_dims, output_dims) = (self.input_shape.get(), self.output_shape.get());
+        let (stride_h, stride_w) = self.shortcut_stride();
+        let subsampled = index(input, &[
+            Seq::new(0., (input_dims[0] - 1) as f64, stride_h as f64),
+            Seq::new(0., (input_dims[1] - 1) as f64, stride_w as f64),
+            Seq::default(),
+            Seq::default(),
+        ]);
+        if input_dims[2] == output_dims[2] {
+            return subsampled;
+        }
+        let padding = constant(0 as PrimitiveType, Dim::new(&[output_dims[0], output_dims[1], output_dims[2] - input_dims[2], input.dims()[3]]));
+        join(2, &subsampled, &padding)
+    }
+
+    /// Computes the derivative of the shortcut.
+    fn shortcut_grad(&self, dz: &Tensor) -> Tensor {
+        if self.input_shape == self.output_shape {
+            return dz.clone();
+        }
+
+        // The padded channels don't depend on the input and the skipped pixels receive no gradient
+        let input_dims = self.input_shape.get();
+        let dchannels = index(dz, &[Seq::default(), Seq::default(), Seq::new(0., (input_dims[2] - 1) as f64, 1.), Seq::default()]);
+        let (stride_h, stride_w) = self.shortcut_stride();
+        if (stride_h, stride_w) == (1, 1) {
+            return dchannels;
+        }
+        let mut dinput = constant(0 as PrimitiveType, Dim::new(&[input_dims[0], input_dims[1], input_dims[2], dz.dims()[3]]));
+        let seqs = [
+            Seq::new(0., (input_dims[0] - 1) as f64, stride_h as f64),
+            Seq::new(0., (input_dims[1] - 1) as f64, stride_w as f64),
+            Seq::default(),
+            Seq::default(),
+        ];
+        assign_seq(&mut dinput, &seqs, &dchannels);
+        dinput
+    }
+}
+
+/// Returns the stride of the subsampling of the shortcut along the height and the width.
+fn shortcut_stride(input_shape: Dim, output_shape: Dim) -> (u64, u64) {
+    let (input_dims, output_dims) = (input_shape.get(), output_shape.get());
+    ((input_dims[0] + output_dims[0] - 1) / output_dims[0], (input_dims[1] + output_dims[1] - 1) / output_dims[1])
+}
+
+/// Returns whether the shortcut can match the input shape to the output shape.
+fn shortcut_matches(input_shape: Dim, output_shape: Dim) -> bool {
+    let (input_dims, output_dims) = (input_shape.get(), output_shape.get());
+    if output_dims[..3].contains(&0) || output_dims[2] < input_dims[2] {
+        return false;
+    }
+    let (stride_h, stride_w) = shortcut_stride(input_shape, output_shape);
+    (input_dims[0] + stride_h - 1) / stride_h == output_dims[0] && (input_dims[1] + stride_w - 1) / stride_w == output_dims[1]
+}
+
+/// Returns whether the layer has no parameters or exactly the two parameter tensors flattened by the block.
+fn has_two_parameters(layer: &dyn Layer) -> bool {
+    layer.parameters().map_or(true, |parameters| parameters.len() == 2)
+}
+
+/// Concatenates the flattened tensors.
+fn concatenate(tensors: &[&Tensor]) -> Tensor {
+    tensors.iter()
+        .map(|tensor| moddims(tensor, Dim::new(&[tensor.elements() as u64, 1, 1, 1])))
+        .fold(None, |acc: Option<Tensor>, flat| match acc {
+            Some(acc) => Some(join(0, &acc, &flat)),
+            None => Some(flat),
+        })
+        .unwrap_or_else(Tensor::new_empty_tensor)
+}
+
+/// Concatenates the first and the second parameters of the layers.
+fn flatten_parameters(layers: &[Box<dyn Layer>]) -> (Tensor, Tensor) {
+    let parameters: Vec<Vec<&Tensor>> = layers.iter().filter_map(|layer| layer.parameters()).collect();
+    let weights: Vec<&Tensor> = parameters.iter().map(|p| p[0]).collect();
+    let biases: Vec<&Tensor> = parameters.iter().map(|p| p[1]).collect();
+    (concatenate(&weights), concatenate(&biases))
+}
+
+/// Concatenates the derivatives of the first and the second parameters of the layers.
+fn flatten_gradients(layers: &mut [Box<dyn Layer>]) -> (Tensor, Tensor) {
+    let mut dweights = Vec::new();
+    let mut dbiases = Vec::new();
+    for layer in layers.iter_mut() {
+        if let Some(gradients) = layer.gradients_mut() {
+            dweights.push(gradients[0].clone());
+            dbiases.push(gradients[1].clone());
+        }
+    }
+    (concatenate(&dweights.iter().collect::<Vec<_>>()), concatenate(&dbiases.iter().collect::<Vec<_>>()))
+}
+
+/// Copies the slices of the flattened parameters into the parameters of the layers.
+fn unflatten_parameters(layers: &mut [Box<dyn Layer>], weights: &Tensor, biases: &Tensor) {
+    let mut offsets = [0u64; 2];
+    for layer in layers.iter_mut() {
+        if let Some((parameters, _)) = layer.parameters_mut() {
+            for ((parameter, flat), offset) in parameters.into_iter().zip([weights, biases].iter()).zip(offsets.iter_mut()) {
+                let num_elements = parameter.elements() as u64;
+                if num_elements == 0 { continue; }
+                let slice = index(flat, &[Seq::new(*offset as f64, (*offset + num_elements - 1) as f64, 1.)]);
+                *parameter = moddims(&slice, parameter.dims());
+                *offset += num_elements;
+            }
+        }
+    }
+}
+
+impl Layer for Residual {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn accepts_input_shape(&self, input_shape: Dim) -> bool {
+        // The output shapes of the inner layers are only known once they are initialized, hence copies are initialized
+        let mut shape = input_shape;
+        for layer in self.synchronized_layers().iter() {
+            if !layer.accepts_input_shape(shape) { return false; }
+            let mut layer = layer.clone_box();
+            layer.initialize_parameters(shape);
+            if !has_two_parameters(layer.as_ref()) { return false; }
+            shape = layer.output_shape();
+        }
+        shortcut_matches(input_shape, shape)
+    }
+
+    fn initialize_parameters(&mut self, input_shape: Dim) {
+        let layers = self.layers.get_mut().unwrap();
+        let mut shape = input_shape;
+        for layer in layers.iter_mut() {
+            layer.initialize_parameters(shape);
+            assert!(has_two_parameters(layer.as_ref()), "The layer {} of the residual block must have two parameter tensors.", layer.name());
+            shape = layer.output_shape();
+        }
+        assert!(shortcut_matches(input_shape, shape), "The shortcut can't match the input shape {:?} to the output shape {:?}.", input_shape.get(), shape.get());
+        let (weights, biases) = flatten_parameters(layers);
+
+        self.input_shape = input_shape;
+        self.output_shape = shape;
+
+        self.dweights = constant(0 as PrimitiveType, weights.dims());
+        self.dbiases = constant(0 as PrimitiveType, biases.dims());
+        self.weights = weights;
+        self.biases = biases;
+        *self.stale.get_mut() = false;
+    }
+
+    fn compute_activation(&self, input: &Tensor) -> Tensor {
+        let layers = self.synchronized_layers();
+        let main = layers.iter().fold(input.clone(), |activation, layer| layer.compute_activation(&activation));
+        self.activation.eval(&(main + self.shortcut(input)))
+    }
+
+    fn compute_activation_stochastic(&self, input: &Tensor) -> Tensor {
+        let layers = self.synchronized_layers();
+        let main = layers.iter().fold(input.clone(), |activation, layer| layer.compute_activation_stochastic(&activation));
+        self.activation.eval(&(main + self.shortcut(input)))
+    }
+
+    fn compute_activation_mut(&mut self, input: &Tensor) -> Tensor {
+        let layers = self.layers.get_mut().unwrap();
+        if *self.stale.get_mut() {
+            unflatten_parameters(layers, &self.weights, &self.biases);
+            *self.stale.get_mut() = false;
+        }
+        let main = layers.iter_mut().fold(input.clone(), |activation, layer| layer.compute_activation_mut(&activation));
+
+        let linear_activation = main + self.shortcut(input);
+        let nonlinear_activation = self.activation.eval(&linear_activation);
+        self.linear_activation = Some(linear_activation);
+        nonlinear_activation
+    }
+
+    fn compute_dactivation_mut(&mut self, dz: &Tensor) -> Tensor {
+        let linear_activation_grad = match &self.linear_activation {
+            Some(linear_activation) => mul(dz, &self.activation.grad(linear_activation), true),
+            None => panic!("The linear activations have not been computed!"),
+        };
+
+        let layers = self.layers.get_mut().unwrap();
+        let dmain = layers.iter_mut().rev().fold(linear_activation_grad.clone(), |grad, layer| layer.compute_dactivation_mut(&grad));
+        let (dweights, dbiases) = flatten_gradients(layers);
+        self.dweights = dweights;
+        self.dbiases = dbiases;
+
+        dmain + self.shortcut_grad(&linear_activation_grad)
+    }
+
+    fn output_shape(&self) -> Dim {
+        self.output_shape
+    }
+
+    fn activation(&self) -> Option<Activation> {
+        Some(self.activation)
+    }
+
+    fn parameters(&self) -> Option<Vec<&Tensor>> {
+        if self.weights.elements() == 0 { return None; }
+        Some(vec![&self.weights, &self.biases])
+    }
+
+    fn parameters_mut(&mut self) -> Option<(Vec<&mut Tensor>, Vec<&Tensor>)> {
+        if self.weights.elements() == 0 { return None; }
+        // The caller may modify the parameters, hence the inner layers must read them again
+        *self.stale.get_mut() = true;
+        Some((vec![&mut self.weights, &mut self.biases], vec![&self.dweights, &self.dbiases]))
+    }
+
+    fn gradients_mut(&mut self) -> Option<Vec<&mut Tensor>> {
+        if self.weights.elements() == 0 { return None; }
+        Some(vec![&mut self.dweights, &mut self.dbiases])
+    }
+
+    fn save(&self, group: &hdf5::Group, layer_number: usize) -> Result<(), Error> {
+        let group_name = layer_number.to_string() + &String::from("_") + Self::NAME;
+        let residual = group.create_group(&group_name)?;
+
+        let activation = residual.new_dataset::<Activation>().create("activation", 1)?;
+        activation.write(&[self.activation])?;
+
+        let input_shape = residual.new_dataset::<[u64; 4]>().create("input_shape", 1)?;
+        input_shape.write(&[*self.input_shape.get()])?;
+
+        let output_shape = residual.new_dataset::<[u64; 4]>().create("output_shape", 1)?;
+        output_shape.write(&[*self.output_shape.get()])?;
+
+        let layers_group = create_group(&residual, "layers");
+        for (i, layer) in self.synchronized_layers().iter().enumerate() {
+            layer.save(&layers_group, i)?;
+        }
+
+        Ok(())
+    }
+
+    fn set_regularizer(&mut self, regularizer: Option<Regularizer>) {
+        for layer in self.layers.get_mut().unwrap().iter_mut() {
+            layer.set_regularizer(regularizer);
+        }
+    }
+
+    fn set_frozen_statistics(&mut self, frozen: bool) {
+        for layer in self.layers.get_mut().unwrap().iter_mut() {
+            layer.set_frozen_statistics(frozen);
+        }
+    }
+
+    fn set_training(&mut self, training: bool) {
+        for layer in self.layers.get_mut().unwrap().iter_mut() {
+            layer.set_training(training);
+        }
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        for (i, layer) in self.layers.get_mut().unwrap().iter_mut().enumerate() {
+            layer.set_seed(seed.wrapping_add(i as u64));
+        }
+    }
+}
+
+impl Clone for Residual {
+    fn clone(&self) -> Residual {
+        let layers = self.synchronized_layers().iter().map(|layer| layer.clone_box()).collect();
+        Residual {
+            layers: RwLock::new(layers),
+            activation: self.activation,
+            weights: self.weights.clone(),
+            biases: self.biases.clone(),
+            dweights: self.dweights.clone(),
+            dbiases: self.dbiases.clone(),
+            stale: AtomicBool::new(false),
+            input_shape: self.input_shape,
+            output_shape: self.output_shape,
+            linear_activation: self.linear_activation.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Residual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} \t {} \t\t [{}, {}, {}]", Self::NAME, self.weights.elements() + self.biases.elements(), self.output_shape[0], self.output_shape[1], self.output_shape[2])
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::activations::Activation;
+    use crate::assert_approx_eq;
+    use crate::layers::{Conv2D, Dense, Layer, Padding, Residual};
+    use crate::losses::MeanSquaredError;
+    use crate::models::NetworkBuilder;
+    use crate::optimizers::SGD;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_residual() {
+        let mut layer = Residual::new(vec![Dense::new(3, Activation::Linear)], Activation::Linear);
+        layer.initialize_parameters(Dim4::new(&[3, 1, 1, 1]));
+        {
+            let (mut parameters, _) = layer.parameters_mut().unwrap();
+            *parameters[0] = Tensor::new(&[2., 0., 0., 0., 2., 0., 0., 0., 2.], Dim4::new(&[9, 1, 1, 1]));
+            *parameters[1] = Tensor::new(&[1., 1., 1.], Dim4::new(&[3, 1, 1, 1]));
+        }
+
+        // The inner layer reads the updated parameters and the input is added to its output
+        let input = Tensor::new(&[1., 2., 3.], Dim4::new(&[3, 1, 1, 1]));
+        let mut output: [PrimitiveType; 3] = [0.; 3];
+        layer.compute_activation(&input).host(&mut output);
+        assert_approx_eq!(output, [4., 7., 10.]);
+        layer.compute_activation_mut(&input).host(&mut output);
+        assert_approx_eq!(output, [4., 7., 10.]);
+
+        let dz = Tensor::new(&[1., 1., 1.], Dim4::new(&[3, 1, 1, 1]));
+        layer.compute_dactivation_mut(&dz).host(&mut output);
+        assert_approx_eq!(output, [3., 3., 3.]);
+        let mut dbiases: [PrimitiveType; 3] = [0.; 3];
+        layer.gradients_mut().unwrap()[1].host(&mut dbiases);
+        assert_approx_eq!(dbiases, [1., 1., 1.]);
+    }
+
+    #[test]
+    fn test_residual_downsampling() {
+        let mut layer = Residual::new(vec![Conv2D::new(4, (3, 3), (2, 2), Padding::Same)], Activation::ReLU);
+        layer.initialize_parameters(Dim4::new(&[4, 4, 2, 1]));
+        assert_eq!(layer.output_shape().get(), &[2, 2, 4, 1]);
+
+        let input = randn::<PrimitiveType>(Dim4::new(&[4, 4, 2, 3]));
+        let output = layer.compute_activation_mut(&input);
+        assert_eq!(output.dims().get(), &[2, 2, 4, 3]);
+        let dinput = layer.compute_dactivation_mut(&output);
+        assert_eq!(dinput.dims().get(), &[4, 4, 2, 3]);
+    }
+    #[test]
+    fn test_residual_invalid_shortcut() {
+        // The main path changes the height from 4 to 3, which the subsampling of the shortcut can't match
+        let layer = Residual::new(vec![Dense::new(3, Activation::Linear)], Activation::Linear);
+        assert!(!layer.accepts_input_shape(Dim4::new(&[4, 1, 1, 1])));
+        assert!(layer.accepts_input_shape(Dim4::new(&[3, 1, 1, 1])));
+
+        let result = NetworkBuilder::new(Dim4::new(&[4, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1))
+            .add(Residual::new(vec![Dense::new(3, Activation::Linear)], Activation::Linear))
+            .build();
+        assert!(result.is_err());
+    }
+}
@@ -4,10 +4,11 @@
 //! Neuro is a deep learning library that runs on the GPU. The library is designed to be very modular and allow users
 //! to easily add custom activation functions, loss functions, layers, and optimizers.
 //! The library presently supports:
-//! * Layers: BatchNorm, Conv2D, Dense, Dropout, Flatten, MaxPool2D.
+//! * Layers: BatchNorm, ComplexDense, Conv2D, Dense, Dropout, Flatten, GaussianNoise, GlobalAvgPool2D, Masking, MaxPool2D, Projection, Residual.
 //! * Optimizers: Adadelta, Adam, RMSprop, SGD.
 //! * Activations: LeakyReLU, Linear, ReLU, Sigmoid, Softmax, Tanh.
 //! * Loss functions: BinaryCrossEntropy, CrossEntropy, MeanAbsoluteError, MeanSquaredError, SoftmaxCrossEntropy.
+//! * Reference architectures: LeNet, VGG-small, ResNet-18.
 //!
 //! Additionaly, many initialization schemes are available. The current implementation allows the creation
 //! of feedforward and convolutional neural networks. It is planned to add recurrent neural networks in the future.
@@ -36,21 +37,72 @@
 //!
 //! It is highly recommended to build the project in release mode for considerable speedup (e.g. `cargo run my_project --release`).
 //! In order to quickly get started, check out the [examples](https://srenevey.github.io/neuro/examples).
+//!
+//! # Double precision
+//! The tensors, parameters, and losses are single precision by default. Building the crate with the `f64` feature
+//! switches the [PrimitiveType](tensor/type.PrimitiveType.html) to double precision end to end, including the values
+//! stored in HDF5 files. The pure Rust [cpu](cpu/index.html) module remains single precision.
+//!
+//! # CPU backend
+//! ArrayFire and HDF5 are enabled by the default `arrayfire-backend` feature. When the crate is built without default
+//! features, only the pure Rust [cpu](cpu/index.html) module is available. It evaluates networks trained with ArrayFire
+//! and exported with `Network::to_cpu`, which makes it possible to run inference in WebAssembly or on machines where
+//! ArrayFire is not installed.
 
+#[cfg(feature = "arrayfire-backend")]
 pub use self::tensor::Tensor;
 
+#[cfg(feature = "arrayfire-backend")]
 pub mod activations;
+#[cfg(feature = "arrayfire-backend")]
+pub mod active_learning;
+#[cfg(feature = "arrayfire-backend")]
+pub mod anomaly;
+#[cfg(feature = "arrayfire-backend")]
+pub mod attribution;
+pub mod backend;
+#[cfg(feature = "arrayfire-backend")]
+pub mod bench;
+#[cfg(feature = "arrayfire-backend")]
+pub mod calibration;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod cpu;
+#[cfg(feature = "arrayfire-backend")]
 pub mod data;
+#[cfg(feature = "arrayfire-backend")]
+pub mod device;
+#[cfg(feature = "arrayfire-backend")]
 pub mod errors;
+#[cfg(feature = "arrayfire-backend")]
 pub mod initializers;
+#[cfg(feature = "arrayfire-backend")]
 pub(crate) mod io;
+#[cfg(feature = "arrayfire-backend")]
 pub mod layers;
+#[cfg(feature = "arrayfire-backend")]
 pub mod losses;
+#[cfg(feature = "arrayfire-backend")]
 pub mod metrics;
+#[cfg(feature = "arrayfire-backend")]
 pub mod models;
+#[cfg(feature = "arrayfire-backend")]
 pub mod optimizers;
+#[cfg(feature = "arrayfire-backend")]
+pub mod pipeline;
+pub mod privacy;
+#[cfg(feature = "arrayfire-backend")]
+pub mod profiling;
+#[cfg(feature = "arrayfire-backend")]
 pub mod regularizers;
+#[cfg(feature = "arrayfire-backend")]
+pub mod schedulers;
+#[cfg(feature = "serving")]
+pub mod serving;
+#[cfg(feature = "arrayfire-backend")]
 pub mod tensor;
+#[cfg(feature = "arrayfire-backend")]
+pub mod training;
 
 /// Asserts if two expressions are approximately equal.
 #[macro_export]
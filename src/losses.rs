@@ -5,15 +5,55 @@ use arrayfire::*;
 use crate::tensor::*;
 
 /// Defines the behaviors of a loss function.
-pub trait Loss {
+pub trait Loss: Send + Sync {
     /// Returns a unique identifier.
     fn id(&self) -> u64;
 
+    /// Returns a copy of the loss function.
+    fn clone_box(&self) -> Box<dyn Loss>;
+
     /// Computes the value of the loss function from the predicted and true labels.
     fn eval(&self, y_pred: &Tensor, y_true: &Tensor) -> PrimitiveType;
 
     /// Computes the gradient of the loss function from the predicted and true labels.
     fn grad(&self, y_pred: &Tensor, y_true: &Tensor) -> Tensor;
+
+    /// Computes the value of the loss function for each sample of the batch.
+    ///
+    /// The returned tensor has dimensions [1, 1, 1, batch_size]. The default implementation evaluates the loss
+    /// sample by sample and should be overridden by a vectorized version whenever possible.
+    fn eval_per_sample(&self, y_pred: &Tensor, y_true: &Tensor) -> Tensor {
+        let batch_size = y_pred.batch_size();
+        let values: Vec<PrimitiveType> = (0..batch_size).map(|i| {
+            let seqs = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(i as f64, i as f64, 1.0)];
+            self.eval(&index(y_pred, seqs), &index(y_true, seqs))
+        }).collect();
+        Tensor::new(&values[..], Dim::new(&[1, 1, 1, batch_size]))
+    }
+
+    /// Computes the value of the loss function where the contribution of each sample is scaled by a weight.
+    ///
+    /// # Arguments
+    ///
+    /// * `y_pred` - The predicted labels.
+    /// * `y_true` - The true labels.
+    /// * `weights` - The weight of each sample, with dimensions [1, 1, 1, batch_size].
+    fn eval_weighted(&self, y_pred: &Tensor, y_true: &Tensor, weights: &Tensor) -> PrimitiveType {
+        let batch_size = y_pred.batch_size() as PrimitiveType;
+        let weighted_loss = mul(&self.eval_per_sample(y_pred, y_true), weights, true);
+        sum_all(&weighted_loss).0 as PrimitiveType / batch_size
+    }
+}
+
+impl Clone for Box<dyn Loss> {
+    fn clone(&self) -> Box<dyn Loss> {
+        self.clone_box()
+    }
+}
+
+/// Sums the values of each sample, returning a tensor with dimensions [1, 1, 1, batch_size].
+fn sum_per_sample(values: &Tensor) -> Tensor {
+    sum(&values.flatten(), 0)
 }
 
 pub(crate) fn loss_from_id(id: u64) -> Box<dyn Loss> {
@@ -41,6 +81,10 @@ impl Loss for BinaryCrossEntropy {
         1
     }
 
+    fn clone_box(&self) -> Box<dyn Loss> {
+        Box::new(*self)
+    }
+
     fn eval(&self,
             y_pred: &Tensor,
             y_true: &Tensor
@@ -59,6 +103,15 @@ impl Loss for BinaryCrossEntropy {
         let ones = Tensor::ones(y_true.dims());
         -  (y_true / y_pred - (&ones - y_true) / (&ones - y_pred))
     }
+
+    fn eval_per_sample(&self,
+                       y_pred: &Tensor,
+                       y_true: &Tensor
+    ) -> Tensor {
+        let mut loss = clamp(y_pred, &(1e-15 as PrimitiveType), &((1. - 1e-15) as PrimitiveType), true);
+        loss = y_true * log(&loss) + (Tensor::ones(y_true.dims()) - y_true) * log(&sub(&Tensor::ones(loss.dims()), &loss, true));
+        - sum_per_sample(&loss)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -75,6 +128,10 @@ impl Loss for CrossEntropy {
         2
     }
 
+    fn clone_box(&self) -> Box<dyn Loss> {
+        Box::new(*self)
+    }
+
     fn eval(&self,
             y_pred: &Tensor,
             y_true: &Tensor
@@ -92,6 +149,15 @@ impl Loss for CrossEntropy {
     ) -> Tensor {
         - (y_true / y_pred)
     }
+
+    fn eval_per_sample(&self,
+                       y_pred: &Tensor,
+                       y_true: &Tensor
+    ) -> Tensor {
+        let mut loss = clamp(y_pred, &(1e-15 as PrimitiveType), &((1. - 1e-15) as PrimitiveType), true);
+        loss = mul(y_true, &log(&loss), true);
+        - sum_per_sample(&loss)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -108,6 +174,10 @@ impl Loss for MeanAbsoluteError {
         3
     }
 
+    fn clone_box(&self) -> Box<dyn Loss> {
+        Box::new(*self)
+    }
+
     fn eval(&self,
             y_pred: &Tensor,
             y_true: &Tensor
@@ -123,6 +193,13 @@ impl Loss for MeanAbsoluteError {
         let cond = ge(y_pred, y_true, true);
         selectr(&Tensor::ones(y_pred.dims()), &cond, -1.0f64)
     }
+
+    fn eval_per_sample(&self,
+                       y_pred: &Tensor,
+                       y_true: &Tensor
+    ) -> Tensor {
+        sum_per_sample(&abs(&(y_pred - y_true)))
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -139,6 +216,10 @@ impl Loss for MeanSquaredError {
         4
     }
 
+    fn clone_box(&self) -> Box<dyn Loss> {
+        Box::new(*self)
+    }
+
     fn eval(&self,
             y_pred: &Tensor,
             y_true: &Tensor
@@ -153,32 +234,53 @@ impl Loss for MeanSquaredError {
     ) -> Tensor {
         (y_pred - y_true) * 2. as PrimitiveType
     }
+
+    fn eval_per_sample(&self,
+                       y_pred: &Tensor,
+                       y_true: &Tensor
+    ) -> Tensor {
+        sum_per_sample(&pow(&(y_pred - y_true), &(2.0 as PrimitiveType), true))
+    }
 }
 
 
 /// Applies the softmax function on the input and then computes the cross entropy loss.
+///
+/// The loss operates on the logits, i.e. the output of a layer with a linear activation. The log-probabilities are
+/// computed with the log-sum-exp trick such that the loss remains finite when the probabilities saturate to 0 or 1.
 #[derive(Debug, Copy, Clone)]
 pub struct SoftmaxCrossEntropy;
 
 impl SoftmaxCrossEntropy {
+    /// Creates the softmax cross-entropy computed from the logits.
     pub fn new() -> Box<SoftmaxCrossEntropy> {
         Box::new(SoftmaxCrossEntropy)
     }
 }
 
+/// Computes the logarithm of the softmax of the logits along the first dimension.
+///
+/// The maximum logit is subtracted before the exponentiation such that log(sum(exp(z))) never overflows.
+pub(crate) fn log_softmax(logits: &Tensor) -> Tensor {
+    let z_shifted = sub(logits, &max(logits, 0), true);
+    sub(&z_shifted, &log(&sum(&exp(&z_shifted), 0)), true)
+}
+
 impl Loss for SoftmaxCrossEntropy {
     fn id(&self) -> u64 {
         5
     }
 
+    fn clone_box(&self) -> Box<dyn Loss> {
+        Box::new(*self)
+    }
+
     fn eval(&self,
             y_pred: &Tensor,
             y_true: &Tensor
     ) -> PrimitiveType {
         let batch_size = y_pred.dims().get()[3] as PrimitiveType;
-        // Prevent the log to explode by clipping the predicted values
-        let mut loss = clamp(y_pred, &(1e-15 as PrimitiveType), &((1. - 1e-15) as PrimitiveType), true);
-        loss = y_true * &log(&loss);
+        let loss = mul(y_true, &log_softmax(y_pred), false);
          - 1. / batch_size * sum_all(&loss).0 as PrimitiveType
     }
 
@@ -186,7 +288,15 @@ impl Loss for SoftmaxCrossEntropy {
             y_pred: &Tensor,
             y_true: &Tensor
     ) -> Tensor {
-        y_pred - y_true
+        exp(&log_softmax(y_pred)) - y_true
+    }
+
+    fn eval_per_sample(&self,
+                       y_pred: &Tensor,
+                       y_true: &Tensor
+    ) -> Tensor {
+        let loss = mul(y_true, &log_softmax(y_pred), false);
+        - sum_per_sample(&loss)
     }
 }
 
@@ -229,6 +339,24 @@ mod tests {
         assert_approx_eq!([loss1, loss2, loss3], [expected_output1, expected_output2, expected_output3]);
     }
 
+    #[test]
+    fn test_mse_eval_per_sample() {
+        let loss_fun = MeanSquaredError;
+
+        // 2 samples, 2 outputs
+        let y_true = Tensor::new(&[-16.8, 2.34, -0.2, 31.7], Dim::new(&[2, 1, 1, 2]));
+        let y_pred = Tensor::new(&[-16.5, -0.9, -3.4, 29.6], Dim::new(&[2, 1, 1, 2]));
+        let loss = loss_fun.eval_per_sample(&y_pred, &y_true);
+        let mut output: [PrimitiveType; 2] = [0.; 2];
+        loss.host(&mut output);
+        let expected_output: [PrimitiveType; 2] = [10.5876, 14.65];
+        assert_approx_eq!(output, expected_output);
+
+        let weights = Tensor::new(&[1., 0.], Dim::new(&[1, 1, 1, 2]));
+        let weighted_loss = loss_fun.eval_weighted(&y_pred, &y_true, &weights);
+        assert_approx_eq!([weighted_loss], [5.2938]);
+    }
+
     #[test]
     fn test_mse_grad() {
         let device_id = get_device();
@@ -251,11 +379,28 @@ mod tests {
         let mut y_pred = Tensor::new(&[0.2337, 0.3056, 0.4608, 0.4079, 0.1819, 0.4102, 0.4034, 0.2517, 0.3449, 0.2227, 0.2946, 0.4828], Dim::new(&[3, 1, 1, 4]));
         let mut y_true = Tensor::new(&[0., 0., 1., 0., 0., 1., 1., 0., 0., 0., 0., 1.], Dim::new(&[3, 1, 1, 4]));
         let output = loss.eval(&y_pred, &y_true);
-        let expected_output: PrimitiveType = 0.825470261541275;
+        let expected_output: PrimitiveType = 0.997206119;
         assert_approx_eq!([output], [expected_output]);
 
     }
 
+    #[test]
+    fn test_softmax_cross_entropy_saturated_logits() {
+        let loss = SoftmaxCrossEntropy;
+
+        // The probabilities saturate to 0 and 1 but the loss remains finite
+        let y_pred = Tensor::new(&[1000., 0., -1000., -500., 500., 0.], Dim::new(&[3, 1, 1, 2]));
+        let y_true = Tensor::new(&[1., 0., 0., 1., 0., 0.], Dim::new(&[3, 1, 1, 2]));
+        let mut per_sample: [PrimitiveType; 2] = [0.; 2];
+        loss.eval_per_sample(&y_pred, &y_true).host(&mut per_sample);
+        assert_approx_eq!(per_sample, [0., 1000.]);
+        assert_approx_eq!([loss.eval(&y_pred, &y_true)], [500.]);
+
+        let mut grad: [PrimitiveType; 6] = [0.; 6];
+        loss.grad(&y_pred, &y_true).host(&mut grad);
+        assert_approx_eq!(grad, [0., 0., 0., -1., 1., 0.]);
+    }
+
     #[test]
     fn test_softmax_cross_entropy_grad() {
         let loss = SoftmaxCrossEntropy;
@@ -264,7 +409,7 @@ mod tests {
         let grad = loss.grad(&y_pred, &y_true);
         let mut output: [PrimitiveType; 12] = [0.; 12];
         grad.host(&mut output);
-        let expected_output: [PrimitiveType; 12] = [0.2337, 0.3056, -0.5392, 0.4079, 0.1819, -0.5898, -0.5966, 0.2517, 0.3449, 0.2227, 0.2946, -0.5172];
+        let expected_output: [PrimitiveType; 12] = [0.300345105, 0.322735197, -0.623080302, 0.357140211, 0.284897211, -0.642037422, -0.643166092, 0.306608114, 0.336557978, 0.296594414, 0.318704900, -0.615299313];
         assert_approx_eq!(output, expected_output);
 
     }
@@ -19,7 +19,8 @@ pub enum Metrics {
 }
 
 impl Metrics {
-    pub(crate) fn eval(&self, y_pred: &Tensor, y_true: &Tensor) -> PrimitiveType {
+    /// Evaluates the metric, where the binary predictions greater than or equal to the threshold belong to the positive class.
+    pub(crate) fn eval_with_threshold(&self, y_pred: &Tensor, y_true: &Tensor, threshold: PrimitiveType) -> PrimitiveType {
         match self {
             Metrics::Accuracy => {
                 let batch_size = y_true.dims().get()[3];
@@ -27,7 +28,7 @@ impl Metrics {
 
 
                 let (predicted_class, true_class) = if num_classes == 1 {
-                    let predicted_class = select(&constant(1u32, y_pred.dims()), &ge(y_pred, &0.5, true), &constant(0u32, y_pred.dims()));
+                    let predicted_class = select(&constant(1u32, y_pred.dims()), &ge(y_pred, &threshold, true), &constant(0u32, y_pred.dims()));
                     let true_class = select(&constant(1u32, y_true.dims()), &ge(y_true, &0.5, true), &constant(0u32, y_true.dims()));
                     (predicted_class, true_class)
                 } else {
@@ -50,6 +51,41 @@ impl Metrics {
             */
         }
     }
+
+    /// Evaluates the metric on the valid timesteps of sequences.
+    ///
+    /// The timesteps are along the first axis and the classes along the second axis of the labels. The mask has
+    /// dimensions [timesteps, 1, 1, batch_size] and contains 1 for the valid timesteps and 0 for the padded ones. The
+    /// binary predictions greater than or equal to the threshold belong to the positive class.
+    pub(crate) fn eval_masked(&self, y_pred: &Tensor, y_true: &Tensor, mask: &Tensor, threshold: PrimitiveType) -> PrimitiveType {
+        match self {
+            Metrics::Accuracy => {
+                let num_classes = y_true.dims().get()[1];
+
+                let correctly_classified = if num_classes == 1 {
+                    eq(&ge(y_pred, &threshold, true), &ge(y_true, &0.5, true), false)
+                } else {
+                    eq(&imax(y_pred, 1).1, &imax(y_true, 1).1, false)
+                };
+                let valid = mul(&constant(1 as PrimitiveType, correctly_classified.dims()), mask, true);
+                let num_valid = sum_all(&valid).0 as PrimitiveType;
+                if num_valid == 0. { return 0.; }
+
+                let num_correct = sum_all(&mul(&correctly_classified.cast::<PrimitiveType>(), &valid, false)).0 as PrimitiveType;
+                num_correct / num_valid
+            },
+        }
+    }
+}
+
+
+/// Computes the F1 score from the confusion matrix of a binary classifier.
+///
+/// The score is zero if there is no true positive.
+pub fn f1_score(true_positives: u64, false_positives: u64, _true_negatives: u64, false_negatives: u64) -> PrimitiveType {
+    if true_positives == 0 { return 0.; }
+    let tp = true_positives as PrimitiveType;
+    2. * tp / (2. * tp + false_positives as PrimitiveType + false_negatives as PrimitiveType)
 }
 
 
@@ -67,7 +103,31 @@ mod tests {
         let y_true = Array::new(&true_values, Dim4::new(&[3, 1, 1, 3]));
 
         let metrics = Metrics::Accuracy;
-        let score = metrics.eval(&y_pred, &y_true);
+        let score = metrics.eval_with_threshold(&y_pred, &y_true, 0.5);
         assert_approx_eq!([score], [0.6666666]);
     }
+
+    #[test]
+    fn test_accuracy_masked() {
+        // Two sequences of 3 timesteps with one binary output, the last timestep of the first sequence is padded
+        let predictions = [0.9, 0.2, 0.8, 0.1, 0.7, 0.4];
+        let true_values = [1., 0., 0., 0., 1., 1.];
+        let mask_values = [1., 1., 0., 1., 1., 1.];
+        let y_pred = Array::new(&predictions, Dim4::new(&[3, 1, 1, 2]));
+        let y_true = Array::new(&true_values, Dim4::new(&[3, 1, 1, 2]));
+        let mask = Array::new(&mask_values, Dim4::new(&[3, 1, 1, 2]));
+
+        let score = Metrics::Accuracy.eval_masked(&y_pred, &y_true, &mask, 0.5);
+        assert_approx_eq!([score], [0.8]);
+    }
+
+    #[test]
+    fn test_accuracy_with_threshold() {
+        let y_pred = Array::new(&[0.2, 0.4, 0.7, 0.35], Dim4::new(&[1, 1, 1, 4]));
+        let y_true = Array::new(&[0., 1., 1., 0.], Dim4::new(&[1, 1, 1, 4]));
+
+        let metrics = Metrics::Accuracy;
+        assert_approx_eq!([metrics.eval_with_threshold(&y_pred, &y_true, 0.5)], [0.75]);
+        assert_approx_eq!([metrics.eval_with_threshold(&y_pred, &y_true, 0.38)], [1.]);
+    }
 }
\ No newline at end of file
@@ -2,19 +2,33 @@
 //! Base module to create neural networks.
 use arrayfire::*;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashMap;
 use std::fmt;
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Instant;
 use rand::prelude::*;
 
-use crate::data::{DataSet, BatchIterator};
+use crate::activations::Activation;
+use crate::calibration;
+use crate::cpu::CpuNetwork;
+use crate::data::{DataSet, DataSetError, BatchIterator, SparseBatchIterator};
+use crate::device;
 use crate::errors::Error;
 use crate::io::*;
 use crate::layers::*;
 use crate::losses::*;
 use crate::metrics::*;
 use crate::optimizers::*;
+use crate::privacy::{self, PrivacyAccountant};
+use crate::profiling::{Pass, Trace, TraceEvent};
 use crate::regularizers::*;
 use crate::tensor::*;
+use crate::training::*;
+
+pub mod distributed;
+pub mod federated;
+pub mod zoo;
 
 enum Mode {
     Test,
@@ -22,8 +36,37 @@ enum Mode {
     Valid,
 }
 
+/// Additional output of the network branching from a layer of the main stack.
+#[derive(Clone)]
+struct Head {
+    branch: usize,
+    layers: Vec<Box<dyn Layer>>,
+    loss_function: Box<dyn Loss>,
+    weight: PrimitiveType,
+    auxiliary: bool,
+    input: Option<Tensor>,
+    dinput: Option<Tensor>,
+}
+
+/// State of the optimization carried across the mini-batches of a training.
+#[derive(Default)]
+struct OptimizationState {
+    accumulated_gradients: Vec<Vec<Tensor>>,
+    num_accumulated: u64,
+}
+
 
 /// Structure representing a neural network.
+///
+/// A network is `Send` and `Sync`. The inference methods ([predict](struct.Network.html#method.predict) and
+/// [predict_class](struct.Network.html#method.predict_class)) only borrow the network immutably, such that a trained
+/// model can be wrapped in an `Arc` and used concurrently by multiple threads. Note that ArrayFire selects the active
+/// device per thread.
+///
+/// Cloning a network copies its layers with their parameters, the state of its optimizer, and its configuration, for
+/// instance to snapshot a model during the training or to keep a frozen teacher for a distillation. The clone is
+/// independent of the original network: training one doesn't modify the other.
+#[derive(Clone)]
 pub struct Network
 {
     layers: Vec<Box<dyn Layer>>,
@@ -33,6 +76,16 @@ pub struct Network
     input_shape: Dim,
     output_shape: Dim,
     classes: Option<Vec<String>>,
+    optimizer_initialized: bool,
+    eval_points: EvalPoints,
+    training: bool,
+    tied_weights: Vec<(usize, usize, bool)>, // source, target, transpose
+    gradient_norms: Vec<(String, Tensor)>,
+    threshold: PrimitiveType,
+    temperature: Option<PrimitiveType>,
+    heads: Vec<Head>,
+    best_parameters: Option<Vec<Tensor>>,
+    num_steps: u64,
 }
 
 impl Network
@@ -58,6 +111,16 @@ impl Network
             input_shape,
             output_shape: Dim::new(&[0, 0, 0, 0]),
             classes: None,
+            optimizer_initialized: false,
+            eval_points: EvalPoints::Layer,
+            training: true,
+            tied_weights: Vec::new(),
+            gradient_norms: Vec::new(),
+            threshold: 0.5,
+            temperature: None,
+            heads: Vec::new(),
+            best_parameters: None,
+            num_steps: 0,
         })
     }
 
@@ -72,28 +135,412 @@ impl Network
         if let Some(layer) = self.layers.last_mut() {
             layer.initialize_parameters(input_shape);
             layer.set_regularizer(self.regularizer);
+            layer.set_training(self.training);
 
             self.output_shape = layer.output_shape();
         }
     }
 
+    /// Adds a stack of layers to the network, in order.
+    ///
+    /// This adds the pre-configured stacks of the [backbones](../layers/backbones/index.html) and
+    /// [heads](../layers/heads/index.html) modules.
+    pub fn add_all(&mut self, layers: Vec<Box<dyn Layer>>) {
+        for layer in layers {
+            self.add(layer);
+        }
+    }
+
+
+    /// Replaces the loss function of the network.
+    ///
+    /// This allows a trained or loaded network to be fine-tuned with a different objective. The state of the
+    /// optimizer is preserved. The loss function is saved with the network, such that a network using a custom loss
+    /// function can only be loaded if the loss function is one of the losses of the crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `IncompatibleLoss` error if the loss function is incompatible with the activation of the output
+    /// layer, in which case the loss function is not replaced.
+    pub fn set_loss(&mut self, loss_function: Box<dyn Loss>) -> Result<(), Error> {
+        self.check_loss(loss_function.id())?;
+        self.loss_function = loss_function;
+        Ok(())
+    }
+
+
+    /// Checks that the loss function with the given id is compatible with the activation of the output layer.
+    fn check_loss(&self, loss_id: u64) -> Result<(), Error> {
+        // The activation of the output is the one of the last layer with an activation
+        let output_activation = self.layers.iter().rev().find_map(|layer| layer.activation());
+        if let Some(activation) = output_activation {
+            match (loss_id, activation) {
+                (id, Activation::Softmax) if id == SoftmaxCrossEntropy.id() => {
+                    return Err(Error::IncompatibleLoss(String::from("the softmax cross-entropy already applies a softmax, the output layer should have a linear activation")));
+                },
+                (id, Activation::Softmax) if id == CrossEntropy.id() => {},
+                (id, _) if id == CrossEntropy.id() => {
+                    return Err(Error::IncompatibleLoss(String::from("the cross-entropy expects probabilities, the output layer should have a softmax activation")));
+                },
+                (id, Activation::Sigmoid) if id == BinaryCrossEntropy.id() => {},
+                (id, _) if id == BinaryCrossEntropy.id() => {
+                    return Err(Error::IncompatibleLoss(String::from("the binary cross-entropy expects probabilities, the output layer should have a sigmoid activation")));
+                },
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+
+    /// Adds an output head branching from a layer of the network.
+    ///
+    /// A head is a stack of layers computing an additional output from the activation of a layer of the main stack,
+    /// for instance a regression output computed from the features shared with a classifier. During the training,
+    /// the loss of each head is scaled by its weight and its gradient is added to the backward pass of the main stack.
+    /// The labels of the heads are given by [DataSet::y_train_heads](../data/trait.DataSet.html#method.y_train_heads).
+    /// The losses reported during the training are the losses of the main output. The heads are not saved with the
+    /// network.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - The index of the layer whose activation is the input of the head.
+    /// * `layers` - The layers of the head.
+    /// * `loss_function` - The loss function of the head.
+    /// * `weight` - The weight of the loss of the head.
+    ///
+    /// # Return value
+    ///
+    /// The index of the head.
+    pub fn add_head(&mut self,
+                    branch: usize,
+                    layers: Vec<Box<dyn Layer>>,
+                    loss_function: Box<dyn Loss>,
+                    weight: PrimitiveType
+    ) -> Result<usize, Error> {
+        self.insert_head(branch, layers, loss_function, weight, false)
+    }
+
+
+    /// Adds an auxiliary classifier attached to an intermediate layer of the network (Szegedy et al., 2015).
+    ///
+    /// An auxiliary head is trained on the labels of the network: its loss, scaled by its weight, supervises the
+    /// intermediate layers directly, which strengthens the gradients reaching the first layers of deep networks. The
+    /// auxiliary heads are only used during the training and don't affect the predictions of the network. A weight of
+    /// 0.3 is a common choice.
+    ///
+    /// # Arguments
+    ///
+    /// * `branch` - The index of the layer whose activation is the input of the head.
+    /// * `layers` - The layers of the head. The output shape of the last layer must be the output shape of the network.
+    /// * `loss_function` - The loss function of the head.
+    /// * `weight` - The weight of the loss of the head.
+    ///
+    /// # Return value
+    ///
+    /// The index of the head.
+    pub fn add_auxiliary_head(&mut self,
+                              branch: usize,
+                              layers: Vec<Box<dyn Layer>>,
+                              loss_function: Box<dyn Loss>,
+                              weight: PrimitiveType
+    ) -> Result<usize, Error> {
+        self.insert_head(branch, layers, loss_function, weight, true)
+    }
+
+
+    /// Initializes the layers of a head and adds it to the network.
+    fn insert_head(&mut self,
+                   branch: usize,
+                   layers: Vec<Box<dyn Layer>>,
+                   loss_function: Box<dyn Loss>,
+                   weight: PrimitiveType,
+                   auxiliary: bool
+    ) -> Result<usize, Error> {
+        if branch >= self.layers.len() {
+            return Err(Error::InvalidLayerIndex(branch));
+        }
+        if layers.is_empty() {
+            return Err(Error::NoLayer);
+        }
+
+        let mut input_shape = self.layers[branch].output_shape();
+        let mut head_layers = Vec::with_capacity(layers.len());
+        for mut layer in layers {
+            layer.initialize_parameters(input_shape);
+            layer.set_regularizer(self.regularizer);
+            layer.set_training(self.training);
+            input_shape = layer.output_shape();
+            head_layers.push(layer);
+        }
+        if auxiliary && input_shape != self.output_shape {
+            return Err(Error::OutputShapeMismatch);
+        }
+
+        // The state of the optimizer is extended with the parameters of the new layers
+        if self.optimizer_initialized {
+            self.optimizer.initialize_parameters(optimizer_dims(head_layers.iter()));
+        }
+
+        self.heads.push(Head {
+            branch,
+            layers: head_layers,
+            loss_function,
+            weight,
+            auxiliary,
+            input: None,
+            dinput: None,
+        });
+        Ok(self.heads.len() - 1)
+    }
+
+
+    /// Computes the outputs of the heads for the given input.
+    ///
+    /// The outputs are returned in the order in which the heads were added.
+    pub fn predict_heads(&self, input: &Tensor) -> Vec<Tensor> {
+        let mut branch_activations = vec![None; self.layers.len()];
+        let mut activation = input.copy();
+        for (idx, layer) in self.layers.iter().enumerate() {
+            activation = layer.compute_activation(&activation);
+            if self.heads.iter().any(|head| head.branch == idx) { branch_activations[idx] = Some(activation.clone()); }
+        }
+
+        self.heads.iter().map(|head| {
+            let input = branch_activations[head.branch].clone().expect("The input of the head has not been computed.");
+            let output = head.layers.iter().fold(input, |a_prev, layer| layer.compute_activation(&a_prev));
+            output.eval();
+            output
+        }).collect()
+    }
+
+
+    /// Evaluates the loss of each head on the given samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the network, used by the auxiliary heads.
+    /// * `head_labels` - The labels of the other heads, in the order in which the heads were added.
+    pub fn evaluate_heads(&self, x: &Tensor, y: &Tensor, head_labels: &[Tensor]) -> Vec<PrimitiveType> {
+        let mut head_labels = head_labels.iter();
+        self.predict_heads(x).iter()
+            .zip(self.heads.iter())
+            .filter_map(|(y_pred, head)| {
+                let y_true = if head.auxiliary { Some(y) } else { head_labels.next() };
+                y_true.map(|y_true| head.loss_function.eval(y_pred, y_true))
+            })
+            .collect()
+    }
+
+
+    /// Sets the points at which the lazy expressions built by ArrayFire are evaluated.
+    ///
+    /// By default, the output of each layer is evaluated.
+    pub fn set_eval_points(&mut self, eval_points: EvalPoints) {
+        self.eval_points = eval_points;
+    }
+
+    /// Switches the network to training mode.
+    ///
+    /// In training mode, the dropout layers drop units and the batch normalization layers normalize with the
+    /// statistics of the mini-batch. This is the default mode and [fit](struct.Network.html#method.fit) always
+    /// switches to it.
+    pub fn train_mode(&mut self) {
+        self.set_training(true);
+    }
+
+    /// Switches the network to inference mode.
+    ///
+    /// In inference mode, the dropout layers let all the values through and the batch normalization layers normalize
+    /// with their running statistics, including in the passes computed by
+    /// [train_on_batch](struct.Network.html#method.train_on_batch). The inference methods always behave as in
+    /// inference mode.
+    pub fn eval_mode(&mut self) {
+        self.set_training(false);
+    }
+
+    /// Returns true if the network is in training mode.
+    pub fn is_training(&self) -> bool {
+        self.training
+    }
+
+    fn set_training(&mut self, training: bool) {
+        self.training = training;
+        for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+            layer.set_training(training);
+        }
+    }
+
+    /// Seeds the random engine of ArrayFire and the random number generators of the layers for a mini-batch, each
+    /// layer with a different seed derived from the seed of the mini-batch.
+    fn seed_batch(&mut self, epoch_seed: u64, batch: u64) {
+        let seed = batch_seed(epoch_seed, batch);
+        set_seed(seed);
+        for (idx, layer) in all_layers_mut(&mut self.layers, &mut self.heads).enumerate() {
+            layer.set_seed(seed.wrapping_add(idx as u64));
+        }
+    }
+
+    /// Freezes or unfreezes the running statistics of the batch normalization layers.
+    ///
+    /// This is typically used to fine-tune a loaded network on a small data set, where the mini-batch statistics
+    /// are not representative of the data.
+    pub fn set_frozen_statistics(&mut self, frozen: bool) {
+        for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+            layer.set_frozen_statistics(frozen);
+        }
+    }
+
+    /// Returns the shape of the input of the network.
+    pub fn input_shape(&self) -> Dim {
+        self.input_shape
+    }
+
+    /// Returns the shape of the output of the network.
+    pub fn output_shape(&self) -> Dim {
+        self.output_shape
+    }
+
+    /// Returns the names of the classes of a classifier, if any.
+    ///
+    /// The names are retrieved from the dataset during the training, for instance the names of the directories of an
+    /// [ImageDataSet](../data/struct.ImageDataSet.html), and are saved with the model. The i-th name corresponds to the
+    /// i-th output of the network, or to the label i of binary classifiers with a single output.
+    pub fn class_names(&self) -> Option<&[String]> {
+        self.classes.as_deref()
+    }
+
+    /// Sets the names of the classes of a classifier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of names doesn't match the number of outputs of the network.
+    pub fn set_class_names(&mut self, class_names: Vec<String>) {
+        let num_outputs = self.output_shape.elements() as usize;
+        assert!(class_names.len() == num_outputs || (num_outputs == 1 && class_names.len() == 2),
+                "The number of class names doesn't match the number of outputs of the network.");
+        self.classes = Some(class_names);
+    }
+
+    /// Returns the optimizer of the network.
+    pub fn optimizer(&self) -> &dyn Optimizer {
+        &*self.optimizer
+    }
+
+    /// Returns the optimizer of the network such that its hyperparameters can be adjusted during the training.
+    ///
+    /// ```ignore
+    /// nn.optimizer_mut().set_hyperparameter("beta1", 0.95)?;
+    /// ```
+    pub fn optimizer_mut(&mut self) -> &mut dyn Optimizer {
+        &mut *self.optimizer
+    }
+
 
     /// Computes the output of the network for a given input.
     fn forward(&self, input: &Tensor) -> Tensor {
-        self.layers.iter().fold(
+        self.forward_masked(input).0
+    }
+
+
+    /// Computes the output of the network and the mask of the timesteps produced by the layers, if any.
+    fn forward_masked(&self, input: &Tensor) -> (Tensor, Option<Tensor>) {
+        let mut mask = None;
+        let output = self.layers.iter().fold(
             input.copy(),
-            |a_prev, layer| layer.compute_activation(&a_prev)
-        )
+            |a_prev, layer| {
+                if let Some(layer_mask) = layer.compute_mask(&a_prev) { mask = Some(layer_mask); }
+                let activation = layer.compute_activation(&a_prev);
+                if self.eval_points == EvalPoints::Layer { activation.eval(); }
+                activation
+            }
+        );
+        output.eval();
+        (output, mask)
     }
 
 
     /// Computes a forward pass of the network.
     ///
     /// The intermediate linear activations computed during the forward pass are stored in each layer for efficient back propagation.
-    fn forward_mut(&mut self, input: &mut Tensor) {
-        for layer in self.layers.iter_mut() {
+    ///
+    /// # Return value
+    ///
+    /// The mask of the timesteps produced by the layers, if any.
+    fn forward_mut(&mut self, input: &mut Tensor) -> Option<Tensor> {
+        let mut mask = None;
+        for (idx, layer) in self.layers.iter_mut().enumerate() {
+            if let Some(layer_mask) = layer.compute_mask(input) { mask = Some(layer_mask); }
             *input = layer.compute_activation_mut(input);
+            if self.eval_points == EvalPoints::Layer { input.eval(); }
+
+            store_head_inputs(&mut self.heads, idx, input);
+        }
+        input.eval();
+        mask
+    }
+
+
+    /// Computes the output of the network for sparse inputs.
+    ///
+    /// The first layer of the network must accept sparse inputs.
+    fn forward_sparse(&self, input: &SparseTensor) -> Result<Tensor, Error> {
+        let first_layer = self.layers.first().ok_or(Error::NoLayer)?;
+        let first_activation = first_layer.compute_activation_sparse(input).ok_or(Error::SparseInputNotSupported)?;
+        let output = self.layers[1..].iter().fold(
+            first_activation,
+            |a_prev, layer| {
+                let activation = layer.compute_activation(&a_prev);
+                if self.eval_points == EvalPoints::Layer { activation.eval(); }
+                activation
+            }
+        );
+        output.eval();
+        Ok(output)
+    }
+
+
+    /// Computes a forward pass of the network for sparse inputs.
+    ///
+    /// The intermediate linear activations computed during the forward pass are stored in each layer for efficient back propagation.
+    fn forward_sparse_mut(&mut self, input: &SparseTensor) -> Result<Tensor, Error> {
+        let (first_layer, layers) = self.layers.split_first_mut().ok_or(Error::NoLayer)?;
+        let mut activation = first_layer.compute_activation_sparse_mut(input).ok_or(Error::SparseInputNotSupported)?;
+        store_head_inputs(&mut self.heads, 0, &activation);
+        for (idx, layer) in layers.iter_mut().enumerate() {
+            activation = layer.compute_activation_mut(&activation);
+            if self.eval_points == EvalPoints::Layer { activation.eval(); }
+            store_head_inputs(&mut self.heads, idx + 1, &activation);
         }
+        activation.eval();
+        Ok(activation)
+    }
+
+
+    /// Evaluates the loss, ignoring the masked timesteps.
+    ///
+    /// The loss is averaged over the valid timesteps. The mask must apply to the first axis of the output, which is
+    /// checked by `check_mask`.
+    fn eval_loss(&self,
+                 y_pred: &Tensor,
+                 y_true: &Tensor,
+                 mask: Option<&Tensor>,
+                 sample_weights: Option<&Tensor>
+    ) -> PrimitiveType {
+        let (y_pred, valid_fraction) = match mask {
+            // The masked predictions are replaced by the labels such that they don't contribute to the loss
+            Some(mask) => {
+                let masked_pred = add(&mul(y_pred, mask, true), &mul(y_true, &sub(&(1.0 as PrimitiveType), mask, true), true), false);
+                (masked_pred, valid_fraction(mask))
+            },
+            None => (y_pred.clone(), 1.),
+        };
+        let loss = match sample_weights {
+            Some(weights) => self.loss_function.eval_weighted(&y_pred, y_true, weights),
+            None => self.loss_function.eval(&y_pred, y_true),
+        };
+        loss / valid_fraction
     }
 
     /// Computes a backward pass of the network.
@@ -102,13 +549,34 @@ impl Network
     ///
     /// * `y_pred` - The output of the network.
     /// * `y_true` - The true labels.
+    /// * `sample_weights` - The weights scaling the gradient of each sample, if any.
+    /// * `mask` - The mask of the timesteps, if any. The gradient of the masked timesteps is set to zero.
     fn backward(&mut self,
                 y_pred: &Tensor,
-                y_true: &Tensor
+                y_true: &Tensor,
+                sample_weights: Option<&Tensor>,
+                mask: Option<&Tensor>,
     ) {
-        self.layers.iter_mut().rev().fold(
-            self.loss_function.grad(y_pred, y_true),
-            |da_prev, layer| layer.compute_dactivation_mut(&da_prev)
+        let mut loss_grad = match sample_weights {
+            Some(weights) => mul(&self.loss_function.grad(y_pred, y_true), weights, true),
+            None => self.loss_function.grad(y_pred, y_true),
+        };
+        if let Some(mask) = mask {
+            loss_grad = mul(&loss_grad, mask, true) / valid_fraction(mask);
+        }
+        let heads = &mut self.heads;
+        self.layers.iter_mut().enumerate().rev().fold(
+            loss_grad,
+            |da_prev, (idx, layer)| {
+                // Add the gradients backpropagated from the heads branching from this layer
+                let da_prev = heads.iter_mut()
+                    .filter(|head| head.branch == idx)
+                    .fold(da_prev, |da_prev, head| match head.dinput.take() {
+                        Some(dinput) => add(&da_prev, &dinput, false),
+                        None => da_prev,
+                    });
+                layer.compute_dactivation_mut(&da_prev)
+            }
         );
 
         /*
@@ -119,159 +587,1284 @@ impl Network
         */
     }
 
+    /// Computes the backward pass through the heads from the inputs stored during the last forward pass.
+    ///
+    /// The gradients with respect to the inputs of the heads are stored and added to the main backward pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `y_true` - The labels of the network, used by the auxiliary heads.
+    /// * `head_labels` - The labels of the other heads. The heads without labels are skipped.
+    /// * `sample_weights` - The weights scaling the gradient of each sample, if any.
+    fn backward_heads(&mut self, y_true: &Tensor, head_labels: &[Tensor], sample_weights: Option<&Tensor>) {
+        let mut head_labels = head_labels.iter();
+        for head in self.heads.iter_mut() {
+            let labels = if head.auxiliary { Some(y_true) } else { head_labels.next() };
+            let (input, y_true) = match (head.input.take(), labels) {
+                (Some(input), Some(y_true)) => (input, y_true),
+                _ => continue,
+            };
+            let y_pred = head.layers.iter_mut().fold(input, |a_prev, layer| layer.compute_activation_mut(&a_prev));
+            let mut loss_grad = head.loss_function.grad(&y_pred, y_true) * head.weight;
+            if let Some(weights) = sample_weights { loss_grad = mul(&loss_grad, weights, true); }
+            head.dinput = Some(head.layers.iter_mut().rev().fold(loss_grad, |da_prev, layer| layer.compute_dactivation_mut(&da_prev)));
+        }
+    }
+
     /// Fits the neural network with the training data.
     ///
     /// The training data are shuffled at the beginning of each epoch, before batches are created. The progress is printed
-    /// at every `print_loss` epoch.
+    /// at every `print_loss` epoch, followed by the peak device memory at the end of the training.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [fit_with_config](#method.fit_with_config), for instance an `IncompatibleLoss` error if the
+    /// loss function is incompatible with the activation of the output layer.
     pub fn fit<T: DataSet>(&mut self,
                data: &T,
                batch_size: u64,
                epochs: u64,
                print_loss: Option<u64>,
                metrics: Option<Vec<Metrics>>,
-    ) {
+    ) -> Result<(), Error> {
+        self.fit_with_options(data, batch_size, epochs, print_loss, metrics, None)
+    }
+
+    /// Fits the neural network with the training data, where the contribution of each sample is scaled by the weight of its class.
+    ///
+    /// This is a simpler alternative to a weighted loss function to train on imbalanced data sets. The weight of each
+    /// sample is computed from its one-hot encoded label. The losses printed during training are not weighted.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data set.
+    /// * `batch_size` - The size of the mini-batches.
+    /// * `epochs` - The number of epochs.
+    /// * `print_loss` - The losses and metrics are printed every `print_loss` epochs.
+    /// * `metrics` - The metrics that are evaluated when the losses are printed.
+    /// * `class_weights` - The weight of each class, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [fit_with_config](#method.fit_with_config), for instance an `InvalidFitConfig` error if the
+    /// number of class weights doesn't match the number of outputs of the network, or if the batch size or the printing
+    /// frequency is zero.
+    pub fn fit_with_options<T: DataSet>(&mut self,
+                                        data: &T,
+                                        batch_size: u64,
+                                        epochs: u64,
+                                        print_loss: Option<u64>,
+                                        metrics: Option<Vec<Metrics>>,
+                                        class_weights: Option<&[PrimitiveType]>,
+    ) -> Result<(), Error> {
+        let mut config = FitConfig::new()
+            .epochs(epochs)
+            .batch_size(batch_size)
+            .validation_frequency(print_loss);
+        if let Some(metrics) = metrics { config = config.metrics(metrics); }
+        if let Some(class_weights) = class_weights { config = config.class_weights(class_weights); }
+        self.fit_with_config(data, config)?;
+        Ok(())
+    }
+
+    /// Fits the neural network with the training data according to the configuration.
+    ///
+    /// The losses and metrics are evaluated every `validation_frequency` epochs and recorded in the returned history.
+    /// The callbacks are invoked at the end of each epoch and the training stops as soon as one of them returns `false`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidFitConfig` error if a value of the configuration is invalid, if the number of class weights
+    /// doesn't match the number of outputs of the network, or if a curriculum is configured and the training samples
+    /// are sparse or generated on the fly, or if differential privacy is configured with sparse or generated training
+    /// samples, a curriculum, or sharpness-aware minimization, and a `HeadLabelsMismatch` error if the data set contains labels for the
+    /// heads but not one set of labels per non-auxiliary head. The network is not trained in these cases. An
+    /// `InvalidFitConfig` error is also returned during the training if the curriculum selects no sample or an index
+    /// out of range, in which case the training stops at that epoch.
+    ///
+    /// Returns an `IncompatibleLoss` error if the loss function is incompatible with the activation of the output layer,
+    /// for instance a softmax activation followed by a softmax cross-entropy loss, in which case the network is not
+    /// trained either.
+    pub fn fit_with_config<T: DataSet>(&mut self, data: &T, mut config: FitConfig) -> Result<History, Error> {
+        config.validate()?;
+        self.check_loss(self.loss_function.id())?;
+        if let Some(weights) = &config.class_weights {
+            if weights.len() as u64 != self.output_shape[0] {
+                return Err(Error::InvalidFitConfig(format!("there must be one weight per class, got {} weights for {} classes", weights.len(), self.output_shape[0])));
+            }
+        }
+        if config.curriculum.is_some() && (data.batch_generator().is_some() || data.x_train_sparse().is_some()) {
+            return Err(Error::InvalidFitConfig(String::from("a curriculum requires dense training samples")));
+        }
+        if config.dp.is_some() && (data.batch_generator().is_some() || data.x_train_sparse().is_some() || config.curriculum.is_some() || config.sam_radius.is_some()) {
+            return Err(Error::InvalidFitConfig(String::from("differential privacy requires dense training samples, without curriculum nor sharpness-aware minimization")));
+        }
+        self.check_mask()?;
+        let num_head_labels = data.y_train_heads().len();
+        let num_labeled_heads = self.heads.iter().filter(|head| !head.auxiliary).count();
+        if num_head_labels > 0 && num_head_labels != num_labeled_heads {
+            return Err(Error::HeadLabelsMismatch);
+        }
+        let class_weights = config.class_weights.as_ref().map(|weights| Tensor::new(&weights[..], Dim::new(&[weights.len() as u64, 1, 1, 1])));
+        let batch_size = config.batch_size;
+        let epochs = config.epochs;
+        let input_corruption = config.input_corruption.or_else(|| data.input_noise().map(|std| InputCorruption::GaussianNoise { std }));
+
         let device = get_device();
-        let (name, platform, _, _) = device_info();
-        println!("Running on {} using {}.", name, platform);
+        if config.verbose {
+            let (name, platform, _, _) = device_info();
+            println!("Running on {} using {}.", name, platform);
+        }
 
         self.initialize_optimizer();
+        self.train_mode();
+        device::reset_peak_memory();
 
         // If it's a classification problem, store the classes.
         self.classes = data.classes();
 
         // Initialize progress bar
-        let num_bins = match print_loss {
+        let num_bins = match config.validation_frequency {
             Some(p) => {
-                let num_batches_train = 2 * p * (data.num_train_samples() as f64 / batch_size as f64).ceil() as u64;
+                let num_batches_train = match data.batch_generator() {
+                    Some((num_batches, _)) => 2 * p * num_batches,
+                    None => 2 * p * (data.num_train_samples() as f64 / batch_size as f64).ceil() as u64,
+                };
                 let num_batches_valid = (data.num_valid_samples() as f64 / batch_size as f64).ceil() as u64;
                 num_batches_train + num_batches_valid
             },
             None => epochs
         };
-        let mut progress_bar = ProgressBar::new(num_bins);
         let sty = ProgressStyle::default_bar()
             .template("[{elapsed_precise}] [{bar:50}] {msg}")
             .progress_chars("##-");
-        progress_bar.set_style(sty.clone());
+        let verbose = config.verbose;
+        let new_progress_bar = || {
+            let progress_bar = if verbose { ProgressBar::new(num_bins) } else { ProgressBar::hidden() };
+            progress_bar.set_style(sty.clone());
+            progress_bar
+        };
+        let mut progress_bar = new_progress_bar();
 
+        let mut history = History::with_monitor(config.monitor);
+        let mut seed_rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let mut state = OptimizationState::default();
+        if config.monitor.is_some() { self.best_parameters = None; }
+
+        // Calibrate the noise of the differentially private training to the privacy budget
+        let mut accountant = match config.dp {
+            Some((epsilon, delta, _)) => {
+                let num_samples = data.num_train_samples();
+                let sampling_rate = (batch_size as f64 / num_samples as f64).min(1.);
+                let steps = epochs * (num_samples as f64 / batch_size as f64).ceil() as u64;
+                let noise_multiplier = privacy::calibrate_noise(epsilon, delta, sampling_rate, steps)
+                    .ok_or_else(|| Error::InvalidFitConfig(format!("the privacy budget {} can't be met with a delta of {}", epsilon, delta)))?;
+                Some(PrivacyAccountant::new(noise_multiplier, sampling_rate))
+            },
+            None => None,
+        };
 
         // Train
         for epoch in 1..=epochs {
-            let (x_train_shuffled, y_train_shuffled) = Tensor::shuffle(data.x_train(), data.y_train());
-            let batches = BatchIterator::new((&x_train_shuffled, &y_train_shuffled), batch_size);
 
             // Reset progress bar
             if progress_bar.is_finished() {
-                progress_bar = ProgressBar::new(num_bins);
-                progress_bar.set_style(sty.clone());
+                progress_bar = new_progress_bar();
             }
             progress_bar.set_message(&format!("epoch: {}/{}", epoch, epochs));
 
+            // Seed the order of the samples of the epoch and record the seed such that the epoch can be replayed
+            let epoch_seed = match config.replay {
+                Some((seed, num_steps)) => {
+                    self.num_steps = num_steps;
+                    seed
+                },
+                None => seed_rng.gen(),
+            };
+            history.push_seed(epoch_seed, self.num_steps);
+            let mut rng = StdRng::seed_from_u64(epoch_seed);
 
             // Iterate over the batches
-            for (mut mini_batch_x, mini_batch_y) in batches {
-
-                // Compute a pass on the network
-                self.forward_mut(&mut mini_batch_x);
-                self.backward(&mini_batch_x, &mini_batch_y);
+            if let Some((num_batches, generator)) = data.batch_generator() {
+                let mut batch_indices: Vec<u64> = (0..num_batches).collect();
+                if config.shuffle { batch_indices.shuffle(&mut rng); }
+                for (count, batch_index) in batch_indices.into_iter().enumerate() {
+                    self.seed_batch(epoch_seed, count as u64);
+                    let (mini_batch_x, mini_batch_y) = generator(batch_index);
+                    let mini_batch_x = match &input_corruption {
+                        Some(corruption) => corruption.apply(&mini_batch_x),
+                        None => mini_batch_x,
+                    };
+                    let sample_weights = batch_sample_weights(None, class_weights.as_ref(), &mini_batch_y);
+                    self.compute_gradients(config.sam_radius, |nn| {
+                        let mut y_pred = mini_batch_x.clone();
+                        let mask = nn.forward_mut(&mut y_pred);
+                        nn.backward_heads(&mini_batch_y, &[], sample_weights.as_ref());
+                        nn.backward(&y_pred, &mini_batch_y, sample_weights.as_ref(), mask.as_ref());
+                    });
+                    self.optimization_step(&config, &mut state, count as u64 + 1 == num_batches);
+
+                    sync(device);
+                    device::track_memory();
+                    progress_bar.inc(1);
+                }
+            } else if let Some(x_train) = data.x_train_sparse() {
+                let batches = SparseBatchIterator::new((x_train, data.y_train()), data.sample_weights(), batch_size, config.shuffle);
+                let num_batches = batches.num_batches();
+                for (count, (mini_batch_x, mini_batch_y, mini_batch_weights)) in batches.enumerate() {
+                    self.seed_batch(epoch_seed, count as u64);
+                    let sample_weights = batch_sample_weights(mini_batch_weights, class_weights.as_ref(), &mini_batch_y);
+                    self.compute_gradients(config.sam_radius, |nn| {
+                        let y_pred = nn.forward_sparse_mut(&mini_batch_x).expect("The network could not be evaluated on the sparse samples.");
+                        nn.backward_heads(&mini_batch_y, &[], sample_weights.as_ref());
+                        nn.backward(&y_pred, &mini_batch_y, sample_weights.as_ref(), None);
+                    });
+                    self.optimization_step(&config, &mut state, count as u64 + 1 == num_batches);
+
+                    sync(device);
+                    device::track_memory();
+                    progress_bar.inc(1);
+                }
+            } else {
+                // Shuffle the samples with the same permutation for the labels, the weights, and the labels of the heads.
+                // The curriculum, if any, selects and orders the samples instead.
+                let permutation = if let Some(curriculum) = config.curriculum.as_mut() {
+                    let losses = self.compute_sample_losses(data.x_train(), data.y_train(), batch_size);
+                    let mut losses_host = vec![0 as PrimitiveType; losses.elements()];
+                    losses.host(&mut losses_host);
+                    let indices = curriculum.select(epoch, &losses_host);
+                    if indices.is_empty() || indices.iter().any(|&index| index >= data.x_train().batch_size()) {
+                        return Err(Error::InvalidFitConfig(format!("the curriculum selected no sample or an invalid sample at epoch {}", epoch)));
+                    }
+                    Some(Array::new(&indices[..], Dim4::new(&[indices.len() as u64, 1, 1, 1])))
+                } else if config.shuffle {
+                    let mut indices: Vec<u64> = (0..data.x_train().batch_size()).collect();
+                    indices.shuffle(&mut rng);
+                    Some(Array::new(&indices[..], Dim4::new(&[indices.len() as u64, 1, 1, 1])))
+                } else {
+                    None
+                };
+                let permute = |tensor: &Tensor| match &permutation {
+                    Some(indices) => lookup(tensor, indices, 3),
+                    None => tensor.clone(),
+                };
+                let x_train_shuffled = permute(data.x_train());
+                let y_train_shuffled = permute(data.y_train());
+                let weights_shuffled = data.sample_weights().map(|weights| permute(weights));
+                let heads_shuffled: Vec<Tensor> = data.y_train_heads().iter().map(|y_train| permute(y_train)).collect();
+
+                let batches = BatchIterator::with_weights((&x_train_shuffled, &y_train_shuffled), weights_shuffled.as_ref(), batch_size);
+                let num_batches = batches.num_batches();
+                for (count, (mini_batch_x, mini_batch_y, mini_batch_weights)) in batches.enumerate() {
+                    self.seed_batch(epoch_seed, count as u64);
+
+                    // Select the labels of the heads for the mini-batch
+                    let lb = count as u64 * batch_size;
+                    let ub = std::cmp::min(lb + batch_size, x_train_shuffled.batch_size()) - 1;
+                    let seqs = [Seq::default(), Seq::default(), Seq::default(), Seq::new(lb as f64, ub as f64, 1.0)];
+                    let mini_batch_heads: Vec<Tensor> = heads_shuffled.iter().map(|y_train| index(y_train, &seqs)).collect();
+
+                    // Compute a pass on the network
+                    let mini_batch_x = match &input_corruption {
+                        Some(corruption) => corruption.apply(&mini_batch_x),
+                        None => mini_batch_x,
+                    };
+                    let sample_weights = batch_sample_weights(mini_batch_weights, class_weights.as_ref(), &mini_batch_y);
+                    if let (Some(accountant), Some((_, _, clip))) = (accountant.as_mut(), config.dp) {
+                        let noise_std = accountant.noise_multiplier() as PrimitiveType * clip;
+                        self.compute_private_gradients(&mini_batch_x, &mini_batch_y, &mini_batch_heads, sample_weights.as_ref(), clip, noise_std, batch_size);
+                        accountant.step();
+                    } else {
+                        self.compute_gradients(config.sam_radius, |nn| {
+                            let mut y_pred = mini_batch_x.clone();
+                            let mask = nn.forward_mut(&mut y_pred);
+                            nn.backward_heads(&mini_batch_y, &mini_batch_heads, sample_weights.as_ref());
+                            nn.backward(&y_pred, &mini_batch_y, sample_weights.as_ref(), mask.as_ref());
+                        });
+                    }
 
-                // Update the parameters of the model
-                self.update_parameters();
+                    // Update the parameters of the model
+                    self.optimization_step(&config, &mut state, count as u64 + 1 == num_batches);
 
-                sync(device);
-                progress_bar.inc(1);
+                    sync(device);
+                    device::track_memory();
+                    progress_bar.inc(1);
+                }
             }
 
             // Compute and print the losses and the metrics
-            if let Some(print_iter) = print_loss {
+            if let Some(print_iter) = config.validation_frequency {
                 if epoch % print_iter == 0 {
+                    let metrics = &config.metrics;
 
                     // Compute the loss and metrics evaluated on the training set
-                    let (train_loss, train_pred) = self.compute_loss(data, batch_size, Mode::Train, Some(&progress_bar));
-                    let train_metrics_values = self.compute_metrics(&train_pred, &data.y_train(), batch_size, &metrics);
+                    let (train_loss, train_metrics_values) = match data.batch_generator() {
+                        Some((num_batches, generator)) => self.compute_generated_loss(num_batches, generator, metrics, Some(&progress_bar)),
+                        None => {
+                            let (train_loss, train_pred, train_mask) = self.compute_loss(data, batch_size, Mode::Train, Some(&progress_bar));
+                            (train_loss, self.compute_metrics(&train_pred, &data.y_train(), train_mask.as_ref(), batch_size, metrics))
+                        }
+                    };
 
                     // Compute the loss and metrics evaluated on the validation set
-                    if data.num_valid_samples() > 0 {
-                        let (valid_loss, valid_pred) = self.compute_loss(data, batch_size, Mode::Valid, Some(&progress_bar));
-                        let valid_metrics_values = self.compute_metrics(&valid_pred, &data.y_valid().unwrap(), batch_size, &metrics);
+                    let (valid_loss, valid_metrics_values) = if data.num_valid_samples() > 0 {
+                        let (valid_loss, valid_pred, valid_mask) = self.compute_loss(data, batch_size, Mode::Valid, Some(&progress_bar));
+                        let valid_metrics_values = self.compute_metrics(&valid_pred, &data.y_valid().unwrap(), valid_mask.as_ref(), batch_size, metrics);
                         progress_bar.finish_with_message(&format!("epoch: {}/{}, train_loss: {}, train_metrics: {:?}, valid_loss: {}, valid_metrics: {:?}", epoch, epochs, train_loss, train_metrics_values, valid_loss, valid_metrics_values));
-
+                        (Some(valid_loss), Some(valid_metrics_values))
                     } else {
                         progress_bar.finish_with_message(&format!("epoch: {}/{}, train_loss: {}, train_metrics: {:?}", epoch, epochs, train_loss, train_metrics_values));
-                    }
+                        (None, None)
+                    };
+
+                    let improved = history.push(EpochLogs {
+                        epoch,
+                        train_loss,
+                        train_metrics: train_metrics_values,
+                        valid_loss,
+                        valid_metrics: valid_metrics_values,
+                    });
+
+                    // Keep the parameters of the best epoch in memory
+                    if improved { self.best_parameters = Some(self.parameters()); }
                 }
             }
+
+            // Invoke the callbacks
+            let mut stop = false;
+            for callback in config.callbacks.iter_mut() {
+                if !callback.on_epoch_end(self, epoch, &history) { stop = true; }
+            }
+            if stop { break; }
+        }
+
+        // Evaluate the loss and metrics on the test set with the best parameters
+        if config.evaluate_test && data.x_test().is_some() && data.y_test().is_some() {
+            let last_parameters = self.best_parameters.as_ref().map(|_| self.parameters());
+            if last_parameters.is_some() { self.restore_best()?; }
+            let (test_loss, test_pred, test_mask) = self.compute_loss(data, batch_size, Mode::Test, None);
+            let test_metrics = self.compute_metrics(&test_pred, data.y_test().unwrap(), test_mask.as_ref(), batch_size, &config.metrics);
+            if let Some(parameters) = last_parameters { self.set_parameters_along(&parameters, &parameters, 0.); }
+            if config.verbose { println!("test_loss: {}, test_metrics: {:?}", test_loss, test_metrics); }
+            history.set_test_results(test_loss, test_metrics);
         }
+
+        if let (Some(accountant), Some((_, delta, _))) = (&accountant, config.dp) {
+            let epsilon = accountant.epsilon(delta);
+            if config.verbose { println!("Privacy budget spent: epsilon = {:.3}, delta = {}.", epsilon, delta); }
+            history.set_epsilon(epsilon);
+        }
+        if config.verbose {
+            println!("Peak device memory: {}.", device::format_bytes(device::peak_memory()));
+        }
+        Ok(history)
     }
 
 
-    /// Initializes the parameters of the optimizer.
-    fn initialize_optimizer(&mut self) {
-        let mut dims = Vec::<(Dim4, Dim4)>::new();
-        for layer in self.layers.iter() {
-            match layer.parameters() {
-                Some(param) => dims.push((param[0].dims(), param[1].dims())),
-                None => dims.push((Dim4::new(&[1, 1, 1, 1]), Dim4::new(&[1, 1, 1, 1])))
+    /// Computes the gradients of a mini-batch with the given forward and backward passes.
+    ///
+    /// If a radius is given, the gradients are computed at the parameters perturbed by Sharpness-Aware Minimization:
+    /// the passes are performed a first time to compute the perturbation, which moves the parameters by `radius` along
+    /// the normalized gradients, and a second time at the perturbed parameters. The parameters are then restored.
+    fn compute_gradients<F: FnMut(&mut Network)>(&mut self, sam_radius: Option<PrimitiveType>, mut passes: F) {
+        passes(self);
+        if let Some(radius) = sam_radius {
+            let mut gradients = Vec::new();
+            for layer in self.layers.iter_mut() {
+                if let Some(layer_gradients) = layer.gradients_mut() {
+                    gradients.extend(layer_gradients.iter().map(|gradient| gradient.copy()));
+                }
             }
+            let norm = inner_product(&gradients, &gradients).sqrt();
+            if norm == 0. { return; }
+
+            let parameters = self.parameters();
+            self.set_parameters_along(&parameters, &gradients, radius / norm);
+            passes(self);
+            self.set_parameters_along(&parameters, &gradients, 0.);
         }
-        self.optimizer.initialize_parameters(dims);
     }
 
 
-    /// Computes the loss and the predicted output.
-    ///
-    /// # Arguments
-    ///
-    /// * `data` - The dataset containing the training, validation, and optionally test data.
-    /// * `batch_size` - The size of the mini-batches used to compute the loss.
-    /// * `mode` - Flag specifying whether the loss is computed on the training, validation, or test set.
-    /// * `bar` - The reference to a progress bar used to show the training progress.
-    ///
-    /// # Return value
+    /// Computes the gradients of a mini-batch for differentially private training.
     ///
-    /// Tuple containing the loss and the predicted output.
-    fn compute_loss<T: DataSet>(&self,
-                    data: &T,
-                    batch_size: u64,
-                    mode: Mode,
-                    progress_bar: Option<&ProgressBar>
-    ) -> (PrimitiveType, Tensor) {
-        let mut loss = 0.;
-        let mut y_pred = Array::new_empty(self.output_shape);
+    /// The gradients of the samples are computed one at a time and clipped to `clip`. Their sum is perturbed by
+    /// Gaussian noise with the standard deviation `noise_std` and divided by the expected batch size, such that the
+    /// result doesn't depend on the actual number of samples in the mini-batch.
+    #[allow(clippy::too_many_arguments)]
+    fn compute_private_gradients(&mut self,
+                                 x: &Tensor,
+                                 y: &Tensor,
+                                 heads: &[Tensor],
+                                 sample_weights: Option<&Tensor>,
+                                 clip: PrimitiveType,
+                                 noise_std: PrimitiveType,
+                                 batch_size: u64,
+    ) {
+        let mut summed_gradients = Vec::new();
+        for idx in 0..x.batch_size() {
+            let seqs = [Seq::default(), Seq::default(), Seq::default(), Seq::new(idx as f64, idx as f64, 1.0)];
+            let mut y_pred = index(x, &seqs);
+            let y_true = index(y, &seqs);
+            let sample_heads: Vec<Tensor> = heads.iter().map(|y_head| index(y_head, &seqs)).collect();
+            let sample_weight = sample_weights.map(|weights| index(weights, &seqs));
+
+            let mask = self.forward_mut(&mut y_pred);
+            self.backward_heads(&y_true, &sample_heads, sample_weight.as_ref());
+            self.backward(&y_pred, &y_true, sample_weight.as_ref(), mask.as_ref());
+            self.clip_gradients(clip);
+            self.accumulate_gradients(&mut summed_gradients);
+        }
 
-        // Create batch iterator
-        let (x, y) = match mode {
-            Mode::Train => (data.x_train(), data.y_train()),
-            Mode::Valid => (data.x_valid().unwrap(), data.y_valid().unwrap()),
-            Mode::Test => (data.x_test().expect("No test samples have been provided."), data.y_test().expect("No test labels have been provided.")),
-        };
-        let batches = BatchIterator::new((x, y), batch_size);
-        let num_batches = batches.num_batches() as PrimitiveType;
+        for (layer, summed) in all_layers_mut(&mut self.layers, &mut self.heads).zip(summed_gradients) {
+            if let Some(gradients) = layer.gradients_mut() {
+                for (gradient, summed) in gradients.into_iter().zip(summed) {
+                    *gradient = summed;
+                }
+            }
+        }
+        self.add_gradient_noise(noise_std);
+        for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+            if let Some(gradients) = layer.gradients_mut() {
+                for gradient in gradients {
+                    *gradient = &*gradient / batch_size as PrimitiveType;
+                }
+            }
+        }
+    }
 
-        for (count, (mini_batch_x, mini_batch_y)) in batches.enumerate() {
-            let y_pred_batch = self.forward(&mini_batch_x);
 
-            let regularization = match &self.regularizer {
-                Some(regularizer) => {
-                    let mut weights: Vec<&Tensor> = Vec::new();
-                    for layer in self.layers.iter() {
-                        if let Some(parameters) = layer.parameters() { weights.push(parameters[0]) }
+    /// Updates the parameters of the model with the gradients computed during the last backward pass.
+    ///
+    /// If several accumulation steps are configured, the gradients are accumulated and the parameters are only
+    /// updated once the number of steps is reached or at the last mini-batch of the epoch.
+    fn optimization_step(&mut self, config: &FitConfig, state: &mut OptimizationState, last_batch: bool) {
+        if config.accumulation_steps > 1 {
+            self.accumulate_gradients(&mut state.accumulated_gradients);
+            state.num_accumulated += 1;
+            if state.num_accumulated < config.accumulation_steps && !last_batch { return; }
+
+            // Replace the gradients by the mean of the accumulated gradients
+            for (layer, accumulated) in all_layers_mut(&mut self.layers, &mut self.heads).zip(state.accumulated_gradients.drain(..)) {
+                if let Some(gradients) = layer.gradients_mut() {
+                    for (gradient, accumulated) in gradients.into_iter().zip(accumulated) {
+                        *gradient = accumulated / state.num_accumulated as PrimitiveType;
                     }
-                    regularizer.eval(weights)
-                },
-                None => 0.0,
-            };
-            loss += self.loss_function.eval(&y_pred_batch, &mini_batch_y) + regularization;
-
-            if count == 0 {
-                y_pred = y_pred_batch;
-            } else {
-                y_pred = join(3, &y_pred, &y_pred_batch);
+                }
             }
+            state.num_accumulated = 0;
+        }
 
-            if let Some(progress_bar) = progress_bar { progress_bar.inc(1) }
+        if let Some((eta, gamma)) = config.gradient_noise {
+            let std = (eta / (1. + self.num_steps as PrimitiveType).powf(gamma)).sqrt();
+            self.add_gradient_noise(std);
+        }
+        if let Some(max_norm) = config.clip_norm {
+            self.clip_gradients(max_norm);
+        }
+        if let Some(schedule) = &config.learning_rate_schedule {
+            self.optimizer.set_learning_rate(schedule.learning_rate(self.num_steps));
         }
-        (loss / num_batches, y_pred)
+        self.update_parameters();
+        self.num_steps += 1;
+    }
+
+
+    /// Adds the gradients computed during the last backward pass to the accumulated gradients.
+    fn accumulate_gradients(&mut self, accumulated_gradients: &mut Vec<Vec<Tensor>>) {
+        if accumulated_gradients.is_empty() {
+            for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+                let gradients = layer.gradients_mut().map_or(Vec::new(), |gradients| gradients.iter().map(|gradient| gradient.copy()).collect());
+                accumulated_gradients.push(gradients);
+            }
+        } else {
+            for (layer, accumulated) in all_layers_mut(&mut self.layers, &mut self.heads).zip(accumulated_gradients.iter_mut()) {
+                if let Some(gradients) = layer.gradients_mut() {
+                    for (accumulated, gradient) in accumulated.iter_mut().zip(gradients) {
+                        *accumulated = add(accumulated, &*gradient, false);
+                    }
+                }
+            }
+        }
+    }
+
+
+    /// Adds Gaussian noise with zero mean and the given standard deviation to the gradients.
+    fn add_gradient_noise(&mut self, std: PrimitiveType) {
+        for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+            if let Some(gradients) = layer.gradients_mut() {
+                for gradient in gradients {
+                    let noise = randn::<PrimitiveType>(gradient.dims()) * std;
+                    *gradient += noise;
+                }
+            }
+        }
+    }
+
+
+    /// Rescales the gradients such that their global L2 norm doesn't exceed `max_norm`.
+    fn clip_gradients(&mut self, max_norm: PrimitiveType) {
+        let mut squared_norm: PrimitiveType = 0.;
+        for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+            if let Some(gradients) = layer.gradients_mut() {
+                for gradient in gradients {
+                    squared_norm += sum_all(&mul(&*gradient, &*gradient, false)).0 as PrimitiveType;
+                }
+            }
+        }
+
+        let norm = squared_norm.sqrt();
+        if norm > max_norm {
+            let scale = max_norm / norm;
+            for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+                if let Some(gradients) = layer.gradients_mut() {
+                    for gradient in gradients {
+                        *gradient = &*gradient * scale;
+                    }
+                }
+            }
+        }
+    }
+
+
+    /// Fits the network with the L-BFGS optimizer.
+    ///
+    /// The loss and the gradients are evaluated on the full training set at each iteration, such that the training
+    /// samples must fit in the memory of the device. The network is evaluated in inference mode during the
+    /// optimization to keep the loss deterministic: the dropout layers are disabled and the batch normalization layers
+    /// use their running statistics. The optimizer of the network is not used. The history contains the losses of
+    /// each iteration.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data set. The training samples must be dense.
+    /// * `optimizer` - The L-BFGS optimizer.
+    /// * `verbose` - Whether the losses are printed at each iteration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the training samples are sparse or generated on the fly.
+    pub fn fit_lbfgs<T: DataSet>(&mut self, data: &T, optimizer: &mut LBFGS, verbose: bool) -> History {
+        assert!(data.batch_generator().is_none() && data.x_train_sparse().is_none(), "L-BFGS requires dense training samples.");
+        let training = self.training;
+        self.eval_mode();
+        self.classes = data.classes();
+
+        let (x, y, weights) = (data.x_train(), data.y_train(), data.sample_weights());
+        let mut history = History::new();
+        let (mut loss, mut gradients) = self.full_batch_gradients(x, y, weights);
+
+        for iteration in 1..=optimizer.max_iterations() {
+            let max_gradient = gradients.iter().fold(0., |acc: PrimitiveType, gradient| acc.max(max_all(&abs(gradient)).0 as PrimitiveType));
+            if max_gradient <= optimizer.tolerance() { break; }
+
+            // Fall back to the steepest descent if the direction is not a descent direction
+            let mut direction = optimizer.direction(&gradients);
+            let mut slope = inner_product(&gradients, &direction);
+            if slope >= 0. {
+                optimizer.reset();
+                direction = gradients.iter().map(|gradient| gradient * (-1.0 as PrimitiveType)).collect();
+                slope = inner_product(&gradients, &direction);
+            }
+
+            // Backtracking line search
+            let initial_parameters = self.parameters();
+            let mut step = if optimizer.is_history_empty() { (1. / max_gradient).min(1.) } else { 1. };
+            let mut accepted = false;
+            for _ in 0..optimizer.max_line_search_steps() {
+                self.set_parameters_along(&initial_parameters, &direction, step);
+                if self.full_batch_loss(x, y, weights) <= loss + LBFGS::ARMIJO_CONSTANT * step * slope {
+                    accepted = true;
+                    break;
+                }
+                step *= 0.5;
+            }
+            if !accepted {
+                self.set_parameters_along(&initial_parameters, &direction, 0.);
+                break;
+            }
+
+            let (new_loss, new_gradients) = self.full_batch_gradients(x, y, weights);
+            let param_diff = direction.iter().map(|d| d * step).collect();
+            let grad_diff = new_gradients.iter().zip(gradients.iter()).map(|(new, old)| sub(new, old, false)).collect();
+            optimizer.update_history(param_diff, grad_diff);
+            let decrease = loss - new_loss;
+            loss = new_loss;
+            gradients = new_gradients;
+
+            let valid_loss = if data.num_valid_samples() > 0 {
+                Some(self.compute_loss(data, data.num_valid_samples(), Mode::Valid, None).0)
+            } else {
+                None
+            };
+            if verbose {
+                match valid_loss {
+                    Some(valid_loss) => println!("iteration: {}, train_loss: {}, valid_loss: {}", iteration, loss, valid_loss),
+                    None => println!("iteration: {}, train_loss: {}", iteration, loss),
+                }
+            }
+            history.push(EpochLogs {
+                epoch: iteration,
+                train_loss: loss,
+                train_metrics: Vec::new(),
+                valid_loss,
+                valid_metrics: None,
+            });
+
+            if decrease <= optimizer.tolerance() * loss.abs().max(1.) { break; }
+        }
+
+        self.set_training(training);
+        history
+    }
+
+
+    /// Computes the product of the Hessian of the loss with a vector.
+    ///
+    /// The product is approximated with central finite differences of the gradients:
+    /// Hv = (g(θ + εv) - g(θ - εv)) / 2ε, where ε is scaled with the norms of the parameters and of the vector. The
+    /// network is evaluated in inference mode and its parameters are restored afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    /// * `vector` - The vector, given as one tensor per trainable parameter in the order of
+    ///   [parameters](struct.Network.html#method.parameters).
+    pub fn hessian_vector_product(&mut self, x: &Tensor, y: &Tensor, vector: &[Tensor]) -> Result<Vec<Tensor>, Error> {
+        let parameters = self.parameters();
+        if parameters.len() != vector.len() || parameters.iter().zip(vector).any(|(p, v)| p.dims() != v.dims()) {
+            return Err(Error::ParameterShapeMismatch);
+        }
+
+        let training = self.training;
+        self.eval_mode();
+
+        let vector_norm = inner_product(vector, vector).sqrt();
+        if vector_norm == 0. {
+            self.set_training(training);
+            return Ok(vector.iter().map(|v| Tensor::zeros(v.dims())).collect());
+        }
+        let parameters_norm = inner_product(&parameters, &parameters).sqrt();
+        let eps = (PrimitiveType::EPSILON.sqrt() * (1. + parameters_norm)) / vector_norm;
+
+        self.set_parameters_along(&parameters, vector, eps);
+        let (_, gradients_plus) = self.full_batch_gradients(x, y, None);
+        self.set_parameters_along(&parameters, vector, -eps);
+        let (_, gradients_minus) = self.full_batch_gradients(x, y, None);
+        self.set_parameters_along(&parameters, vector, 0.);
+        self.set_training(training);
+
+        Ok(gradients_plus.iter().zip(gradients_minus.iter()).map(|(plus, minus)| sub(plus, minus, false) / (2. * eps)).collect())
+    }
+
+
+    /// Estimates the largest eigenvalue of the Hessian of the loss with the power iteration.
+    ///
+    /// The eigenvalue with the largest magnitude measures the sharpness of the loss surface around the current
+    /// parameters. Each iteration computes a [Hessian-vector product](struct.Network.html#method.hessian_vector_product).
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    /// * `iterations` - The number of iterations of the power method.
+    pub fn hessian_max_eigenvalue(&mut self, x: &Tensor, y: &Tensor, iterations: u64) -> Result<PrimitiveType, Error> {
+        let mut vector: Vec<Tensor> = self.parameters().iter().map(|p| randn::<PrimitiveType>(p.dims())).collect();
+        if vector.is_empty() { return Err(Error::NoLayer); }
+
+        let mut eigenvalue = 0.;
+        for _ in 0..iterations {
+            let norm = inner_product(&vector, &vector).sqrt();
+            vector = vector.iter().map(|v| v / norm).collect();
+            let product = self.hessian_vector_product(x, y, &vector)?;
+            eigenvalue = inner_product(&vector, &product);
+            vector = product;
+        }
+        Ok(eigenvalue)
+    }
+
+
+    /// Computes the loss and the gradients of the parameters on the given samples.
+    fn full_batch_gradients(&mut self, x: &Tensor, y: &Tensor, sample_weights: Option<&Tensor>) -> (PrimitiveType, Vec<Tensor>) {
+        let mut y_pred = x.copy();
+        let mask = self.forward_mut(&mut y_pred);
+        let loss = self.eval_loss(&y_pred, y, mask.as_ref(), sample_weights) + self.regularization_loss();
+        self.backward(&y_pred, y, sample_weights, mask.as_ref());
+        self.accumulate_tied_gradients();
+
+        let mut gradients = Vec::new();
+        for layer in self.layers.iter_mut() {
+            if let Some(layer_gradients) = layer.gradients_mut() {
+                gradients.extend(layer_gradients.iter().map(|gradient| gradient.copy()));
+            }
+        }
+        (loss, gradients)
+    }
+
+
+    /// Computes the gradient of the outputs weighted by `output_weights` with respect to the inputs, in inference mode.
+    ///
+    /// The gradients of the parameters computed along the way are overwritten.
+    pub(crate) fn input_gradient(&mut self, x: &Tensor, output_weights: &Tensor) -> Tensor {
+        let training = self.training;
+        self.eval_mode();
+        let mut output = x.copy();
+        self.forward_mut(&mut output);
+        let input_gradient = self.layers.iter_mut().rev().fold(output_weights.clone(), |da_prev, layer| layer.compute_dactivation_mut(&da_prev));
+        self.set_training(training);
+        input_gradient
+    }
+
+
+    /// Computes the loss on the given samples.
+    fn full_batch_loss(&self, x: &Tensor, y: &Tensor, sample_weights: Option<&Tensor>) -> PrimitiveType {
+        let (y_pred, mask) = self.forward_masked(x);
+        self.eval_loss(&y_pred, y, mask.as_ref(), sample_weights) + self.regularization_loss()
+    }
+
+
+    /// Returns a copy of the trainable parameters of the network, one tensor per parameter.
+    ///
+    /// The parameters are ordered by layer, and for each layer in the order returned by
+    /// [Layer::parameters](../layers/trait.Layer.html#method.parameters).
+    pub fn parameters(&self) -> Vec<Tensor> {
+        self.layers.iter()
+            .filter_map(|layer| layer.parameters())
+            .flat_map(|parameters| parameters.into_iter().map(|parameter| parameter.copy()))
+            .collect()
+    }
+
+
+    /// Returns a copy of the trainable parameters of the layers of the main stack followed by the layers of the heads.
+    pub(crate) fn all_parameters(&self) -> Vec<Tensor> {
+        all_layers(&self.layers, &self.heads)
+            .filter_map(|layer| layer.parameters())
+            .flat_map(|parameters| parameters.into_iter().map(|parameter| parameter.copy()))
+            .collect()
+    }
+
+    /// Returns a copy of the trainable parameters of a layer.
+    ///
+    /// The parameters are returned in the order of [Layer::parameters](../layers/trait.Layer.html#method.parameters),
+    /// for instance the weights and the biases of a dense layer. The vector is empty for layers without parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the layer in the network.
+    pub fn get_weights(&self, index: usize) -> Result<Vec<Tensor>, Error> {
+        let layer = self.layers.get(index).ok_or(Error::InvalidLayerIndex(index))?;
+        Ok(layer.parameters().map(|parameters| parameters.into_iter().map(|parameter| parameter.copy()).collect()).unwrap_or_default())
+    }
+
+    /// Sets the trainable parameters of a layer.
+    ///
+    /// The state of the optimizer for the parameters of the layer is reset, and the weights shared with other layers are
+    /// copied again.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the layer in the network.
+    /// * `weights` - The parameters of the layer, in the order returned by [get_weights](struct.Network.html#method.get_weights).
+    pub fn set_weights(&mut self, index: usize, weights: &[Tensor]) -> Result<(), Error> {
+        let layer = self.layers.get_mut(index).ok_or(Error::InvalidLayerIndex(index))?;
+        let (parameters, _) = layer.parameters_mut().ok_or(Error::ParameterShapeMismatch)?;
+        if parameters.len() != weights.len() || parameters.iter().zip(weights).any(|(parameter, weight)| parameter.dims() != weight.dims()) {
+            return Err(Error::ParameterShapeMismatch);
+        }
+        for (parameter, weight) in parameters.into_iter().zip(weights) {
+            *parameter = weight.copy();
+        }
+        self.optimizer.reset_layer(index);
+        self.copy_tied_weights();
+        Ok(())
+    }
+
+    /// Averages the trainable parameters of several checkpoints of the same architecture into a new network.
+    ///
+    /// Averaging the weights of networks fine-tuned from the same pretrained network, known as a model soup, often
+    /// gives a better network than the best of the checkpoints at the cost of a single network at inference. The
+    /// layers, the running statistics of the batch normalization layers, and the other settings are taken from the first
+    /// checkpoint, and the state of the optimizer is reset. The layers of the heads are compared and averaged like the
+    /// layers of the main stack, although the heads are currently not saved with the checkpoints.
+    ///
+    /// # Arguments
+    ///
+    /// * `filenames` - The names of the HDF5 files of the checkpoints.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::LayerMismatch` if a layer differs between the checkpoints, the layers of the heads being numbered
+    /// after the layers of the main stack.
+    pub fn average_checkpoints(filenames: &[&str]) -> Result<Network, Error> {
+        let (first, others) = filenames.split_first().ok_or(Error::NoCheckpoint)?;
+        let mut network = Network::load(first)?;
+        let mut sums = network.all_parameters();
+        let num_layers = all_layers(&network.layers, &network.heads).count();
+        for filename in others {
+            let other = Network::load(filename)?;
+            let other_num_layers = all_layers(&other.layers, &other.heads).count();
+            if other_num_layers != num_layers {
+                return Err(Error::LayerMismatch(num_layers.min(other_num_layers)));
+            }
+            for (idx, (layer, other_layer)) in all_layers(&network.layers, &network.heads).zip(all_layers(&other.layers, &other.heads)).enumerate() {
+                if layer.name() != other_layer.name() || (layer.parameters().is_some() && !parameters_match(layer.as_ref(), other_layer.as_ref())) {
+                    return Err(Error::LayerMismatch(idx));
+                }
+            }
+            for (sum, parameter) in sums.iter_mut().zip(other.all_parameters()) {
+                *sum += parameter;
+            }
+        }
+
+        let num_checkpoints = filenames.len() as PrimitiveType;
+        let mut averages = sums.into_iter().map(|sum| sum / num_checkpoints);
+        for (idx, layer) in all_layers_mut(&mut network.layers, &mut network.heads).enumerate() {
+            if let Some((parameters, _)) = layer.parameters_mut() {
+                for parameter in parameters {
+                    *parameter = averages.next().unwrap();
+                }
+                network.optimizer.reset_layer(idx);
+            }
+        }
+        network.copy_tied_weights();
+        Ok(network)
+    }
+
+    /// Exports the trainable parameters of the network to a map of flat vectors.
+    ///
+    /// The parameters are named `{layer name}_{layer index}/{parameter index}`, for instance `Dense_0/0` for the weights
+    /// of a first dense layer. The values are given in column-major order.
+    pub fn export_parameters(&self) -> HashMap<String, Vec<PrimitiveType>> {
+        let mut exported = HashMap::new();
+        for (idx, layer) in self.layers.iter().enumerate() {
+            if let Some(parameters) = layer.parameters() {
+                for (param_idx, parameter) in parameters.into_iter().enumerate() {
+                    let mut values = vec![0 as PrimitiveType; parameter.elements()];
+                    parameter.host(&mut values);
+                    exported.insert(format!("{}_{}/{}", layer.name(), idx, param_idx), values);
+                }
+            }
+        }
+        exported
+    }
+
+
+    /// Restores the parameters of the best epoch of the last training.
+    ///
+    /// The best epoch is selected by the monitor of the training configuration, see
+    /// [FitConfig::monitor](../training/struct.FitConfig.html#method.monitor). The statistics of the normalization
+    /// layers and the state of the optimizer are not restored.
+    pub fn restore_best(&mut self) -> Result<(), Error> {
+        let parameters = self.best_parameters.take().ok_or(Error::NoBestParameters)?;
+        self.set_parameters_along(&parameters, &parameters, 0.);
+        self.best_parameters = Some(parameters);
+        Ok(())
+    }
+
+
+    /// Sets the trainable parameters to `initial + step * direction`.
+    fn set_parameters_along(&mut self, initial: &[Tensor], direction: &[Tensor], step: PrimitiveType) {
+        let mut idx = 0;
+        for layer in self.layers.iter_mut() {
+            if let Some((parameters, _)) = layer.parameters_mut() {
+                for parameter in parameters {
+                    *parameter = add(&initial[idx], &(&direction[idx] * step), false);
+                    idx += 1;
+                }
+            }
+        }
+        self.copy_tied_weights();
+    }
+
+
+    /// Fits the network on a single mini-batch where the loss of each sample is scaled by a weight.
+    ///
+    /// This is typically used for policy-gradient methods, where the weights are the advantages, or to emphasize
+    /// some samples of a curriculum.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    /// * `weights` - The weight of each sample. The tensor must contain batch_size elements.
+    ///
+    /// # Return value
+    ///
+    /// The weighted loss of the mini-batch, evaluated before the parameters are updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the network has no layer, if the shapes of the samples or labels don't match the network,
+    /// or if the number of weights, samples, and labels differ.
+    pub fn fit_batch_weighted(&mut self,
+                              x: &Tensor,
+                              y: &Tensor,
+                              weights: &Tensor
+    ) -> Result<PrimitiveType, Error> {
+        self.check_batch(x, y)?;
+        if weights.elements() as u64 != x.batch_size() {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        let weights = moddims(weights, Dim::new(&[1, 1, 1, x.batch_size()]));
+        Ok(self.train_batch(x, y, Some(&weights)))
+    }
+
+
+    /// Checks that a mini-batch of samples and labels can be fed to the network.
+    fn check_batch(&self, x: &Tensor, y: &Tensor) -> Result<(), Error> {
+        if self.layers.is_empty() {
+            return Err(Error::NoLayer);
+        }
+        if x.batch_size() != y.batch_size() {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        if x.dims().get()[..3] != self.input_shape.get()[..3] {
+            return Err(Error::InputShapeMismatch);
+        }
+        if y.dims().get()[..3] != self.output_shape.get()[..3] {
+            return Err(Error::OutputShapeMismatch);
+        }
+        self.check_mask()
+    }
+
+
+    /// Checks that the timesteps masked by a masking layer, if any, are still along the first axis of the output.
+    fn check_mask(&self) -> Result<(), Error> {
+        match self.layers.iter().rev().find_map(|layer| layer.mask_timesteps()) {
+            Some(timesteps) if timesteps != self.output_shape[0] => Err(Error::MaskShapeMismatch),
+            _ => Ok(()),
+        }
+    }
+
+
+    /// Trains the network on a single mini-batch.
+    ///
+    /// A forward and backward pass are computed and the parameters are updated once by the optimizer. This allows
+    /// the creation of custom training loops.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    ///
+    /// # Return value
+    ///
+    /// The loss of the mini-batch, evaluated before the parameters are updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the network has no layer, if the shapes of the samples or labels don't match the network,
+    /// or if the numbers of samples and labels differ.
+    pub fn train_on_batch(&mut self, x: &Tensor, y: &Tensor) -> Result<PrimitiveType, Error> {
+        self.check_batch(x, y)?;
+        Ok(self.train_batch(x, y, None))
+    }
+
+
+    /// Updates the model incrementally with new samples.
+    ///
+    /// A single optimization step is performed with the given samples, without requiring a full dataset. This allows
+    /// the model to be continuously updated from a live data feed.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The new input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the new samples.
+    ///
+    /// # Return value
+    ///
+    /// The loss on the new samples, evaluated before the parameters are updated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the network has no layer, if the shapes of the samples or labels don't match the network,
+    /// or if the numbers of samples and labels differ.
+    pub fn partial_fit(&mut self, x: &Tensor, y: &Tensor) -> Result<PrimitiveType, Error> {
+        self.check_batch(x, y)?;
+        Ok(self.train_batch(x, y, None))
+    }
+
+
+    /// Evaluates the loss of the network on a single mini-batch without updating the parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the network has no layer, if the shapes of the samples or labels don't match the network,
+    /// or if the numbers of samples and labels differ.
+    pub fn test_on_batch(&self, x: &Tensor, y: &Tensor) -> Result<PrimitiveType, Error> {
+        self.check_batch(x, y)?;
+        let (y_pred, mask) = self.forward_masked(x);
+        Ok(self.eval_loss(&y_pred, y, mask.as_ref(), None) + self.regularization_loss())
+    }
+
+
+    /// Computes the loss of each sample without reducing it over the batch.
+    ///
+    /// The losses are computed in inference mode and don't include the regularization. They can be used to mine the
+    /// hard examples, to build curricula, or to select the samples to label in active learning.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    ///
+    /// # Return value
+    ///
+    /// Tensor of shape [1, 1, 1, batch size] containing the loss of each sample.
+    pub fn per_sample_loss(&self, x: &Tensor, y: &Tensor) -> Tensor {
+        assert_eq!(x.batch_size(), y.batch_size());
+        self.compute_sample_losses(x, y, x.batch_size())
+    }
+
+
+    /// Trains the network on a single mini-batch of sparse samples.
+    ///
+    /// The first layer of the network must accept sparse inputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The sparse input samples.
+    /// * `y` - The labels of the samples.
+    ///
+    /// # Return value
+    ///
+    /// The loss of the mini-batch, evaluated before the parameters are updated.
+    pub fn train_on_sparse_batch(&mut self, x: &SparseTensor, y: &Tensor) -> Result<PrimitiveType, Error> {
+        if x.batch_size() != y.batch_size() {
+            return Err(Error::from(DataSetError::DimensionMismatch));
+        }
+        self.initialize_optimizer();
+
+        let y_pred = self.forward_sparse_mut(x)?;
+        let loss = self.loss_function.eval(&y_pred, y);
+        self.backward(&y_pred, y, None, None);
+        self.update_parameters();
+
+        Ok(loss + self.regularization_loss())
+    }
+
+
+    /// Computes a forward and backward pass on a mini-batch and updates the parameters of the model.
+    ///
+    /// # Return value
+    ///
+    /// The loss of the mini-batch, evaluated before the parameters are updated.
+    fn train_batch(&mut self,
+                   x: &Tensor,
+                   y: &Tensor,
+                   sample_weights: Option<&Tensor>
+    ) -> PrimitiveType {
+        self.initialize_optimizer();
+
+        let mut y_pred = x.copy();
+        let mask = self.forward_mut(&mut y_pred);
+        let loss = self.eval_loss(&y_pred, y, mask.as_ref(), sample_weights);
+        self.backward(&y_pred, y, sample_weights, mask.as_ref());
+        self.update_parameters();
+
+        loss + self.regularization_loss()
+    }
+
+
+    /// Initializes the parameters of the optimizer.
+    ///
+    /// The optimizer is only initialized once, such that its state is preserved across successive calls.
+    fn initialize_optimizer(&mut self) {
+        if self.optimizer_initialized { return; }
+
+        let dims = optimizer_dims(all_layers(&self.layers, &self.heads));
+        self.optimizer.initialize_parameters(dims);
+        self.optimizer_initialized = true;
+    }
+
+
+    /// Computes the regularization term of the loss.
+    fn regularization_loss(&self) -> PrimitiveType {
+        match &self.regularizer {
+            Some(regularizer) => {
+                // The shared weights are only regularized once, through their source layer
+                let mut weights: Vec<&Tensor> = Vec::new();
+                for (idx, layer) in all_layers(&self.layers, &self.heads).enumerate() {
+                    if self.tied_weights.iter().any(|&(_, target, _)| target == idx) { continue; }
+                    if let Some(parameters) = layer.parameters() { weights.push(parameters[0]) }
+                }
+                regularizer.eval(weights)
+            },
+            None => 0.0,
+        }
+    }
+
+
+    /// Computes the loss and the predicted output.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The dataset containing the training, validation, and optionally test data.
+    /// * `batch_size` - The size of the mini-batches used to compute the loss.
+    /// * `mode` - Flag specifying whether the loss is computed on the training, validation, or test set.
+    /// * `bar` - The reference to a progress bar used to show the training progress.
+    ///
+    /// # Return value
+    ///
+    /// Tuple containing the loss, the predicted output, and the mask of the timesteps, if any.
+    fn compute_loss<T: DataSet>(&self,
+                    data: &T,
+                    batch_size: u64,
+                    mode: Mode,
+                    progress_bar: Option<&ProgressBar>
+    ) -> (PrimitiveType, Tensor, Option<Tensor>) {
+        let sparse_x = match mode {
+            Mode::Train => data.x_train_sparse(),
+            Mode::Valid => data.x_valid_sparse(),
+            Mode::Test => None,
+        };
+        if let Some(x) = sparse_x {
+            let y = match mode {
+                Mode::Valid => data.y_valid().unwrap(),
+                _ => data.y_train(),
+            };
+            let weights = match mode {
+                Mode::Train => data.sample_weights(),
+                _ => None,
+            };
+            return self.compute_loss_sparse(x, y, weights, batch_size, progress_bar);
+        }
+
+        let mut loss = 0.;
+        let mut y_pred = Array::new_empty(self.output_shape);
+        let mut mask: Option<Tensor> = None;
+
+        // Create batch iterator
+        let (x, y) = match mode {
+            Mode::Train => (data.x_train(), data.y_train()),
+            Mode::Valid => (data.x_valid().unwrap(), data.y_valid().unwrap()),
+            Mode::Test => (data.x_test().expect("No test samples have been provided."), data.y_test().expect("No test labels have been provided.")),
+        };
+        let weights = match mode {
+            Mode::Train => data.sample_weights(),
+            _ => None,
+        };
+        let batches = BatchIterator::with_weights((x, y), weights, batch_size);
+        let num_batches = batches.num_batches() as PrimitiveType;
+
+        for (count, (mini_batch_x, mini_batch_y, mini_batch_weights)) in batches.enumerate() {
+            let (y_pred_batch, mask_batch) = self.forward_masked(&mini_batch_x);
+            loss += self.eval_loss(&y_pred_batch, &mini_batch_y, mask_batch.as_ref(), mini_batch_weights.as_ref()) + self.regularization_loss();
+
+            if count == 0 {
+                y_pred = y_pred_batch;
+            } else {
+                y_pred = join(3, &y_pred, &y_pred_batch);
+            }
+            mask = match (mask, mask_batch) {
+                (Some(mask), Some(mask_batch)) => Some(join(3, &mask, &mask_batch)),
+                (_, mask_batch) => mask_batch,
+            };
+
+            if let Some(progress_bar) = progress_bar { progress_bar.inc(1) }
+        }
+        (loss / num_batches, y_pred, mask)
+    }
+
+
+    /// Computes the loss of each sample, with dimensions [1, 1, 1, number of samples].
+    ///
+    /// The masked timesteps don't contribute to the losses.
+    fn compute_sample_losses(&self, x: &Tensor, y: &Tensor, batch_size: u64) -> Tensor {
+        let mut losses = Tensor::new_empty_tensor();
+        for (count, (mini_batch_x, mini_batch_y, _)) in BatchIterator::new((x, y), batch_size).enumerate() {
+            let (y_pred, mask) = self.forward_masked(&mini_batch_x);
+            let y_pred = match mask {
+                Some(mask) => add(&mul(&y_pred, &mask, true), &mul(&mini_batch_y, &sub(&(1.0 as PrimitiveType), &mask, true), true), false),
+                None => y_pred,
+            };
+            let batch_losses = self.loss_function.eval_per_sample(&y_pred, &mini_batch_y);
+            losses = if count == 0 { batch_losses } else { join(3, &losses, &batch_losses) };
+        }
+        losses
+    }
+
+
+    /// Computes the loss and the predicted output for sparse samples.
+    fn compute_loss_sparse(&self,
+                           x: &SparseTensor,
+                           y: &Tensor,
+                           weights: Option<&Tensor>,
+                           batch_size: u64,
+                           progress_bar: Option<&ProgressBar>
+    ) -> (PrimitiveType, Tensor, Option<Tensor>) {
+        let mut loss = 0.;
+        let mut y_pred = Array::new_empty(self.output_shape);
+
+        let batches = SparseBatchIterator::new((x, y), weights, batch_size, false);
+        let num_batches = batches.num_batches() as PrimitiveType;
+
+        for (count, (mini_batch_x, mini_batch_y, mini_batch_weights)) in batches.enumerate() {
+            let y_pred_batch = self.forward_sparse(&mini_batch_x).expect("The network could not be evaluated on the sparse samples.");
+            loss += self.eval_loss(&y_pred_batch, &mini_batch_y, None, mini_batch_weights.as_ref()) + self.regularization_loss();
+
+            if count == 0 {
+                y_pred = y_pred_batch;
+            } else {
+                y_pred = join(3, &y_pred, &y_pred_batch);
+            }
+
+            if let Some(progress_bar) = progress_bar { progress_bar.inc(1) }
+        }
+        (loss / num_batches, y_pred, None)
+    }
+
+
+    /// Computes the loss and the metrics on mini-batches generated on the fly.
+    ///
+    /// # Arguments
+    ///
+    /// * `num_batches` - The number of mini-batches.
+    /// * `generator` - The closure generating the mini-batches.
+    /// * `metrics` - The metrics that are evaluated.
+    /// * `bar` - The reference to a progress bar used to show the training progress.
+    ///
+    /// # Return value
+    ///
+    /// Tuple containing the loss and the values of the metrics averaged over the mini-batches.
+    fn compute_generated_loss(&self,
+                              num_batches: u64,
+                              generator: &dyn Fn(u64) -> (Tensor, Tensor),
+                              metrics: &Option<Vec<Metrics>>,
+                              progress_bar: Option<&ProgressBar>
+    ) -> (PrimitiveType, Vec<PrimitiveType>) {
+        let mut loss = 0.;
+        let mut metrics_values = vec![0.; metrics.as_ref().map_or(0, |m| m.len())];
+
+        for batch_index in 0..num_batches {
+            let (mini_batch_x, mini_batch_y) = generator(batch_index);
+            let (y_pred_batch, mask) = self.forward_masked(&mini_batch_x);
+            loss += self.eval_loss(&y_pred_batch, &mini_batch_y, mask.as_ref(), None) + self.regularization_loss();
+            if let Some(m) = metrics {
+                for (value, metric) in metrics_values.iter_mut().zip(m.iter()) {
+                    *value += match &mask {
+                        Some(mask) => metric.eval_masked(&y_pred_batch, &mini_batch_y, mask, self.threshold),
+                        None => metric.eval_with_threshold(&y_pred_batch, &mini_batch_y, self.threshold),
+                    };
+                }
+            }
+
+            if let Some(progress_bar) = progress_bar { progress_bar.inc(1) }
+        }
+
+        for value in metrics_values.iter_mut() {
+            *value /= num_batches as PrimitiveType;
+        }
+        (loss / num_batches as PrimitiveType, metrics_values)
     }
 
 
@@ -287,9 +1880,10 @@ impl Network
     ) {
         // TODO: find a way to automatically compute a batch size that fits in the available GPU/CPU memory
         let batch_size = 128;
-        let (loss, y_pred) = self.compute_loss(data, batch_size, Mode::Test, None);
+        if let Err(err) = self.check_mask() { panic!("{}", err); }
+        let (loss, y_pred, mask) = self.compute_loss(data, batch_size, Mode::Test, None);
         let y_test = data.y_test().expect("The dataset does not contain any test data.");
-        let metrics_values = self.compute_metrics(&y_pred, y_test, batch_size, &metrics);
+        let metrics_values = self.compute_metrics(&y_pred, y_test, mask.as_ref(), batch_size, &metrics);
         println!("Evaluation of the test set: loss: {}, metrics: {:?}", loss, metrics_values);
     }
 
@@ -300,6 +1894,7 @@ impl Network
     ///
     /// * `y_pred` - The labels predicted by the model.
     /// * `y_true` - The true labels.
+    /// * `mask` - The mask of the timesteps, if any. The masked timesteps are ignored by the metrics.
     /// * `batch_size` - y_pred and y_true are split in chunks of batch_size to reduce the memory footprint
     ///
     /// # Return value
@@ -308,6 +1903,7 @@ impl Network
     fn compute_metrics(&self,
                        y_pred: &Tensor,
                        y_true: &Tensor,
+                       mask: Option<&Tensor>,
                        batch_size: u64,
                        metrics: &Option<Vec<Metrics>>,
     ) -> Vec<PrimitiveType> {
@@ -322,10 +1918,15 @@ impl Network
             Some(m) => {
                 let batches = BatchIterator::new((y_pred, y_true), batch_size);
                 let num_batches = batches.num_batches() as PrimitiveType;
+                let mut mask_batches = mask.map(|mask| BatchIterator::new((mask, mask), batch_size));
 
-                for (y_pred_batch, y_true_batch) in batches {
+                for (y_pred_batch, y_true_batch, _) in batches {
+                    let mask_batch = mask_batches.as_mut().and_then(|batches| batches.next()).map(|(mask_batch, _, _)| mask_batch);
                     for (i, metrics) in m.iter().enumerate() {
-                        let metrics_value = metrics.eval(&y_pred_batch, &y_true_batch);
+                        let metrics_value = match &mask_batch {
+                            Some(mask_batch) => metrics.eval_masked(&y_pred_batch, &y_true_batch, mask_batch, self.threshold),
+                            None => metrics.eval_with_threshold(&y_pred_batch, &y_true_batch, self.threshold),
+                        };
                         metrics_values[i] += metrics_value;
                     }
                 }
@@ -342,10 +1943,375 @@ impl Network
 
     /// Updates the parameters of the model.
     fn update_parameters(&mut self) {
+        self.accumulate_tied_gradients();
+        self.store_gradient_norms();
         self.optimizer.update_time_step();
-        for (idx, layer) in self.layers.iter_mut().enumerate() {
+        for (idx, layer) in all_layers_mut(&mut self.layers, &mut self.heads).enumerate() {
             self.optimizer.update_parameters(&mut **layer, idx);
         }
+        self.copy_tied_weights();
+    }
+
+
+    /// Stores the L2 norm of the gradients of each layer with parameters.
+    ///
+    /// The norms are kept on the device and only copied to the host when they are requested.
+    fn store_gradient_norms(&mut self) {
+        self.gradient_norms.clear();
+        for (idx, layer) in all_layers_mut(&mut self.layers, &mut self.heads).enumerate() {
+            let name = format!("{}_{}", layer.name(), idx);
+            if let Some(gradients) = layer.gradients_mut() {
+                let squared_norm = gradients.iter().fold(constant(0 as PrimitiveType, Dim::new(&[1, 1, 1, 1])), |acc, gradient| {
+                    add(&acc, &sum(&flat(&mul(&**gradient, &**gradient, false)), 0), false)
+                });
+                let norm = sqrt(&squared_norm);
+                norm.eval();
+                self.gradient_norms.push((name, norm));
+            }
+        }
+    }
+
+
+    /// Returns the L2 norm of the gradients of each layer used at the last optimization step.
+    ///
+    /// The layers are identified by their name followed by their index in the network, for instance `Dense_2`. Only
+    /// the layers with trainable parameters are included. The norms are computed after the gradients have been
+    /// accumulated, perturbed, and clipped, and include the contributions of the layers sharing their weights. The
+    /// vector is empty if the network hasn't been trained yet.
+    pub fn last_gradient_norms(&self) -> Vec<(String, PrimitiveType)> {
+        self.gradient_norms.iter().map(|(name, norm)| {
+            let mut value = [0 as PrimitiveType];
+            norm.host(&mut value);
+            (name.clone(), value[0])
+        }).collect()
+    }
+
+
+    /// Shares the weights of a layer with another layer.
+    ///
+    /// The weights of the target layer are replaced by the weights of the source layer, optionally transposed. During
+    /// the training, the gradient of the shared weights accumulates the contributions of both layers, and the target
+    /// layer keeps using the updated weights of the source layer. The biases are not shared. This is typically used
+    /// to tie the weights of the encoder and the decoder of an autoencoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The index of the layer owning the weights.
+    /// * `target` - The index of the layer using the weights of the source layer.
+    /// * `transpose` - If true, the target layer uses the transpose of the weights.
+    pub fn tie_weights(&mut self, source: usize, target: usize, transpose: bool) -> Result<(), Error> {
+        if source == target || source >= self.layers.len() || target >= self.layers.len() {
+            return Err(Error::InvalidWeightSharing);
+        }
+
+        let source_dims = match self.layers[source].parameters() {
+            Some(parameters) => parameters[0].dims(),
+            None => return Err(Error::InvalidWeightSharing),
+        };
+        let target_dims = match self.layers[target].parameters() {
+            Some(parameters) => parameters[0].dims(),
+            None => return Err(Error::InvalidWeightSharing),
+        };
+        let expected_dims = if transpose {
+            Dim::new(&[source_dims[1], source_dims[0], source_dims[2], source_dims[3]])
+        } else {
+            source_dims
+        };
+        if target_dims != expected_dims {
+            return Err(Error::InvalidWeightSharing);
+        }
+
+        self.tied_weights.push((source, target, transpose));
+        self.copy_tied_weights();
+        Ok(())
+    }
+
+
+    /// Re-initializes the parameters of a layer with its initializers.
+    ///
+    /// This is typically used to train a new classification head when fine-tuning a network, or to escape a bad
+    /// initialization. The state of the optimizer for the parameters of the layer is reset, and the weights shared
+    /// with other layers are copied again.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - The index of the layer in the network.
+    pub fn reinitialize_layer(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.layers.len() {
+            return Err(Error::InvalidLayerIndex(index));
+        }
+
+        let input_shape = if index == 0 { self.input_shape } else { self.layers[index - 1].output_shape() };
+        self.layers[index].initialize_parameters(input_shape);
+        self.optimizer.reset_layer(index);
+        self.copy_tied_weights();
+        Ok(())
+    }
+
+
+    /// Returns a Graphviz (DOT) description of the architecture of the network.
+    ///
+    /// Each layer is represented by a node labeled with its type, number of trainable parameters, and output shape.
+    /// The heads are drawn as branches from the layer they are attached to and the shared weights as dashed edges. The
+    /// description can be rendered with `dot -Tpng network.dot -o network.png`.
+    pub fn to_dot(&self) -> String {
+        fn node(id: &str, layer: &dyn Layer) -> String {
+            let num_parameters: usize = layer.parameters().map(|parameters| parameters.iter().map(|parameter| parameter.elements()).sum()).unwrap_or(0);
+            let shape = layer.output_shape();
+            format!("    {} [label=\"{{{}|parameters: {}|output: [{}, {}, {}]}}\"];\n", id, layer.name(), num_parameters, shape[0], shape[1], shape[2])
+        }
+
+        let mut dot = String::from("digraph network {\n    node [shape=record];\n");
+        dot.push_str(&format!("    input [label=\"{{Input|[{}, {}, {}]}}\"];\n", self.input_shape[0], self.input_shape[1], self.input_shape[2]));
+        let mut previous = String::from("input");
+        for (idx, layer) in self.layers.iter().enumerate() {
+            let id = format!("layer_{}", idx);
+            dot.push_str(&node(&id, layer.as_ref()));
+            dot.push_str(&format!("    {} -> {};\n", previous, id));
+            previous = id;
+        }
+
+        for (head_idx, head) in self.heads.iter().enumerate() {
+            let mut previous = format!("layer_{}", head.branch);
+            for (idx, layer) in head.layers.iter().enumerate() {
+                let id = format!("head_{}_{}", head_idx, idx);
+                dot.push_str(&node(&id, layer.as_ref()));
+                dot.push_str(&format!("    {} -> {};\n", previous, id));
+                previous = id;
+            }
+        }
+
+        for &(source, target, _) in &self.tied_weights {
+            dot.push_str(&format!("    layer_{} -> layer_{} [style=dashed, label=\"shared weights\"];\n", source, target));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+
+    /// Loads the trainable parameters of the layers from a model saved in HDF5 format.
+    ///
+    /// The layers of the network are matched with the layers of the file by position. A layer is loaded if the layer
+    /// of the file has the same type and parameters of the same shapes. This allows to fine-tune a network whose head
+    /// differs from the one of the checkpoint. Only the trainable parameters are loaded: the running statistics of the
+    /// batch normalization layers and the state of the optimizer are not. The state of the optimizer for the loaded
+    /// layers is reset.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file.
+    /// * `skip_mismatched` - If true, the layers that don't match are skipped and reported. Otherwise, an error is
+    /// returned and no layer is loaded.
+    pub fn load_weights(&mut self, filename: &str, skip_mismatched: bool) -> Result<WeightLoadReport, Error> {
+        let _ = hdf5::silence_errors();
+        let file = hdf5::File::open(filename)?;
+        let layers_group = file.group("layers")?;
+        let mut checkpoint: Vec<Option<Box<dyn Layer>>> = Vec::new();
+        for layer in list_subgroups(&layers_group) {
+            let group = layers_group.group(&layer)?;
+            let layer_type = layer.split('_').nth(1).unwrap_or("");
+            checkpoint.push(layer_from_hdf5_group(layer_type, &group).ok());
+        }
+
+        let mut report = WeightLoadReport { loaded: Vec::new(), skipped: Vec::new() };
+        for (idx, layer) in self.layers.iter().enumerate() {
+            if layer.parameters().is_none() { continue; }
+            match checkpoint.get(idx).and_then(|layer| layer.as_ref()) {
+                Some(source) if parameters_match(layer.as_ref(), source.as_ref()) => report.loaded.push(idx),
+                _ if skip_mismatched => report.skipped.push(idx),
+                _ => return Err(Error::LayerMismatch(idx)),
+            }
+        }
+
+        for &idx in &report.loaded {
+            let source = checkpoint[idx].as_ref().and_then(|layer| layer.parameters()).unwrap();
+            if let Some((parameters, _)) = self.layers[idx].parameters_mut() {
+                for (parameter, value) in parameters.into_iter().zip(source) {
+                    *parameter = value.copy();
+                }
+            }
+            self.optimizer.reset_layer(idx);
+        }
+        self.copy_tied_weights();
+        Ok(report)
+    }
+
+
+    /// Folds the batch normalization layers into the preceding dense or convolutional layers for inference.
+    ///
+    /// At inference, a batch normalization layer scales and shifts each feature with its running statistics. When it
+    /// follows a dense or convolutional layer with a linear activation, along the units or the channels, this
+    /// transformation is folded into the weights and biases of that layer and the batch normalization layer is
+    /// removed. The folded network computes the same predictions with fewer layers and can be saved as usual.
+    ///
+    /// The layers whose weights are shared and the layers from which a head branches are left untouched.
+    ///
+    /// # Return value
+    ///
+    /// The number of batch normalization layers removed from the network.
+    pub fn fold_batchnorm(&mut self) -> usize {
+        let mut num_folded = 0;
+        let mut idx = 1;
+        while idx < self.layers.len() {
+            let foldable = self.layers[idx].name() == BatchNorm::NAME
+                && !self.tied_weights.iter().any(|&(source, target, _)| source == idx - 1 || target == idx - 1)
+                && !self.heads.iter().any(|head| head.branch == idx - 1 || head.branch == idx);
+            if foldable {
+                if let Some((scale, shift)) = self.layers[idx].affine_transformation() {
+                    if self.layers[idx - 1].fold_affine_transformation(&scale, &shift) {
+                        self.remove_layer(idx);
+                        num_folded += 1;
+                        continue;
+                    }
+                }
+            }
+            idx += 1;
+        }
+        num_folded
+    }
+
+
+    /// Optimizes the network for inference.
+    ///
+    /// The following passes are applied:
+    /// * the batch normalization layers are folded into the preceding layers, see
+    /// [fold_batchnorm](struct.Network.html#method.fold_batchnorm),
+    /// * the dropout and Gaussian noise layers, which let all the values through at inference, are removed,
+    /// * the flatten layers whose input is already flat, such as consecutive flatten layers, are removed,
+    /// * the lazy expressions built by ArrayFire are only evaluated at the output of the network, such that the
+    /// elementwise operations of successive layers, such as the activations, are fused into fewer kernels.
+    ///
+    /// The optimized network computes the same predictions and can be saved as usual. The layers from which a head
+    /// branches are left untouched.
+    ///
+    /// The evaluation points remain set to `EvalPoints::Network` afterwards, including during a later training. Call
+    /// [set_eval_points](struct.Network.html#method.set_eval_points) with `EvalPoints::Layer` to evaluate the output
+    /// of each layer again before training the network further.
+    ///
+    /// # Return value
+    ///
+    /// The number of layers removed from the network.
+    pub fn optimize_for_inference(&mut self) -> usize {
+        let mut num_removed = self.fold_batchnorm();
+        let mut idx = 0;
+        while idx < self.layers.len() {
+            let input_shape = if idx == 0 { self.input_shape } else { self.layers[idx - 1].output_shape() };
+            let removable = match self.layers[idx].name() {
+                Dropout::NAME | GaussianNoise::NAME => true,
+                Flatten::NAME => input_shape.get()[1] == 1 && input_shape.get()[2] == 1,
+                _ => false,
+            };
+            if removable && self.layers.len() > 1 && !self.heads.iter().any(|head| head.branch == idx) {
+                self.remove_layer(idx);
+                num_removed += 1;
+            } else {
+                idx += 1;
+            }
+        }
+        self.eval_points = EvalPoints::Network;
+        num_removed
+    }
+
+
+    /// Removes a layer that doesn't change the shape of its input and updates the indices referring to the layers.
+    fn remove_layer(&mut self, index: usize) {
+        self.layers.remove(index);
+        for (source, target, _) in self.tied_weights.iter_mut() {
+            if *source > index { *source -= 1; }
+            if *target > index { *target -= 1; }
+        }
+        for head in self.heads.iter_mut() {
+            if head.branch > index { head.branch -= 1; }
+        }
+        if self.optimizer_initialized { self.optimizer.remove_layer(index); }
+    }
+
+
+    /// Adds the gradient of the weights of each target layer to the gradient of its source layer.
+    ///
+    /// The regularization of the shared weights is already part of the gradient of the source layer, so it is removed
+    /// from the contribution of the target layer.
+    fn accumulate_tied_gradients(&mut self) {
+        for &(source, target, transpose) in self.tied_weights.iter() {
+            let mut dweights = match self.layers[target].gradients_mut() {
+                Some(gradients) => gradients[0].copy(),
+                None => continue,
+            };
+            if let (Some(regularizer), Some(parameters)) = (self.regularizer, self.layers[target].parameters()) {
+                dweights -= regularizer.grad(parameters[0]);
+            }
+            if transpose { dweights = arrayfire::transpose(&dweights, false); }
+            if let Some(mut gradients) = self.layers[source].gradients_mut() {
+                *gradients[0] += dweights;
+            }
+        }
+    }
+
+
+    /// Copies the weights of the source layers into their target layers.
+    fn copy_tied_weights(&mut self) {
+        for &(source, target, transpose) in self.tied_weights.iter() {
+            let weights = match self.layers[source].parameters() {
+                Some(parameters) => if transpose { arrayfire::transpose(parameters[0], false) } else { parameters[0].copy() },
+                None => continue,
+            };
+            if let Some((mut parameters, _)) = self.layers[target].parameters_mut() {
+                *parameters[0] = weights;
+            }
+        }
+    }
+
+
+    /// Records the time spent by each layer during one forward and one backward pass.
+    ///
+    /// The passes are computed as during the training but the parameters are not updated. The output of each layer is
+    /// evaluated and the device is synchronized before the time is measured, such that the durations reflect the cost
+    /// of the layers rather than the scheduling of the lazy expressions built by ArrayFire.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - The input samples, stacked along the fourth dimension.
+    /// * `y` - The labels of the samples.
+    pub fn trace(&mut self, x: &Tensor, y: &Tensor) -> Trace {
+        let device = get_device();
+        sync(device);
+
+        let mut trace = Trace::new();
+        let trace_start = Instant::now();
+        let mut activation = x.copy();
+        for (idx, layer) in self.layers.iter_mut().enumerate() {
+            let start = Instant::now();
+            let output = layer.compute_activation_mut(&activation);
+            output.eval();
+            sync(device);
+            trace.push(TraceEvent {
+                layer: format!("{}_{}", layer.name(), idx),
+                pass: Pass::Forward,
+                input_shape: *activation.dims().get(),
+                output_shape: *output.dims().get(),
+                start: start.duration_since(trace_start),
+                duration: start.elapsed(),
+            });
+            activation = output;
+        }
+
+        let mut gradient = self.loss_function.grad(&activation, y);
+        for (idx, layer) in self.layers.iter_mut().enumerate().rev() {
+            let start = Instant::now();
+            let output = layer.compute_dactivation_mut(&gradient);
+            output.eval();
+            sync(device);
+            trace.push(TraceEvent {
+                layer: format!("{}_{}", layer.name(), idx),
+                pass: Pass::Backward,
+                input_shape: *gradient.dims().get(),
+                output_shape: *output.dims().get(),
+                start: start.duration_since(trace_start),
+                duration: start.elapsed(),
+            });
+            gradient = output;
+        }
+        trace
     }
 
 
@@ -360,56 +2326,360 @@ impl Network
         self.forward(&input)
     }
 
+    /// Computes the output of the network for sparse inputs.
+    ///
+    /// The first layer of the network must accept sparse inputs, which is the case of the [Dense](../layers/struct.Dense.html) layers.
+    ///
+    /// # Return value
+    ///
+    /// Tensor of the predicted output
+    pub fn predict_sparse(&self, input: &SparseTensor) -> Result<Tensor, Error> {
+        self.forward_sparse(input)
+    }
+
+    /// Computes the class probabilities for the input.
+    ///
+    /// For the networks trained with the softmax cross-entropy, the softmax is applied to the output of the network.
+    /// If the network has been [calibrated](struct.Network.html#method.calibrate), the logits are divided by the fitted
+    /// temperature before the softmax. The output of the network is returned unchanged for the other networks.
+    pub fn predict_proba(&self, input: &Tensor) -> Tensor {
+        self.probabilities(self.predict(input))
+    }
+
+
+    /// Computes the class probabilities for the input with Monte Carlo dropout (Gal and Ghahramani, 2016).
+    ///
+    /// The dropout layers remain active and the input is evaluated `num_samples` times, such that the spread of the
+    /// predicted probabilities estimates the uncertainty of the network. The probabilities are computed as with
+    /// [predict_proba](struct.Network.html#method.predict_proba).
+    pub fn predict_proba_mc(&self, input: &Tensor, num_samples: u64) -> Vec<Tensor> {
+        (0..num_samples).map(|_| {
+            let output = self.layers.iter().fold(input.copy(), |a_prev, layer| layer.compute_activation_stochastic(&a_prev));
+            self.probabilities(output)
+        }).collect()
+    }
+
+
+    /// Converts the output of the network into class probabilities.
+    fn probabilities(&self, output: Tensor) -> Tensor {
+        match self.logits(&output) {
+            Some(logits) => calibration::scaled_softmax(&logits, self.temperature.unwrap_or(1.)),
+            None => output,
+        }
+    }
+
+
+    /// Fits the temperature scaling the logits on the validation set to calibrate the class probabilities.
+    ///
+    /// The temperature minimizing the negative log-likelihood of the validation labels is saved with the network and
+    /// applied by [predict_proba](struct.Network.html#method.predict_proba). The output of the network must be a
+    /// softmax, or the network must be trained with the softmax cross-entropy.
+    ///
+    /// # Return value
+    ///
+    /// The fitted temperature.
+    pub fn calibrate<T: DataSet>(&mut self, data: &T) -> Result<PrimitiveType, Error> {
+        if data.num_valid_samples() == 0 {
+            return Err(Error::DataSetError(DataSetError::MissingValidationSet));
+        }
+        let y_valid = data.y_valid().ok_or(DataSetError::MissingValidationSet)?;
+
+        let output = self.compute_loss(data, data.num_valid_samples(), Mode::Valid, None).1;
+        let logits = self.logits(&output).ok_or_else(|| Error::IncompatibleLoss(String::from("the temperature scaling requires a softmax output or the softmax cross-entropy")))?;
+        let temperature = calibration::fit_temperature(&logits, y_valid);
+        self.temperature = Some(temperature);
+        Ok(temperature)
+    }
+
+
+    /// Returns the temperature fitted by the calibration, if any.
+    pub fn temperature(&self) -> Option<PrimitiveType> {
+        self.temperature
+    }
+
+
+    /// Recovers the logits from the output of the network, if the output is a categorical distribution.
+    fn logits(&self, output: &Tensor) -> Option<Tensor> {
+        let output_activation = self.layers.iter().rev().find_map(|layer| layer.activation());
+        if matches!(output_activation, Some(Activation::Softmax)) {
+            // The softmax of the log-probabilities gives back the probabilities
+            Some(log(&clamp(output, &(1e-15 as PrimitiveType), &(1. as PrimitiveType), true)))
+        } else if self.loss_function.id() == SoftmaxCrossEntropy.id() {
+            Some(output.clone())
+        } else {
+            None
+        }
+    }
+
+
     /// Predicts the class for the input.
     ///
-    /// Multiple samples can be evaluated at once by stacking them along the fourth dimension of the tensor.
+    /// Multiple samples can be evaluated at once by stacking them along the fourth dimension of the tensor.
+    ///
+    /// # Return value
+    ///
+    /// Vector of tuples containing the predicted class and the probability for each sample.
+    ///
+    /// # Panic
+    ///
+    /// Panics if the model doesn't contain a classes dictionary.
+    pub fn predict_class(&self, input: &Tensor) -> Vec<(String, PrimitiveType)> {
+        let batch_size = input.dims().get()[3] as usize;
+        let mut predictions: Vec<(String, PrimitiveType)> = Vec::with_capacity(batch_size);
+
+        // Compute the output of the network and retrieve value and index of maximum value
+        let y_pred = self.predict_proba(input);
+        let (probabilities_tensor, class_idxs_tensor) = imax(&y_pred, 0);
+
+        // Retrieve values from GPU
+        let mut probabilities: Vec<PrimitiveType> = vec![0 as PrimitiveType; batch_size as usize];
+        let mut class_idxs: Vec<u32> = vec![0; batch_size as usize];
+        probabilities_tensor.host(&mut probabilities);
+        class_idxs_tensor.host(&mut class_idxs);
+
+        // Build output structure
+        match &self.classes {
+            Some(classes) => {
+                for i in 0..batch_size {
+                    // Handle multiclass and binary classification
+                    if classes.len() > 2 {
+                        predictions.push((classes[class_idxs[i] as usize].clone(), probabilities[i]));
+                    } else {
+                        let idx = if probabilities[i] >= self.threshold { 1 } else { 0 };
+                        if idx == 0 { probabilities[i] = 1. - probabilities[i]; }
+                        predictions.push((classes[idx].clone(), probabilities[i]));
+                    }
+                }
+            },
+            None => panic!("The network does not contain any classes dictionary."),
+        }
+        predictions
+    }
+
+
+    /// Returns the decision threshold of binary classifiers.
+    ///
+    /// The samples whose output is greater than or equal to the threshold are assigned to the positive class. The
+    /// threshold is 0.5 by default.
+    pub fn threshold(&self) -> PrimitiveType {
+        self.threshold
+    }
+
+
+    /// Sets the decision threshold of binary classifiers.
+    pub fn set_threshold(&mut self, threshold: PrimitiveType) {
+        self.threshold = threshold;
+    }
+
+
+    /// Returns the number of optimization steps performed by [fit_with_config](struct.Network.html#method.fit_with_config).
+    ///
+    /// The steps are counted across successive trainings and saved with the network. They position the learning rate
+    /// schedules and the annealing of the gradient noise, such that a resumed training continues where it stopped.
+    pub fn num_steps(&self) -> u64 {
+        self.num_steps
+    }
+
+
+    /// Sets the number of optimization steps performed, for instance to 0 to restart the learning rate schedules.
+    pub fn set_num_steps(&mut self, num_steps: u64) {
+        self.num_steps = num_steps;
+    }
+
+
+    /// Tunes the decision threshold of a binary classifier on the validation set.
+    ///
+    /// The thresholds are swept over the predictions of the validation samples and the one maximizing the metric is
+    /// kept. The threshold is saved with the network and used by [predict_class](struct.Network.html#method.predict_class)
+    /// and the accuracy. The output layer should have a sigmoid activation.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data set containing the validation samples.
+    /// * `metric` - The metric computed from the numbers of true positives, false positives, true negatives, and false
+    ///   negatives, in this order. The [F1 score](../metrics/fn.f1_score.html) is used if `None`.
     ///
     /// # Return value
     ///
-    /// Vector of tuples containing the predicted class and the probability for each sample.
+    /// The selected threshold.
+    pub fn tune_threshold<T: DataSet>(&mut self,
+                                      data: &T,
+                                      metric: Option<&dyn Fn(u64, u64, u64, u64) -> PrimitiveType>
+    ) -> Result<PrimitiveType, Error> {
+        if self.output_shape.get()[..3] != [1, 1, 1] {
+            return Err(Error::InvalidOutputShape);
+        }
+        if data.num_valid_samples() == 0 {
+            return Err(Error::DataSetError(DataSetError::MissingValidationSet));
+        }
+
+        let y_pred = self.compute_loss(data, data.num_valid_samples(), Mode::Valid, None).1;
+        let mut predictions = vec![0 as PrimitiveType; y_pred.elements()];
+        y_pred.host(&mut predictions);
+        let y_true = data.y_valid().ok_or(DataSetError::MissingValidationSet)?;
+        let mut labels = vec![0 as PrimitiveType; y_true.elements()];
+        y_true.host(&mut labels);
+
+        // Sort the samples by decreasing prediction such that each threshold adds one sample to the positive class
+        let mut samples: Vec<(PrimitiveType, bool)> = predictions.into_iter().zip(labels.into_iter().map(|label| label >= 0.5)).collect();
+        samples.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        let num_positives = samples.iter().filter(|(_, positive)| *positive).count() as u64;
+        let num_negatives = samples.len() as u64 - num_positives;
+
+        let mut best = (PrimitiveType::NEG_INFINITY, self.threshold);
+        let (mut true_positives, mut false_positives) = (0, 0);
+        for (i, &(prediction, positive)) in samples.iter().enumerate() {
+            if positive { true_positives += 1; } else { false_positives += 1; }
+
+            // Samples with the same prediction are on the same side of the threshold
+            if i + 1 < samples.len() && samples[i + 1].0 == prediction { continue; }
+
+            let false_negatives = num_positives - true_positives;
+            let true_negatives = num_negatives - false_positives;
+            let score = match metric {
+                Some(metric) => metric(true_positives, false_positives, true_negatives, false_negatives),
+                None => f1_score(true_positives, false_positives, true_negatives, false_negatives),
+            };
+            if score > best.0 { best = (score, prediction); }
+        }
+
+        self.threshold = best.1;
+        Ok(self.threshold)
+    }
+
+
+    /// Writes the predictions for the test set to a csv file.
     ///
-    /// # Panic
+    /// The first column contains the index of the test sample and is followed by the outputs of the network. If the
+    /// labels have been scaled, the inverse scaling is applied to the predictions. For classification problems, the
+    /// predicted class is written before the probabilities of the classes, such that submission files can be produced
+    /// directly.
     ///
-    /// Panics if the model doesn't contain a classes dictionary.
-    pub fn predict_class(&self, input: &Tensor) -> Vec<(String, PrimitiveType)> {
-        let batch_size = input.dims().get()[3] as usize;
-        let mut predictions: Vec<(String, PrimitiveType)> = Vec::with_capacity(batch_size);
-
-        // Compute the output of the network and retrieve value and index of maximum value
-        let y_pred = self.predict(input);
-        let (probabilities_tensor, class_idxs_tensor) = imax(&y_pred, 0);
+    /// # Arguments
+    ///
+    /// * `data` - The data set containing the test samples.
+    /// * `path` - The path to the csv file.
+    /// * `include_labels` - Whether the true labels, if any, are appended to each row.
+    pub fn predict_to_csv<T: DataSet>(&self, data: &T, path: &Path, include_labels: bool) -> Result<(), Error> {
+        let x_test = data.x_test().ok_or(DataSetError::MissingTestSet)?;
+        let y_test = if include_labels { data.y_test() } else { None };
+        let num_samples = x_test.batch_size();
+        let num_outputs = self.output_shape[0] * self.output_shape[1] * self.output_shape[2];
+
+        // Write the header
+        let output_names: Vec<String> = match &self.classes {
+            Some(classes) if classes.len() as u64 == num_outputs => classes.clone(),
+            Some(classes) if num_outputs == 1 && classes.len() == 2 => vec![classes[1].clone()],
+            _ if num_outputs == 1 => vec![String::from("prediction")],
+            _ => (0..num_outputs).map(|i| format!("prediction_{}", i)).collect(),
+        };
+        let mut header = vec![String::from("id")];
+        if self.classes.is_some() { header.push(String::from("class")); }
+        header.extend(output_names);
+        let label_size = y_test.map_or(0, |y| (y.dims()[0] * y.dims()[1] * y.dims()[2]) as usize);
+        if label_size == 1 {
+            header.push(String::from("label"));
+        } else {
+            header.extend((0..label_size).map(|i| format!("label_{}", i)));
+        }
+        let mut writer = csv::Writer::from_path(path).map_err(DataSetError::Csv)?;
+        writer.write_record(&header).map_err(DataSetError::Csv)?;
 
-        // Retrieve values from GPU
-        let mut probabilities: Vec<PrimitiveType> = vec![0 as PrimitiveType; batch_size as usize];
-        let mut class_idxs: Vec<u32> = vec![0; batch_size as usize];
-        probabilities_tensor.host(&mut probabilities);
-        class_idxs_tensor.host(&mut class_idxs);
+        // Evaluate the test set by mini-batches and write the predictions
+        let batch_size = 128;
+        let mut lb = 0;
+        while lb < num_samples {
+            let ub = std::cmp::min(lb + batch_size, num_samples) - 1;
+            let seqs = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(lb as f64, ub as f64, 1.0)];
+            let mut y_pred = self.forward(&index(x_test, seqs));
+            let mut y_true = y_test.map(|y| index(y, seqs));
+            if let Some((scaling, c1, c2)) = data.y_train_stats() {
+                y_pred = scaling.invert(&y_pred, c1, c2);
+                y_true = y_true.map(|y| scaling.invert(&y, c1, c2));
+            }
 
-        // Build output structure
-        match &self.classes {
-            Some(classes) => {
-                for i in 0..batch_size {
-                    // Handle multiclass and binary classification
-                    if classes.len() > 2 {
-                        predictions.push((classes[class_idxs[i] as usize].clone(), probabilities[i]));
+            let mut predictions = vec![0 as PrimitiveType; y_pred.elements()];
+            y_pred.host(&mut predictions);
+            let labels = y_true.map(|y| {
+                let mut labels = vec![0 as PrimitiveType; y.elements()];
+                y.host(&mut labels);
+                labels
+            });
+
+            for (i, outputs) in predictions.chunks(num_outputs as usize).enumerate() {
+                let mut record = vec![(lb + i as u64).to_string()];
+                if let Some(classes) = &self.classes {
+                    let class_idx = if num_outputs == 1 {
+                        if outputs[0] >= self.threshold { 1 } else { 0 }
                     } else {
-                        let idx = probabilities[i].round() as usize;
-                        if probabilities[i] < 0.5 { probabilities[i] = 1. - probabilities[i]; }
-                        predictions.push((classes[idx].clone(), probabilities[i]));
-                    }
+                        outputs.iter().enumerate().fold(0, |max_idx, (idx, value)| if *value > outputs[max_idx] { idx } else { max_idx })
+                    };
+                    record.push(classes.get(class_idx).cloned().unwrap_or_else(|| class_idx.to_string()));
                 }
-            },
-            None => panic!("The network does not contain any classes dictionary."),
+                record.extend(outputs.iter().map(|value| value.to_string()));
+                if let Some(labels) = &labels {
+                    record.extend(labels[i * label_size..(i + 1) * label_size].iter().map(|value| value.to_string()));
+                }
+                writer.write_record(&record).map_err(DataSetError::Csv)?;
+            }
+
+            lb += batch_size;
         }
-        predictions
+        writer.flush().map_err(DataSetError::Io)?;
+        Ok(())
     }
 
 
     /// Saves the model in HDF5 format.
     pub fn save(&self, filename: &str) -> Result<(), Error> {
+        self.save_with_precision(filename, StoragePrecision::Single)
+    }
+
+    /// Saves the model in HDF5 format with the parameters of the layers stored in the given precision.
+    ///
+    /// Storing the parameters in half precision halves the size of the file at the cost of a loss of precision. The
+    /// state of the optimizer is always stored in single precision. The parameters are converted back to single
+    /// precision when the model is loaded.
+    pub fn save_with_precision(&self, filename: &str, precision: StoragePrecision) -> Result<(), Error> {
+        self.save_with_options(filename, precision, None)
+    }
+
+    /// Saves the model in HDF5 format with the parameters of the layers compressed with gzip.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file.
+    /// * `level` - The gzip compression level, between 1 (fastest) and 9 (smallest file).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidCompressionLevel` if the compression level is not between 1 and 9.
+    pub fn save_compressed(&self, filename: &str, level: u8) -> Result<(), Error> {
+        self.save_with_options(filename, StoragePrecision::Single, Some(level))
+    }
+
+    /// Saves the model in HDF5 format with the given precision and gzip compression level of the parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidCompressionLevel` if the compression level is not between 1 and 9.
+    pub fn save_with_options(&self, filename: &str, precision: StoragePrecision, compression: Option<u8>) -> Result<(), Error> {
+        if let Some(level) = compression {
+            if !(1..=9).contains(&level) {
+                return Err(Error::InvalidCompressionLevel(level));
+            }
+        }
 
         let file = hdf5::File::create(filename)?;
 
+        let precision_ds = file.new_dataset::<StoragePrecision>().create("precision", 1)?;
+        write_scalar(&precision_ds, &precision);
+
+        if let Some(level) = compression {
+            let compression_ds = file.new_dataset::<u8>().create("compression", 1)?;
+            write_scalar(&compression_ds, &level);
+        }
+
         let neuro_version: &'static str = env!("CARGO_PKG_VERSION");
         let version = file.new_dataset::<hdf5::types::VarLenUnicode>().create("neuro_version", 1)?;
         version.write(&[hdf5::types::VarLenUnicode::from_str(neuro_version).unwrap()])?;
@@ -434,11 +2704,28 @@ impl Network
             classes_ds.write(&str[..])?;
         }
 
+        let threshold = file.new_dataset::<PrimitiveType>().create("threshold", 1)?;
+        threshold.write(&[self.threshold])?;
+
+        let num_steps = file.new_dataset::<u64>().create("num_steps", 1)?;
+        num_steps.write(&[self.num_steps])?;
+
+        if let Some(temperature) = self.temperature {
+            let temperature_ds = file.new_dataset::<PrimitiveType>().create("temperature", 1)?;
+            temperature_ds.write(&[temperature])?;
+        }
+
         let layers_group = create_group(&file, "layers");
         for (i, layer) in self.layers.iter().enumerate() {
             layer.save(&layers_group, i)?;
         }
 
+        if !self.tied_weights.is_empty() {
+            let tied_weights = file.new_dataset::<[u64; 3]>().create("tied_weights", self.tied_weights.len())?;
+            let ties: Vec<[u64; 3]> = self.tied_weights.iter().map(|&(source, target, transpose)| [source as u64, target as u64, transpose as u64]).collect();
+            tied_weights.write(&ties[..])?;
+        }
+
         println!("Model saved in: {}", filename);
         Ok(())
     }
@@ -461,16 +2748,7 @@ impl Network
                 for layer in &layers_name {
                     let group = layers_group.group(layer).unwrap();
                     let layer_type: Vec<&str> = layer.split('_').collect();
-
-                    match layer_type[1] {
-                        BatchNorm::NAME => layers.push(BatchNorm::from_hdf5_group(&group)),
-                        Conv2D::NAME => layers.push(Conv2D::from_hdf5_group(&group)),
-                        Dense::NAME =>  layers.push(Dense::from_hdf5_group(&group)),
-                        Dropout::NAME => layers.push(Dropout::from_hdf5_group(&group)),
-                        Flatten::NAME => layers.push(Flatten::from_hdf5_group(&group)),
-                        MaxPool2D::NAME => layers.push(MaxPool2D::from_hdf5_group(&group)),
-                        _ => panic!("Unknown layer."),
-                    }
+                    layers.push(layer_from_hdf5_group(layer_type[1], &group).expect("Unknown layer."));
                 }
 
                 // Optimizer
@@ -489,6 +2767,26 @@ impl Network
 
                 let regularizer = Regularizer::from_hdf5_group(&file);
 
+                let tied_weights = file.dataset("tied_weights")
+                    .and_then(|ds| ds.read_raw::<[u64; 3]>())
+                    .map(|ties| ties.iter().map(|tie| (tie[0] as usize, tie[1] as usize, tie[2] != 0)).collect())
+                    .unwrap_or_default();
+
+                let threshold = file.dataset("threshold")
+                    .and_then(|ds| ds.read_raw::<PrimitiveType>())
+                    .map(|threshold| threshold[0])
+                    .unwrap_or(0.5);
+
+                let num_steps = file.dataset("num_steps")
+                    .and_then(|ds| ds.read_raw::<u64>())
+                    .map(|num_steps| num_steps[0])
+                    .unwrap_or(0);
+
+                let temperature = file.dataset("temperature")
+                    .and_then(|ds| ds.read_raw::<PrimitiveType>())
+                    .map(|temperature| temperature[0])
+                    .ok();
+
                 let classes = if let Ok(classes_group) = file.dataset("classes") {
                     let classes_vec = classes_group
                         .read_raw::<hdf5::types::VarLenUnicode>()
@@ -506,12 +2804,236 @@ impl Network
                     regularizer,
                     input_shape: Dim::new(&input_shape[0]),
                     output_shape: Dim::new(&output_shape[0]),
-                    classes
+                    classes,
+                    optimizer_initialized: true,
+                    eval_points: EvalPoints::Layer,
+                    training: true,
+                    tied_weights,
+                    gradient_norms: Vec::new(),
+                    threshold,
+                    temperature,
+                    heads: Vec::new(),
+                    best_parameters: None,
+                    num_steps,
                 })
             },
             Err(err) => Err(Error::from(err)),
         }
     }
+
+    /// Converts the network for the pure Rust CPU backend.
+    ///
+    /// The converted network can be saved with [CpuNetwork::save](../cpu/struct.CpuNetwork.html#method.save) and
+    /// evaluated without ArrayFire, for instance in WebAssembly.
+    pub fn to_cpu(&self) -> Result<CpuNetwork, Error> {
+        let layers = self.layers.iter()
+            .map(|layer| layer.to_cpu().ok_or_else(|| Error::UnsupportedLayer(layer.name().to_string())))
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(CpuNetwork::new(*self.input_shape.get(), *self.output_shape.get(), layers))
+    }
+}
+
+
+/// Builder accumulating the layers and the configuration of a network.
+///
+/// The network is only constructed once the stack of layers has been validated: the output shape of each layer must
+/// be valid and the loss function must be compatible with the activation of the output layer.
+///
+/// ```ignore
+/// let nn = NetworkBuilder::new(Dim::new(&[28, 28, 1, 1]), SoftmaxCrossEntropy::new(), Adam::new(0.001))
+///     .add(Flatten::new())
+///     .add(Dense::new(128, Activation::ReLU))
+///     .add(Dense::new(10, Activation::Linear))
+///     .build()?;
+/// ```
+pub struct NetworkBuilder {
+    input_shape: Dim,
+    loss_function: Box<dyn Loss>,
+    optimizer: Box<dyn Optimizer>,
+    regularizer: Option<Regularizer>,
+    eval_points: EvalPoints,
+    layers: Vec<Box<dyn Layer>>,
+}
+
+impl NetworkBuilder {
+    /// Creates a network builder.
+    ///
+    /// The input shape must be in the form [height, width, channel, 1]. Mini-batches are created along the fourth dimension.
+    pub fn new(input_shape: Dim, loss_function: Box<dyn Loss>, optimizer: Box<dyn Optimizer>) -> NetworkBuilder {
+        NetworkBuilder {
+            input_shape,
+            loss_function,
+            optimizer,
+            regularizer: None,
+            eval_points: EvalPoints::Layer,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Sets the regularizer applied to the weights of the layers.
+    pub fn regularizer(mut self, regularizer: Regularizer) -> NetworkBuilder {
+        self.regularizer = Some(regularizer);
+        self
+    }
+
+    /// Sets the points at which the lazy expressions built by ArrayFire are evaluated.
+    pub fn eval_points(mut self, eval_points: EvalPoints) -> NetworkBuilder {
+        self.eval_points = eval_points;
+        self
+    }
+
+    /// Appends a layer to the network.
+    pub fn add(mut self, layer: Box<dyn Layer>) -> NetworkBuilder {
+        self.layers.push(layer);
+        self
+    }
+
+    /// Adds a stack of layers at the end of the network, in order.
+    pub fn add_all(mut self, layers: Vec<Box<dyn Layer>>) -> NetworkBuilder {
+        self.layers.extend(layers);
+        self
+    }
+
+    /// Validates the stack of layers and constructs the network.
+    ///
+    /// # Errors
+    ///
+    /// * `Error::InvalidInputShape` if the input shape contains a zero dimension.
+    /// * `Error::NoLayer` if no layer has been added.
+    /// * `Error::InvalidLayerShape` if a layer is incompatible with the shape of its input, for instance a pooling window larger than the input or a number of input channels that is not divisible by the number of groups of a convolution.
+    /// * `Error::IncompatibleLoss` if the loss function is incompatible with the activation of the output layer, for instance a softmax activation followed by a softmax cross-entropy loss.
+    pub fn build(self) -> Result<Network, Error> {
+        if self.input_shape.get()[..3].contains(&0) {
+            return Err(Error::InvalidInputShape);
+        }
+        if self.layers.is_empty() {
+            return Err(Error::NoLayer);
+        }
+
+        let mut network = Network::new(self.input_shape, self.loss_function, self.optimizer, self.regularizer)?;
+        network.set_eval_points(self.eval_points);
+        for (index, layer) in self.layers.into_iter().enumerate() {
+            let input_shape = network.layers.last().map_or(network.input_shape, |layer| layer.output_shape());
+            if !layer.accepts_input_shape(input_shape) {
+                return Err(Error::InvalidLayerShape(index, layer.name().to_string()));
+            }
+            network.add(layer);
+            if network.output_shape.get()[..3].contains(&0) {
+                return Err(Error::InvalidLayerShape(index, network.layers[index].name().to_string()));
+            }
+        }
+        network.check_loss(network.loss_function.id())?;
+
+        Ok(network)
+    }
+}
+
+
+/// Creates a layer of the given type from its HDF5 group.
+pub(crate) fn layer_from_hdf5_group(layer_type: &str, group: &hdf5::Group) -> Result<Box<dyn Layer>, Error> {
+    match layer_type {
+        BatchNorm::NAME => Ok(BatchNorm::from_hdf5_group(group)),
+        ComplexDense::NAME => Ok(ComplexDense::from_hdf5_group(group)),
+        Conv2D::NAME => Ok(Conv2D::from_hdf5_group(group)),
+        Dense::NAME => Ok(Dense::from_hdf5_group(group)),
+        Dropout::NAME => Ok(Dropout::from_hdf5_group(group)),
+        Flatten::NAME => Ok(Flatten::from_hdf5_group(group)),
+        GaussianNoise::NAME => Ok(GaussianNoise::from_hdf5_group(group)),
+        GlobalAvgPool2D::NAME => Ok(GlobalAvgPool2D::from_hdf5_group(group)),
+        Masking::NAME => Ok(Masking::from_hdf5_group(group)),
+        MaxPool2D::NAME => Ok(MaxPool2D::from_hdf5_group(group)),
+        Projection::NAME => Ok(Projection::from_hdf5_group(group)),
+        Residual::NAME => Ok(Residual::from_hdf5_group(group)),
+        _ => Err(Error::UnknownLayer),
+    }
+}
+
+/// Checks whether two layers have the same type and trainable parameters of the same shapes.
+fn parameters_match(layer: &dyn Layer, other: &dyn Layer) -> bool {
+    if layer.name() != other.name() { return false; }
+    match (layer.parameters(), other.parameters()) {
+        (Some(parameters), Some(other_parameters)) => {
+            parameters.len() == other_parameters.len()
+                && parameters.iter().zip(other_parameters.iter()).all(|(p, q)| p.dims() == q.dims())
+        },
+        _ => false,
+    }
+}
+
+/// Stores the activation of a layer as the input of the heads branching from it and resets the gradients of the heads.
+fn store_head_inputs(heads: &mut [Head], branch: usize, activation: &Tensor) {
+    for head in heads.iter_mut().filter(|head| head.branch == branch) {
+        head.input = Some(activation.clone());
+        for layer in head.layers.iter_mut() {
+            if let Some(gradients) = layer.gradients_mut() {
+                for gradient in gradients { *gradient = Tensor::zeros(gradient.dims()); }
+            }
+        }
+    }
+}
+
+/// Returns the layers of the main stack followed by the layers of the heads.
+fn all_layers<'a>(layers: &'a [Box<dyn Layer>], heads: &'a [Head]) -> impl Iterator<Item = &'a Box<dyn Layer>> {
+    layers.iter().chain(heads.iter().flat_map(|head| head.layers.iter()))
+}
+
+/// Returns mutable references to the layers of the main stack followed by the layers of the heads.
+fn all_layers_mut<'a>(layers: &'a mut [Box<dyn Layer>], heads: &'a mut [Head]) -> impl Iterator<Item = &'a mut Box<dyn Layer>> {
+    layers.iter_mut().chain(heads.iter_mut().flat_map(|head| head.layers.iter_mut()))
+}
+
+/// Returns the dimensions of the parameters of each layer used to initialize the optimizer.
+fn optimizer_dims<'a>(layers: impl Iterator<Item = &'a Box<dyn Layer>>) -> Vec<(Dim4, Dim4)> {
+    layers.map(|layer| match layer.parameters() {
+        Some(param) => (param[0].dims(), param[1].dims()),
+        None => (Dim4::new(&[1, 1, 1, 1]), Dim4::new(&[1, 1, 1, 1])),
+    }).collect()
+}
+
+/// Returns the fraction of valid timesteps in the mask.
+fn valid_fraction(mask: &Tensor) -> PrimitiveType {
+    let num_valid = sum_all(mask).0 as PrimitiveType;
+    if num_valid > 0. { num_valid / mask.elements() as PrimitiveType } else { 1. }
+}
+
+/// Computes the weight of each sample from the weights of the classes and the one-hot encoded labels.
+///
+/// The returned tensor has dimensions [1, 1, 1, batch_size].
+fn class_sample_weights(class_weights: &Tensor, y_true: &Tensor) -> Tensor {
+    sum(&mul(y_true, class_weights, true), 0)
+}
+
+/// Combines the weights of the samples of a mini-batch with the weights of their classes, if any.
+fn batch_sample_weights(sample_weights: Option<Tensor>, class_weights: Option<&Tensor>, y_true: &Tensor) -> Option<Tensor> {
+    match (sample_weights, class_weights) {
+        (Some(sample_weights), Some(class_weights)) => Some(mul(&sample_weights, &class_sample_weights(class_weights, y_true), true)),
+        (Some(sample_weights), None) => Some(sample_weights),
+        (None, Some(class_weights)) => Some(class_sample_weights(class_weights, y_true)),
+        (None, None) => None,
+    }
+}
+
+
+/// Points at which the lazy expressions built by ArrayFire are evaluated.
+///
+/// ArrayFire records the element-wise operations and compiles them into a single kernel when a tensor is evaluated.
+/// Fewer evaluation points therefore mean fewer kernel launches, at the cost of larger kernels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EvalPoints {
+    /// The output of each layer is evaluated.
+    Layer,
+    /// Only the output of the network is evaluated.
+    Network,
+}
+
+
+/// Layers loaded by [load_weights](struct.Network.html#method.load_weights).
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightLoadReport {
+    /// The indices of the layers whose parameters were loaded.
+    pub loaded: Vec<usize>,
+    /// The indices of the layers with trainable parameters that didn't match the layers of the file.
+    pub skipped: Vec<usize>,
 }
 
 
@@ -532,4 +3054,757 @@ impl fmt::Display for Network
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::activations::Activation;
+    use crate::assert_approx_eq;
+    use crate::data::TabularDataSet;
+    use crate::errors::Error;
+    use crate::initializers::Initializer;
+    use crate::layers::{BatchNorm, Conv2D, Dense, Dropout, Flatten, MaxPool2D, Padding};
+    use crate::losses::{CrossEntropy, MeanSquaredError, SoftmaxCrossEntropy};
+    use crate::models::{class_sample_weights, EvalPoints, Network, NetworkBuilder};
+    use crate::optimizers::{LBFGS, SGD};
+    use crate::regularizers::Regularizer;
+    use crate::schedulers::{CosineDecay, LearningRateSchedule};
+    use crate::tensor::*;
+    use crate::training::{Callback, Curriculum, FitConfig, History};
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_network_is_send_sync() {
+        assert_send_sync::<Network>();
+    }
+
+    #[test]
+    fn test_train_on_batch() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.05), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+        let x = Tensor::new(&[1., 2., -1., 0.5], Dim4::new(&[2, 1, 1, 2]));
+        let y = Tensor::new(&[1., -1.], Dim4::new(&[1, 1, 1, 2]));
+
+        // The evaluation doesn't update the parameters
+        let initial_loss = nn.test_on_batch(&x, &y).unwrap();
+        assert_eq!(nn.test_on_batch(&x, &y).unwrap(), initial_loss);
+
+        // The loss returned by a training step is evaluated before the update
+        assert!((nn.train_on_batch(&x, &y).unwrap() - initial_loss).abs() < 1e-6);
+        for _ in 0..20 {
+            nn.train_on_batch(&x, &y).unwrap();
+        }
+        assert!(nn.test_on_batch(&x, &y).unwrap() < initial_loss);
+
+        // The samples and labels must match the network
+        let y_single = Tensor::new(&[1.], Dim4::new(&[1, 1, 1, 1]));
+        assert!(nn.train_on_batch(&x, &y_single).is_err());
+        let x_wrong = Tensor::new(&[1., 2., 3.], Dim4::new(&[3, 1, 1, 1]));
+        assert!(nn.test_on_batch(&x_wrong, &y_single).is_err());
+    }
+
+    #[test]
+    fn test_tie_weights() {
+        // Builds a 2-3-2 network whose second layer uses the transposed weights of the first layer
+        let build = |regularizer: Option<Regularizer>| {
+            let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), regularizer).unwrap();
+            nn.add(Dense::new(3, Activation::Tanh));
+            nn.add(Dense::new(2, Activation::Linear));
+            if let Some((mut parameters, _)) = nn.layers[0].parameters_mut() {
+                *parameters[0] = Tensor::new(&[0.1, -0.2, 0.3, 0.4, 0.5, -0.6], Dim4::new(&[3, 2, 1, 1]));
+            }
+            nn.tie_weights(0, 1, true).unwrap();
+            nn
+        };
+        let x = Tensor::new(&[1., 2., -1., 0.5], Dim4::new(&[2, 1, 1, 2]));
+        let y = Tensor::new(&[0.5, -0.5, 1., 0.], Dim4::new(&[2, 1, 1, 2]));
+        let mut weights: [PrimitiveType; 6] = [0.; 6];
+        build(None).layers[0].parameters().unwrap()[0].host(&mut weights);
+
+        // Gradient of the shared weights deduced from one step of gradient descent
+        let step_gradient = |nn: &mut Network| {
+            nn.train_on_batch(&x, &y).unwrap();
+            let mut updated: [PrimitiveType; 6] = [0.; 6];
+            nn.layers[0].parameters().unwrap()[0].host(&mut updated);
+            let mut tied: [PrimitiveType; 6] = [0.; 6];
+            transpose(nn.layers[1].parameters().unwrap()[0], false).host(&mut tied);
+            assert_approx_eq!(tied, updated);
+            let mut gradient: [PrimitiveType; 6] = [0.; 6];
+            for i in 0..6 { gradient[i] = (weights[i] - updated[i]) / 0.1; }
+            gradient
+        };
+        let gradient = step_gradient(&mut build(None));
+
+        // Reference computed by finite differences of the loss wrt the shared weights
+        let eps = 1e-2;
+        for i in 0..6 {
+            let loss = |shift: PrimitiveType| {
+                let mut nn = build(None);
+                let mut values = weights;
+                values[i] += shift;
+                if let Some((mut parameters, _)) = nn.layers[0].parameters_mut() {
+                    *parameters[0] = Tensor::new(&values, Dim4::new(&[3, 2, 1, 1]));
+                }
+                nn.copy_tied_weights();
+                nn.test_on_batch(&x, &y).unwrap()
+            };
+            let numerical = (loss(eps) - loss(-eps)) / (2. * eps);
+            assert!((numerical - gradient[i]).abs() < 1e-2, "weight {}: {} != {}", i, numerical, gradient[i]);
+        }
+
+        // The regularization of the shared weights is counted once
+        let regularizer = Regularizer::L2(0.3);
+        let regularized_gradient = step_gradient(&mut build(Some(regularizer)));
+        let mut regularization: [PrimitiveType; 6] = [0.; 6];
+        regularizer.grad(&Tensor::new(&weights, Dim4::new(&[3, 2, 1, 1]))).host(&mut regularization);
+        for i in 0..6 {
+            assert!((regularized_gradient[i] - gradient[i] - regularization[i]).abs() < 1e-4);
+        }
+
+        let mut nn = build(None);
+        assert!(nn.tie_weights(0, 1, false).is_err());
+        assert!(nn.tie_weights(0, 0, true).is_err());
+    }
+
+    #[test]
+    fn test_eval_points() {
+        let mut nn = Network::new(Dim4::new(&[3, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(4, Activation::Tanh));
+        nn.add(Dense::new(2, Activation::Sigmoid));
+        let x = Tensor::new(&[0.5, -1., 2., 1., 0.3, -0.2], Dim4::new(&[3, 1, 1, 2]));
+        let y = Tensor::new(&[1., 0., 0., 1.], Dim4::new(&[2, 1, 1, 2]));
+
+        // Evaluating the lazy expressions once per network gives the same results as once per layer
+        let mut expected = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut expected);
+        let expected_loss = nn.test_on_batch(&x, &y).unwrap();
+        nn.set_eval_points(EvalPoints::Network);
+        let mut output = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut output);
+        assert_approx_eq!(output, expected);
+        assert!((nn.train_on_batch(&x, &y).unwrap() - expected_loss).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_clone() {
+        let x = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 7.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x.copy(), y, None, None, None, None).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(1, Activation::Linear));
+        let snapshot = nn.clone();
+        let mut expected = [0 as PrimitiveType; 4];
+        snapshot.predict(&x).host(&mut expected);
+
+        // Training the network leaves the snapshot untouched
+        nn.fit_with_config(&data, FitConfig::new().epochs(2).verbose(false)).unwrap();
+        let mut output = [0 as PrimitiveType; 4];
+        snapshot.predict(&x).host(&mut output);
+        assert_eq!(output, expected);
+        nn.predict(&x).host(&mut output);
+        assert_ne!(output, expected);
+        assert_eq!(snapshot.num_steps(), 0);
+    }
+
+    #[test]
+    fn test_class_sample_weights() {
+        let class_weights = Tensor::new(&[0.5, 2., 4.], Dim4::new(&[3, 1, 1, 1]));
+        let y_true = Tensor::new(&[0., 1., 0., 1., 0., 0., 0., 0., 1.], Dim4::new(&[3, 1, 1, 3]));
+        let weights = class_sample_weights(&class_weights, &y_true);
+        assert_eq!(weights.dims().get(), &[1, 1, 1, 3]);
+
+        let mut output: [PrimitiveType; 3] = [0.; 3];
+        weights.host(&mut output);
+        assert_approx_eq!(output, [2., 0.5, 4.]);
+    }
+
+    #[test]
+    fn test_predict_to_csv() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+        let x = Tensor::new(&[1., 2., -1., 0.5, 0., 3.], Dim4::new(&[2, 1, 1, 3]));
+        let y = Tensor::new(&[1., -1., 2.], Dim4::new(&[1, 1, 1, 3]));
+        let data = TabularDataSet::from_tensor(x.copy(), y.copy(), None, None, Some(x.copy()), Some(y)).unwrap();
+
+        let path = std::env::temp_dir().join("neuro_test_predict_to_csv.csv");
+        nn.predict_to_csv(&data, &path, true).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // One row per test sample with its index, the prediction and the label
+        let mut expected = [0 as PrimitiveType; 3];
+        nn.predict(&x).host(&mut expected);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "id,prediction,label");
+        for (i, (line, label)) in lines[1..].iter().zip([1., -1., 2.].iter()).enumerate() {
+            let fields: Vec<&str> = line.split(',').collect();
+            assert_eq!(fields[0], i.to_string());
+            assert!((fields[1].parse::<PrimitiveType>().unwrap() - expected[i]).abs() < 1e-6);
+            assert_eq!(fields[2].parse::<PrimitiveType>().unwrap(), *label);
+        }
+
+        let data = TabularDataSet::from_tensor(x.copy(), x.copy(), None, None, None, None).unwrap();
+        assert!(nn.predict_to_csv(&data, &path, false).is_err());
+    }
+
+    struct StopAfter(u64);
+
+    impl Callback for StopAfter {
+        fn on_epoch_end(&mut self, _network: &mut Network, epoch: u64, _history: &History) -> bool {
+            epoch < self.0
+        }
+    }
+
+    #[test]
+    fn test_fit_with_config() {
+        let x = Tensor::new(&[1., 2., -1., 0.5, 0., 3., 2., -2.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., -1., 2., 0.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x.copy(), y, None, None, None, None).unwrap();
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+
+        // The losses are recorded at each evaluated epoch until a callback stops the training
+        let history = nn.fit_with_config(&data, FitConfig::new().epochs(3).batch_size(2).verbose(false)).unwrap();
+        assert_eq!(history.epochs().iter().map(|logs| logs.epoch).collect::<Vec<u64>>(), vec![1, 2, 3]);
+        assert_eq!(history.train_loss().len(), 3);
+        assert!(history.valid_loss().is_empty());
+        let history = nn.fit_with_config(&data, FitConfig::new().epochs(5).verbose(false).callback(Box::new(StopAfter(2)))).unwrap();
+        assert_eq!(history.epochs().len(), 2);
+
+        // An invalid configuration is rejected before the network is trained
+        let mut expected = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut expected);
+        let configs = vec![
+            FitConfig::new().batch_size(0),
+            FitConfig::new().validation_frequency(Some(0)),
+            FitConfig::new().clip_gradient_norm(0.),
+            FitConfig::new().accumulation_steps(0),
+            FitConfig::new().class_weights(&[1., 2.]),
+        ];
+        for config in configs {
+            assert!(matches!(nn.fit_with_config(&data, config.verbose(false)), Err(Error::InvalidFitConfig(_))));
+        }
+        let mut output = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut output);
+        assert_eq!(output, expected);
+    }
+
+    /// Curriculum selecting a fixed list of samples.
+    struct FixedCurriculum(Vec<u64>);
+
+    impl Curriculum for FixedCurriculum {
+        fn select(&mut self, _epoch: u64, _losses: &[PrimitiveType]) -> Vec<u64> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_fit_with_curriculum() {
+        let x = Tensor::new(&[1 as PrimitiveType, 2., -1., 0.5, 0., 3., 2., -2.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1 as PrimitiveType, -1., 2., 0.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap();
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+
+        let config = FitConfig::new().epochs(2).verbose(false);
+        assert!(nn.fit_with_config(&data, config.curriculum(Box::new(FixedCurriculum(vec![3, 0])))).is_ok());
+        for indices in vec![vec![], vec![0, 4]] {
+            let config = FitConfig::new().epochs(2).verbose(false).curriculum(Box::new(FixedCurriculum(indices)));
+            assert!(matches!(nn.fit_with_config(&data, config), Err(Error::InvalidFitConfig(_))));
+        }
+    }
+
+    #[test]
+    fn test_reinitialize_layer() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(2, Activation::Linear));
+
+        let weights = nn.layers[0].parameters().unwrap()[0].copy();
+        nn.reinitialize_layer(0).unwrap();
+        let new_weights = nn.layers[0].parameters().unwrap()[0];
+        assert_eq!(new_weights.dims(), weights.dims());
+        assert!(sum_all(&neq(&weights, new_weights, false)).0 > 0.);
+        assert!(matches!(nn.reinitialize_layer(2), Err(Error::InvalidLayerIndex(2))));
+    }
+
+    #[test]
+    fn test_load_weights() {
+        let mut source = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        source.add(Dense::new(3, Activation::ReLU));
+        source.add(Dense::new(2, Activation::Linear));
+        let path = std::env::temp_dir().join("neuro_test_load_weights.h5");
+        source.save(path.to_str().unwrap()).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(4, Activation::Linear));
+        assert!(matches!(nn.load_weights(path.to_str().unwrap(), false), Err(Error::LayerMismatch(1))));
+
+        let report = nn.load_weights(path.to_str().unwrap(), true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(report.loaded, vec![0]);
+        assert_eq!(report.skipped, vec![1]);
+        let weights = &source.layers[0].parameters().unwrap()[0];
+        assert_eq!(sum_all(&neq(*weights, nn.layers[0].parameters().unwrap()[0], false)).0, 0.);
+    }
+
+    #[test]
+    fn test_get_set_weights() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dropout::new(0.5));
+        nn.add(Dense::new(2, Activation::Linear));
+
+        let weights = nn.get_weights(2).unwrap();
+        assert_eq!(weights.len(), 2);
+        assert!(nn.get_weights(1).unwrap().is_empty());
+        assert!(matches!(nn.get_weights(3), Err(Error::InvalidLayerIndex(3))));
+
+        let new_weights = vec![constant(1 as PrimitiveType, weights[0].dims()), constant(2 as PrimitiveType, weights[1].dims())];
+        nn.set_weights(2, &new_weights).unwrap();
+        assert!(matches!(nn.set_weights(0, &new_weights), Err(Error::ParameterShapeMismatch)));
+
+        let exported = nn.export_parameters();
+        assert_eq!(exported.len(), 4);
+        assert_eq!(exported["Dense_2/0"], vec![1.; 6]);
+        assert_eq!(exported["Dense_2/1"], vec![2.; 2]);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(1, Activation::Linear));
+        nn.add_head(0, vec![Dense::new(2, Activation::Linear)], MeanSquaredError::new(), 1.).unwrap();
+
+        let dot = nn.to_dot();
+        assert!(dot.starts_with("digraph network {"));
+        assert!(dot.contains("layer_0 [label=\"{Dense|parameters: 9|output: [3, 1, 1]}\"];"));
+        assert!(dot.contains("input -> layer_0;"));
+        assert!(dot.contains("layer_0 -> layer_1;"));
+        assert!(dot.contains("layer_0 -> head_0_0;"));
+    }
+
+    #[test]
+    fn test_last_gradient_norms() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(2, Activation::Linear));
+        assert!(nn.last_gradient_norms().is_empty());
+
+        let x = Tensor::new(&[1., 2., -1., 0.5], Dim4::new(&[2, 1, 1, 2]));
+        let y = Tensor::new(&[1., 0., 0., 1.], Dim4::new(&[2, 1, 1, 2]));
+        nn.train_on_batch(&x, &y).unwrap();
+        let norms = nn.last_gradient_norms();
+        assert_eq!(norms.len(), 2);
+        assert_eq!(norms[0].0, "Dense_0");
+        assert_eq!(norms[1].0, "Dense_1");
+        assert!(norms.iter().all(|(_, norm)| *norm >= 0.));
+    }
+
+    #[test]
+    fn test_fit_lbfgs() {
+        let x = Tensor::new(&[0., 1., 2., 3.], Dim4::new(&[1, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[1, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+        let history = nn.fit_lbfgs(&data, &mut LBFGS::new(), false);
+        assert!(history.last().unwrap().train_loss < 1e-4);
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(1, Activation::Linear));
+
+        let x = Tensor::new(&[0., 1., 2., 3.], Dim4::new(&[2, 1, 1, 2]));
+        let y = Tensor::new(&[1., 2.], Dim4::new(&[1, 1, 1, 2]));
+        let trace = nn.trace(&x, &y);
+        let layers: Vec<&str> = trace.events().iter().map(|event| event.layer.as_str()).collect();
+        assert_eq!(layers, vec!["Dense_0", "Dense_1", "Dense_1", "Dense_0"]);
+        assert_eq!(trace.events()[0].output_shape, [3, 1, 1, 2]);
+        assert_eq!(trace.events()[3].output_shape, [2, 1, 1, 2]);
+    }
+
+    #[test]
+    fn test_fold_batchnorm() {
+        let x = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 7.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x.copy(), y, None, None, None, None).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(3, Activation::Linear));
+        nn.add(BatchNorm::new());
+        nn.add(Dense::new(1, Activation::Linear));
+        nn.fit_with_config(&data, FitConfig::new().epochs(5).verbose(false)).unwrap();
+
+        let mut expected = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut expected);
+        assert_eq!(nn.fold_batchnorm(), 1);
+        assert_eq!(nn.parameters().len(), 4);
+        let mut output = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut output);
+        assert!(output.iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_num_steps_saved() {
+        let x = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 7.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+        let config = || FitConfig::new().epochs(3).batch_size(2).verbose(false).learning_rate_schedule(CosineDecay::new(0.01, 0.001, 10).warmup(2));
+        nn.fit_with_config(&data, config()).unwrap();
+        assert_eq!(nn.num_steps(), 6);
+
+        // The steps are restored with the network and the next training resumes the schedule
+        let path = std::env::temp_dir().join("neuro_test_num_steps_saved.h5");
+        nn.save(path.to_str().unwrap()).unwrap();
+        let mut nn = Network::load(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(nn.num_steps(), 6);
+        nn.fit_with_config(&data, config()).unwrap();
+        assert_eq!(nn.num_steps(), 12);
+    }
+
+    #[test]
+    fn test_average_checkpoints() {
+        let paths: Vec<_> = (0..2).map(|i| std::env::temp_dir().join(format!("neuro_test_average_checkpoints_{}.h5", i))).collect();
+        let mut parameters = Vec::new();
+        for path in &paths {
+            let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+            nn.add(Dense::new(3, Activation::ReLU));
+            nn.add(Dense::new(1, Activation::Linear));
+            nn.save(path.to_str().unwrap()).unwrap();
+            parameters.push(nn.parameters());
+        }
+        let filenames: Vec<&str> = paths.iter().map(|path| path.to_str().unwrap()).collect();
+        let soup = Network::average_checkpoints(&filenames).unwrap();
+
+        for (averaged, (first, second)) in soup.parameters().iter().zip(parameters[0].iter().zip(parameters[1].iter())) {
+            let expected = (first + second) / 2 as PrimitiveType;
+            assert!(max_all(&abs(&(averaged - expected))).0 < 1e-6);
+        }
+
+        // The checkpoints must have the same architecture
+        let mut other = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        other.add(Dense::new(4, Activation::ReLU));
+        other.add(Dense::new(1, Activation::Linear));
+        other.save(filenames[1]).unwrap();
+        assert!(matches!(Network::average_checkpoints(&filenames), Err(Error::LayerMismatch(0))));
+        assert!(matches!(Network::average_checkpoints(&[]), Err(Error::NoCheckpoint)));
+
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_replay_epoch() {
+        let x = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 7.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dropout::new(0.5));
+        nn.add(Dense::new(1, Activation::Linear));
+        let initial_parameters = nn.parameters();
+
+        let schedule = || CosineDecay::new(0.01, 0.001, 8).warmup(2);
+        let history = nn.fit_with_config(&data, FitConfig::new().epochs(2).batch_size(1).verbose(false).seed(3).learning_rate_schedule(schedule())).unwrap();
+        assert_eq!(history.seeds().len(), 2);
+        assert_eq!(history.seed(3), None);
+        assert_eq!(history.start_step(2), Some(4));
+        assert_ne!(history.batch_seed(1, 1), history.batch_seed(1, 2));
+        assert_eq!(history.batch_seed(1, 0), None);
+        assert!(FitConfig::new().replay_epoch(&history, 3).is_err());
+        let trained_parameters = nn.parameters();
+
+        // Replaying the epochs from the initial parameters reproduces the training, including the learning rate
+        // schedule which resumes from the step count of the replayed epoch
+        nn.set_parameters_along(&initial_parameters, &initial_parameters, 0.);
+        for epoch in 1..=2 {
+            let config = FitConfig::new().batch_size(1).verbose(false).learning_rate_schedule(schedule()).replay_epoch(&history, epoch).unwrap();
+            nn.fit_with_config(&data, config).unwrap();
+        }
+        assert_eq!(nn.num_steps(), 8);
+        for (trained, replayed) in trained_parameters.iter().zip(nn.parameters().iter()) {
+            assert_eq!(sum_all(&neq(trained, replayed, false)).0, 0.);
+        }
+    }
+
+    #[test]
+    fn test_differential_privacy() {
+        let x = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 7.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap();
+
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dense::new(1, Activation::Linear));
+        let initial_parameters = nn.parameters();
+
+        let history = nn.fit_with_config(&data, FitConfig::new().epochs(3).batch_size(2).verbose(false).dp(2., 1e-3, 1.)).unwrap();
+        let epsilon = history.epsilon().unwrap();
+        assert!(epsilon > 0. && epsilon <= 2.);
+        for (initial, trained) in initial_parameters.iter().zip(nn.parameters().iter()) {
+            assert!(sum_all(&neq(initial, trained, false)).0 > 0.);
+        }
+        assert!(nn.fit_with_config(&data, FitConfig::new().verbose(false)).unwrap().epsilon().is_none());
+
+        // The invalid configurations are rejected before the training
+        assert!(nn.fit_with_config(&data, FitConfig::new().verbose(false).dp(2., 1., 1.)).is_err());
+        assert!(nn.fit_with_config(&data, FitConfig::new().verbose(false).dp(2., 1e-3, 1.).sharpness_aware(0.05)).is_err());
+    }
+
+    #[test]
+    fn test_optimize_for_inference() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.01), None).unwrap();
+        nn.add(Flatten::new());
+        nn.add(Dense::new(3, Activation::ReLU));
+        nn.add(Dropout::new(0.5));
+        nn.add(Dense::new(1, Activation::Linear));
+
+        let x = Tensor::new(&[0 as PrimitiveType, 1., 2., 3.], Dim4::new(&[2, 1, 1, 2]));
+        let mut expected = [0 as PrimitiveType; 2];
+        nn.predict(&x).host(&mut expected);
+        assert_eq!(nn.optimize_for_inference(), 2);
+        assert!(nn.eval_points == EvalPoints::Network);
+        let mut output = [0 as PrimitiveType; 2];
+        nn.predict(&x).host(&mut output);
+        assert_approx_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_hessian_vector_product() {
+        // The Hessian of the mean squared error of a linear model is [[7, 3], [3, 2]] for these samples
+        let x = Tensor::new(&[0., 1., 2., 3.], Dim4::new(&[1, 1, 1, 4]));
+        let y = Tensor::new(&[1., 3., 5., 7.], Dim4::new(&[1, 1, 1, 4]));
+        let mut nn = Network::new(Dim4::new(&[1, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+
+        let vector = vec![Tensor::new(&[1.], Dim4::new(&[1, 1, 1, 1])), Tensor::new(&[0.], Dim4::new(&[1, 1, 1, 1]))];
+        let product = nn.hessian_vector_product(&x, &y, &vector).unwrap();
+        let mut values = [0 as PrimitiveType; 2];
+        product[0].host(&mut values[0..1]);
+        product[1].host(&mut values[1..2]);
+        assert!((values[0] - 7.).abs() < 0.05 && (values[1] - 3.).abs() < 0.05);
+
+        let eigenvalue = nn.hessian_max_eigenvalue(&x, &y, 20).unwrap();
+        assert!((eigenvalue - 8.405).abs() < 0.05);
+        assert!(nn.hessian_vector_product(&x, &y, &vector[0..1]).is_err());
+    }
+
+    #[test]
+    fn test_set_loss() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(2, Activation::Linear));
+        assert!(matches!(nn.set_loss(CrossEntropy::new()), Err(Error::IncompatibleLoss(_))));
+        assert!(nn.set_loss(MeanSquaredError::new()).is_ok());
+
+        // A softmax output followed by the softmax cross-entropy is rejected before training
+        let x = Tensor::new(&[0 as PrimitiveType, 1., 1., 0.], Dim4::new(&[2, 1, 1, 2]));
+        let data = TabularDataSet::from_tensor(x.copy(), x.copy(), None, None, None, None).unwrap();
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(2, Activation::Softmax));
+        let mut expected = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut expected);
+        assert!(matches!(nn.fit_with_config(&data, FitConfig::new().verbose(false)), Err(Error::IncompatibleLoss(_))));
+        assert!(matches!(nn.fit(&data, 2, 1, None, None), Err(Error::IncompatibleLoss(_))));
+        let mut output = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut output);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_per_sample_loss() {
+        let nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        let x = Tensor::new(&[1., 2., 3., 4., 5., 6.], Dim4::new(&[2, 1, 1, 3]));
+        let y = Tensor::new(&[1., 2., 2., 2., 5., 8.], Dim4::new(&[2, 1, 1, 3]));
+        let losses = nn.per_sample_loss(&x, &y);
+        assert_eq!(losses.dims().get(), &[1, 1, 1, 3]);
+        let mut output = [0 as PrimitiveType; 3];
+        losses.host(&mut output);
+        assert_approx_eq!(output, [0., 5., 4.]);
+    }
+
+    #[test]
+    fn test_class_names() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::Linear));
+        assert_eq!(nn.class_names(), None);
+
+        let names = vec![String::from("cat"), String::from("dog"), String::from("bird")];
+        nn.set_class_names(names.clone());
+        assert_eq!(nn.class_names(), Some(&names[..]));
+    }
+
+    #[test]
+    fn test_add_head() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(4, Activation::ReLU));
+        nn.add(Dense::new(1, Activation::Linear));
+        assert!(matches!(nn.add_head(2, vec![Dense::new(3, Activation::Linear)], MeanSquaredError::new(), 1.), Err(Error::InvalidLayerIndex(2))));
+        assert!(matches!(nn.add_head(0, Vec::new(), MeanSquaredError::new(), 1.), Err(Error::NoLayer)));
+        assert_eq!(nn.add_head(0, vec![Dense::new(3, Activation::Linear)], MeanSquaredError::new(), 0.5).unwrap(), 0);
+
+        let x = Tensor::new(&[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], Dim4::new(&[2, 1, 1, 3]));
+        let outputs = nn.predict_heads(&x);
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].dims().get(), &[3, 1, 1, 3]);
+
+        // The head is trained with its own labels, which must be given for every head
+        let y = Tensor::new(&[1., 0., -1.], Dim4::new(&[1, 1, 1, 3]));
+        let y_head = Tensor::new(&[1., 0., 0., 0., 1., 0., 0., 0., 1.], Dim4::new(&[3, 1, 1, 3]));
+        let mut data = TabularDataSet::from_tensor(x.copy(), y.copy(), None, None, None, None).unwrap();
+        data.add_head_labels(y_head.copy()).unwrap();
+        let loss = nn.evaluate_heads(&x, &y, &[y_head.copy()])[0];
+        nn.fit_with_config(&data, FitConfig::new().epochs(5).verbose(false)).unwrap();
+        assert!(nn.evaluate_heads(&x, &y, &[y_head.copy()])[0] < loss);
+        data.add_head_labels(y_head).unwrap();
+        assert!(matches!(nn.fit_with_config(&data, FitConfig::new().verbose(false)), Err(Error::HeadLabelsMismatch)));
+    }
+
+    #[test]
+    fn test_add_auxiliary_head() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(4, Activation::ReLU));
+        nn.add(Dense::new(4, Activation::ReLU));
+        nn.add(Dense::new(3, Activation::Linear));
+        assert!(matches!(nn.add_auxiliary_head(0, vec![Dense::new(2, Activation::Linear)], SoftmaxCrossEntropy::new(), 0.3), Err(Error::OutputShapeMismatch)));
+        assert_eq!(nn.add_auxiliary_head(0, vec![Dense::new(3, Activation::Linear)], SoftmaxCrossEntropy::new(), 0.3).unwrap(), 0);
+
+        let x = Tensor::new(&[1., 2., 3., 4.], Dim4::new(&[2, 1, 1, 2]));
+        let y = Tensor::new(&[1., 0., 0., 0., 0., 1.], Dim4::new(&[3, 1, 1, 2]));
+        let losses = nn.evaluate_heads(&x, &y, &[]);
+        assert_eq!(losses.len(), 1);
+        assert!(losses[0] > 0.);
+    }
+
+    #[test]
+    fn test_tune_threshold() {
+        let x_valid = Tensor::new(&[0.1, 0.3, 0.35, 0.8], Dim4::new(&[1, 1, 1, 4]));
+        let y_valid = Tensor::new(&[0., 1., 1., 1.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x_valid.copy(), y_valid.copy(), Some(x_valid), Some(y_valid), None, None).unwrap();
+
+        // The identity network outputs its input, such that the predictions are the samples
+        let mut nn = Network::new(Dim4::new(&[1, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::with_param(1, Activation::Linear, Initializer::Ones, Initializer::Zeros));
+        let threshold = nn.tune_threshold(&data, None).unwrap();
+        assert_approx_eq!([threshold], [0.3]);
+        assert_approx_eq!([nn.threshold()], [0.3]);
+    }
+
+    #[test]
+    fn test_network_builder_detects_double_softmax() {
+        let result = NetworkBuilder::new(Dim4::new(&[4, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1))
+            .add(Dense::new(8, Activation::ReLU))
+            .add(Dense::new(3, Activation::Softmax))
+            .build();
+        assert!(matches!(result, Err(Error::IncompatibleLoss(_))));
+
+        let result = NetworkBuilder::new(Dim4::new(&[4, 1, 1, 1]), SoftmaxCrossEntropy::new(), SGD::new(0.1))
+            .add(Dense::new(8, Activation::ReLU))
+            .add(Dense::new(3, Activation::Linear))
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_network_builder_rejects_invalid_shapes() {
+        // The pooling window is larger than the output of the convolution
+        let result = NetworkBuilder::new(Dim4::new(&[4, 4, 1, 1]), MeanSquaredError::new(), SGD::new(0.1))
+            .add(Conv2D::new(2, (3, 3), (1, 1), Padding::Valid))
+            .add(MaxPool2D::new((3, 3)))
+            .add(Flatten::new())
+            .add(Dense::new(1, Activation::Linear))
+            .build();
+        assert!(matches!(result, Err(Error::InvalidLayerShape(1, _))));
+
+        // The number of input channels is not divisible by the number of groups
+        let mut conv = Conv2D::new(4, (3, 3), (1, 1), Padding::Same);
+        conv.set_groups(2);
+        let result = NetworkBuilder::new(Dim4::new(&[4, 4, 3, 1]), MeanSquaredError::new(), SGD::new(0.1))
+            .add(conv)
+            .build();
+        assert!(matches!(result, Err(Error::InvalidLayerShape(0, _))));
+
+        let mut conv = Conv2D::new(4, (3, 3), (1, 1), Padding::Same);
+        conv.set_groups(2);
+        let result = NetworkBuilder::new(Dim4::new(&[4, 4, 2, 1]), MeanSquaredError::new(), SGD::new(0.1))
+            .add(conv)
+            .add(MaxPool2D::new((2, 2)))
+            .add(Flatten::new())
+            .add(Dense::new(1, Activation::Linear))
+            .build();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_gradient_noise() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+        let x = Tensor::new(&[1., 2., -1., 0.5, 0., 3., 2., -2.], Dim4::new(&[2, 1, 1, 4]));
+        let y = nn.predict(&x);
+        let data = TabularDataSet::from_tensor(x.copy(), y, None, None, None, None).unwrap();
+        let mut expected = [0 as PrimitiveType; 4];
+        nn.predict(&x).host(&mut expected);
+
+        // The labels are predicted exactly, so the parameters only change with the noise
+        let mut output = [0 as PrimitiveType; 4];
+        nn.fit_with_config(&data, FitConfig::new().epochs(2).verbose(false)).unwrap();
+        nn.predict(&x).host(&mut output);
+        assert_eq!(output, expected);
+        nn.fit_with_config(&data, FitConfig::new().epochs(2).verbose(false).gradient_noise(1., 0.55)).unwrap();
+        nn.predict(&x).host(&mut output);
+        assert_ne!(output, expected);
+
+        let result = nn.fit_with_config(&data, FitConfig::new().verbose(false).gradient_noise(-1., 0.55));
+        assert!(matches!(result, Err(Error::InvalidFitConfig(_))));
+    }
+
+    #[test]
+    fn test_sharpness_aware() {
+        let x = Tensor::new(&[1., 2., -1., 0.5, 0., 3., 2., -2.], Dim4::new(&[2, 1, 1, 4]));
+        let y = Tensor::new(&[1., -1., 2., 0.], Dim4::new(&[1, 1, 1, 4]));
+        let data = TabularDataSet::from_tensor(x.copy(), y.copy(), None, None, None, None).unwrap();
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+        let radius = 0.05;
+
+        // Reference update: the gradients are evaluated at the parameters moved along the normalized gradients
+        let parameters = nn.parameters();
+        nn.train_on_batch(&x, &y).unwrap();
+        let gradients: Vec<Tensor> = parameters.iter().zip(nn.parameters().iter()).map(|(initial, updated)| (initial - updated) / (0.1 as PrimitiveType)).collect();
+        let norm = gradients.iter().map(|gradient| sum_all(&(gradient * gradient)).0 as PrimitiveType).sum::<PrimitiveType>().sqrt();
+        nn.set_parameters_along(&parameters, &gradients, radius / norm);
+        let perturbed = nn.parameters();
+        nn.train_on_batch(&x, &y).unwrap();
+        let expected: Vec<Tensor> = parameters.iter().zip(perturbed.iter()).zip(nn.parameters().iter())
+            .map(|((initial, perturbed), updated)| initial - perturbed + updated)
+            .collect();
+
+        nn.set_parameters_along(&parameters, &parameters, 0.);
+        let config = FitConfig::new().batch_size(4).shuffle(false).verbose(false).validation_frequency(None).sharpness_aware(radius);
+        nn.fit_with_config(&data, config).unwrap();
+        for (expected, trained) in expected.iter().zip(nn.parameters().iter()) {
+            assert!(max_all(&abs(&(expected - trained))).0 < 1e-5);
+        }
+
+        let result = nn.fit_with_config(&data, FitConfig::new().verbose(false).sharpness_aware(0.));
+        assert!(matches!(result, Err(Error::InvalidFitConfig(_))));
+    }
+}
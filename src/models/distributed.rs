@@ -0,0 +1,295 @@
+//! Experimental distributed training by periodic parameter averaging.
+//!
+//! Several processes, possibly on different machines, train copies of the same network on their own shards of the
+//! data set. Each process trains independently, and every few epochs the trainable parameters are averaged across the
+//! processes, weighted by the number of training samples of their shards. The processes communicate over TCP: the
+//! process of rank 0 listens on the given address, gathers the parameters of the other processes, and sends the
+//! average back to them.
+//!
+//! The state of the optimizers and the statistics of the batch normalization layers are not averaged. All the
+//! processes must be configured with the same architecture and number of epochs, and no callback may stop the training
+//! of a single process early, otherwise the other processes wait for it indefinitely.
+use std::cell::RefCell;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use super::Network;
+use crate::data::DataSet;
+use crate::errors::Error;
+use crate::tensor::*;
+use crate::training::{Callback, FitConfig, History};
+
+/// Group of processes exchanging parameters over TCP.
+pub struct ProcessGroup {
+    rank: usize,
+    world_size: usize,
+    /// Connections to the processes of rank 1 to `world_size - 1` for the process of rank 0, or to the process of rank 0
+    /// for the other processes.
+    streams: Vec<TcpStream>,
+}
+
+impl ProcessGroup {
+    /// Connects the processes of the group.
+    ///
+    /// The process of rank 0 listens on the address until all the other processes are connected. The other processes
+    /// retry to connect to the address until the timeout is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the process of rank 0, for instance `10.0.0.1:7000`.
+    /// * `world_size` - The number of processes.
+    /// * `rank` - The rank of the process, from 0 to `world_size - 1`.
+    /// * `timeout` - The maximum time to wait for the connections.
+    pub fn connect(addr: &str, world_size: usize, rank: usize, timeout: Duration) -> Result<ProcessGroup, Error> {
+        if world_size == 0 || rank >= world_size {
+            return Err(Error::CommunicationError(format!("invalid rank {} for {} processes", rank, world_size)));
+        }
+
+        let mut streams = Vec::new();
+        if rank == 0 {
+            let listener = TcpListener::bind(addr).map_err(communication_error)?;
+            let mut peers: Vec<Option<TcpStream>> = (1..world_size).map(|_| None).collect();
+            for _ in 1..world_size {
+                let (mut stream, _) = listener.accept().map_err(communication_error)?;
+                let mut buffer = [0u8; 8];
+                stream.read_exact(&mut buffer).map_err(communication_error)?;
+                let peer_rank = u64::from_le_bytes(buffer) as usize;
+                match peers.get_mut(peer_rank.wrapping_sub(1)) {
+                    Some(peer) if peer.is_none() => *peer = Some(stream),
+                    _ => return Err(Error::CommunicationError(format!("unexpected process of rank {}", peer_rank))),
+                }
+            }
+            streams = peers.into_iter().flatten().collect();
+        } else {
+            let start = Instant::now();
+            let mut stream = loop {
+                match TcpStream::connect(addr) {
+                    Ok(stream) => break stream,
+                    Err(err) if start.elapsed() > timeout => return Err(communication_error(err)),
+                    Err(_) => std::thread::sleep(Duration::from_millis(100)),
+                }
+            };
+            stream.write_all(&(rank as u64).to_le_bytes()).map_err(communication_error)?;
+            streams.push(stream);
+        }
+        for stream in streams.iter() {
+            stream.set_nodelay(true).map_err(communication_error)?;
+        }
+        Ok(ProcessGroup { rank, world_size, streams })
+    }
+
+    /// Returns the rank of the process.
+    pub fn rank(&self) -> usize {
+        self.rank
+    }
+
+    /// Returns the number of processes in the group.
+    pub fn world_size(&self) -> usize {
+        self.world_size
+    }
+
+    /// Replaces the values of every process by the values of the process of rank 0.
+    pub fn broadcast(&mut self, values: &mut Vec<PrimitiveType>) -> Result<(), Error> {
+        if self.rank == 0 {
+            for stream in self.streams.iter_mut() {
+                write_message(stream, 1., values)?;
+            }
+        } else {
+            let (_, received) = read_message(&mut self.streams[0])?;
+            *values = received;
+        }
+        Ok(())
+    }
+
+    /// Computes the weighted average of the values of all the processes.
+    ///
+    /// # Arguments
+    ///
+    /// * `values` - The values of the process, which must have the same length in every process.
+    /// * `weight` - The weight of the values of the process in the average.
+    ///
+    /// # Return value
+    ///
+    /// The average, identical in every process.
+    pub fn average(&mut self, values: &[PrimitiveType], weight: f64) -> Result<Vec<PrimitiveType>, Error> {
+        if self.rank != 0 {
+            write_message(&mut self.streams[0], weight, values)?;
+            return Ok(read_message(&mut self.streams[0])?.1);
+        }
+
+        let mut sums: Vec<f64> = values.iter().map(|&value| weight * value as f64).collect();
+        let mut total_weight = weight;
+        for stream in self.streams.iter_mut() {
+            let (peer_weight, peer_values) = read_message(stream)?;
+            if peer_values.len() != sums.len() {
+                return Err(Error::CommunicationError("the processes don't have the same number of values".to_string()));
+            }
+            for (sum, value) in sums.iter_mut().zip(peer_values) {
+                *sum += peer_weight * value as f64;
+            }
+            total_weight += peer_weight;
+        }
+        if total_weight <= 0. {
+            return Err(Error::CommunicationError("the sum of the weights must be positive".to_string()));
+        }
+
+        let average: Vec<PrimitiveType> = sums.into_iter().map(|sum| (sum / total_weight) as PrimitiveType).collect();
+        for stream in self.streams.iter_mut() {
+            write_message(stream, total_weight, &average)?;
+        }
+        Ok(average)
+    }
+}
+
+impl Network {
+    /// Trains the network on a shard of the data set in a group of processes, averaging the parameters periodically.
+    ///
+    /// Each process calls the method with its own shard of the data set. The parameters of the process of rank 0 are
+    /// first sent to the other processes such that all the copies start from the same point. The parameters are then
+    /// averaged every `sync_frequency` epochs and after the last epoch, such that every process ends with the same
+    /// parameters. See the [distributed](distributed/index.html) module for the limitations of this mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `addr` - The address of the process of rank 0, on which it listens for the other processes.
+    /// * `world_size` - The number of processes.
+    /// * `rank` - The rank of the process, from 0 to `world_size - 1`.
+    /// * `data` - The shard of the data set of the process.
+    /// * `config` - The configuration of the training, identical in every process except for the seed.
+    /// * `sync_frequency` - The number of epochs between two averagings of the parameters.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::CommunicationError` if the processes can't connect within a minute or if a connection fails
+    /// during the training, in which case the training of the process is stopped. The errors of
+    /// [fit_with_config](struct.Network.html#method.fit_with_config) are returned as well, and an `InvalidFitConfig`
+    /// error is returned if `sync_frequency` is zero.
+    pub fn fit_distributed<T: DataSet>(&mut self,
+                                       addr: &str,
+                                       world_size: usize,
+                                       rank: usize,
+                                       data: &T,
+                                       config: FitConfig,
+                                       sync_frequency: u64,
+    ) -> Result<History, Error> {
+        if sync_frequency == 0 {
+            return Err(Error::InvalidFitConfig(String::from("the synchronization frequency must be greater than zero")));
+        }
+        let mut group = ProcessGroup::connect(addr, world_size, rank, Duration::from_secs(60))?;
+
+        let mut parameters = self.flat_parameters();
+        group.broadcast(&mut parameters)?;
+        self.set_flat_parameters(&parameters)?;
+
+        let error = Rc::new(RefCell::new(None));
+        let averaging = ParameterAveraging {
+            group,
+            frequency: sync_frequency,
+            epochs: config.epochs,
+            weight: data.num_train_samples() as f64,
+            error: Rc::clone(&error),
+        };
+        let result = self.fit_with_config(data, config.callback(Box::new(averaging)));
+        let communication_error = error.borrow_mut().take();
+        match communication_error {
+            Some(err) => Err(err),
+            None => result,
+        }
+    }
+}
+
+/// Callback averaging the parameters of the processes at the end of the epochs.
+struct ParameterAveraging {
+    group: ProcessGroup,
+    frequency: u64,
+    epochs: u64,
+    weight: f64,
+    error: Rc<RefCell<Option<Error>>>,
+}
+
+impl Callback for ParameterAveraging {
+    fn on_epoch_end(&mut self, network: &mut Network, epoch: u64, _history: &History) -> bool {
+        if epoch % self.frequency != 0 && epoch != self.epochs { return true; }
+        let result = self.group.average(&network.flat_parameters(), self.weight)
+            .and_then(|average| network.set_flat_parameters(&average));
+        match result {
+            Ok(()) => true,
+            Err(err) => {
+                *self.error.borrow_mut() = Some(err);
+                false
+            },
+        }
+    }
+}
+
+/// Converts an I/O error of a connection.
+fn communication_error(err: std::io::Error) -> Error {
+    Error::CommunicationError(err.to_string())
+}
+
+/// Sends a weight and values. The values are sent in double precision whatever the primitive type.
+fn write_message(stream: &mut TcpStream, weight: f64, values: &[PrimitiveType]) -> Result<(), Error> {
+    let mut bytes = Vec::with_capacity(16 + 8 * values.len());
+    bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&weight.to_le_bytes());
+    for &value in values {
+        bytes.extend_from_slice(&(value as f64).to_le_bytes());
+    }
+    stream.write_all(&bytes).map_err(communication_error)
+}
+
+/// Receives a weight and values sent by `write_message`.
+fn read_message(stream: &mut TcpStream) -> Result<(f64, Vec<PrimitiveType>), Error> {
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header).map_err(communication_error)?;
+    let length = u64::from_le_bytes(header[..8].try_into().unwrap()) as usize;
+    let weight = f64::from_le_bytes(header[8..].try_into().unwrap());
+    let mut bytes = vec![0u8; 8 * length];
+    stream.read_exact(&mut bytes).map_err(communication_error)?;
+    let values = bytes.chunks_exact(8).map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()) as PrimitiveType).collect();
+    Ok((weight, values))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::activations::Activation;
+    use crate::data::TabularDataSet;
+    use crate::layers::Dense;
+    use crate::losses::MeanSquaredError;
+    use crate::models::distributed::*;
+    use crate::optimizers::SGD;
+
+    #[test]
+    fn test_fit_distributed() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let addr = format!("127.0.0.1:{}", port);
+
+        let workers: Vec<_> = (0..2).map(|rank| {
+            let addr = addr.clone();
+            thread::spawn(move || {
+                let offset = rank as PrimitiveType * 4.;
+                let x_values: Vec<PrimitiveType> = (0..8).map(|value| value as PrimitiveType + offset).collect();
+                let y_values: Vec<PrimitiveType> = (0..4).map(|value| 2. * value as PrimitiveType + offset).collect();
+                let x = Tensor::new(&x_values, Dim4::new(&[2, 1, 1, 4]));
+                let y = Tensor::new(&y_values, Dim4::new(&[1, 1, 1, 4]));
+                let data = TabularDataSet::from_tensor(x, y, None, None, None, None).unwrap();
+
+                let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.001), None).unwrap();
+                nn.add(Dense::new(1, Activation::Linear));
+                let config = FitConfig::new().epochs(3).batch_size(2).verbose(false).validation_frequency(None);
+                nn.fit_distributed(&addr, 2, rank, &data, config, 2).unwrap();
+                nn.flat_parameters()
+            })
+        }).collect();
+
+        let parameters: Vec<Vec<PrimitiveType>> = workers.into_iter().map(|worker| worker.join().unwrap()).collect();
+        assert_eq!(parameters[0], parameters[1]);
+    }
+}
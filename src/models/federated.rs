@@ -0,0 +1,192 @@
+//! Building blocks of federated training.
+//!
+//! The orchestration of the workers, such as the transport of the parameters and the selection of the workers, is
+//! left to the application. A round of Federated Averaging (McMahan et al., 2017) is composed of the following steps:
+//!
+//! 1. The server sends its parameters, obtained with [flat_parameters](../struct.Network.html#method.flat_parameters),
+//! to the workers, which load them with [set_flat_parameters](../struct.Network.html#method.set_flat_parameters).
+//! 2. Each worker trains the network on its local data and computes the change of its parameters with
+//! [parameter_delta](../struct.Network.html#method.parameter_delta).
+//! 3. The server combines the deltas of the workers with [aggregate_deltas](fn.aggregate_deltas.html) and updates
+//! its parameters with [apply_delta](../struct.Network.html#method.apply_delta).
+//!
+//! The parameters are flattened layer by layer, in the order of [parameters](../struct.Network.html#method.parameters)
+//! followed by the parameters of the layers of the heads, and the values of each parameter are given in column-major
+//! order. The workers and the server must use the same architecture.
+use super::{all_layers_mut, Network};
+use crate::errors::Error;
+use crate::tensor::*;
+
+/// Change of the trainable parameters of a worker during a round of federated training.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterDelta {
+    /// The changes of the flattened parameters.
+    pub values: Vec<PrimitiveType>,
+    /// The number of samples on which the worker trained, used to weight the delta in the aggregation.
+    pub num_samples: u64,
+}
+
+/// Averages the deltas of several workers, weighted by their number of samples (FedAvg).
+///
+/// The number of samples of the aggregated delta is the total number of samples of the workers.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidDeltas` if no delta is given, if the deltas don't have the same length, or if the workers
+/// trained on no sample.
+pub fn aggregate_deltas(deltas: &[ParameterDelta]) -> Result<ParameterDelta, Error> {
+    let first = deltas.first().ok_or_else(|| Error::InvalidDeltas("no delta was given".to_string()))?;
+    if deltas.iter().any(|delta| delta.values.len() != first.values.len()) {
+        return Err(Error::InvalidDeltas("the deltas don't have the same length".to_string()));
+    }
+    let num_samples: u64 = deltas.iter().map(|delta| delta.num_samples).sum();
+    if num_samples == 0 {
+        return Err(Error::InvalidDeltas("the workers trained on no sample".to_string()));
+    }
+
+    let mut values = vec![0 as PrimitiveType; first.values.len()];
+    for delta in deltas {
+        let weight = delta.num_samples as PrimitiveType / num_samples as PrimitiveType;
+        for (value, delta_value) in values.iter_mut().zip(&delta.values) {
+            *value += weight * delta_value;
+        }
+    }
+    Ok(ParameterDelta { values, num_samples })
+}
+
+impl Network {
+    /// Returns the trainable parameters of the network flattened in a single vector.
+    pub fn flat_parameters(&self) -> Vec<PrimitiveType> {
+        let mut values = Vec::new();
+        for parameter in self.all_parameters() {
+            let mut parameter_values = vec![0 as PrimitiveType; parameter.elements()];
+            parameter.host(&mut parameter_values);
+            values.extend(parameter_values);
+        }
+        values
+    }
+
+    /// Sets the trainable parameters of the network from a flattened vector.
+    ///
+    /// The state of the optimizer is kept, and the weights shared with other layers are copied again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ParameterShapeMismatch` if the length of the vector doesn't match the number of parameters.
+    pub fn set_flat_parameters(&mut self, values: &[PrimitiveType]) -> Result<(), Error> {
+        let parameters = self.unflatten(values)?;
+        self.set_all_parameters(parameters);
+        Ok(())
+    }
+
+    /// Returns the change of the trainable parameters since the reference parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - The flattened parameters at the beginning of the round, typically received from the server.
+    /// * `num_samples` - The number of samples on which the network was trained during the round.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ParameterShapeMismatch` if the length of the reference doesn't match the number of parameters.
+    pub fn parameter_delta(&self, reference: &[PrimitiveType], num_samples: u64) -> Result<ParameterDelta, Error> {
+        let values = self.flat_parameters();
+        if values.len() != reference.len() {
+            return Err(Error::ParameterShapeMismatch);
+        }
+        let values = values.iter().zip(reference).map(|(value, reference)| value - reference).collect();
+        Ok(ParameterDelta { values, num_samples })
+    }
+
+    /// Adds a delta, typically aggregated from the workers, to the trainable parameters.
+    ///
+    /// The state of the optimizer is kept, and the weights shared with other layers are copied again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::ParameterShapeMismatch` if the length of the delta doesn't match the number of parameters.
+    pub fn apply_delta(&mut self, delta: &ParameterDelta) -> Result<(), Error> {
+        let direction = self.unflatten(&delta.values)?;
+        let parameters = self.all_parameters().into_iter().zip(direction).map(|(parameter, delta)| parameter + delta).collect();
+        self.set_all_parameters(parameters);
+        Ok(())
+    }
+
+    /// Splits a flattened vector into tensors with the shapes of the trainable parameters.
+    fn unflatten(&self, values: &[PrimitiveType]) -> Result<Vec<Tensor>, Error> {
+        let shapes: Vec<Dim> = self.all_parameters().iter().map(|parameter| parameter.dims()).collect();
+        if shapes.iter().map(|dims| dims.elements() as usize).sum::<usize>() != values.len() {
+            return Err(Error::ParameterShapeMismatch);
+        }
+        let mut offset = 0;
+        Ok(shapes.into_iter().map(|dims| {
+            let num_elements = dims.elements() as usize;
+            let tensor = Tensor::new(&values[offset..offset + num_elements], dims);
+            offset += num_elements;
+            tensor
+        }).collect())
+    }
+
+    /// Replaces the trainable parameters of the layers of the main stack and of the heads.
+    fn set_all_parameters(&mut self, values: Vec<Tensor>) {
+        let mut values = values.into_iter();
+        for layer in all_layers_mut(&mut self.layers, &mut self.heads) {
+            if let Some((parameters, _)) = layer.parameters_mut() {
+                for parameter in parameters {
+                    *parameter = values.next().unwrap();
+                }
+            }
+        }
+        self.copy_tied_weights();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::activations::Activation;
+    use crate::layers::Dense;
+    use crate::losses::MeanSquaredError;
+    use crate::models::federated::*;
+    use crate::optimizers::SGD;
+
+    #[test]
+    fn test_federated_averaging() {
+        let mut server = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        server.add(Dense::new(2, Activation::Linear));
+        let global = server.flat_parameters();
+        assert_eq!(global.len(), 6);
+
+        // Two workers train on different samples from the global parameters
+        let mut deltas = Vec::new();
+        for (target, num_samples) in [(1., 1), (-1., 3)].iter() {
+            let mut worker = server.clone();
+            worker.set_flat_parameters(&global).unwrap();
+            let x = Tensor::new(&[1., 2.], Dim4::new(&[2, 1, 1, 1]));
+            let y = constant(*target as PrimitiveType, Dim4::new(&[2, 1, 1, 1]));
+            worker.train_on_batch(&x, &y).unwrap();
+            deltas.push(worker.parameter_delta(&global, *num_samples).unwrap());
+        }
+
+        let aggregated = aggregate_deltas(&deltas).unwrap();
+        assert_eq!(aggregated.num_samples, 4);
+        server.apply_delta(&aggregated).unwrap();
+        for (idx, value) in server.flat_parameters().iter().enumerate() {
+            let expected = global[idx] + 0.25 * deltas[0].values[idx] + 0.75 * deltas[1].values[idx];
+            assert!((value - expected).abs() < 1e-5);
+        }
+
+        assert!(server.set_flat_parameters(&global[1..]).is_err());
+        assert!(aggregate_deltas(&[]).is_err());
+
+        // The parameters of the heads follow the parameters of the main stack
+        server.add_head(0, vec![Dense::new(1, Activation::Linear)], MeanSquaredError::new(), 1.).unwrap();
+        let with_head = server.flat_parameters();
+        assert_eq!(with_head.len(), 9);
+        let mut shifted = with_head.clone();
+        shifted[8] += 1.;
+        server.set_flat_parameters(&shifted).unwrap();
+        assert_eq!(server.flat_parameters(), shifted);
+    }
+}
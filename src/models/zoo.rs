@@ -0,0 +1,139 @@
+//! Reference architectures for image classification.
+//!
+//! The constructors build classic networks sized for small images such as MNIST (28x28x1) or CIFAR-10 (32x32x3). The
+//! networks output the logits of the classes and are trained with the
+//! [SoftmaxCrossEntropy](../../losses/struct.SoftmaxCrossEntropy.html) loss. With the `pretrained` feature, the
+//! [pretrained](pretrained/index.html) module downloads trained weights for the networks.
+use super::{Network, NetworkBuilder};
+use crate::activations::Activation;
+use crate::errors::Error;
+use crate::initializers::Initializer;
+use crate::layers::heads::classification;
+use crate::layers::*;
+use crate::losses::SoftmaxCrossEntropy;
+use crate::optimizers::Optimizer;
+use crate::tensor::*;
+
+#[cfg(feature = "pretrained")]
+pub mod pretrained;
+
+/// Creates a 3x3 convolution with the same padding.
+fn conv3x3(num_filters: u64, stride: u64, activation: Activation) -> Box<Conv2D> {
+    Conv2D::with_param(num_filters, (3, 3), (stride, stride), Padding::Same, activation, Initializer::HeNormal, Initializer::Zeros)
+}
+
+/// Creates the LeNet-5 network of LeCun et al. (1998).
+///
+/// Two 5x5 convolutions with 6 and 16 filters, each followed by a 2x2 max pooling, feed three dense layers with 120,
+/// 84 and `num_classes` units. The activations are ReLU instead of the original hyperbolic tangent.
+///
+/// # Arguments
+///
+/// * `input_shape` - The shape of the images, at least 12x12.
+/// * `num_classes` - The number of classes.
+/// * `optimizer` - The optimizer used to train the network.
+pub fn lenet(input_shape: Dim, num_classes: u64, optimizer: Box<dyn Optimizer>) -> Result<Network, Error> {
+    NetworkBuilder::new(input_shape, SoftmaxCrossEntropy::new(), optimizer)
+        .add(Conv2D::new(6, (5, 5), (1, 1), Padding::Same))
+        .add(MaxPool2D::new((2, 2)))
+        .add(Conv2D::new(16, (5, 5), (1, 1), Padding::Valid))
+        .add(MaxPool2D::new((2, 2)))
+        .add(Flatten::new())
+        .add(Dense::new(120, Activation::ReLU))
+        .add(Dense::new(84, Activation::ReLU))
+        .add(Dense::new(num_classes, Activation::Linear))
+        .build()
+}
+
+/// Creates a small VGG network, as proposed by Simonyan and Zisserman (2014) and scaled down for small images.
+///
+/// Three blocks of two 3x3 convolutions with 64, 128 and 256 filters are each followed by a 2x2 max pooling. Every
+/// convolution is followed by a batch normalization. The classification head averages the feature maps and applies a
+/// dropout of 0.5 before the output layer.
+///
+/// # Arguments
+///
+/// * `input_shape` - The shape of the images, at least 8x8.
+/// * `num_classes` - The number of classes.
+/// * `optimizer` - The optimizer used to train the network.
+pub fn vgg_small(input_shape: Dim, num_classes: u64, optimizer: Box<dyn Optimizer>) -> Result<Network, Error> {
+    let mut layers: Vec<Box<dyn Layer>> = Vec::new();
+    for &num_filters in &[64, 128, 256] {
+        for _ in 0..2 {
+            layers.push(conv3x3(num_filters, 1, Activation::ReLU));
+            layers.push(BatchNorm::new());
+        }
+        layers.push(MaxPool2D::new((2, 2)));
+    }
+
+    NetworkBuilder::new(input_shape, SoftmaxCrossEntropy::new(), optimizer)
+        .add_all(layers)
+        .add_all(classification(num_classes, 0.5))
+        .build()
+}
+
+/// Creates a basic residual block of ResNet.
+///
+/// The block stacks two 3x3 convolutions, each followed by a batch normalization. The first convolution applies the
+/// stride and the ReLU activation, the second one is linear and the ReLU is applied after the addition of the shortcut.
+///
+/// # Arguments
+///
+/// * `num_filters` - The number of filters of the convolutions.
+/// * `stride` - The stride of the first convolution, 2 to halve the height and width of the feature maps.
+pub fn basic_block(num_filters: u64, stride: u64) -> Box<Residual> {
+    Residual::new(vec![
+        conv3x3(num_filters, stride, Activation::ReLU),
+        BatchNorm::new(),
+        conv3x3(num_filters, 1, Activation::Linear),
+        BatchNorm::new(),
+    ], Activation::ReLU)
+}
+
+/// Creates the ResNet-18 network of He et al. (2015), adapted to small images.
+///
+/// As for CIFAR-10 in the original paper, the stem is a single 3x3 convolution without pooling and the shortcuts
+/// have no parameters. Four stages of two [basic blocks](fn.basic_block.html) with 64, 128, 256 and 512 filters
+/// follow, the first block of the last three stages halving the height and width of the feature maps. The head
+/// averages the feature maps before the output layer.
+///
+/// # Arguments
+///
+/// * `input_shape` - The shape of the images, at least 8x8.
+/// * `num_classes` - The number of classes.
+/// * `optimizer` - The optimizer used to train the network.
+pub fn resnet18(input_shape: Dim, num_classes: u64, optimizer: Box<dyn Optimizer>) -> Result<Network, Error> {
+    let mut layers: Vec<Box<dyn Layer>> = vec![conv3x3(64, 1, Activation::ReLU), BatchNorm::new()];
+    for (stage, &num_filters) in [64, 128, 256, 512].iter().enumerate() {
+        let stride = if stage == 0 { 1 } else { 2 };
+        layers.push(basic_block(num_filters, stride));
+        layers.push(basic_block(num_filters, 1));
+    }
+
+    NetworkBuilder::new(input_shape, SoftmaxCrossEntropy::new(), optimizer)
+        .add_all(layers)
+        .add_all(classification(num_classes, 0.))
+        .build()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::models::zoo::*;
+    use crate::optimizers::SGD;
+
+    #[test]
+    fn test_zoo() {
+        let lenet = lenet(Dim4::new(&[28, 28, 1, 1]), 10, SGD::new(0.1)).unwrap();
+        assert_eq!(lenet.output_shape().get(), &[10, 1, 1, 1]);
+
+        let vgg = vgg_small(Dim4::new(&[32, 32, 3, 1]), 10, SGD::new(0.1)).unwrap();
+        assert_eq!(vgg.output_shape().get(), &[10, 1, 1, 1]);
+
+        let resnet = resnet18(Dim4::new(&[16, 16, 3, 1]), 100, SGD::new(0.1)).unwrap();
+        assert_eq!(resnet.output_shape().get(), &[100, 1, 1, 1]);
+        let output = resnet.predict(&randn::<PrimitiveType>(Dim4::new(&[16, 16, 3, 2])));
+        assert_eq!(output.dims().get(), &[100, 1, 1, 2]);
+    }
+}
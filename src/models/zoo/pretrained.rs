@@ -0,0 +1,162 @@
+//! Pretrained weights for the reference architectures.
+//!
+//! A checkpoint is a network saved in HDF5 format with [save](../../struct.Network.html#method.save) and hosted at a
+//! URL, together with the SHA-256 checksum of the file. The file is downloaded the first time it is requested and
+//! stored in a local cache, in the directory given by the `NEURO_CACHE_DIR` environment variable or in
+//! `$HOME/.cache/neuro` by default. The checksum is verified after each download and before a cached file is used,
+//! such that a corrupted or tampered file is never loaded.
+//!
+//! The weights are loaded with [load_weights](../../struct.Network.html#method.load_weights). For transfer
+//! learning, the output layer of the network is usually sized for the new classes and skipped:
+//!
+//! ```no_run
+//! # use neuro::errors::Error;
+//! # fn main() -> Result<(), Error> {
+//! use neuro::models::zoo::{pretrained, resnet18};
+//! use neuro::optimizers::Adam;
+//! use neuro::tensor::*;
+//!
+//! let checkpoint = pretrained::Checkpoint::new("https://example.com/resnet18-cifar10.h5", "<sha256 of the file>");
+//! let mut nn = resnet18(Dim::new(&[32, 32, 3, 1]), 5, Adam::new(0.001))?;
+//! let report = pretrained::load_pretrained(&mut nn, &checkpoint, true)?;
+//! println!("Loaded layers: {:?}", report.loaded);
+//! # Ok(())
+//! # }
+//! ```
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::errors::Error;
+use crate::models::{Network, WeightLoadReport};
+
+/// Location and checksum of a hosted checkpoint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// The URL of the HDF5 file.
+    pub url: String,
+    /// The SHA-256 checksum of the file, in hexadecimal.
+    pub sha256: String,
+}
+
+impl Checkpoint {
+    /// Creates a checkpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The URL of the HDF5 file.
+    /// * `sha256` - The SHA-256 checksum of the file, in hexadecimal.
+    pub fn new(url: &str, sha256: &str) -> Checkpoint {
+        Checkpoint {
+            url: url.to_string(),
+            sha256: sha256.to_lowercase(),
+        }
+    }
+
+    /// Returns the name of the file in the cache.
+    ///
+    /// The name starts with the checksum, such that different versions of a file hosted at the same URL don't collide.
+    fn file_name(&self) -> String {
+        let base_name = self.url.rsplit('/').next().filter(|name| !name.is_empty()).unwrap_or("checkpoint.h5");
+        format!("{}-{}", &self.sha256[..self.sha256.len().min(16)], base_name)
+    }
+}
+
+/// Returns the directory where the downloaded checkpoints are cached.
+pub fn cache_dir() -> PathBuf {
+    match std::env::var_os("NEURO_CACHE_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE")).unwrap_or_else(|| ".".into());
+            Path::new(&home).join(".cache").join("neuro")
+        },
+    }
+}
+
+/// Downloads a checkpoint unless a valid copy is already cached.
+///
+/// # Return value
+///
+/// The path of the cached file.
+pub fn fetch(checkpoint: &Checkpoint) -> Result<PathBuf, Error> {
+    fetch_in(&cache_dir(), checkpoint)
+}
+
+/// Loads the weights of a checkpoint into a network, downloading the checkpoint if needed.
+///
+/// # Arguments
+///
+/// * `network` - The network, typically created by one of the constructors of the [zoo](../index.html).
+/// * `checkpoint` - The checkpoint.
+/// * `skip_mismatched` - If true, the layers that don't match the checkpoint, such as an output layer sized for other
+/// classes, are skipped and reported. Otherwise, an error is returned and no layer is loaded.
+pub fn load_pretrained(network: &mut Network, checkpoint: &Checkpoint, skip_mismatched: bool) -> Result<WeightLoadReport, Error> {
+    let path = fetch(checkpoint)?;
+    network.load_weights(&path.to_string_lossy(), skip_mismatched)
+}
+
+/// Downloads a checkpoint in the given directory unless a valid copy is already there.
+fn fetch_in(dir: &Path, checkpoint: &Checkpoint) -> Result<PathBuf, Error> {
+    let path = dir.join(checkpoint.file_name());
+    if path.is_file() && sha256_hex(&path).map(|hash| hash == checkpoint.sha256).unwrap_or(false) {
+        return Ok(path);
+    }
+
+    fs::create_dir_all(dir).map_err(|err| Error::DownloadError(err.to_string()))?;
+    // The file is written under a temporary name and only renamed once verified, such that an interrupted download
+    // never leaves a partial file in the cache
+    let partial_path = path.with_extension("part");
+    download(&checkpoint.url, &partial_path)?;
+    let hash = sha256_hex(&partial_path).map_err(|err| Error::DownloadError(err.to_string()))?;
+    if hash != checkpoint.sha256 {
+        let _ = fs::remove_file(&partial_path);
+        return Err(Error::ChecksumMismatch(checkpoint.url.clone()));
+    }
+    fs::rename(&partial_path, &path).map_err(|err| Error::DownloadError(err.to_string()))?;
+    Ok(path)
+}
+
+/// Downloads a file.
+fn download(url: &str, path: &Path) -> Result<(), Error> {
+    let response = ureq::get(url).call().map_err(|err| Error::DownloadError(err.to_string()))?;
+    let mut file = fs::File::create(path).map_err(|err| Error::DownloadError(err.to_string()))?;
+    io::copy(&mut response.into_reader(), &mut file).map_err(|err| Error::DownloadError(err.to_string()))?;
+    Ok(())
+}
+
+/// Computes the SHA-256 checksum of a file, in hexadecimal.
+fn sha256_hex(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::models::zoo::pretrained::*;
+
+    #[test]
+    fn test_cached_checkpoint() {
+        let dir = std::env::temp_dir().join("neuro_test_cached_checkpoint");
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("weights.h5");
+        fs::write(&file, b"neuro").unwrap();
+        let hash = sha256_hex(&file).unwrap();
+
+        // A valid cached file is used without downloading
+        let checkpoint = Checkpoint::new("http://127.0.0.1:1/lenet.h5", &hash.to_uppercase());
+        fs::rename(&file, dir.join(checkpoint.file_name())).unwrap();
+        assert_eq!(fetch_in(&dir, &checkpoint).unwrap(), dir.join(checkpoint.file_name()));
+
+        // A cached file with another checksum triggers a download, which fails here
+        let other = Checkpoint::new(&checkpoint.url, &"0".repeat(64));
+        fs::write(dir.join(other.file_name()), b"neuro").unwrap();
+        assert!(fetch_in(&dir, &other).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,7 @@
 
 //! Optimizers used to train the neural network.
 use arrayfire::*;
+use std::collections::VecDeque;
 use std::str::FromStr;
 
 use crate::errors::Error;
@@ -10,17 +11,63 @@ use crate::tensor::*;
 
 
 /// Defines the trait that needs to be implemented by any optimizer working with neuro.
-pub trait Optimizer
+pub trait Optimizer: Send + Sync
 {
     fn name(&self) -> &str;
+    /// Returns a copy of the optimizer, including the estimates kept for the parameters.
+    fn clone_box(&self) -> Box<dyn Optimizer>;
     fn update_parameters(&mut self, layer: &mut dyn Layer, layer_idx: usize);
     fn update_time_step(&mut self) {}
+    /// Returns the learning rate of the optimizer, if it has one.
+    fn learning_rate(&self) -> Option<PrimitiveType> { None }
+    /// Sets the learning rate of the optimizer. Optimizers without a learning rate ignore it.
+    fn set_learning_rate(&mut self, _learning_rate: PrimitiveType) {}
+    /// Returns the value of the hyperparameter with the given name, if the optimizer has one.
+    fn hyperparameter(&self, _name: &str) -> Option<PrimitiveType> { None }
+    /// Sets the value of the hyperparameter with the given name.
+    ///
+    /// This allows the callbacks to adjust the optimizer during the training. The names of the hyperparameters are the
+    /// names of the arguments of the `with_param` constructor of the optimizer.
+    fn set_hyperparameter(&mut self, name: &str, _value: PrimitiveType) -> Result<(), Error> {
+        Err(Error::UnknownHyperparameter(name.to_string()))
+    }
     fn initialize_parameters(&mut self, layers_dims: Vec<(Dim, Dim)>);
+    /// Resets the state kept by the optimizer for the parameters of a layer.
+    fn reset_layer(&mut self, _layer_idx: usize) {}
+    /// Removes the state kept by the optimizer for the parameters of a layer removed from the network.
+    fn remove_layer(&mut self, _layer_idx: usize) {}
     fn save(&self, file: &hdf5::File) -> Result<(), Error>;
 }
 
+impl Clone for Box<dyn Optimizer> {
+    fn clone(&self) -> Box<dyn Optimizer> {
+        self.clone_box()
+    }
+}
+
+
+/// Sets the estimates of the parameters of a layer to zero.
+fn reset_estimates(estimates: &mut [Vec<Tensor>; 2], layer_idx: usize) {
+    for estimate in estimates.iter_mut() {
+        if let Some(tensor) = estimate.get_mut(layer_idx) {
+            *tensor = Tensor::zeros(tensor.dims());
+        }
+    }
+}
+
+
+/// Removes the estimates of the parameters of a layer.
+fn remove_estimates(estimates: &mut [Vec<Tensor>; 2], layer_idx: usize) {
+    for estimate in estimates.iter_mut() {
+        if layer_idx < estimate.len() {
+            estimate.remove(layer_idx);
+        }
+    }
+}
+
 
 /// Stochastic Gradient Descent
+#[derive(Clone)]
 pub struct SGD {
     learning_rate: PrimitiveType,
     momentum: PrimitiveType,
@@ -49,6 +96,11 @@ impl SGD {
         })
     }
 
+    /// Sets the momentum.
+    pub fn set_momentum(&mut self, momentum: PrimitiveType) {
+        self.momentum = momentum;
+    }
+
     pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<SGD> {
         let learning_rate = group.dataset("learning_rate").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the learning rate.");
         let momentum = group.dataset("momentum").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the momentum.");
@@ -70,6 +122,35 @@ impl Optimizer for SGD
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+
+    fn learning_rate(&self) -> Option<PrimitiveType> {
+        Some(self.learning_rate)
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: PrimitiveType) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn hyperparameter(&self, name: &str) -> Option<PrimitiveType> {
+        match name {
+            "learning_rate" => Some(self.learning_rate),
+            "momentum" => Some(self.momentum),
+            _ => None,
+        }
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: PrimitiveType) -> Result<(), Error> {
+        match name {
+            "learning_rate" => self.set_learning_rate(value),
+            "momentum" => self.set_momentum(value),
+            _ => return Err(Error::UnknownHyperparameter(name.to_string())),
+        }
+        Ok(())
+    }
+
     fn update_parameters(&mut self,
                          layer: &mut dyn Layer,
                          layer_idx: usize
@@ -90,6 +171,14 @@ impl Optimizer for SGD
         }
     }
 
+    fn reset_layer(&mut self, layer_idx: usize) {
+        reset_estimates(&mut self.first_moment_est, layer_idx);
+    }
+
+    fn remove_layer(&mut self, layer_idx: usize) {
+        remove_estimates(&mut self.first_moment_est, layer_idx);
+    }
+
     fn save(&self, file: &hdf5::File) -> Result<(), Error> {
 
         let optimizer = file.create_group("optimizer")?;
@@ -112,6 +201,7 @@ impl Optimizer for SGD
 
 
 /// Adaptive moments estimation
+#[derive(Clone)]
 pub struct Adam {
     learning_rate: PrimitiveType,
     beta1: PrimitiveType,
@@ -167,6 +257,21 @@ impl Adam {
         })
     }
 
+    /// Sets the exponential decay rate for the first moment estimate.
+    pub fn set_beta1(&mut self, beta1: PrimitiveType) {
+        self.beta1 = beta1;
+    }
+
+    /// Sets the exponential decay rate for the second moment estimate.
+    pub fn set_beta2(&mut self, beta2: PrimitiveType) {
+        self.beta2 = beta2;
+    }
+
+    /// Sets the small constant used for numerical stability.
+    pub fn set_eps(&mut self, eps: PrimitiveType) {
+        self.eps = eps;
+    }
+
     pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<Adam> {
         let learning_rate = group.dataset("learning_rate").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the learning rate.");
         let beta1 = group.dataset("beta1").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve beta1.");
@@ -196,6 +301,39 @@ impl Optimizer for Adam
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+
+    fn learning_rate(&self) -> Option<PrimitiveType> {
+        Some(self.learning_rate)
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: PrimitiveType) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn hyperparameter(&self, name: &str) -> Option<PrimitiveType> {
+        match name {
+            "learning_rate" => Some(self.learning_rate),
+            "beta1" => Some(self.beta1),
+            "beta2" => Some(self.beta2),
+            "eps" => Some(self.eps),
+            _ => None,
+        }
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: PrimitiveType) -> Result<(), Error> {
+        match name {
+            "learning_rate" => self.set_learning_rate(value),
+            "beta1" => self.set_beta1(value),
+            "beta2" => self.set_beta2(value),
+            "eps" => self.set_eps(value),
+            _ => return Err(Error::UnknownHyperparameter(name.to_string())),
+        }
+        Ok(())
+    }
+
     fn update_parameters(&mut self,
                          layer: &mut dyn Layer,
                          layer_idx: usize
@@ -234,6 +372,16 @@ impl Optimizer for Adam
         }
     }
 
+    fn reset_layer(&mut self, layer_idx: usize) {
+        reset_estimates(&mut self.first_moment_est, layer_idx);
+        reset_estimates(&mut self.second_moment_est, layer_idx);
+    }
+
+    fn remove_layer(&mut self, layer_idx: usize) {
+        remove_estimates(&mut self.first_moment_est, layer_idx);
+        remove_estimates(&mut self.second_moment_est, layer_idx);
+    }
+
     fn save(&self, file: &hdf5::File) -> Result<(), Error> {
 
         let optimizer = file.create_group("optimizer")?;
@@ -267,6 +415,7 @@ impl Optimizer for Adam
 
 
 /// RMSProp
+#[derive(Clone)]
 pub struct RMSProp {
     learning_rate: PrimitiveType,
     decay_rate: PrimitiveType,
@@ -305,6 +454,16 @@ impl RMSProp {
         })
     }
 
+    /// Sets the exponential decay rate (rho) of the moving average of the squared gradients.
+    pub fn set_decay_rate(&mut self, decay_rate: PrimitiveType) {
+        self.decay_rate = decay_rate;
+    }
+
+    /// Sets the small constant used for numerical stability.
+    pub fn set_eps(&mut self, eps: PrimitiveType) {
+        self.eps = eps;
+    }
+
     pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<RMSProp> {
         let learning_rate = group.dataset("learning_rate").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the learning rate.");
         let decay_rate = group.dataset("decay_rate").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the decay rate.");
@@ -326,6 +485,37 @@ impl Optimizer for RMSProp
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+
+    fn learning_rate(&self) -> Option<PrimitiveType> {
+        Some(self.learning_rate)
+    }
+
+    fn set_learning_rate(&mut self, learning_rate: PrimitiveType) {
+        self.learning_rate = learning_rate;
+    }
+
+    fn hyperparameter(&self, name: &str) -> Option<PrimitiveType> {
+        match name {
+            "learning_rate" => Some(self.learning_rate),
+            "decay_rate" => Some(self.decay_rate),
+            "eps" => Some(self.eps),
+            _ => None,
+        }
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: PrimitiveType) -> Result<(), Error> {
+        match name {
+            "learning_rate" => self.set_learning_rate(value),
+            "decay_rate" => self.set_decay_rate(value),
+            "eps" => self.set_eps(value),
+            _ => return Err(Error::UnknownHyperparameter(name.to_string())),
+        }
+        Ok(())
+    }
+
     fn update_parameters(&mut self,
                          layer: &mut dyn Layer,
                          layer_idx: usize
@@ -346,6 +536,14 @@ impl Optimizer for RMSProp
         }
     }
 
+    fn reset_layer(&mut self, layer_idx: usize) {
+        reset_estimates(&mut self.first_moment_est, layer_idx);
+    }
+
+    fn remove_layer(&mut self, layer_idx: usize) {
+        remove_estimates(&mut self.first_moment_est, layer_idx);
+    }
+
     fn save(&self, file: &hdf5::File) -> Result<(), Error> {
         let optimizer = file.create_group("optimizer")?;
 
@@ -368,7 +566,7 @@ impl Optimizer for RMSProp
 }
 
 /// AdaDelta
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct AdaDelta {
     decay_rate: PrimitiveType,
     eps: PrimitiveType,
@@ -403,6 +601,16 @@ impl AdaDelta {
         })
     }
 
+    /// Sets the exponential decay rate (rho) of the moving averages.
+    pub fn set_decay_rate(&mut self, decay_rate: PrimitiveType) {
+        self.decay_rate = decay_rate;
+    }
+
+    /// Sets the small constant used for numerical stability.
+    pub fn set_eps(&mut self, eps: PrimitiveType) {
+        self.eps = eps;
+    }
+
     pub(crate) fn from_hdf5_group(group: &hdf5::Group) -> Box<AdaDelta> {
         let decay_rate = group.dataset("decay_rate").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the decay rate.");
         let eps = group.dataset("eps").and_then(|ds| ds.read_raw::<PrimitiveType>()).expect("Could not retrieve the epsilon value.");
@@ -426,6 +634,27 @@ impl Optimizer for AdaDelta
         Self::NAME
     }
 
+    fn clone_box(&self) -> Box<dyn Optimizer> {
+        Box::new(self.clone())
+    }
+
+    fn hyperparameter(&self, name: &str) -> Option<PrimitiveType> {
+        match name {
+            "decay_rate" => Some(self.decay_rate),
+            "eps" => Some(self.eps),
+            _ => None,
+        }
+    }
+
+    fn set_hyperparameter(&mut self, name: &str, value: PrimitiveType) -> Result<(), Error> {
+        match name {
+            "decay_rate" => self.set_decay_rate(value),
+            "eps" => self.set_eps(value),
+            _ => return Err(Error::UnknownHyperparameter(name.to_string())),
+        }
+        Ok(())
+    }
+
     fn update_parameters(&mut self,
                          layer: &mut dyn Layer,
                          layer_idx: usize
@@ -457,6 +686,16 @@ impl Optimizer for AdaDelta
         }
     }
 
+    fn reset_layer(&mut self, layer_idx: usize) {
+        reset_estimates(&mut self.grad_acc, layer_idx);
+        reset_estimates(&mut self.updates_acc, layer_idx);
+    }
+
+    fn remove_layer(&mut self, layer_idx: usize) {
+        remove_estimates(&mut self.grad_acc, layer_idx);
+        remove_estimates(&mut self.updates_acc, layer_idx);
+    }
+
     fn save(&self, file: &hdf5::File) -> Result<(), Error> {
         let optimizer = file.create_group("optimizer")?;
 
@@ -476,4 +715,171 @@ impl Optimizer for AdaDelta
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+
+/// Limited-memory BFGS
+///
+/// L-BFGS approximates the inverse of the Hessian of the loss from the differences of the parameters and of the
+/// gradients over the last iterations. The step along the resulting direction is chosen with a backtracking line search
+/// satisfying the Armijo condition. The loss and the gradients are evaluated on the full training set at each
+/// iteration, which makes L-BFGS suited to small models, typically fully-connected regression networks, where it
+/// converges much faster than the stochastic optimizers.
+///
+/// Since the line search evaluates the loss several times per iteration, L-BFGS doesn't implement the
+/// [Optimizer](trait.Optimizer.html) trait and is used with
+/// [Network::fit_lbfgs](../models/struct.Network.html#method.fit_lbfgs).
+pub struct LBFGS {
+    history_size: usize,
+    max_iterations: u64,
+    tolerance: PrimitiveType,
+    max_line_search_steps: u64,
+    param_diffs: VecDeque<Vec<Tensor>>,
+    grad_diffs: VecDeque<Vec<Tensor>>,
+}
+
+impl LBFGS {
+
+    /// Sufficient decrease constant of the Armijo condition.
+    pub(crate) const ARMIJO_CONSTANT: PrimitiveType = 1e-4;
+
+    /// Creates an L-BFGS optimizer.
+    ///
+    /// The last 10 iterations are used to approximate the Hessian, at most 100 iterations are performed, and the
+    /// tolerance is set to 1e-6.
+    ///
+    pub fn new() -> Box<LBFGS> {
+        LBFGS::with_param(10, 100, 1e-6)
+    }
+
+    /// Creates an L-BFGS optimizer with the given parameters.
+    ///
+    /// # Arguments
+    /// * `history_size` - number of past iterations used to approximate the Hessian.
+    /// * `max_iterations` - maximum number of iterations.
+    /// * `tolerance` - the optimization stops when the largest absolute value of the gradients or the relative decrease of the loss falls below this value.
+    ///
+    pub fn with_param(history_size: usize, max_iterations: u64, tolerance: PrimitiveType) -> Box<LBFGS> {
+        assert!(history_size > 0, "The history size must be greater than zero.");
+        Box::new(LBFGS {
+            history_size,
+            max_iterations,
+            tolerance,
+            max_line_search_steps: 20,
+            param_diffs: VecDeque::new(),
+            grad_diffs: VecDeque::new(),
+        })
+    }
+
+    /// Returns the maximum number of iterations.
+    pub(crate) fn max_iterations(&self) -> u64 {
+        self.max_iterations
+    }
+
+    /// Returns the tolerance of the stopping criteria.
+    pub(crate) fn tolerance(&self) -> PrimitiveType {
+        self.tolerance
+    }
+
+    /// Returns the maximum number of steps of the line search.
+    pub(crate) fn max_line_search_steps(&self) -> u64 {
+        self.max_line_search_steps
+    }
+
+    /// Returns true if no difference has been stored yet.
+    pub(crate) fn is_history_empty(&self) -> bool {
+        self.param_diffs.is_empty()
+    }
+
+    /// Clears the stored differences of the parameters and of the gradients.
+    pub fn reset(&mut self) {
+        self.param_diffs.clear();
+        self.grad_diffs.clear();
+    }
+
+    /// Computes the descent direction from the gradients with the two-loop recursion.
+    pub(crate) fn direction(&self, gradients: &[Tensor]) -> Vec<Tensor> {
+        let mut q: Vec<Tensor> = gradients.iter().map(|gradient| gradient.copy()).collect();
+        let mut alphas = Vec::with_capacity(self.param_diffs.len());
+
+        for (s, y) in self.param_diffs.iter().zip(self.grad_diffs.iter()).rev() {
+            let rho = 1. / inner_product(y, s);
+            let alpha = rho * inner_product(s, &q);
+            for (q, y) in q.iter_mut().zip(y) {
+                *q = &*q - y * alpha;
+            }
+            alphas.push((rho, alpha));
+        }
+
+        // Scale the initial approximation of the inverse of the Hessian
+        let gamma = match (self.param_diffs.back(), self.grad_diffs.back()) {
+            (Some(s), Some(y)) => inner_product(s, y) / inner_product(y, y),
+            _ => 1.,
+        };
+        let mut r: Vec<Tensor> = q.iter().map(|q| q * gamma).collect();
+
+        for ((s, y), (rho, alpha)) in self.param_diffs.iter().zip(self.grad_diffs.iter()).zip(alphas.into_iter().rev()) {
+            let beta = rho * inner_product(y, &r);
+            for (r, s) in r.iter_mut().zip(s) {
+                *r = &*r + s * (alpha - beta);
+            }
+        }
+
+        r.iter().map(|r| r * (-1.0 as PrimitiveType)).collect()
+    }
+
+    /// Stores the differences of the parameters and of the gradients of the last iteration.
+    ///
+    /// The differences are discarded if the curvature condition is not satisfied.
+    pub(crate) fn update_history(&mut self, param_diff: Vec<Tensor>, grad_diff: Vec<Tensor>) {
+        if inner_product(&param_diff, &grad_diff) <= 1e-10 { return; }
+
+        if self.param_diffs.len() == self.history_size {
+            self.param_diffs.pop_front();
+            self.grad_diffs.pop_front();
+        }
+        self.param_diffs.push_back(param_diff);
+        self.grad_diffs.push_back(grad_diff);
+    }
+}
+
+/// Computes the dot product of two sets of tensors.
+pub(crate) fn inner_product(a: &[Tensor], b: &[Tensor]) -> PrimitiveType {
+    a.iter().zip(b).map(|(a, b)| sum_all(&mul(a, b, false)).0 as PrimitiveType).sum()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::activations::Activation;
+    use crate::errors::Error;
+    use crate::layers::Dense;
+    use crate::losses::MeanSquaredError;
+    use crate::models::Network;
+    use crate::optimizers::*;
+
+    #[test]
+    fn test_set_hyperparameter() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), Adam::new(0.01), None).unwrap();
+        nn.add(Dense::new(1, Activation::Linear));
+
+        // The hyperparameters are adjusted through the network
+        nn.optimizer_mut().set_hyperparameter("beta1", 0.95).unwrap();
+        nn.optimizer_mut().set_hyperparameter("learning_rate", 0.001).unwrap();
+        assert_eq!(nn.optimizer().hyperparameter("beta1"), Some(0.95));
+        assert_eq!(nn.optimizer().learning_rate(), Some(0.001));
+        assert!(matches!(nn.optimizer_mut().set_hyperparameter("momentum", 0.9), Err(Error::UnknownHyperparameter(_))));
+        assert_eq!(nn.optimizer().hyperparameter("momentum"), None);
+
+        let mut sgd = SGD::new(0.1);
+        sgd.set_hyperparameter("momentum", 0.9).unwrap();
+        assert_eq!(sgd.hyperparameter("momentum"), Some(0.9));
+
+        // AdaDelta doesn't have any learning rate
+        let mut adadelta = AdaDelta::new();
+        assert!(adadelta.set_hyperparameter("learning_rate", 0.1).is_err());
+        adadelta.set_hyperparameter("decay_rate", 0.9).unwrap();
+        assert_eq!(adadelta.hyperparameter("decay_rate"), Some(0.9));
+    }
+}
@@ -0,0 +1,224 @@
+//! End-to-end inference pipelines.
+//!
+//! A pipeline bundles a trained network with the preprocessing applied to the training set: the transforms and scaling
+//! of the inputs and the scaling of the outputs of tabular data, and the resizing and scaling of images. Both are saved in the same HDF5 file such that a
+//! deployed model can be evaluated on raw inputs.
+use arrayfire::*;
+
+use crate::data::{DataSet, FittedTransform, ImageDataSet, Scaling};
+use crate::errors::Error;
+use crate::io::*;
+use crate::models::Network;
+use crate::tensor::*;
+
+/// Preprocessing applied to the images before they are fed to the network.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct ImagePreprocessing {
+    image_size: (u32, u32),
+    scale: Option<PrimitiveType>,
+}
+
+/// Trained network together with the preprocessing of its inputs and the postprocessing of its outputs.
+pub struct Pipeline {
+    network: Network,
+    transforms: Vec<FittedTransform>,
+    x_scaling: Option<(Scaling, Tensor, Tensor)>,
+    y_scaling: Option<(Scaling, Tensor, Tensor)>,
+    image: Option<ImagePreprocessing>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline from a trained network and the dataset it was trained on.
+    ///
+    /// The transforms of the features and the scaling of the inputs and outputs of the dataset, if any, are applied by
+    /// the pipeline.
+    pub fn new<T: DataSet>(network: Network, data: &T) -> Pipeline {
+        Pipeline {
+            network,
+            transforms: data.transforms().to_vec(),
+            x_scaling: data.x_train_stats().as_ref().map(|(scaling, c1, c2)| (*scaling, c1.copy(), c2.copy())),
+            y_scaling: data.y_train_stats().as_ref().map(|(scaling, c1, c2)| (*scaling, c1.copy(), c2.copy())),
+            image: None,
+        }
+    }
+
+    /// Creates a pipeline from a trained network and the image dataset it was trained on.
+    ///
+    /// The raw images are resized to the size of the images of the dataset and scaled by the same factor.
+    pub fn from_image_data(network: Network, data: &ImageDataSet) -> Pipeline {
+        let mut pipeline = Pipeline::new(network, data);
+        pipeline.image = Some(ImagePreprocessing {
+            image_size: data.image_size(),
+            scale: data.image_ops().scale_factor(),
+        });
+        pipeline
+    }
+
+    /// Returns the network of the pipeline.
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    /// Computes the output of the network for raw inputs.
+    ///
+    /// The inputs are preprocessed as the training samples and the scaling of the outputs is reverted.
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_input` - The raw samples, stacked along the fourth dimension. Images are given by the values of their
+    /// pixels, with the channels along the third dimension.
+    pub fn predict(&self, raw_input: &Tensor) -> Tensor {
+        let output = self.network.predict(&self.preprocess(raw_input));
+        match &self.y_scaling {
+            Some((scaling, c1, c2)) => scaling.invert(&output, c1, c2),
+            None => output,
+        }
+    }
+
+    /// Applies the preprocessing of the training samples to raw inputs.
+    fn preprocess(&self, raw_input: &Tensor) -> Tensor {
+        let mut x = raw_input.copy();
+        if let Some(image) = &self.image {
+            let (height, width) = (image.image_size.0 as i64, image.image_size.1 as i64);
+            if x.dims()[0] as i64 != height || x.dims()[1] as i64 != width {
+                x = resize(&x, height, width, InterpType::NEAREST);
+            }
+            if let Some(factor) = image.scale {
+                x = mul(&x, &factor, false);
+            }
+        }
+        for transform in &self.transforms {
+            x = transform.apply(&x);
+        }
+        match &self.x_scaling {
+            Some((scaling, c1, c2)) => scaling.apply(&x, c1, c2),
+            None => x,
+        }
+    }
+
+    /// Saves the network and the preprocessing in HDF5 format.
+    pub fn save(&self, filename: &str) -> Result<(), Error> {
+        self.network.save(filename)?;
+
+        let file = hdf5::File::open_rw(filename)?;
+        let pipeline = create_group(&file, "pipeline");
+        Self::save_scaling(&pipeline, "x", &self.x_scaling)?;
+        Self::save_scaling(&pipeline, "y", &self.y_scaling)?;
+
+        if !self.transforms.is_empty() {
+            let transforms = pipeline.create_group("transforms")?;
+            for (i, transform) in self.transforms.iter().enumerate() {
+                transform.save(&transforms, i)?;
+            }
+        }
+
+        if let Some(image) = &self.image {
+            let image_size = pipeline.new_dataset::<[u32; 2]>().create("image_size", 1)?;
+            image_size.write(&[[image.image_size.0, image.image_size.1]])?;
+            if let Some(factor) = image.scale {
+                let scale = pipeline.new_dataset::<PrimitiveType>().create("image_scale", 1)?;
+                write_scalar(&scale, &factor);
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a pipeline from a HDF5 file.
+    ///
+    /// Files containing only a network yield a pipeline without any preprocessing.
+    pub fn load(filename: &str) -> Result<Pipeline, Error> {
+        let network = Network::load(filename)?;
+
+        let file = hdf5::File::open(filename)?;
+        let pipeline = match file.group("pipeline") {
+            Ok(pipeline) => pipeline,
+            Err(_) => return Ok(Pipeline { network, transforms: Vec::new(), x_scaling: None, y_scaling: None, image: None }),
+        };
+
+        let image = pipeline.dataset("image_size").and_then(|ds| ds.read_raw::<[u32; 2]>()).ok().map(|image_size| {
+            ImagePreprocessing {
+                image_size: (image_size[0][0], image_size[0][1]),
+                scale: pipeline.dataset("image_scale").map(|ds| read_scalar::<PrimitiveType>(&ds)).ok(),
+            }
+        });
+
+        let transforms = match pipeline.group("transforms") {
+            Ok(group) => {
+                let num_transforms = list_subgroups(&group).len();
+                (0..num_transforms)
+                    .map(|i| group.group(&format!("transform_{}", i)).map(|transform| FittedTransform::from_hdf5_group(&transform)))
+                    .collect::<hdf5::Result<Vec<FittedTransform>>>()?
+            },
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Pipeline {
+            network,
+            transforms,
+            x_scaling: Self::load_scaling(&pipeline, "x"),
+            y_scaling: Self::load_scaling(&pipeline, "y"),
+            image,
+        })
+    }
+
+    /// Writes the scaling of the inputs (prefix `x`) or outputs (prefix `y`) in the group.
+    fn save_scaling(group: &hdf5::Group, prefix: &str, scaling: &Option<(Scaling, Tensor, Tensor)>) -> hdf5::Result<()> {
+        if let Some((scaling, c1, c2)) = scaling {
+            let scaling_ds = group.new_dataset::<Scaling>().create(&format!("{}_scaling", prefix), 1)?;
+            write_scalar(&scaling_ds, scaling);
+            write_tensor(group, &format!("{}_c1", prefix), c1)?;
+            write_tensor(group, &format!("{}_c2", prefix), c2)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the scaling of the inputs (prefix `x`) or outputs (prefix `y`) from the group.
+    fn load_scaling(group: &hdf5::Group, prefix: &str) -> Option<(Scaling, Tensor, Tensor)> {
+        let scaling = group.dataset(&format!("{}_scaling", prefix)).map(|ds| read_scalar::<Scaling>(&ds)).ok()?;
+        let c1 = read_tensor(group, &format!("{}_c1", prefix)).expect("Could not retrieve the scaling parameters.");
+        let c2 = read_tensor(group, &format!("{}_c2", prefix)).expect("Could not retrieve the scaling parameters.");
+        Some((scaling, c1, c2))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::assert_approx_eq;
+    use crate::data::Scaling;
+    use crate::losses::MeanSquaredError;
+    use crate::models::Network;
+    use crate::optimizers::SGD;
+    use crate::pipeline::*;
+
+    #[test]
+    fn test_preprocess() {
+        let network = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        let pipeline = Pipeline {
+            network,
+            transforms: Vec::new(),
+            x_scaling: Some((Scaling::Standardized, Tensor::new(&[1., 2.], Dim4::new(&[2, 1, 1, 1])), Tensor::new(&[2., 4.], Dim4::new(&[2, 1, 1, 1])))),
+            y_scaling: None,
+            image: None,
+        };
+        let x = pipeline.preprocess(&Tensor::new(&[3., 2., 5., 10.], Dim4::new(&[2, 1, 1, 2])));
+        let mut output = [0 as PrimitiveType; 4];
+        x.host(&mut output);
+        assert_approx_eq!(output, [1., 0., 2., 2.]);
+
+        let network = Network::new(Dim4::new(&[2, 2, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        let pipeline = Pipeline {
+            network,
+            transforms: Vec::new(),
+            x_scaling: None,
+            y_scaling: None,
+            image: Some(ImagePreprocessing { image_size: (2, 2), scale: Some(0.5) }),
+        };
+        let x = pipeline.preprocess(&constant(4 as PrimitiveType, Dim4::new(&[4, 4, 1, 3])));
+        assert_eq!(x.dims().get(), &[2, 2, 1, 3]);
+        let mut output = [0 as PrimitiveType; 12];
+        x.host(&mut output);
+        assert_approx_eq!(output, [2.; 12]);
+    }
+}
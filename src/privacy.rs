@@ -0,0 +1,148 @@
+//! Privacy accounting of differentially private training.
+//!
+//! With [FitConfig::dp](../training/struct.FitConfig.html#method.dp), the network is trained with DP-SGD (Abadi et
+//! al., 2016): the gradient of each sample is clipped to a maximum L2 norm and Gaussian noise proportional to this
+//! norm is added to the sum of the clipped gradients of each mini-batch. Each optimization step is then an instance
+//! of the sampled Gaussian mechanism, whose privacy loss is tracked with the Rényi differential privacy (RDP)
+//! accountant of Mironov et al. (2019) and converted to an (ε, δ) guarantee.
+//!
+//! The accountant assumes that the mini-batches are sampled independently with the rate `batch_size / num_samples`.
+//! The shuffled mini-batches of the training are a common approximation of this sampling.
+
+/// Orders of the Rényi divergence at which the privacy loss is evaluated.
+const ORDERS: std::ops::RangeInclusive<u32> = 2..=256;
+
+/// Tracks the privacy loss of the optimization steps of DP-SGD.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PrivacyAccountant {
+    noise_multiplier: f64,
+    sampling_rate: f64,
+    steps: u64,
+}
+
+impl PrivacyAccountant {
+    /// Creates an accountant without any step.
+    ///
+    /// # Arguments
+    ///
+    /// * `noise_multiplier` - The ratio of the standard deviation of the noise to the clipping norm.
+    /// * `sampling_rate` - The probability that a sample is part of a mini-batch.
+    pub fn new(noise_multiplier: f64, sampling_rate: f64) -> PrivacyAccountant {
+        assert!(noise_multiplier > 0., "The noise multiplier must be positive.");
+        assert!(sampling_rate > 0. && sampling_rate <= 1., "The sampling rate must be in (0, 1].");
+        PrivacyAccountant { noise_multiplier, sampling_rate, steps: 0 }
+    }
+
+    /// Records an optimization step.
+    pub fn step(&mut self) {
+        self.steps += 1;
+    }
+
+    /// Returns the number of recorded steps.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns the noise multiplier.
+    pub fn noise_multiplier(&self) -> f64 {
+        self.noise_multiplier
+    }
+
+    /// Returns the ε spent by the recorded steps for the given δ.
+    pub fn epsilon(&self, delta: f64) -> f64 {
+        compute_epsilon(self.noise_multiplier, self.sampling_rate, self.steps, delta)
+    }
+}
+
+/// Computes the ε of the (ε, δ) guarantee of DP-SGD.
+///
+/// # Arguments
+///
+/// * `noise_multiplier` - The ratio of the standard deviation of the noise to the clipping norm.
+/// * `sampling_rate` - The probability that a sample is part of a mini-batch.
+/// * `steps` - The number of optimization steps.
+/// * `delta` - The probability with which the guarantee may not hold.
+pub fn compute_epsilon(noise_multiplier: f64, sampling_rate: f64, steps: u64, delta: f64) -> f64 {
+    assert!(delta > 0. && delta < 1., "The delta must be in (0, 1).");
+    if steps == 0 || sampling_rate == 0. { return 0.; }
+    ORDERS.map(|order| {
+        let rdp = steps as f64 * rdp_sampled_gaussian(sampling_rate, noise_multiplier, order);
+        rdp + (1. / delta).ln() / (order - 1) as f64
+    }).fold(f64::INFINITY, f64::min)
+}
+
+/// Finds the smallest noise multiplier with which DP-SGD satisfies the (ε, δ) guarantee.
+///
+/// # Arguments
+///
+/// * `epsilon` - The privacy budget.
+/// * `delta` - The probability with which the guarantee may not hold.
+/// * `sampling_rate` - The probability that a sample is part of a mini-batch.
+/// * `steps` - The number of optimization steps.
+///
+/// # Return value
+///
+/// The noise multiplier, or `None` if the budget is too small to be met with any amount of noise.
+pub fn calibrate_noise(epsilon: f64, delta: f64, sampling_rate: f64, steps: u64) -> Option<f64> {
+    let satisfies = |noise_multiplier| compute_epsilon(noise_multiplier, sampling_rate, steps, delta) <= epsilon;
+
+    // Find an upper bound before bisecting
+    let mut upper = 1.;
+    while !satisfies(upper) {
+        upper *= 2.;
+        if upper > 1e6 { return None; }
+    }
+    let mut lower = 0.;
+    for _ in 0..60 {
+        let middle = 0.5 * (lower + upper);
+        if satisfies(middle) { upper = middle; } else { lower = middle; }
+    }
+    Some(upper)
+}
+
+/// Computes the RDP of one step of the sampled Gaussian mechanism at an integer order (Mironov et al., 2019).
+fn rdp_sampled_gaussian(q: f64, sigma: f64, order: u32) -> f64 {
+    let alpha = order as f64;
+    if q == 1. { return alpha / (2. * sigma * sigma); }
+
+    // log A = logsumexp_k [log C(alpha, k) + (alpha - k) log(1 - q) + k log q + (k^2 - k) / (2 sigma^2)]
+    let mut log_binomial: f64 = 0.;
+    let terms: Vec<f64> = (0..=order).map(|k| {
+        let k = k as f64;
+        if k > 0. { log_binomial += (alpha - k + 1.).ln() - k.ln(); }
+        log_binomial + (alpha - k) * (1. - q).ln() + k * q.ln() + (k * k - k) / (2. * sigma * sigma)
+    }).collect();
+    let max = terms.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let log_a = max + terms.iter().map(|term| (term - max).exp()).sum::<f64>().ln();
+    log_a / (alpha - 1.)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::privacy::*;
+
+    #[test]
+    fn test_accountant() {
+        // Without sampling, the RDP of the Gaussian mechanism is alpha / (2 sigma^2)
+        let (sigma, steps, delta): (f64, u64, f64) = (2., 10, 1e-5);
+        let expected = (2..=256).map(|order| {
+            let alpha = order as f64;
+            steps as f64 * alpha / (2. * sigma * sigma) + (1. / delta).ln() / (alpha - 1.)
+        }).fold(f64::INFINITY, f64::min);
+        assert!((compute_epsilon(sigma, 1., steps, delta) - expected).abs() < 1e-9);
+        assert!((rdp_sampled_gaussian(0.999_999_999, sigma, 4) - 0.5).abs() < 1e-6);
+
+        // Subsampling amplifies the privacy
+        let mut accountant = PrivacyAccountant::new(1.1, 0.01);
+        assert_eq!(accountant.epsilon(delta), 0.);
+        for _ in 0..1000 { accountant.step(); }
+        let epsilon = accountant.epsilon(delta);
+        assert!(epsilon > 0. && epsilon < compute_epsilon(1.1, 1., 1000, delta));
+
+        // The calibrated noise meets the budget
+        let noise_multiplier = calibrate_noise(epsilon, delta, 0.01, 1000).unwrap();
+        assert!((noise_multiplier - 1.1).abs() < 1e-3);
+        assert!(calibrate_noise(1e-3, delta, 0.01, 1000).is_none());
+    }
+}
@@ -0,0 +1,133 @@
+//! Profiling of the forward and backward passes of a network.
+//!
+//! A trace is recorded with [Network::trace](../models/struct.Network.html#method.trace). It contains the time spent
+//! by each layer during one forward and one backward pass, which shows the layers dominating the runtime. The trace
+//! can be printed as a report or exported in the Chrome trace format and opened in `chrome://tracing` or Perfetto.
+use std::fmt;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Pass during which an event was recorded.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pass {
+    Forward,
+    Backward,
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Pass::Forward => write!(f, "forward"),
+            Pass::Backward => write!(f, "backward"),
+        }
+    }
+}
+
+/// Computation of a layer recorded in a trace.
+#[derive(Debug, Clone)]
+pub struct TraceEvent {
+    /// The name of the layer followed by its index in the network.
+    pub layer: String,
+    /// The pass during which the computation was performed.
+    pub pass: Pass,
+    /// The dimensions of the input of the computation.
+    pub input_shape: [u64; 4],
+    /// The dimensions of the output of the computation.
+    pub output_shape: [u64; 4],
+    /// The time elapsed between the beginning of the trace and the beginning of the computation.
+    pub start: Duration,
+    /// The duration of the computation.
+    pub duration: Duration,
+}
+
+/// Sequence of the computations of the layers during one forward and one backward pass.
+#[derive(Debug, Clone, Default)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    /// Creates an empty trace.
+    pub(crate) fn new() -> Trace {
+        Trace { events: Vec::new() }
+    }
+
+    /// Appends an event to the trace.
+    pub(crate) fn push(&mut self, event: TraceEvent) {
+        self.events.push(event);
+    }
+
+    /// Returns the recorded events, in the order of the computations.
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    /// Returns the total time spent in the layers.
+    pub fn total_duration(&self) -> Duration {
+        self.events.iter().map(|event| event.duration).sum()
+    }
+
+    /// Exports the trace in the Chrome trace event format.
+    ///
+    /// The JSON string can be saved to a file and opened in `chrome://tracing` or Perfetto.
+    pub fn to_chrome_trace(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        for (i, event) in self.events.iter().enumerate() {
+            if i > 0 { json.push(','); }
+            write!(json,
+                   "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":0,\"args\":{{\"input_shape\":\"{:?}\",\"output_shape\":\"{:?}\"}}}}",
+                   event.layer, event.pass, event.start.as_micros(), event.duration.as_micros(), event.input_shape, event.output_shape
+            ).expect("Could not write the event.");
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total_duration().as_secs_f64();
+        writeln!(f, "Layer \t\t Pass \t\t Input shape \t\t Output shape \t\t Time (ms) \t Fraction")?;
+        writeln!(f, "-----------------------------------------------------------------------------------------------------------")?;
+        for event in self.events.iter() {
+            let duration = event.duration.as_secs_f64();
+            let fraction = if total > 0. { duration / total } else { 0. };
+            writeln!(f, "{} \t {} \t {:?} \t {:?} \t {:.3} \t\t {:.4}", event.layer, event.pass, event.input_shape, event.output_shape, duration * 1e3, fraction)?;
+        }
+        writeln!(f, "-----------------------------------------------------------------------------------------------------------")?;
+        writeln!(f, "Total time: {:.3} ms", total * 1e3)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::profiling::*;
+
+    #[test]
+    fn test_chrome_trace() {
+        let mut trace = Trace::new();
+        trace.push(TraceEvent {
+            layer: String::from("Dense_0"),
+            pass: Pass::Forward,
+            input_shape: [2, 1, 1, 4],
+            output_shape: [3, 1, 1, 4],
+            start: Duration::from_micros(10),
+            duration: Duration::from_micros(25),
+        });
+        trace.push(TraceEvent {
+            layer: String::from("Dense_0"),
+            pass: Pass::Backward,
+            input_shape: [3, 1, 1, 4],
+            output_shape: [2, 1, 1, 4],
+            start: Duration::from_micros(40),
+            duration: Duration::from_micros(50),
+        });
+
+        assert_eq!(trace.total_duration(), Duration::from_micros(75));
+        assert_eq!(trace.to_chrome_trace(), "{\"traceEvents\":[\
+            {\"name\":\"Dense_0\",\"cat\":\"forward\",\"ph\":\"X\",\"ts\":10,\"dur\":25,\"pid\":0,\"tid\":0,\"args\":{\"input_shape\":\"[2, 1, 1, 4]\",\"output_shape\":\"[3, 1, 1, 4]\"}},\
+            {\"name\":\"Dense_0\",\"cat\":\"backward\",\"ph\":\"X\",\"ts\":40,\"dur\":50,\"pid\":0,\"tid\":0,\"args\":{\"input_shape\":\"[3, 1, 1, 4]\",\"output_shape\":\"[2, 1, 1, 4]\"}}]}");
+    }
+}
@@ -0,0 +1,303 @@
+//! Learning rate schedules applied during the training.
+//!
+//! A schedule is attached to the training configuration with
+//! [FitConfig::learning_rate_schedule](../training/struct.FitConfig.html#method.learning_rate_schedule). The learning
+//! rate of the optimizer is updated before each optimization step, that is after each mini-batch or after each group
+//! of accumulated mini-batches.
+//!
+//! Schedules are composed with [then](trait.LearningRateSchedule.html#method.then) and
+//! [warmup](trait.LearningRateSchedule.html#method.warmup), for instance to warm up the learning rate before a cosine
+//! decay:
+//!
+//! ```ignore
+//! let schedule = CosineDecay::new(0.1, 0.001, 1000).warmup(100);
+//! ```
+//!
+//! The steps are counted by the network across successive trainings and saved with the network, such that resuming
+//! the training of a saved network continues the schedule where it stopped.
+use crate::tensor::*;
+
+/// Trait implemented by the learning rate schedules.
+pub trait LearningRateSchedule: Send + Sync {
+    /// Returns the learning rate used for the given optimization step, starting at 0.
+    fn learning_rate(&self, step: u64) -> PrimitiveType;
+
+    /// Follows the schedule for the first `steps` steps and the `next` schedule afterwards.
+    ///
+    /// The steps of the `next` schedule start at 0 when it takes over. Chaining constant learning rates creates step
+    /// decays:
+    ///
+    /// ```ignore
+    /// let schedule = ConstantLearningRate::new(0.1)
+    ///     .then(100, ConstantLearningRate::new(0.01))
+    ///     .then(200, ConstantLearningRate::new(0.001));
+    /// ```
+    fn then(self: Box<Self>, steps: u64, next: Box<dyn LearningRateSchedule>) -> Box<ChainedSchedule> where Self: Sized + 'static {
+        Box::new(ChainedSchedule {
+            first: self,
+            steps,
+            next,
+        })
+    }
+
+    /// Increases the learning rate linearly during the first `steps` steps up to the initial learning rate of the
+    /// schedule, which is then followed from its first step.
+    ///
+    /// The warmup avoids large updates while the statistics of adaptive optimizers and of the batch normalization
+    /// layers are still inaccurate.
+    fn warmup(self: Box<Self>, steps: u64) -> Box<ChainedSchedule> where Self: Sized + 'static {
+        let target_learning_rate = self.learning_rate(0);
+        let ramp = PolynomialDecay::new(target_learning_rate / (steps + 1) as PrimitiveType, target_learning_rate, steps.max(1));
+        ramp.then(steps, self)
+    }
+}
+
+
+/// Schedule following a first schedule for a given number of steps and a second one afterwards.
+///
+/// Chained schedules are created with [then](trait.LearningRateSchedule.html#method.then).
+pub struct ChainedSchedule {
+    first: Box<dyn LearningRateSchedule>,
+    steps: u64,
+    next: Box<dyn LearningRateSchedule>,
+}
+
+impl LearningRateSchedule for ChainedSchedule {
+    fn learning_rate(&self, step: u64) -> PrimitiveType {
+        if step < self.steps {
+            self.first.learning_rate(step)
+        } else {
+            self.next.learning_rate(step - self.steps)
+        }
+    }
+}
+
+
+/// Constant learning rate.
+///
+/// Mostly useful to chain constant phases with [then](trait.LearningRateSchedule.html#method.then).
+pub struct ConstantLearningRate {
+    learning_rate: PrimitiveType,
+}
+
+impl ConstantLearningRate {
+    /// Creates a constant learning rate.
+    pub fn new(learning_rate: PrimitiveType) -> Box<ConstantLearningRate> {
+        Box::new(ConstantLearningRate { learning_rate })
+    }
+}
+
+impl LearningRateSchedule for ConstantLearningRate {
+    fn learning_rate(&self, _step: u64) -> PrimitiveType {
+        self.learning_rate
+    }
+}
+
+
+/// Polynomial decay of the learning rate.
+///
+/// The learning rate decays from its initial value to its final value over `decay_steps` steps and remains constant
+/// afterwards:
+///
+/// lr = (initial_lr - final_lr) * (1 - step / decay_steps)^power + final_lr
+pub struct PolynomialDecay {
+    initial_learning_rate: PrimitiveType,
+    final_learning_rate: PrimitiveType,
+    decay_steps: u64,
+    power: PrimitiveType,
+}
+
+impl PolynomialDecay {
+    /// Creates a linear decay of the learning rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_learning_rate` - The learning rate at the first step.
+    /// * `final_learning_rate` - The learning rate reached after `decay_steps` steps.
+    /// * `decay_steps` - The number of steps over which the learning rate decays.
+    pub fn new(initial_learning_rate: PrimitiveType, final_learning_rate: PrimitiveType, decay_steps: u64) -> Box<PolynomialDecay> {
+        PolynomialDecay::with_param(initial_learning_rate, final_learning_rate, decay_steps, 1.)
+    }
+
+    /// Creates a polynomial decay of the learning rate with the given power.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of decay steps is zero.
+    pub fn with_param(initial_learning_rate: PrimitiveType,
+                      final_learning_rate: PrimitiveType,
+                      decay_steps: u64,
+                      power: PrimitiveType
+    ) -> Box<PolynomialDecay> {
+        assert!(decay_steps > 0, "The number of decay steps must be greater than zero.");
+        Box::new(PolynomialDecay {
+            initial_learning_rate,
+            final_learning_rate,
+            decay_steps,
+            power,
+        })
+    }
+}
+
+impl LearningRateSchedule for PolynomialDecay {
+    fn learning_rate(&self, step: u64) -> PrimitiveType {
+        let progress = std::cmp::min(step, self.decay_steps) as PrimitiveType / self.decay_steps as PrimitiveType;
+        (self.initial_learning_rate - self.final_learning_rate) * (1. - progress).powf(self.power) + self.final_learning_rate
+    }
+}
+
+
+/// Cosine decay of the learning rate (Loshchilov and Hutter, 2017).
+///
+/// The learning rate follows half a cosine from its initial value to its final value over `decay_steps` steps and
+/// remains constant afterwards:
+///
+/// lr = final_lr + 0.5 * (initial_lr - final_lr) * (1 + cos(pi * step / decay_steps))
+pub struct CosineDecay {
+    initial_learning_rate: PrimitiveType,
+    final_learning_rate: PrimitiveType,
+    decay_steps: u64,
+}
+
+impl CosineDecay {
+    /// Creates a cosine decay of the learning rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_learning_rate` - The learning rate at the first step.
+    /// * `final_learning_rate` - The learning rate reached after `decay_steps` steps.
+    /// * `decay_steps` - The number of steps over which the learning rate decays.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of decay steps is zero.
+    pub fn new(initial_learning_rate: PrimitiveType, final_learning_rate: PrimitiveType, decay_steps: u64) -> Box<CosineDecay> {
+        assert!(decay_steps > 0, "The number of decay steps must be greater than zero.");
+        Box::new(CosineDecay {
+            initial_learning_rate,
+            final_learning_rate,
+            decay_steps,
+        })
+    }
+}
+
+impl LearningRateSchedule for CosineDecay {
+    fn learning_rate(&self, step: u64) -> PrimitiveType {
+        let progress = std::cmp::min(step, self.decay_steps) as f64 / self.decay_steps as f64;
+        let cosine = (0.5 * (1. + (std::f64::consts::PI * progress).cos())) as PrimitiveType;
+        self.final_learning_rate + (self.initial_learning_rate - self.final_learning_rate) * cosine
+    }
+}
+
+
+/// Policies of the cyclical learning rate.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CyclicalPolicy {
+    /// The amplitude of the cycles is constant.
+    Triangular,
+    /// The amplitude of the cycles is halved at each cycle.
+    Triangular2,
+    /// The amplitude of the cycles is scaled by gamma^step.
+    ExpRange(PrimitiveType),
+}
+
+/// Cyclical learning rate (Smith, 2017).
+///
+/// The learning rate oscillates linearly between a base value and a maximum value. Each cycle lasts 2 * `step_size`
+/// steps: the learning rate increases during the first half of the cycle and decreases during the second half.
+pub struct CyclicalLearningRate {
+    base_learning_rate: PrimitiveType,
+    max_learning_rate: PrimitiveType,
+    step_size: u64,
+    policy: CyclicalPolicy,
+}
+
+impl CyclicalLearningRate {
+    /// Creates a cyclical learning rate with the triangular policy.
+    ///
+    /// # Arguments
+    ///
+    /// * `base_learning_rate` - The learning rate at the beginning of each cycle.
+    /// * `max_learning_rate` - The learning rate at the middle of each cycle.
+    /// * `step_size` - The number of steps in half a cycle.
+    pub fn new(base_learning_rate: PrimitiveType, max_learning_rate: PrimitiveType, step_size: u64) -> Box<CyclicalLearningRate> {
+        CyclicalLearningRate::with_param(base_learning_rate, max_learning_rate, step_size, CyclicalPolicy::Triangular)
+    }
+
+    /// Creates a cyclical learning rate with the given policy.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the step size is zero.
+    pub fn with_param(base_learning_rate: PrimitiveType,
+                      max_learning_rate: PrimitiveType,
+                      step_size: u64,
+                      policy: CyclicalPolicy
+    ) -> Box<CyclicalLearningRate> {
+        assert!(step_size > 0, "The step size must be greater than zero.");
+        Box::new(CyclicalLearningRate {
+            base_learning_rate,
+            max_learning_rate,
+            step_size,
+            policy,
+        })
+    }
+}
+
+impl LearningRateSchedule for CyclicalLearningRate {
+    fn learning_rate(&self, step: u64) -> PrimitiveType {
+        let cycle = step / (2 * self.step_size);
+        let x = (step as PrimitiveType / self.step_size as PrimitiveType - 2. * cycle as PrimitiveType - 1.).abs();
+        let scale = match self.policy {
+            CyclicalPolicy::Triangular => 1.,
+            CyclicalPolicy::Triangular2 => (0.5 as PrimitiveType).powi(cycle as i32),
+            CyclicalPolicy::ExpRange(gamma) => gamma.powf(step as PrimitiveType),
+        };
+        self.base_learning_rate + (self.max_learning_rate - self.base_learning_rate) * (1. - x).max(0.) * scale
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::assert_approx_eq;
+    use crate::schedulers::*;
+
+    #[test]
+    fn test_polynomial_decay() {
+        let schedule = PolynomialDecay::with_param(0.1, 0.01, 4, 2.);
+        let learning_rates: Vec<PrimitiveType> = (0..6).map(|step| schedule.learning_rate(step)).collect();
+        assert_approx_eq!(learning_rates, [0.1, 0.060625, 0.0325, 0.015625, 0.01, 0.01]);
+    }
+
+    #[test]
+    fn test_cyclical_learning_rate() {
+        let schedule = CyclicalLearningRate::new(0.1, 0.5, 2);
+        let learning_rates: Vec<PrimitiveType> = (0..6).map(|step| schedule.learning_rate(step)).collect();
+        assert_approx_eq!(learning_rates, [0.1, 0.3, 0.5, 0.3, 0.1, 0.3]);
+
+        let schedule = CyclicalLearningRate::with_param(0.1, 0.5, 2, CyclicalPolicy::Triangular2);
+        let learning_rates: Vec<PrimitiveType> = (4..8).map(|step| schedule.learning_rate(step)).collect();
+        assert_approx_eq!(learning_rates, [0.1, 0.2, 0.3, 0.2]);
+    }
+
+    #[test]
+    fn test_cosine_decay() {
+        let schedule = CosineDecay::new(0.1, 0.01, 4);
+        let learning_rates: Vec<PrimitiveType> = (0..6).map(|step| schedule.learning_rate(step)).collect();
+        assert_approx_eq!(learning_rates, [0.1, 0.08681980, 0.055, 0.02318019, 0.01, 0.01]);
+    }
+
+    #[test]
+    fn test_schedule_composition() {
+        let schedule = ConstantLearningRate::new(0.1)
+            .then(2, ConstantLearningRate::new(0.01))
+            .then(3, ConstantLearningRate::new(0.001));
+        let learning_rates: Vec<PrimitiveType> = (0..5).map(|step| schedule.learning_rate(step)).collect();
+        assert_approx_eq!(learning_rates, [0.1, 0.1, 0.01, 0.001, 0.001]);
+
+        let schedule = CosineDecay::new(0.1, 0.01, 4).warmup(3);
+        let learning_rates: Vec<PrimitiveType> = (0..8).map(|step| schedule.learning_rate(step)).collect();
+        assert_approx_eq!(learning_rates, [0.025, 0.05, 0.075, 0.1, 0.08681980, 0.055, 0.02318019, 0.01]);
+    }
+}
@@ -0,0 +1,266 @@
+//! Serving of trained models over HTTP.
+//!
+//! This module is only available when the crate is built with the `serving` feature.
+//!
+//! The server exposes two endpoints:
+//! * `GET /info` returns the input and output shapes of the network.
+//! * `POST /predict` computes the output of the network. The body of the request must be a JSON object of the form
+//! `{"values": [...]}` where the values of the samples are concatenated in column-major order. The response has the
+//! form `{"dims": [...], "values": [...]}`.
+//!
+//! The requests received within a short time window are batched together before being evaluated by the network.
+use arrayfire::*;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::data::{DataSet, Scaling};
+use crate::errors::Error;
+use crate::models::Network;
+use crate::tensor::*;
+
+/// HTTP server computing the predictions of a trained network.
+pub struct Server {
+    network: Network,
+    x_scaling: Option<(Scaling, Tensor, Tensor)>,
+    y_scaling: Option<(Scaling, Tensor, Tensor)>,
+    max_batch_size: u64,
+    batch_timeout: Duration,
+}
+
+impl Server {
+    /// Creates a server for the given network.
+    ///
+    /// By default, up to 64 samples are batched together and the server waits at most 5 ms for additional requests
+    /// before evaluating a batch.
+    pub fn new(network: Network) -> Server {
+        Server {
+            network,
+            x_scaling: None,
+            y_scaling: None,
+            max_batch_size: 64,
+            batch_timeout: Duration::from_millis(5),
+        }
+    }
+
+    /// Creates a server with the given batching parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `network` - The trained network.
+    /// * `max_batch_size` - The maximum number of samples evaluated at once.
+    /// * `batch_timeout` - The maximum duration during which requests are accumulated before a batch is evaluated.
+    pub fn with_param(network: Network, max_batch_size: u64, batch_timeout: Duration) -> Server {
+        Server {
+            network,
+            x_scaling: None,
+            y_scaling: None,
+            max_batch_size,
+            batch_timeout,
+        }
+    }
+
+    /// Uses the scaling applied to the dataset to preprocess the inputs and postprocess the outputs.
+    pub fn with_scaling<T: DataSet>(mut self, data: &T) -> Server {
+        self.x_scaling = data.x_train_stats().as_ref().map(|(scaling, c1, c2)| (*scaling, c1.copy(), c2.copy()));
+        self.y_scaling = data.y_train_stats().as_ref().map(|(scaling, c1, c2)| (*scaling, c1.copy(), c2.copy()));
+        self
+    }
+
+    /// Starts the server on the given address (e.g. "0.0.0.0:8000").
+    ///
+    /// This method blocks and processes the requests until an error occurs.
+    pub fn serve(&self, address: &str) -> Result<(), Error> {
+        let server = tiny_http::Server::http(address).map_err(|err| Error::ServingError(err.to_string()))?;
+        println!("Serving the model on {}.", address);
+        self.process_requests(&server)
+    }
+
+    /// Processes the requests received by the HTTP server until an error occurs.
+    fn process_requests(&self, server: &tiny_http::Server) -> Result<(), Error> {
+        loop {
+            // Wait for a first request and accumulate the following ones until the batch is full or the timeout expires
+            let first_request = server.recv().map_err(|err| Error::ServingError(err.to_string()))?;
+            let deadline = Instant::now() + self.batch_timeout;
+            let mut batch: Vec<(tiny_http::Request, Tensor)> = Vec::new();
+            let mut num_samples = 0;
+
+            let mut next_request = Some(first_request);
+            while let Some(request) = next_request.take() {
+                if let Some(input) = self.handle_request(request) {
+                    num_samples += input.1.batch_size();
+                    batch.push(input);
+                }
+
+                let now = Instant::now();
+                if num_samples >= self.max_batch_size || now >= deadline { break; }
+                next_request = server.recv_timeout(deadline - now).map_err(|err| Error::ServingError(err.to_string()))?;
+            }
+
+            if !batch.is_empty() {
+                self.predict_batch(batch);
+            }
+        }
+    }
+
+    /// Answers the requests that don't require a prediction and decodes the inputs of the others.
+    fn handle_request(&self, mut request: tiny_http::Request) -> Option<(tiny_http::Request, Tensor)> {
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        match (method, url.as_str()) {
+            (tiny_http::Method::Get, "/info") => {
+                let body = json!({
+                    "input_shape": &self.network.input_shape().get()[..3],
+                    "output_shape": &self.network.output_shape().get()[..3],
+                });
+                respond(request, 200, body);
+                None
+            },
+            (tiny_http::Method::Post, "/predict") => {
+                let input = serde_json::from_reader::<_, Value>(request.as_reader())
+                    .map_err(|err| err.to_string())
+                    .and_then(|value| decode_tensor(&value, self.network.input_shape()));
+                match input {
+                    Ok(input) => Some((request, input)),
+                    Err(err) => {
+                        respond(request, 400, json!({ "error": err }));
+                        None
+                    }
+                }
+            },
+            _ => {
+                respond(request, 404, json!({ "error": "Unknown endpoint." }));
+                None
+            }
+        }
+    }
+
+    /// Evaluates a batch of requests and sends the predictions back.
+    fn predict_batch(&self, batch: Vec<(tiny_http::Request, Tensor)>) {
+        let mut inputs = batch[0].1.copy();
+        for (_, input) in batch.iter().skip(1) {
+            inputs = join(3, &inputs, input);
+        }
+
+        if let Some((scaling, c1, c2)) = &self.x_scaling {
+            inputs = scaling.apply(&inputs, c1, c2);
+        }
+        let mut outputs = self.network.predict(&inputs);
+        if let Some((scaling, c1, c2)) = &self.y_scaling {
+            outputs = scaling.invert(&outputs, c1, c2);
+        }
+
+        // Split the outputs and answer each request
+        let mut offset = 0;
+        for (request, input) in batch {
+            let num_samples = input.batch_size();
+            let seqs = &[Seq::default(), Seq::default(), Seq::default(), Seq::new(offset as f64, (offset + num_samples - 1) as f64, 1.0)];
+            let output = index(&outputs, seqs);
+            respond(request, 200, encode_tensor(&output));
+            offset += num_samples;
+        }
+    }
+}
+
+/// Decodes a tensor from a JSON object of the form `{"values": [...]}`.
+///
+/// The number of samples is inferred from the number of values and the shape of the samples.
+fn decode_tensor(value: &Value, sample_shape: Dim) -> Result<Tensor, String> {
+    let values = value.get("values")
+        .and_then(|values| values.as_array())
+        .ok_or_else(|| String::from("The request must contain an array of values."))?
+        .iter()
+        .map(|entry| entry.as_f64().map(|entry| entry as PrimitiveType))
+        .collect::<Option<Vec<PrimitiveType>>>()
+        .ok_or_else(|| String::from("The values must be numbers."))?;
+
+    let sample_size = sample_shape[0] * sample_shape[1] * sample_shape[2];
+    if values.is_empty() || values.len() as u64 % sample_size != 0 {
+        return Err(format!("The number of values must be a multiple of {}.", sample_size));
+    }
+    let num_samples = values.len() as u64 / sample_size;
+    Ok(Tensor::new(&values[..], Dim::new(&[sample_shape[0], sample_shape[1], sample_shape[2], num_samples])))
+}
+
+/// Encodes a tensor as a JSON object of the form `{"dims": [...], "values": [...]}`.
+fn encode_tensor(tensor: &Tensor) -> Value {
+    let mut values = vec![0 as PrimitiveType; tensor.elements()];
+    tensor.host(&mut values);
+    json!({
+        "dims": tensor.dims().get(),
+        "values": values,
+    })
+}
+
+/// Sends a JSON response.
+fn respond(request: tiny_http::Request, status_code: u16, body: Value) {
+    let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    let response = tiny_http::Response::from_string(body.to_string())
+        .with_status_code(status_code)
+        .with_header(header);
+    if let Err(err) = request.respond(response) {
+        eprintln!("Could not send the response: {}", err);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use serde_json::{json, Value};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Duration;
+    use crate::activations::Activation;
+    use crate::layers::Dense;
+    use crate::losses::MeanSquaredError;
+    use crate::models::Network;
+    use crate::optimizers::SGD;
+    use crate::serving::Server;
+    use crate::tensor::*;
+
+    /// Sends an HTTP request and returns the status code and the JSON body of the response.
+    fn send_request(address: &str, method: &str, path: &str, body: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               method, path, address, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status_code = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let (_, body) = response.split_at(response.find("\r\n\r\n").unwrap() + 4);
+        (status_code, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_predict_round_trip() {
+        let mut nn = Network::new(Dim4::new(&[2, 1, 1, 1]), MeanSquaredError::new(), SGD::new(0.1), None).unwrap();
+        nn.add(Dense::new(3, Activation::Linear));
+        let x = Tensor::new(&[1., 2., -0.5, 0.25], Dim4::new(&[2, 1, 1, 2]));
+        let mut expected = [0 as PrimitiveType; 6];
+        nn.predict(&x).host(&mut expected);
+
+        let server = Server::with_param(nn, 8, Duration::from_millis(1));
+        let http_server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let address = http_server.server_addr().to_string();
+        thread::spawn(move || server.process_requests(&http_server));
+
+        let (status_code, response) = send_request(&address, "GET", "/info", "");
+        assert_eq!(status_code, 200);
+        assert_eq!(response["input_shape"], json!([2, 1, 1]));
+        assert_eq!(response["output_shape"], json!([3, 1, 1]));
+
+        let (status_code, response) = send_request(&address, "POST", "/predict", r#"{"values": [1, 2, -0.5, 0.25]}"#);
+        assert_eq!(status_code, 200);
+        assert_eq!(response["dims"], json!([3, 1, 1, 2]));
+        let values = response["values"].as_array().unwrap();
+        assert_eq!(values.len(), expected.len());
+        for (value, expected) in values.iter().zip(expected.iter()) {
+            assert!((value.as_f64().unwrap() - *expected as f64).abs() < 1e-6);
+        }
+
+        // The number of values must be a multiple of the size of a sample
+        let (status_code, _) = send_request(&address, "POST", "/predict", r#"{"values": [1, 2, 3]}"#);
+        assert_eq!(status_code, 400);
+    }
+}
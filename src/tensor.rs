@@ -1,19 +1,44 @@
 
 //! Wrapper around ArrayFire's array with additional helper methods.
 use arrayfire::*;
+use num::Complex;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
+use std::convert::TryInto;
+
+use crate::backend::{Backend, DefaultBackend};
+use crate::cpu::CpuTensor;
+use crate::errors::Error;
 
 /// This type is defined to easily change between f32 and f64 as the primitive type used by the crate.
 /// It has important consequences on the memory footprint of the crate when running deep and/or wide neural networks.
+///
+/// The primitive type is f32 by default and f64 when the crate is built with the `f64` feature.
+#[cfg(not(feature = "f64"))]
 pub type PrimitiveType = f32;
 
+/// This type is defined to easily change between f32 and f64 as the primitive type used by the crate.
+/// It has important consequences on the memory footprint of the crate when running deep and/or wide neural networks.
+///
+/// The primitive type is f32 by default and f64 when the crate is built with the `f64` feature.
+#[cfg(feature = "f64")]
+pub type PrimitiveType = f64;
+
 /// Type alias for ArrayFire's Array.
 pub type Tensor = Array<PrimitiveType>;
 
+/// Type alias for ArrayFire's boolean arrays, used as masks.
+pub type BoolTensor = Array<bool>;
+
+/// Type alias for ArrayFire's arrays of indices, as returned by the sorts.
+pub type IndexTensor = Array<u32>;
+
 /// Type alias for ArrayFire's Dim4.
 pub type Dim = Dim4;
 
+/// Type alias for ArrayFire's complex arrays.
+pub type ComplexTensor = Array<Complex<PrimitiveType>>;
+
 /// Defines reduction methods.
 pub enum Reduction {
     SumBatches,
@@ -22,6 +47,12 @@ pub enum Reduction {
 
 const BATCH_AXIS: usize = 3;
 
+/// Identifies the serialized tensors.
+const TENSOR_MAGIC: &[u8; 4] = b"NRTN";
+
+/// Size of the header of the serialized tensors: the identifier, the type code, the padding, and the dimensions.
+const TENSOR_HEADER_SIZE: usize = 40;
+
 /// Defines additional methods for the Tensor type.
 pub trait TensorTrait {
     /// Creates a tensor of ones with the given dimensions.
@@ -39,8 +70,22 @@ pub trait TensorTrait {
     /// Shuffles two vectors with identical indices permutation along the last dimension.
     fn shuffle(tensor1: &Tensor, tensor2: &Tensor) -> (Tensor, Tensor);
 
-    /// Shuffles two vectors with identical indices permutation along the last dimension inplace.
-    fn shuffle_mut(tensor1: &mut Tensor, tensor2: &mut Tensor);
+    /// Shuffles any number of tensors with identical indices permutation along the last dimension inplace.
+    ///
+    /// The permutation is drawn from the given random number generator, such that a seeded generator makes the
+    /// shuffling reproducible.
+    ///
+    /// # Return value
+    ///
+    /// The permutation: the i-th sample of the shuffled tensors is the sample `permutation[i]` of the original ones.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tensors don't have the same batch size.
+    fn shuffle_mut<R: Rng + ?Sized>(tensors: &mut [&mut Tensor], rng: &mut R) -> Vec<u64>;
+
+    /// Shuffles three vectors with identical indices permutation along the last dimension.
+    fn shuffle_weighted(tensor1: &Tensor, tensor2: &Tensor, tensor3: &Tensor) -> (Tensor, Tensor, Tensor);
 
     /// Creates a tensor with the given dimensions where each entry is drawn from a uniform distribution.
     fn scaled_uniform(lower_bound: PrimitiveType, upper_bound: PrimitiveType, dims: Dim4) -> Tensor;
@@ -48,6 +93,29 @@ pub trait TensorTrait {
     /// Creates a tensor with the given dimensions where each entry is drawn from a normal distribution.
     fn scaled_normal(mean: PrimitiveType, standard_deviation: PrimitiveType, dims: Dim4) -> Tensor;
 
+    /// Creates a tensor whose entries are drawn from a normal distribution with the given random engine.
+    ///
+    /// Unlike [scaled_normal](#tymethod.scaled_normal), the values only depend on the state of the engine, such that
+    /// an engine created by [random_engine](fn.random_engine.html) with a fixed seed gives reproducible tensors.
+    fn randn(mean: PrimitiveType, standard_deviation: PrimitiveType, dims: Dim4, engine: &RandomEngine) -> Tensor;
+
+    /// Creates a tensor whose entries are drawn uniformly within [lower_bound, upper_bound) with the given random engine.
+    fn rand_uniform(lower_bound: PrimitiveType, upper_bound: PrimitiveType, dims: Dim4, engine: &RandomEngine) -> Tensor;
+
+    /// Creates a tensor of ones with probability `p` and zeros otherwise with the given random engine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `p` is not between 0 and 1.
+    fn bernoulli(p: PrimitiveType, dims: Dim4, engine: &RandomEngine) -> Tensor;
+
+    /// Creates a tensor of integers drawn uniformly within [low, high) with the given random engine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `low` is not less than `high`.
+    fn randint(low: i64, high: i64, dims: Dim4, engine: &RandomEngine) -> Tensor;
+
     /// Reduces the tensor.
     fn reduce(&self, reduction: Reduction) -> Tensor;
 
@@ -70,6 +138,142 @@ pub trait TensorTrait {
     fn reshape_mut(&mut self, dims: Dim4);
 
     fn print_tensor(&self);
+
+    /// Evaluates the tensor and blocks until the device has completed all pending operations.
+    ///
+    /// ArrayFire evaluates the expressions lazily. This method forces the evaluation, for instance to measure the
+    /// time spent in a computation.
+    fn sync(&self);
+
+    /// Concatenates tensors along a dimension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no tensor is given, if the dimension is greater than 3, or if the tensors differ along
+    /// another dimension.
+    fn concat(tensors: &[&Tensor], dim: usize) -> Result<Tensor, Error>;
+
+    /// Stacks tensors of the same shape along a new dimension.
+    ///
+    /// The dimensions from `dim` onwards are shifted by one, hence the fourth dimension of the tensors must be one. For
+    /// instance, stacking tensors with dimensions [h, w, c, 1] along the fourth dimension gives a batch with
+    /// dimensions [h, w, c, num_tensors].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no tensor is given, if the dimension is greater than 3, if the shapes of the tensors differ,
+    /// or if their fourth dimension is not one.
+    fn stack(tensors: &[&Tensor], dim: usize) -> Result<Tensor, Error>;
+
+    /// Repeats the tensor along each dimension.
+    ///
+    /// The dimensions without a number of repetitions are not repeated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if more than four numbers of repetitions are given.
+    fn tile(&self, repetitions: &[u64]) -> Result<Tensor, Error>;
+
+    /// Pads the tensor with a constant value.
+    ///
+    /// Each width gives the number of values added before and after the tensor along a dimension. The dimensions
+    /// without a width are not padded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if more than four widths are given.
+    fn pad(&self, widths: &[(u64, u64)], value: PrimitiveType) -> Result<Tensor, Error>;
+
+    /// Selects the values of `a` where the condition holds and the values of `b` elsewhere.
+    ///
+    /// The dimensions of size one are broadcast to the dimensions of the other tensors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the shapes of the tensors can't be broadcast to a common shape.
+    fn select_where(condition: &BoolTensor, a: &Tensor, b: &Tensor) -> Result<Tensor, Error>;
+
+    /// Replaces the values where the mask holds with the given value.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the mask can't be broadcast to the shape of the tensor.
+    fn assign_where(&mut self, mask: &BoolTensor, value: PrimitiveType) -> Result<(), Error>;
+
+    /// Returns the mask of the values greater than the values of `other`.
+    ///
+    /// The comparisons broadcast the dimensions of size one, such that a tensor can be compared with a single value.
+    /// They return an error if the shapes of the tensors can't be broadcast to a common shape.
+    fn greater(&self, other: &Tensor) -> Result<BoolTensor, Error>;
+
+    /// Returns the mask of the values greater than or equal to the values of `other`.
+    fn greater_equal(&self, other: &Tensor) -> Result<BoolTensor, Error>;
+
+    /// Returns the mask of the values less than the values of `other`.
+    fn less(&self, other: &Tensor) -> Result<BoolTensor, Error>;
+
+    /// Returns the mask of the values less than or equal to the values of `other`.
+    fn less_equal(&self, other: &Tensor) -> Result<BoolTensor, Error>;
+
+    /// Returns the mask of the values equal to the values of `other`.
+    fn equal(&self, other: &Tensor) -> Result<BoolTensor, Error>;
+
+    /// Returns the mask of the values different from the values of `other`.
+    fn not_equal(&self, other: &Tensor) -> Result<BoolTensor, Error>;
+
+    /// Sorts the values along a dimension.
+    ///
+    /// # Return value
+    ///
+    /// The sorted values and, for each of them, its index along the dimension in the original tensor.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dimension is greater than 3.
+    fn argsort(&self, dim: usize, ascending: bool) -> Result<(Tensor, IndexTensor), Error>;
+
+    /// Returns the `k` largest values along a dimension, in descending order, and their indices along the dimension.
+    ///
+    /// For instance, the top 5 predicted classes of a batch of outputs with dimensions [num_classes, 1, 1, batch_size]
+    /// are given by `y_pred.topk(5, 0)`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the dimension is greater than 3, or if `k` is zero or greater than the size of the dimension.
+    fn topk(&self, k: u64, dim: usize) -> Result<(Tensor, IndexTensor), Error>;
+
+    /// Creates the one-hot encoding of class indices.
+    ///
+    /// The indices have dimensions [1, 1, 1, batch_size] and the encoded labels [num_classes, 1, 1, batch_size].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the indices don't have the expected dimensions or if an index is not an integer between 0
+    /// and `num_classes - 1`.
+    fn one_hot(indices: &Tensor, num_classes: u64) -> Result<Tensor, Error>;
+
+    /// Converts one-hot encoded labels or predicted class scores into class indices.
+    ///
+    /// The class with the largest value along the first dimension is selected for each sample, such that a tensor with
+    /// dimensions [num_classes, 1, 1, batch_size] is converted into the indices with dimensions [1, 1, 1, batch_size],
+    /// in the format expected by [one_hot](#tymethod.one_hot).
+    fn argmax_to_labels(&self) -> Tensor;
+
+    /// Serializes the tensor into bytes.
+    ///
+    /// The bytes contain a header with the identifier `NRTN`, the type of the values (0 for f32, 1 for f64), three
+    /// bytes of padding, and the four dimensions as little-endian u64, followed by the values as little-endian floats
+    /// in row-major order, the last dimension varying the fastest.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Deserializes a tensor from the bytes created by [to_bytes](#tymethod.to_bytes).
+    ///
+    /// The values are converted to the `PrimitiveType` of the crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header is invalid or if the number of values doesn't match the dimensions.
+    fn from_bytes(bytes: &[u8]) -> Result<Tensor, Error>;
 }
 
 impl TensorTrait for Tensor {
@@ -90,44 +294,68 @@ impl TensorTrait for Tensor {
     }
 
     fn shuffle(x: &Tensor, y: &Tensor) -> (Tensor, Tensor) {
-        assert_eq!(x.batch_size(), y.batch_size());
-
-        // Shuffle indices
-        let mut indices: Vec<u64> = (0..x.batch_size()).collect();
-        indices.shuffle(&mut thread_rng());
-
-        let indices_arr = Array::new(&indices[..], Dim4::new(&[x.batch_size(), 1, 1, 1]));
-
-        let x_shuffled = lookup(x, &indices_arr, BATCH_AXIS as i32);
-        let y_shuffled = lookup(y, &indices_arr, BATCH_AXIS as i32);
+        let (mut x_shuffled, mut y_shuffled) = (x.clone(), y.clone());
+        Tensor::shuffle_mut(&mut [&mut x_shuffled, &mut y_shuffled], &mut thread_rng());
         (x_shuffled, y_shuffled)
     }
 
-    fn shuffle_mut(x: &mut Tensor, y: &mut Tensor) {
-        assert_eq!(x.batch_size(), y.batch_size());
+    fn shuffle_mut<R: Rng + ?Sized>(tensors: &mut [&mut Tensor], rng: &mut R) -> Vec<u64> {
+        let batch_size = match tensors.first() {
+            Some(tensor) => tensor.batch_size(),
+            None => return Vec::new(),
+        };
+        assert!(tensors.iter().all(|tensor| tensor.batch_size() == batch_size), "The tensors must have the same batch size.");
 
         // Shuffle indices
-        let mut indices: Vec<u64> = (0..x.batch_size()).collect();
-        indices.shuffle(&mut thread_rng());
-        let indices_arr = Array::new(&indices[..], Dim4::new(&[x.batch_size(), 1, 1, 1]));
+        let mut indices: Vec<u64> = (0..batch_size).collect();
+        indices.shuffle(rng);
+        let indices_arr = Array::new(&indices[..], Dim4::new(&[batch_size, 1, 1, 1]));
+
+        for tensor in tensors.iter_mut() {
+            **tensor = lookup(&**tensor, &indices_arr, BATCH_AXIS as i32);
+        }
+        indices
+    }
 
-        *x = lookup(x, &indices_arr, BATCH_AXIS as i32);
-        *y = lookup(y, &indices_arr, BATCH_AXIS as i32);
+    fn shuffle_weighted(x: &Tensor, y: &Tensor, weights: &Tensor) -> (Tensor, Tensor, Tensor) {
+        let (mut x_shuffled, mut y_shuffled, mut weights_shuffled) = (x.clone(), y.clone(), weights.clone());
+        Tensor::shuffle_mut(&mut [&mut x_shuffled, &mut y_shuffled, &mut weights_shuffled], &mut thread_rng());
+        (x_shuffled, y_shuffled, weights_shuffled)
     }
 
     fn scaled_uniform(lower_bound: PrimitiveType, upper_bound: PrimitiveType, dims: Dim4) -> Tensor {
-        constant(lower_bound, dims) + constant(upper_bound - lower_bound, dims) * randu::<PrimitiveType>(dims)
+        DefaultBackend::random_uniform(lower_bound, upper_bound, *dims.get())
     }
 
     fn scaled_normal(mean: PrimitiveType, standard_deviation: PrimitiveType, dims: Dim4) -> Tensor {
-        constant(standard_deviation, dims) * randn::<PrimitiveType>(dims) + constant(mean, dims)
+        DefaultBackend::random_normal(mean, standard_deviation, *dims.get())
+    }
+
+    fn randn(mean: PrimitiveType, standard_deviation: PrimitiveType, dims: Dim4, engine: &RandomEngine) -> Tensor {
+        random_normal::<PrimitiveType>(dims, engine) * standard_deviation + mean
+    }
+
+    fn rand_uniform(lower_bound: PrimitiveType, upper_bound: PrimitiveType, dims: Dim4, engine: &RandomEngine) -> Tensor {
+        random_uniform::<PrimitiveType>(dims, engine) * (upper_bound - lower_bound) + lower_bound
+    }
+
+    fn bernoulli(p: PrimitiveType, dims: Dim4, engine: &RandomEngine) -> Tensor {
+        assert!((0. ..=1.).contains(&p), "The probability must be between 0 and 1.");
+        lt(&random_uniform::<PrimitiveType>(dims, engine), &p, true).cast::<PrimitiveType>()
+    }
+
+    fn randint(low: i64, high: i64, dims: Dim4, engine: &RandomEngine) -> Tensor {
+        assert!(low < high, "The lower bound must be less than the upper bound.");
+        let values = floor(&(random_uniform::<PrimitiveType>(dims, engine) * (high - low) as PrimitiveType)) + low as PrimitiveType;
+        // Rounding errors could give the upper bound for the largest uniform values
+        clamp(&values, &(low as PrimitiveType), &((high - 1) as PrimitiveType), true)
     }
 
     fn reduce(&self, reduction: Reduction) -> Tensor
     {
         match reduction {
-            Reduction::SumBatches => { sum(self, BATCH_AXIS as i32)},
-            Reduction::MeanBatches => { mean(self, BATCH_AXIS as i64)},
+            Reduction::SumBatches => { DefaultBackend::sum(self, BATCH_AXIS) },
+            Reduction::MeanBatches => { DefaultBackend::mean(self, BATCH_AXIS) },
         }
     }
 
@@ -158,6 +386,520 @@ impl TensorTrait for Tensor {
     fn print_tensor(&self) {
         print(self);
     }
+
+    fn sync(&self) {
+        self.eval();
+        sync(get_device());
+    }
+
+    fn concat(tensors: &[&Tensor], dim: usize) -> Result<Tensor, Error> {
+        if dim > 3 { return Err(Error::InvalidAxis(dim)); }
+        let first = tensors.first().ok_or(Error::NoTensor)?;
+        for (i, tensor) in tensors.iter().enumerate().skip(1) {
+            if (0..4).any(|d| d != dim && tensor.dims()[d] != first.dims()[d]) {
+                return Err(Error::TensorShapeMismatch(format!(
+                    "the tensor {} has dimensions {:?} while the first one has dimensions {:?}, they can only differ along the dimension {}",
+                    i, tensor.dims().get(), first.dims().get(), dim)));
+            }
+        }
+
+        // ArrayFire joins at most 10 tensors at once
+        let mut joined: Vec<Tensor> = tensors.iter().map(|&tensor| tensor.clone()).collect();
+        while joined.len() > 1 {
+            joined = joined.chunks(10).map(|chunk| match chunk.len() {
+                1 => chunk[0].clone(),
+                _ => join_many(dim as i32, chunk.iter().collect()),
+            }).collect();
+        }
+        Ok(joined.remove(0))
+    }
+
+    fn stack(tensors: &[&Tensor], dim: usize) -> Result<Tensor, Error> {
+        if dim > 3 { return Err(Error::InvalidAxis(dim)); }
+        let first = tensors.first().ok_or(Error::NoTensor)?;
+        let dims = *first.dims().get();
+        if dims[3] != 1 {
+            return Err(Error::TensorShapeMismatch(format!("the fourth dimension of the tensors must be one to stack them, got dimensions {:?}", dims)));
+        }
+        if let Some(i) = tensors.iter().position(|tensor| tensor.dims() != first.dims()) {
+            return Err(Error::TensorShapeMismatch(format!(
+                "the tensor {} has dimensions {:?} while the first one has dimensions {:?}", i, tensors[i].dims().get(), dims)));
+        }
+
+        let mut expanded_dims = [1; 4];
+        expanded_dims[..dim].copy_from_slice(&dims[..dim]);
+        expanded_dims[dim + 1..].copy_from_slice(&dims[dim..3]);
+        let expanded: Vec<Tensor> = tensors.iter().map(|tensor| moddims(tensor, Dim4::new(&expanded_dims))).collect();
+        Self::concat(&expanded.iter().collect::<Vec<&Tensor>>(), dim)
+    }
+
+    fn tile(&self, repetitions: &[u64]) -> Result<Tensor, Error> {
+        if repetitions.len() > 4 { return Err(Error::InvalidAxis(repetitions.len() - 1)); }
+        let mut dims = [1; 4];
+        dims[..repetitions.len()].copy_from_slice(repetitions);
+        Ok(tile(self, Dim4::new(&dims)))
+    }
+
+    fn pad(&self, widths: &[(u64, u64)], value: PrimitiveType) -> Result<Tensor, Error> {
+        if widths.len() > 4 { return Err(Error::InvalidAxis(widths.len() - 1)); }
+        let dims = *self.dims().get();
+        let mut padded_dims = dims;
+        for (padded_dim, &(before, after)) in padded_dims.iter_mut().zip(widths.iter()) {
+            *padded_dim += before + after;
+        }
+        if padded_dims == dims {
+            return Ok(self.clone());
+        }
+
+        let mut padded = constant(value, Dim4::new(&padded_dims));
+        if self.elements() > 0 {
+            let mut seqs = [Seq::<f64>::default(); 4];
+            for (d, &(before, _)) in widths.iter().enumerate() {
+                seqs[d] = Seq::new(before as f64, (before + dims[d] - 1) as f64, 1.);
+            }
+            assign_seq(&mut padded, &seqs, self);
+        }
+        Ok(padded)
+    }
+
+    fn select_where(condition: &BoolTensor, a: &Tensor, b: &Tensor) -> Result<Tensor, Error> {
+        let dims = broadcast_dims(&[condition.dims(), a.dims(), b.dims()])?;
+        Ok(select(&broadcast_to(a, dims), &broadcast_to(condition, dims), &broadcast_to(b, dims)))
+    }
+
+    fn assign_where(&mut self, mask: &BoolTensor, value: PrimitiveType) -> Result<(), Error> {
+        let dims = broadcast_dims(&[mask.dims(), self.dims()])?;
+        if dims != *self.dims().get() {
+            return Err(Error::TensorShapeMismatch(format!("the mask with dimensions {:?} is larger than the tensor with dimensions {:?}", mask.dims().get(), self.dims().get())));
+        }
+        *self = selectl(value as f64, &broadcast_to(mask, dims), self);
+        Ok(())
+    }
+
+    fn greater(&self, other: &Tensor) -> Result<BoolTensor, Error> {
+        broadcast_dims(&[self.dims(), other.dims()])?;
+        Ok(gt(self, other, true))
+    }
+
+    fn greater_equal(&self, other: &Tensor) -> Result<BoolTensor, Error> {
+        broadcast_dims(&[self.dims(), other.dims()])?;
+        Ok(ge(self, other, true))
+    }
+
+    fn less(&self, other: &Tensor) -> Result<BoolTensor, Error> {
+        broadcast_dims(&[self.dims(), other.dims()])?;
+        Ok(lt(self, other, true))
+    }
+
+    fn less_equal(&self, other: &Tensor) -> Result<BoolTensor, Error> {
+        broadcast_dims(&[self.dims(), other.dims()])?;
+        Ok(le(self, other, true))
+    }
+
+    fn equal(&self, other: &Tensor) -> Result<BoolTensor, Error> {
+        broadcast_dims(&[self.dims(), other.dims()])?;
+        Ok(eq(self, other, true))
+    }
+
+    fn not_equal(&self, other: &Tensor) -> Result<BoolTensor, Error> {
+        broadcast_dims(&[self.dims(), other.dims()])?;
+        // Unlike the other comparisons, `neq` is typed as an arithmetic operation although it computes booleans
+        Ok(neq(self, other, true).cast())
+    }
+
+    fn argsort(&self, dim: usize, ascending: bool) -> Result<(Tensor, IndexTensor), Error> {
+        if dim > 3 { return Err(Error::InvalidAxis(dim)); }
+        Ok(sort_index(self, dim as u32, ascending))
+    }
+
+    fn topk(&self, k: u64, dim: usize) -> Result<(Tensor, IndexTensor), Error> {
+        if dim > 3 { return Err(Error::InvalidAxis(dim)); }
+        let size = self.dims()[dim];
+        if k == 0 || k > size {
+            return Err(Error::TensorShapeMismatch(format!("k must be between 1 and {}, the size of the dimension {}, but is {}", size, dim, k)));
+        }
+        let (values, indices) = sort_index(self, dim as u32, false);
+
+        let mut seqs = [Seq::<f64>::default(); 4];
+        seqs[dim] = Seq::new(0., (k - 1) as f64, 1.);
+        Ok((index(&values, &seqs), index(&indices, &seqs)))
+    }
+
+    fn one_hot(indices: &Tensor, num_classes: u64) -> Result<Tensor, Error> {
+        let dims = indices.dims();
+        if dims[0] != 1 || dims[1] != 1 || dims[2] != 1 {
+            return Err(Error::TensorShapeMismatch(format!("the indices must have dimensions [1, 1, 1, batch_size], got {:?}", dims.get())));
+        }
+        let num_invalid = count_all(&or(&or(&lt(indices, &0., true), &ge(indices, &(num_classes as f64), true), false), &neq(indices, &floor(indices), false), false)).0;
+        if num_invalid > 0 {
+            return Err(Error::InvalidLabels(format!("{} indices are not integers between 0 and {}", num_invalid, num_classes as i64 - 1)));
+        }
+
+        let classes = range::<PrimitiveType>(Dim4::new(&[num_classes, 1, 1, dims[3]]), 0);
+        Ok(eq(&classes, indices, true).cast::<PrimitiveType>())
+    }
+
+    fn argmax_to_labels(&self) -> Tensor {
+        imax(self, 0).1.cast::<PrimitiveType>()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let dims = *self.dims().get();
+        let mut values = vec![0 as PrimitiveType; self.elements()];
+        // The reversed dimensions in column-major order are the dimensions in row-major order
+        reorder_v2(self, 3, 2, Some(vec![1, 0])).host(&mut values);
+
+        let element_size = std::mem::size_of::<PrimitiveType>();
+        let mut bytes = Vec::with_capacity(TENSOR_HEADER_SIZE + values.len() * element_size);
+        bytes.extend_from_slice(TENSOR_MAGIC);
+        bytes.push(if element_size == 4 { 0 } else { 1 });
+        bytes.extend_from_slice(&[0; 3]);
+        for dim in &dims {
+            bytes.extend_from_slice(&dim.to_le_bytes());
+        }
+        for value in &values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Tensor, Error> {
+        if bytes.len() < TENSOR_HEADER_SIZE || &bytes[..4] != TENSOR_MAGIC {
+            return Err(Error::InvalidTensorBytes(String::from("the header is missing")));
+        }
+        let element_size = match bytes[4] {
+            0 => 4,
+            1 => 8,
+            code => return Err(Error::InvalidTensorBytes(format!("the type code {} is unknown", code))),
+        };
+        let mut dims = [0u64; 4];
+        for (d, dim) in dims.iter_mut().enumerate() {
+            *dim = u64::from_le_bytes(bytes[8 + 8 * d..16 + 8 * d].try_into().unwrap());
+        }
+
+        let data = &bytes[TENSOR_HEADER_SIZE..];
+        let num_elements = dims.iter().try_fold(1u64, |acc, &dim| acc.checked_mul(dim));
+        if num_elements.and_then(|n| n.checked_mul(element_size as u64)) != Some(data.len() as u64) {
+            return Err(Error::InvalidTensorBytes(format!("{} bytes of values don't match the dimensions {:?}", data.len(), dims)));
+        }
+        let values: Vec<PrimitiveType> = match element_size {
+            4 => data.chunks_exact(4).map(|value| f32::from_le_bytes(value.try_into().unwrap()) as PrimitiveType).collect(),
+            _ => data.chunks_exact(8).map(|value| f64::from_le_bytes(value.try_into().unwrap()) as PrimitiveType).collect(),
+        };
+        let reversed = Tensor::new(&values[..], Dim4::new(&[dims[3], dims[2], dims[1], dims[0]]));
+        Ok(reorder_v2(&reversed, 3, 2, Some(vec![1, 0])))
+    }
+}
+
+/// Computes the shape to which the given shapes are broadcast.
+///
+/// Along each dimension, the sizes must be equal or one.
+fn broadcast_dims(shapes: &[Dim4]) -> Result<[u64; 4], Error> {
+    let mut dims = [1; 4];
+    for shape in shapes {
+        let mut merged = dims;
+        for (d, dim) in merged.iter_mut().enumerate() {
+            match (*dim, shape[d]) {
+                (current, size) if current == size => {},
+                (1, size) => *dim = size,
+                (_, 1) => {},
+                _ => return Err(Error::TensorShapeMismatch(format!("the dimensions {:?} can't be broadcast to {:?}", shape.get(), dims))),
+            }
+        }
+        dims = merged;
+    }
+    Ok(dims)
+}
+
+/// Repeats the dimensions of size one of an array to reach the given dimensions.
+fn broadcast_to<T: HasAfEnum>(array: &Array<T>, dims: [u64; 4]) -> Array<T> {
+    let array_dims = array.dims();
+    if *array_dims.get() == dims {
+        return array.clone();
+    }
+    let repetitions: Vec<u64> = (0..4).map(|d| dims[d] / array_dims[d]).collect();
+    tile(array, Dim4::new(&[repetitions[0], repetitions[1], repetitions[2], repetitions[3]]))
+}
+
+/// Sparse matrix storing samples with many features, most of them being zero.
+///
+/// The samples are stored in the rows of a matrix in compressed sparse row (CSR) format, which avoids densifying
+/// high-dimensional inputs such as bags of words or one-hot encoded features. Sparse inputs are accepted by the
+/// [Dense](../layers/struct.Dense.html) layers when they are the first layer of a network.
+#[derive(Clone)]
+pub struct SparseTensor {
+    row_offsets: Vec<i32>,
+    col_indices: Vec<i32>,
+    values: Vec<PrimitiveType>,
+    num_features: u64,
+    csr: Tensor,
+}
+
+impl SparseTensor {
+    /// Creates a sparse tensor from its CSR representation.
+    ///
+    /// # Arguments
+    ///
+    /// * `row_offsets` - The offsets of the rows in `col_indices` and `values`. It contains num_samples + 1 entries.
+    /// * `col_indices` - The index of the feature of each non-zero value.
+    /// * `values` - The non-zero values.
+    /// * `num_features` - The number of features of the samples.
+    pub fn from_csr(row_offsets: Vec<i32>, col_indices: Vec<i32>, values: Vec<PrimitiveType>, num_features: u64) -> SparseTensor {
+        assert!(!row_offsets.is_empty(), "The row offsets must contain at least one entry.");
+        assert_eq!(col_indices.len(), values.len(), "There must be one column index per value.");
+        assert_eq!(*row_offsets.last().unwrap() as usize, values.len(), "The last row offset must be the number of values.");
+
+        let num_samples = (row_offsets.len() - 1) as u64;
+        let csr = sparse(num_samples,
+                         num_features,
+                         &Tensor::new(&values[..], Dim4::new(&[values.len() as u64, 1, 1, 1])),
+                         &Array::new(&row_offsets[..], Dim4::new(&[row_offsets.len() as u64, 1, 1, 1])),
+                         &Array::new(&col_indices[..], Dim4::new(&[col_indices.len() as u64, 1, 1, 1])),
+                         SparseFormat::CSR);
+        SparseTensor {
+            row_offsets,
+            col_indices,
+            values,
+            num_features,
+            csr,
+        }
+    }
+
+    /// Creates a sparse tensor from a dense tensor with the samples stacked along the fourth dimension.
+    pub fn from_dense(tensor: &Tensor) -> SparseTensor {
+        let num_features = tensor.dims().get()[0] * tensor.dims().get()[1] * tensor.dims().get()[2];
+        let mut buffer = vec![0 as PrimitiveType; tensor.elements()];
+        tensor.host(&mut buffer);
+
+        let mut row_offsets = vec![0];
+        let mut col_indices = Vec::new();
+        let mut values = Vec::new();
+        for sample in buffer.chunks(num_features as usize) {
+            for (feature, &value) in sample.iter().enumerate() {
+                if value != 0. {
+                    col_indices.push(feature as i32);
+                    values.push(value);
+                }
+            }
+            row_offsets.push(values.len() as i32);
+        }
+        SparseTensor::from_csr(row_offsets, col_indices, values, num_features)
+    }
+
+    /// Converts the sparse tensor into a dense tensor with dimensions [num_features, 1, 1, num_samples].
+    pub fn to_dense(&self) -> Tensor {
+        let mut buffer = vec![0 as PrimitiveType; (self.num_features * self.batch_size()) as usize];
+        for (sample, bounds) in self.row_offsets.windows(2).enumerate() {
+            for k in bounds[0] as usize..bounds[1] as usize {
+                buffer[sample * self.num_features as usize + self.col_indices[k] as usize] = self.values[k];
+            }
+        }
+        Tensor::new(&buffer[..], Dim4::new(&[self.num_features, 1, 1, self.batch_size()]))
+    }
+
+    /// Returns the number of samples.
+    pub fn batch_size(&self) -> u64 {
+        (self.row_offsets.len() - 1) as u64
+    }
+
+    /// Returns the number of features of the samples.
+    pub fn num_features(&self) -> u64 {
+        self.num_features
+    }
+
+    /// Returns the number of non-zero values.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns the samples with the given indices.
+    pub fn rows(&self, indices: &[u64]) -> SparseTensor {
+        let mut row_offsets = Vec::with_capacity(indices.len() + 1);
+        row_offsets.push(0);
+        let mut col_indices = Vec::new();
+        let mut values = Vec::new();
+        for &i in indices {
+            let (start, end) = (self.row_offsets[i as usize] as usize, self.row_offsets[i as usize + 1] as usize);
+            col_indices.extend_from_slice(&self.col_indices[start..end]);
+            values.extend_from_slice(&self.values[start..end]);
+            row_offsets.push(values.len() as i32);
+        }
+        SparseTensor::from_csr(row_offsets, col_indices, values, self.num_features)
+    }
+
+    /// Returns the ArrayFire sparse array with dimensions [num_samples, num_features].
+    pub(crate) fn csr(&self) -> &Tensor {
+        &self.csr
+    }
+}
+
+
+/// Creates a random engine with the given seed.
+///
+/// The engine is passed to the random constructors of the tensors, such as [randn](trait.TensorTrait.html#tymethod.randn),
+/// to draw reproducible values independently of the global random state of ArrayFire.
+pub fn random_engine(seed: u64) -> RandomEngine {
+    RandomEngine::new(RandomEngineType::PHILOX_4X32_10, Some(seed))
+}
+
+
+/// Evaluates several tensors at once.
+///
+/// The element-wise operations shared by the tensors are fused by the ArrayFire JIT compiler and launched in as few
+/// kernels as possible.
+pub fn eval_tensors(tensors: &[&Tensor]) {
+    eval_multiple(tensors.to_vec());
+}
+
+
+/// Computes the matrix products of two batches of matrices.
+///
+/// The matrices are stored in the first two dimensions and the batches along the third and fourth dimensions. A batch
+/// dimension of size one is broadcast to the size of the other tensor, such that a single matrix can be multiplied
+/// with a batch of matrices.
+///
+/// # Panics
+///
+/// Panics if the inner dimensions of the matrices don't match or if the batch dimensions are neither equal nor one.
+pub fn batched_matmul(lhs: &Tensor, rhs: &Tensor, transpose_lhs: bool, transpose_rhs: bool) -> Tensor {
+    let (lhs_dims, rhs_dims) = (lhs.dims(), rhs.dims());
+    let lhs_inner = if transpose_lhs { lhs_dims[0] } else { lhs_dims[1] };
+    let rhs_inner = if transpose_rhs { rhs_dims[1] } else { rhs_dims[0] };
+    assert_eq!(lhs_inner, rhs_inner, "The inner dimensions of the matrices don't match: {} and {}.", lhs_inner, rhs_inner);
+
+    let mut lhs_tiles = [1, 1, 1, 1];
+    let mut rhs_tiles = [1, 1, 1, 1];
+    for dim in 2..4 {
+        match (lhs_dims[dim], rhs_dims[dim]) {
+            (l, r) if l == r => {},
+            (1, r) => lhs_tiles[dim] = r,
+            (l, 1) => rhs_tiles[dim] = l,
+            (l, r) => panic!("The batch dimensions {} and {} along axis {} can't be broadcast.", l, r, dim),
+        }
+    }
+    let lhs = if lhs_tiles == [1, 1, 1, 1] { lhs.clone() } else { tile(lhs, Dim4::new(&lhs_tiles)) };
+    let rhs = if rhs_tiles == [1, 1, 1, 1] { rhs.clone() } else { tile(rhs, Dim4::new(&rhs_tiles)) };
+    DefaultBackend::matmul(&lhs, &rhs, transpose_lhs, transpose_rhs)
+}
+
+/// Computes a sum of products of two tensors described with the Einstein notation.
+///
+/// Each letter of the equation names an axis of a tensor, in the order of the dimensions. The axes of both inputs
+/// absent from the output are contracted, the axes shared by the inputs and the output are batch axes, and the axes of
+/// a single input absent from the output are summed. For instance, `"ij,jk->ik"` is the matrix product,
+/// `"ijb,jkb->ikb"` the batched matrix product and `"qhdb,khdb->qkhb"` the attention scores of several heads. The
+/// product is computed by a single batched matrix multiplication, hence the tensors can have more axes in total than
+/// the four dimensions of a tensor.
+///
+/// # Panics
+///
+/// Panics if the equation is malformed, if an operand has more than four axes or non-singleton dimensions without a
+/// letter, or if the sizes of an axis differ between the operands.
+pub fn einsum(equation: &str, lhs: &Tensor, rhs: &Tensor) -> Tensor {
+    let equation: String = equation.chars().filter(|c| !c.is_whitespace()).collect();
+    let (inputs, output) = match equation.find("->") {
+        Some(position) => (&equation[..position], &equation[position + 2..]),
+        None => panic!("The equation must contain an output, such as \"ij,jk->ik\"."),
+    };
+    let operands: Vec<&str> = inputs.split(',').collect();
+    assert_eq!(operands.len(), 2, "The equation must have two operands.");
+    let lhs_axes = einsum_axes(operands[0], Some(lhs.dims()), "first operand");
+    let rhs_axes = einsum_axes(operands[1], Some(rhs.dims()), "second operand");
+    let out_axes = einsum_axes(output, None, "output");
+    for axis in &out_axes {
+        assert!(lhs_axes.contains(axis) || rhs_axes.contains(axis), "The output axis {} doesn't appear in the operands.", axis);
+    }
+
+    let size = |axis: char| -> u64 {
+        let lhs_size = lhs_axes.iter().position(|&a| a == axis).map(|p| lhs.dims()[p]);
+        let rhs_size = rhs_axes.iter().position(|&a| a == axis).map(|p| rhs.dims()[p]);
+        match (lhs_size, rhs_size) {
+            (Some(l), Some(r)) if l != r => panic!("The axis {} has size {} in the first operand and {} in the second one.", axis, l, r),
+            (Some(size), _) | (_, Some(size)) => size,
+            (None, None) => unreachable!(),
+        }
+    };
+
+    let batch: Vec<char> = lhs_axes.iter().cloned().filter(|a| rhs_axes.contains(a) && out_axes.contains(a)).collect();
+    let contracted: Vec<char> = lhs_axes.iter().cloned().filter(|a| rhs_axes.contains(a) && !out_axes.contains(a)).collect();
+    let lhs_free: Vec<char> = lhs_axes.iter().cloned().filter(|a| !rhs_axes.contains(a) && out_axes.contains(a)).collect();
+    let rhs_free: Vec<char> = rhs_axes.iter().cloned().filter(|a| !lhs_axes.contains(a) && out_axes.contains(a)).collect();
+    let lhs_summed: Vec<char> = lhs_axes.iter().cloned().filter(|a| !rhs_axes.contains(a) && !out_axes.contains(a)).collect();
+    let rhs_summed: Vec<char> = rhs_axes.iter().cloned().filter(|a| !lhs_axes.contains(a) && !out_axes.contains(a)).collect();
+    let product = |axes: &[char]| axes.iter().map(|&a| size(a)).product::<u64>();
+
+    // The operands are rearranged into batches of matrices, the summed axes being reduced to singletons first
+    let mut lhs = lhs.clone();
+    for axis in &lhs_summed {
+        lhs = sum(&lhs, lhs_axes.iter().position(|a| a == axis).unwrap() as i32);
+    }
+    let mut rhs = rhs.clone();
+    for axis in &rhs_summed {
+        rhs = sum(&rhs, rhs_axes.iter().position(|a| a == axis).unwrap() as i32);
+    }
+    let lhs_order: Vec<char> = [&lhs_free[..], &contracted[..], &batch[..], &lhs_summed[..]].concat();
+    let rhs_order: Vec<char> = [&contracted[..], &rhs_free[..], &batch[..], &rhs_summed[..]].concat();
+    let lhs_matrices = moddims(&permute(&lhs, &lhs_axes, &lhs_order), Dim4::new(&[product(&lhs_free), product(&contracted), product(&batch), 1]));
+    let rhs_matrices = moddims(&permute(&rhs, &rhs_axes, &rhs_order), Dim4::new(&[product(&contracted), product(&rhs_free), product(&batch), 1]));
+    let result = DefaultBackend::matmul(&lhs_matrices, &rhs_matrices, false, false);
+
+    let result_axes: Vec<char> = [&lhs_free[..], &rhs_free[..], &batch[..]].concat();
+    let mut result_dims = [1; 4];
+    for (dim, &axis) in result_dims.iter_mut().zip(result_axes.iter()) {
+        *dim = size(axis);
+    }
+    permute(&moddims(&result, Dim4::new(&result_dims)), &result_axes, &out_axes)
+}
+
+/// Parses the axes of an operand of an equation in Einstein notation.
+fn einsum_axes(axes: &str, dims: Option<Dim4>, operand: &str) -> Vec<char> {
+    let axes: Vec<char> = axes.chars().collect();
+    assert!(axes.len() <= 4, "The {} has more than four axes.", operand);
+    for (i, &axis) in axes.iter().enumerate() {
+        assert!(axis.is_ascii_alphabetic(), "The axis {} of the {} is not a letter.", axis, operand);
+        assert!(!axes[..i].contains(&axis), "The axis {} is repeated in the {}.", axis, operand);
+    }
+    if let Some(dims) = dims {
+        assert!((axes.len()..4).all(|dim| dims[dim] == 1), "The {} has more dimensions than axes.", operand);
+    }
+    axes
+}
+
+/// Reorders the dimensions of a tensor whose axes are named by letters.
+fn permute(tensor: &Tensor, axes: &[char], order: &[char]) -> Tensor {
+    let mut permutation: Vec<u64> = order.iter().map(|axis| axes.iter().position(|a| a == axis).unwrap() as u64).collect();
+    let missing: Vec<u64> = (0..4).filter(|dim| !permutation.contains(dim)).collect();
+    permutation.extend(missing);
+    if permutation == [0, 1, 2, 3] {
+        return tensor.clone();
+    }
+    reorder_v2(tensor, permutation[0], permutation[1], Some(vec![permutation[2], permutation[3]]))
+}
+
+
+/// Packs a complex tensor into a real tensor with the real and imaginary parts along the third dimension.
+///
+/// The complex layers, such as [ComplexDense](../layers/struct.ComplexDense.html), operate on packed tensors.
+///
+/// # Panics
+///
+/// Panics if the third dimension of the complex tensor is not one.
+pub fn pack_complex(tensor: &ComplexTensor) -> Tensor {
+    assert_eq!(tensor.dims()[2], 1, "The third dimension of a complex tensor must be one to be packed.");
+    join(2, &real(tensor), &imag(tensor))
+}
+
+/// Unpacks a real tensor holding the real and imaginary parts along the third dimension into a complex tensor.
+///
+/// # Panics
+///
+/// Panics if the third dimension of the tensor is not two.
+pub fn unpack_complex(tensor: &Tensor) -> ComplexTensor {
+    assert_eq!(tensor.dims()[2], 2, "A packed complex tensor must have two channels.");
+    let real_part = index(tensor, &[Seq::default(), Seq::default(), Seq::new(0., 0., 1.), Seq::default()]);
+    let imaginary_part = index(tensor, &[Seq::default(), Seq::default(), Seq::new(1., 1., 1.), Seq::default()]);
+    let imaginary_unit = constant(Complex::new(0 as PrimitiveType, 1.), imaginary_part.dims());
+    add(&cplx(&real_part), &mul(&cplx(&imaginary_part), &imaginary_unit, false), false)
 }
 
 
@@ -195,6 +937,16 @@ impl From<&Tensor> for H5Tensor {
     }
 }
 
+impl From<&Tensor> for CpuTensor {
+    fn from(tensor: &Tensor) -> Self {
+        let mut buffer = vec![0 as PrimitiveType; tensor.elements()];
+        tensor.host(&mut buffer);
+        let values: Vec<f32> = buffer.iter().map(|&value| value as f32).collect();
+        let dims = tensor.dims();
+        CpuTensor::new(&values[..], [dims[0], dims[1], dims[2], dims[3]])
+    }
+}
+
 impl From<H5Tensor> for Tensor {
     fn from(h5_tensor: H5Tensor) -> Self {
         Tensor::new(h5_tensor.values.as_slice(), Dim::new(&h5_tensor.dims))
@@ -205,4 +957,227 @@ impl From<&H5Tensor> for Tensor {
     fn from(h5_tensor: &H5Tensor) -> Self {
         Tensor::new(h5_tensor.values.as_slice(), Dim::new(&h5_tensor.dims))
     }
-}
\ No newline at end of file
+}
+
+/// Precision in which the parameters of a network are stored in an HDF5 file.
+///
+/// The tensors are always converted to the `PrimitiveType` of the crate when a file is loaded.
+#[derive(hdf5::H5Type, Debug, Copy, Clone, PartialEq)]
+#[repr(u8)]
+pub enum StoragePrecision {
+    /// Values of the `PrimitiveType` of the crate: 32-bit floating-point values, or 64-bit values when the crate is
+    /// built with the `f64` feature.
+    Single = 0,
+    /// 16-bit floating-point values (IEEE 754 binary16), which halves the size of the files.
+    Half = 1,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::tensor::*;
+
+    #[test]
+    fn test_shuffle_mut() {
+        let mut x = Tensor::new(&[0., 1., 2., 3., 4., 5., 6., 7., 8., 9.], Dim4::new(&[2, 1, 1, 5]));
+        let mut y = Tensor::new(&[0., 1., 2., 3., 4.], Dim4::new(&[1, 1, 1, 5]));
+        let mut ids = Tensor::new(&[10., 11., 12., 13., 14.], Dim4::new(&[1, 1, 1, 5]));
+        let permutation = Tensor::shuffle_mut(&mut [&mut x, &mut y, &mut ids], &mut StdRng::seed_from_u64(42));
+
+        let mut sorted = permutation.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, vec![0, 1, 2, 3, 4]);
+
+        // All the tensors are shuffled with the returned permutation
+        let mut x_host = [0 as PrimitiveType; 10];
+        let mut y_host = [0 as PrimitiveType; 5];
+        let mut ids_host = [0 as PrimitiveType; 5];
+        x.host(&mut x_host);
+        y.host(&mut y_host);
+        ids.host(&mut ids_host);
+        for (i, &j) in permutation.iter().enumerate() {
+            assert_eq!(y_host[i], j as PrimitiveType);
+            assert_eq!(ids_host[i], 10. + j as PrimitiveType);
+            assert_eq!(x_host[2 * i], 2. * j as PrimitiveType);
+        }
+
+        // The same seed gives the same permutation
+        let mut z = Tensor::new(&[0., 1., 2., 3., 4.], Dim4::new(&[1, 1, 1, 5]));
+        assert_eq!(Tensor::shuffle_mut(&mut [&mut z], &mut StdRng::seed_from_u64(42)), permutation);
+    }
+
+    #[test]
+    fn test_batched_matmul() {
+        // A single matrix is multiplied with each matrix of the batch
+        let lhs = Tensor::new(&[1., 2., 3., 4.], Dim4::new(&[2, 2, 1, 1]));
+        let rhs = Tensor::new(&[1., 0., 0., 1., 2., 0., 0., 2.], Dim4::new(&[2, 2, 2, 1]));
+        let mut output = [0 as PrimitiveType; 8];
+        batched_matmul(&lhs, &rhs, false, false).host(&mut output);
+        assert_eq!(output, [1., 2., 3., 4., 2., 4., 6., 8.]);
+    }
+
+    #[test]
+    fn test_einsum() {
+        let a = Tensor::new(&[1., 2., 3., 4., 5., 6.], Dim4::new(&[2, 3, 1, 1]));
+        let b = Tensor::new(&[1., 0., 1., 2., 1., 0.], Dim4::new(&[3, 2, 1, 1]));
+
+        let mut expected = [0 as PrimitiveType; 4];
+        let mut output = [0 as PrimitiveType; 4];
+        matmul(&a, &b, MatProp::NONE, MatProp::NONE).host(&mut expected);
+        einsum("ij,jk->ik", &a, &b).host(&mut output);
+        assert_eq!(output, expected);
+
+        // The output can be transposed and the axes of a single operand summed
+        einsum("ij,jk->ki", &a, &b).host(&mut output);
+        assert_eq!(output, [expected[0], expected[2], expected[1], expected[3]]);
+        let mut sums = [0 as PrimitiveType; 2];
+        einsum("ij,kl->i", &a, &Tensor::new(&[1., 1.], Dim4::new(&[1, 2, 1, 1]))).host(&mut sums);
+        assert_eq!(sums, [18., 24.]);
+
+        // Five axes in total, as for the attention scores of several heads
+        let q = randu::<PrimitiveType>(Dim4::new(&[4, 2, 3, 5]));
+        let k = randu::<PrimitiveType>(Dim4::new(&[6, 2, 3, 5]));
+        let scores = einsum("qhdb,khdb->qkhb", &q, &k);
+        assert_eq!(scores.dims().get(), &[4, 6, 2, 5]);
+        let q_head = index(&q, &[Seq::default(), Seq::new(1., 1., 1.), Seq::default(), Seq::new(3., 3., 1.)]);
+        let k_head = index(&k, &[Seq::default(), Seq::new(1., 1., 1.), Seq::default(), Seq::new(3., 3., 1.)]);
+        let reference = matmul(&moddims(&q_head, Dim4::new(&[4, 3, 1, 1])), &moddims(&k_head, Dim4::new(&[6, 3, 1, 1])), MatProp::NONE, MatProp::TRANS);
+        let head = index(&scores, &[Seq::default(), Seq::default(), Seq::new(1., 1., 1.), Seq::new(3., 3., 1.)]);
+        assert!(max_all(&abs(&(moddims(&head, Dim4::new(&[4, 6, 1, 1])) - reference))).0 < 1e-5);
+    }
+
+    #[test]
+    fn test_concat_stack_tile_pad() {
+        let a = Tensor::new(&[1., 2.], Dim4::new(&[2, 1, 1, 1]));
+        let b = Tensor::new(&[3., 4.], Dim4::new(&[2, 1, 1, 1]));
+        let mut output = [0 as PrimitiveType; 4];
+
+        let concatenated = Tensor::concat(&[&a, &b], 0).unwrap();
+        assert_eq!(concatenated.dims().get(), &[4, 1, 1, 1]);
+        concatenated.host(&mut output);
+        assert_eq!(output, [1., 2., 3., 4.]);
+        assert!(Tensor::concat(&[&a, &concatenated], 1).is_err());
+        assert!(Tensor::concat(&[], 0).is_err());
+        assert!(Tensor::concat(&[&a], 4).is_err());
+
+        assert_eq!(Tensor::stack(&[&a, &b], 0).unwrap().dims().get(), &[2, 2, 1, 1]);
+        let batch = Tensor::stack(&[&a, &b, &a], 3).unwrap();
+        assert_eq!(batch.dims().get(), &[2, 1, 1, 3]);
+        assert!(Tensor::stack(&[&batch], 0).is_err());
+        assert!(Tensor::stack(&[&a, &concatenated], 3).is_err());
+
+        assert_eq!(a.tile(&[2, 3]).unwrap().dims().get(), &[4, 3, 1, 1]);
+        assert!(a.tile(&[1, 1, 1, 1, 2]).is_err());
+
+        let padded = a.pad(&[(1, 0), (0, 1)], -1.).unwrap();
+        assert_eq!(padded.dims().get(), &[3, 2, 1, 1]);
+        let mut padded_host = [0 as PrimitiveType; 6];
+        padded.host(&mut padded_host);
+        assert_eq!(padded_host, [-1., 1., 2., -1., -1., -1.]);
+    }
+
+    #[test]
+    fn test_masks() {
+        let x = Tensor::new(&[-2., -0.5, 0.5, 2.], Dim4::new(&[4, 1, 1, 1]));
+        let threshold = constant(1 as PrimitiveType, Dim4::new(&[1, 1, 1, 1]));
+        let mut output = [0 as PrimitiveType; 4];
+
+        // Huber loss with a threshold of one
+        let small = abs(&x).less_equal(&threshold).unwrap();
+        let huber = Tensor::select_where(&small, &(&x * &x * (0.5 as PrimitiveType)), &(abs(&x) - (0.5 as PrimitiveType))).unwrap();
+        huber.host(&mut output);
+        assert_eq!(output, [1.5, 0.125, 0.125, 1.5]);
+
+        let mut clipped = x.clone();
+        clipped.assign_where(&x.greater(&threshold).unwrap(), 1.).unwrap();
+        clipped.host(&mut output);
+        assert_eq!(output, [-2., -0.5, 0.5, 1.]);
+
+        let y = Tensor::new(&[1., 2.], Dim4::new(&[2, 1, 1, 1]));
+        assert!(x.equal(&y).is_err());
+        assert!(threshold.clone().assign_where(&small, 0.).is_err());
+    }
+
+    #[test]
+    fn test_topk() {
+        // Two samples with four classes
+        let y_pred = Tensor::new(&[0.1, 0.5, 0.3, 0.1, 0.7, 0.0, 0.2, 0.1], Dim4::new(&[4, 1, 1, 2]));
+        let (values, indices) = y_pred.topk(2, 0).unwrap();
+        assert_eq!(indices.dims().get(), &[2, 1, 1, 2]);
+        let mut values_host = [0 as PrimitiveType; 4];
+        let mut indices_host = [0u32; 4];
+        values.host(&mut values_host);
+        indices.host(&mut indices_host);
+        assert_eq!(values_host, [0.5, 0.3, 0.7, 0.2]);
+        assert_eq!(indices_host, [1, 2, 0, 2]);
+        assert!(y_pred.topk(5, 0).is_err());
+        assert!(y_pred.topk(1, 4).is_err());
+
+        let (_, order) = y_pred.argsort(3, true).unwrap();
+        let mut order_host = [0u32; 4];
+        index(&order, &[Seq::new(1., 1., 1.), Seq::default(), Seq::default(), Seq::default()]).host(&mut order_host[..2]);
+        assert_eq!(&order_host[..2], &[1, 0]);
+    }
+
+    #[test]
+    fn test_one_hot() {
+        let indices = Tensor::new(&[2., 0., 1.], Dim4::new(&[1, 1, 1, 3]));
+        let labels = Tensor::one_hot(&indices, 3).unwrap();
+        assert_eq!(labels.dims().get(), &[3, 1, 1, 3]);
+        let mut labels_host = [0 as PrimitiveType; 9];
+        labels.host(&mut labels_host);
+        assert_eq!(labels_host, [0., 0., 1., 1., 0., 0., 0., 1., 0.]);
+
+        let mut indices_host = [0 as PrimitiveType; 3];
+        labels.argmax_to_labels().host(&mut indices_host);
+        assert_eq!(indices_host, [2., 0., 1.]);
+
+        assert!(Tensor::one_hot(&indices, 2).is_err());
+        assert!(Tensor::one_hot(&Tensor::new(&[0.5], Dim4::new(&[1, 1, 1, 1])), 2).is_err());
+        assert!(Tensor::one_hot(&labels, 3).is_err());
+    }
+
+    #[test]
+    fn test_random_constructors() {
+        let dims = Dim4::new(&[1000, 1, 1, 1]);
+        let first = Tensor::randn(1., 2., dims, &random_engine(7));
+        let second = Tensor::randn(1., 2., dims, &random_engine(7));
+        assert_eq!(sum_all(&neq(&first, &second, false)).0, 0.);
+        assert!((mean_all(&first).0 - 1.).abs() < 0.3);
+
+        let uniform = Tensor::rand_uniform(-1., 3., dims, &random_engine(7));
+        assert!(min_all(&uniform).0 >= -1. && max_all(&uniform).0 < 3.);
+
+        let coins = Tensor::bernoulli(0.25, dims, &random_engine(7));
+        // The values are zeros and ones, hence the number of non-zero values is their sum
+        assert_eq!(count_all(&coins).0, sum_all(&coins).0 as u64);
+        assert!((mean_all(&coins).0 - 0.25).abs() < 0.05);
+
+        let integers = Tensor::randint(-2, 3, dims, &random_engine(7));
+        assert_eq!(sum_all(&neq(&integers, &floor(&integers), false)).0, 0.);
+        assert!(min_all(&integers).0 >= -2. && max_all(&integers).0 <= 2.);
+    }
+
+    #[test]
+    fn test_bytes() {
+        let tensor = Tensor::new(&[1., 2., 3., 4., 5., 6.], Dim4::new(&[2, 3, 1, 1]));
+        let bytes = tensor.to_bytes();
+        assert_eq!(bytes.len(), 40 + 6 * std::mem::size_of::<PrimitiveType>());
+
+        // The values are stored in row-major order
+        let first_row: Vec<PrimitiveType> = bytes[40..].chunks_exact(std::mem::size_of::<PrimitiveType>()).take(3)
+            .map(|value| PrimitiveType::from_le_bytes(value.try_into().unwrap()))
+            .collect();
+        assert_eq!(first_row, vec![1., 3., 5.]);
+
+        let restored = Tensor::from_bytes(&bytes).unwrap();
+        assert_eq!(restored.dims().get(), &[2, 3, 1, 1]);
+        assert_eq!(sum_all(&neq(&restored, &tensor, false)).0, 0.);
+
+        assert!(Tensor::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Tensor::from_bytes(b"NRTN").is_err());
+    }
+}
@@ -0,0 +1,679 @@
+//! Configuration and history of the training of a network.
+use arrayfire::*;
+
+use crate::errors::Error;
+use crate::metrics::Metrics;
+use crate::models::Network;
+use crate::schedulers::LearningRateSchedule;
+use crate::tensor::*;
+
+/// Configuration of the training performed by [Network::fit_with_config](../models/struct.Network.html#method.fit_with_config).
+///
+/// The configuration is created with the default values and modified with the builder methods:
+///
+/// ```ignore
+/// let config = FitConfig::new()
+///     .epochs(10)
+///     .batch_size(64)
+///     .metrics(vec![Metrics::Accuracy])
+///     .clip_gradient_norm(1.0);
+/// let history = nn.fit_with_config(&data, config)?;
+/// ```
+///
+/// The values are checked when the training starts, which returns an `InvalidFitConfig` error if one of them is
+/// invalid.
+pub struct FitConfig {
+    pub(crate) epochs: u64,
+    pub(crate) batch_size: u64,
+    pub(crate) validation_frequency: Option<u64>,
+    pub(crate) metrics: Option<Vec<Metrics>>,
+    pub(crate) class_weights: Option<Vec<PrimitiveType>>,
+    pub(crate) callbacks: Vec<Box<dyn Callback>>,
+    pub(crate) verbose: bool,
+    pub(crate) shuffle: bool,
+    pub(crate) clip_norm: Option<PrimitiveType>,
+    pub(crate) accumulation_steps: u64,
+    pub(crate) learning_rate_schedule: Option<Box<dyn LearningRateSchedule>>,
+    pub(crate) gradient_noise: Option<(PrimitiveType, PrimitiveType)>,
+    pub(crate) sam_radius: Option<PrimitiveType>,
+    pub(crate) monitor: Option<Monitor>,
+    pub(crate) evaluate_test: bool,
+    pub(crate) input_corruption: Option<InputCorruption>,
+    pub(crate) curriculum: Option<Box<dyn Curriculum>>,
+    pub(crate) seed: Option<u64>,
+    pub(crate) replay: Option<(u64, u64)>,
+    pub(crate) dp: Option<(f64, f64, PrimitiveType)>,
+}
+
+impl FitConfig {
+    /// Creates a training configuration with the default values.
+    ///
+    /// By default, the network is trained for one epoch with mini-batches of 32 samples. The losses are evaluated at
+    /// the end of each epoch, the samples are shuffled, the progress is printed, and the gradients are neither clipped
+    /// nor accumulated.
+    pub fn new() -> FitConfig {
+        FitConfig {
+            epochs: 1,
+            batch_size: 32,
+            validation_frequency: Some(1),
+            metrics: None,
+            class_weights: None,
+            callbacks: Vec::new(),
+            verbose: true,
+            shuffle: true,
+            clip_norm: None,
+            accumulation_steps: 1,
+            learning_rate_schedule: None,
+            gradient_noise: None,
+            sam_radius: None,
+            monitor: None,
+            evaluate_test: false,
+            input_corruption: None,
+            curriculum: None,
+            seed: None,
+            replay: None,
+            dp: None,
+        }
+    }
+
+    /// Sets the number of epochs.
+    pub fn epochs(mut self, epochs: u64) -> FitConfig {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Sets the number of samples in each mini-batch.
+    pub fn batch_size(mut self, batch_size: u64) -> FitConfig {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Sets the number of epochs between two evaluations of the losses and metrics.
+    ///
+    /// If `None`, the losses and metrics are never evaluated and the history is empty.
+    pub fn validation_frequency(mut self, frequency: Option<u64>) -> FitConfig {
+        self.validation_frequency = frequency;
+        self
+    }
+
+    /// Sets the metrics evaluated with the losses.
+    pub fn metrics(mut self, metrics: Vec<Metrics>) -> FitConfig {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Scales the contribution of each sample to the loss by the weight of its class.
+    ///
+    /// The weight of each sample is computed from its one-hot encoded label.
+    pub fn class_weights(mut self, class_weights: &[PrimitiveType]) -> FitConfig {
+        self.class_weights = Some(class_weights.to_vec());
+        self
+    }
+
+    /// Adds a callback invoked at the end of each epoch.
+    pub fn callback(mut self, callback: Box<dyn Callback>) -> FitConfig {
+        self.callbacks.push(callback);
+        self
+    }
+
+    /// Sets whether the progress is printed.
+    pub fn verbose(mut self, verbose: bool) -> FitConfig {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Sets whether the samples are shuffled at the beginning of each epoch.
+    pub fn shuffle(mut self, shuffle: bool) -> FitConfig {
+        self.shuffle = shuffle;
+        self
+    }
+
+    /// Rescales the gradients such that their global L2 norm doesn't exceed `max_norm`.
+    pub fn clip_gradient_norm(mut self, max_norm: PrimitiveType) -> FitConfig {
+        self.clip_norm = Some(max_norm);
+        self
+    }
+
+    /// Accumulates the gradients of `steps` mini-batches before the parameters are updated.
+    ///
+    /// The parameters are updated with the mean of the accumulated gradients, which emulates mini-batches `steps`
+    /// times larger when the memory of the device is limited.
+    pub fn accumulation_steps(mut self, steps: u64) -> FitConfig {
+        self.accumulation_steps = steps;
+        self
+    }
+
+    /// Sets the schedule of the learning rate.
+    ///
+    /// The learning rate of the optimizer is set before each optimization step from the number of steps performed by
+    /// the network, see [Network::num_steps](../models/struct.Network.html#method.num_steps). The steps are counted
+    /// across successive trainings, such that resuming the training of a saved network continues the schedule.
+    pub fn learning_rate_schedule(mut self, schedule: Box<dyn LearningRateSchedule>) -> FitConfig {
+        self.learning_rate_schedule = Some(schedule);
+        self
+    }
+
+    /// Adds annealed Gaussian noise to the gradients at each optimization step (Neelakantan et al., 2015).
+    ///
+    /// The noise has zero mean and a variance of eta / (1 + t)^gamma, where t is the number of steps performed by the
+    /// network. The noise is added before the gradients are clipped. The authors recommend a
+    /// value of eta in {0.01, 0.3, 1.0} and gamma = 0.55.
+    pub fn gradient_noise(mut self, eta: PrimitiveType, gamma: PrimitiveType) -> FitConfig {
+        self.gradient_noise = Some((eta, gamma));
+        self
+    }
+
+    /// Trains the network with Sharpness-Aware Minimization (Foret et al., 2020).
+    ///
+    /// At each mini-batch, the parameters are first moved by `radius` in the direction of the normalized gradients,
+    /// and the gradients computed at these perturbed parameters are used by the optimizer to update the original
+    /// parameters. This seeks flat minima, which usually generalize better, at the cost of two forward and backward
+    /// passes per mini-batch. A radius of 0.05 is a common choice.
+    pub fn sharpness_aware(mut self, radius: PrimitiveType) -> FitConfig {
+        self.sam_radius = Some(radius);
+        self
+    }
+
+    /// Tracks the best epoch of the training according to the monitored quantity.
+    ///
+    /// The parameters of the network at the best evaluated epoch are kept in memory and can be restored with
+    /// [Network::restore_best](../models/struct.Network.html#method.restore_best). The best epoch is recorded in the
+    /// history.
+    pub fn monitor(mut self, monitor: Monitor) -> FitConfig {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Sets whether the loss and metrics are evaluated on the test set at the end of the training.
+    ///
+    /// The test set is only evaluated if the data set has one. If a monitor is configured, the test set is evaluated
+    /// with the parameters of the best epoch and the parameters of the last epoch are restored afterwards. The results
+    /// are recorded in the history.
+    pub fn evaluate_test(mut self, evaluate_test: bool) -> FitConfig {
+        self.evaluate_test = evaluate_test;
+        self
+    }
+
+    /// Corrupts the training samples of each mini-batch before the forward pass, while the labels are left untouched.
+    ///
+    /// Combined with a data set whose labels are its samples, such as one created with
+    /// [TabularDataSet::from_unlabeled](../data/struct.TabularDataSet.html#method.from_unlabeled), the network learns
+    /// to reconstruct the clean samples from the corrupted ones. This pretrains representations on unlabeled data
+    /// before a supervised fine-tuning. A new corruption is drawn for every mini-batch, and the losses and metrics are
+    /// evaluated on the clean samples. The corruption is not applied to sparse samples.
+    pub fn input_corruption(mut self, corruption: InputCorruption) -> FitConfig {
+        self.input_corruption = Some(corruption);
+        self
+    }
+
+    /// Sets the curriculum selecting the training samples of each epoch and their order.
+    ///
+    /// The loss of each training sample is computed at the beginning of each epoch with the current parameters, as
+    /// with [Network::per_sample_loss](../models/struct.Network.html#method.per_sample_loss), and passed to the
+    /// curriculum, which replaces the shuffling of the samples. The curriculum only applies to dense training samples
+    /// stored in memory.
+    pub fn curriculum(mut self, curriculum: Box<dyn Curriculum>) -> FitConfig {
+        self.curriculum = Some(curriculum);
+        self
+    }
+
+    /// Sets the seed from which the seeds of the epochs are drawn, which makes the training reproducible.
+    ///
+    /// At the beginning of each epoch, a seed is drawn and recorded in the history. It seeds the order of the samples
+    /// and of the mini-batches, and each mini-batch derives from it a seed for the random engine of ArrayFire used by
+    /// the input corruption and for the random engines of the layers such as the dropout. The seed of a mini-batch is
+    /// given by [History::batch_seed](struct.History.html#method.batch_seed). By default, the seeds of the epochs are
+    /// drawn from the entropy of the system.
+    pub fn seed(mut self, seed: u64) -> FitConfig {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Trains the network with differentially private stochastic gradient descent (Abadi et al., 2016).
+    ///
+    /// The gradient of each sample of a mini-batch is computed separately and rescaled such that its L2 norm doesn't
+    /// exceed `clip`. Gaussian noise with a standard deviation of `sigma * clip` is added to the sum of the clipped
+    /// gradients, which is then divided by the batch size and passed to the optimizer. The noise multiplier `sigma` is
+    /// calibrated with the [privacy](../privacy/index.html) accountant such that the whole training satisfies
+    /// (`epsilon`, `delta`)-differential privacy, and the spent budget is recorded in the history.
+    ///
+    /// The per-sample gradients require one forward and backward pass per sample. The training samples must be dense
+    /// and stored in memory, and neither a curriculum nor sharpness-aware minimization can be used. The layers mixing
+    /// the samples of a mini-batch, such as the batch normalization, break the guarantee and must not be trained. A
+    /// `delta` smaller than the inverse of the number of training samples is recommended.
+    pub fn dp(mut self, epsilon: f64, delta: f64, clip: PrimitiveType) -> FitConfig {
+        self.dp = Some((epsilon, delta, clip));
+        self
+    }
+
+    /// Replays a single epoch of a previous training with the seed recorded in its history.
+    ///
+    /// The samples are presented in the same order and the same random values are drawn as during the original
+    /// epoch, and the step count of the network is reset to its value at the beginning of the epoch such that the
+    /// learning rate schedule and the gradient noise resume from the same step. Starting from the parameters and the
+    /// state of the optimizer at the beginning of the epoch, for instance restored from a saved network, the replay
+    /// reproduces the epoch exactly, which helps debugging a divergence. The number of epochs is set to 1, and a
+    /// curriculum, if any, is given the epoch 1.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `InvalidFitConfig` error if the epoch was not trained in the history.
+    pub fn replay_epoch(mut self, history: &History, epoch: u64) -> Result<FitConfig, Error> {
+        match (history.seed(epoch), history.start_step(epoch)) {
+            (Some(seed), Some(num_steps)) => {
+                self.epochs = 1;
+                self.replay = Some((seed, num_steps));
+                Ok(self)
+            },
+            _ => Err(Error::InvalidFitConfig(format!("the epoch {} was not trained in the history", epoch))),
+        }
+    }
+
+    /// Checks that the values of the configuration are valid.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        if self.batch_size == 0 {
+            return Err(Error::InvalidFitConfig(String::from("the batch size must be greater than zero")));
+        }
+        if self.validation_frequency == Some(0) {
+            return Err(Error::InvalidFitConfig(String::from("the validation frequency must be greater than zero")));
+        }
+        if let Some(max_norm) = self.clip_norm {
+            if !(max_norm > 0.) {
+                return Err(Error::InvalidFitConfig(format!("the maximum norm of the gradients must be positive, got {}", max_norm)));
+            }
+        }
+        if self.accumulation_steps == 0 {
+            return Err(Error::InvalidFitConfig(String::from("the number of accumulation steps must be greater than zero")));
+        }
+        if let Some((eta, gamma)) = self.gradient_noise {
+            if !(eta >= 0. && gamma >= 0.) {
+                return Err(Error::InvalidFitConfig(format!("the parameters of the gradient noise must be non-negative, got {} and {}", eta, gamma)));
+            }
+        }
+        if let Some(radius) = self.sam_radius {
+            if !(radius > 0.) {
+                return Err(Error::InvalidFitConfig(format!("the radius of the neighborhood must be positive, got {}", radius)));
+            }
+        }
+        if let Some((epsilon, delta, clip)) = self.dp {
+            if !(epsilon > 0.) {
+                return Err(Error::InvalidFitConfig(format!("the privacy budget must be positive, got {}", epsilon)));
+            }
+            if !(delta > 0. && delta < 1.) {
+                return Err(Error::InvalidFitConfig(format!("the delta must be in (0, 1), got {}", delta)));
+            }
+            if !(clip > 0.) {
+                return Err(Error::InvalidFitConfig(format!("the clipping norm must be positive, got {}", clip)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for FitConfig {
+    fn default() -> FitConfig {
+        FitConfig::new()
+    }
+}
+
+
+/// Corruptions of the training samples used to pretrain networks as denoising autoencoders.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputCorruption {
+    /// Adds Gaussian noise with zero mean and the given standard deviation to the samples.
+    GaussianNoise { std: PrimitiveType },
+    /// Replaces each value of the samples by `value` with the given probability.
+    Masking { probability: PrimitiveType, value: PrimitiveType },
+}
+
+impl InputCorruption {
+    /// Returns a corrupted copy of the samples.
+    pub fn apply(&self, x: &Tensor) -> Tensor {
+        match *self {
+            InputCorruption::GaussianNoise { std } => add(x, &mul(&randn::<PrimitiveType>(x.dims()), &std, false), false),
+            InputCorruption::Masking { probability, value } => {
+                let mask = lt(&randu::<PrimitiveType>(x.dims()), &probability, false);
+                select(&constant(value, x.dims()), &mask, x)
+            },
+        }
+    }
+}
+
+
+/// Losses and metrics evaluated at the end of an epoch.
+#[derive(Debug, Clone)]
+pub struct EpochLogs {
+    /// The epoch, starting at 1.
+    pub epoch: u64,
+    /// The loss on the training set.
+    pub train_loss: PrimitiveType,
+    /// The metrics on the training set, in the order given in the configuration.
+    pub train_metrics: Vec<PrimitiveType>,
+    /// The loss on the validation set, if any.
+    pub valid_loss: Option<PrimitiveType>,
+    /// The metrics on the validation set, if any.
+    pub valid_metrics: Option<Vec<PrimitiveType>>,
+}
+
+
+/// Quantities that can be monitored during the training.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MonitoredQuantity {
+    /// The loss on the training set.
+    TrainLoss,
+    /// The loss on the validation set.
+    ValidLoss,
+    /// The metric on the training set at the given index in the metrics of the configuration.
+    TrainMetric(usize),
+    /// The metric on the validation set at the given index in the metrics of the configuration.
+    ValidMetric(usize),
+}
+
+/// Quantity tracked to select the best epoch of the training.
+///
+/// A monitor is used by the training configuration to keep the best parameters of the network and can be used by the
+/// callbacks, for instance to stop the training when the monitored quantity no longer improves.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Monitor {
+    quantity: MonitoredQuantity,
+    higher_is_better: bool,
+}
+
+impl Monitor {
+    /// Creates a monitor of the given quantity.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantity` - The monitored quantity.
+    /// * `higher_is_better` - Whether higher values of the quantity are better, for instance for the accuracy.
+    pub fn new(quantity: MonitoredQuantity, higher_is_better: bool) -> Monitor {
+        Monitor { quantity, higher_is_better }
+    }
+
+    /// Creates a monitor of the validation loss, where lower values are better.
+    pub fn valid_loss() -> Monitor {
+        Monitor::new(MonitoredQuantity::ValidLoss, false)
+    }
+
+    /// Returns the monitored quantity.
+    pub fn quantity(&self) -> MonitoredQuantity {
+        self.quantity
+    }
+
+    /// Returns whether higher values of the monitored quantity are better.
+    pub fn higher_is_better(&self) -> bool {
+        self.higher_is_better
+    }
+
+    /// Returns the value of the monitored quantity in the logs of an epoch, if it was evaluated.
+    pub fn value(&self, logs: &EpochLogs) -> Option<PrimitiveType> {
+        match self.quantity {
+            MonitoredQuantity::TrainLoss => Some(logs.train_loss),
+            MonitoredQuantity::ValidLoss => logs.valid_loss,
+            MonitoredQuantity::TrainMetric(idx) => logs.train_metrics.get(idx).copied(),
+            MonitoredQuantity::ValidMetric(idx) => logs.valid_metrics.as_ref().and_then(|metrics| metrics.get(idx).copied()),
+        }
+    }
+
+    /// Returns whether a value improves on the best value, if any.
+    pub fn is_improvement(&self, value: PrimitiveType, best: Option<PrimitiveType>) -> bool {
+        match best {
+            Some(best) if self.higher_is_better => value > best,
+            Some(best) => value < best,
+            None => !value.is_nan(),
+        }
+    }
+}
+
+
+/// Derives the seed of a mini-batch, starting at 0, from the seed of its epoch.
+pub(crate) fn batch_seed(epoch_seed: u64, batch: u64) -> u64 {
+    // Spread the seeds of consecutive mini-batches with the golden ratio increment of SplitMix64
+    epoch_seed.wrapping_add((batch + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+
+/// History of the losses and metrics evaluated during the training.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    epochs: Vec<EpochLogs>,
+    monitor: Option<Monitor>,
+    best: Option<usize>,
+    test_loss: Option<PrimitiveType>,
+    test_metrics: Option<Vec<PrimitiveType>>,
+    seeds: Vec<u64>,
+    start_steps: Vec<u64>,
+    epsilon: Option<f64>,
+}
+
+impl History {
+    /// Creates an empty history.
+    pub fn new() -> History {
+        History::with_monitor(None)
+    }
+
+    /// Creates an empty history tracking the best epoch according to the monitor.
+    pub(crate) fn with_monitor(monitor: Option<Monitor>) -> History {
+        History { epochs: Vec::new(), monitor, best: None, test_loss: None, test_metrics: None, seeds: Vec::new(), start_steps: Vec::new(), epsilon: None }
+    }
+
+    /// Appends the logs of an epoch.
+    ///
+    /// # Return value
+    ///
+    /// Returns whether the epoch is the best epoch according to the monitor.
+    pub(crate) fn push(&mut self, logs: EpochLogs) -> bool {
+        let improved = match (&self.monitor, self.monitor.and_then(|monitor| monitor.value(&logs))) {
+            (Some(monitor), Some(value)) => monitor.is_improvement(value, self.best().and_then(|best| monitor.value(best))),
+            _ => false,
+        };
+        self.epochs.push(logs);
+        if improved { self.best = Some(self.epochs.len() - 1); }
+        improved
+    }
+
+    /// Records the seed of the next trained epoch and the number of optimization steps performed before it.
+    pub(crate) fn push_seed(&mut self, seed: u64, num_steps: u64) {
+        self.seeds.push(seed);
+        self.start_steps.push(num_steps);
+    }
+
+    /// Returns the seeds of all the trained epochs, including the epochs at which the losses were not evaluated.
+    ///
+    /// See [FitConfig::seed](struct.FitConfig.html#method.seed).
+    pub fn seeds(&self) -> &[u64] {
+        &self.seeds
+    }
+
+    /// Returns the seed of the given epoch, starting at 1, if it was trained.
+    pub fn seed(&self, epoch: u64) -> Option<u64> {
+        epoch.checked_sub(1).and_then(|idx| self.seeds.get(idx as usize)).copied()
+    }
+
+    /// Returns the seed of the given mini-batch of the given epoch, both starting at 1, if the epoch was trained.
+    ///
+    /// The mini-batches are counted in the order in which they were presented to the network.
+    pub fn batch_seed(&self, epoch: u64, batch: u64) -> Option<u64> {
+        if batch == 0 { return None; }
+        self.seed(epoch).map(|seed| batch_seed(seed, batch - 1))
+    }
+
+    /// Returns the number of optimization steps of the network before the given epoch, starting at 1, if it was
+    /// trained.
+    pub fn start_step(&self, epoch: u64) -> Option<u64> {
+        epoch.checked_sub(1).and_then(|idx| self.start_steps.get(idx as usize)).copied()
+    }
+
+    /// Returns the logs of the epochs at which the losses were evaluated.
+    pub fn epochs(&self) -> &[EpochLogs] {
+        &self.epochs
+    }
+
+    /// Returns the logs of the last evaluated epoch.
+    pub fn last(&self) -> Option<&EpochLogs> {
+        self.epochs.last()
+    }
+
+    /// Returns the logs of the best epoch according to the monitor of the configuration, if any.
+    pub fn best(&self) -> Option<&EpochLogs> {
+        self.best.map(|idx| &self.epochs[idx])
+    }
+
+    /// Records the loss and metrics evaluated on the test set at the end of the training.
+    pub(crate) fn set_test_results(&mut self, loss: PrimitiveType, metrics: Vec<PrimitiveType>) {
+        self.test_loss = Some(loss);
+        self.test_metrics = Some(metrics);
+    }
+
+    /// Returns the loss on the test set, if it was evaluated.
+    pub fn test_loss(&self) -> Option<PrimitiveType> {
+        self.test_loss
+    }
+
+    /// Returns the metrics on the test set, in the order given in the configuration, if they were evaluated.
+    pub fn test_metrics(&self) -> Option<&[PrimitiveType]> {
+        self.test_metrics.as_deref()
+    }
+
+    /// Records the privacy budget spent by a differentially private training.
+    pub(crate) fn set_epsilon(&mut self, epsilon: f64) {
+        self.epsilon = Some(epsilon);
+    }
+
+    /// Returns the ε spent by a differentially private training, see [FitConfig::dp](struct.FitConfig.html#method.dp).
+    pub fn epsilon(&self) -> Option<f64> {
+        self.epsilon
+    }
+
+    /// Returns the training losses.
+    pub fn train_loss(&self) -> Vec<PrimitiveType> {
+        self.epochs.iter().map(|logs| logs.train_loss).collect()
+    }
+
+    /// Returns the validation losses.
+    pub fn valid_loss(&self) -> Vec<PrimitiveType> {
+        self.epochs.iter().filter_map(|logs| logs.valid_loss).collect()
+    }
+}
+
+
+/// Trait implemented by the callbacks invoked during the training.
+pub trait Callback {
+    /// Called at the end of each epoch.
+    ///
+    /// The network can be modified, for instance to adjust the learning rate. The history contains the logs of the
+    /// epochs evaluated so far. Returning `false` stops the training.
+    fn on_epoch_end(&mut self, network: &mut Network, epoch: u64, history: &History) -> bool;
+}
+
+
+/// Trait implemented by the curricula, which select the training samples of each epoch and the order in which they are
+/// presented to the network.
+pub trait Curriculum {
+    /// Returns the indices of the training samples used during the epoch, in the order in which they are presented.
+    ///
+    /// # Arguments
+    ///
+    /// * `epoch` - The epoch, starting at 1.
+    /// * `losses` - The loss of each training sample computed with the current parameters of the network.
+    fn select(&mut self, epoch: u64, losses: &[PrimitiveType]) -> Vec<u64>;
+}
+
+
+/// Self-paced curriculum training on the easiest samples first.
+///
+/// At each epoch, the samples with the lowest losses are kept and presented from the easiest to the hardest. The
+/// fraction of samples kept grows linearly from `initial_fraction` at the first epoch to all the samples after
+/// `warmup_epochs` epochs.
+#[derive(Debug, Copy, Clone)]
+pub struct SelfPacedCurriculum {
+    initial_fraction: f64,
+    warmup_epochs: u64,
+}
+
+impl SelfPacedCurriculum {
+    /// Creates a self-paced curriculum.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the initial fraction is not in (0, 1] or if the number of warmup epochs is zero.
+    pub fn new(initial_fraction: f64, warmup_epochs: u64) -> Box<SelfPacedCurriculum> {
+        assert!(initial_fraction > 0. && initial_fraction <= 1., "The initial fraction must be between 0 and 1.");
+        assert!(warmup_epochs > 0, "The number of warmup epochs must be greater than zero.");
+        Box::new(SelfPacedCurriculum { initial_fraction, warmup_epochs })
+    }
+}
+
+impl Curriculum for SelfPacedCurriculum {
+    fn select(&mut self, epoch: u64, losses: &[PrimitiveType]) -> Vec<u64> {
+        let progress = ((epoch - 1) as f64 / self.warmup_epochs as f64).min(1.);
+        let fraction = self.initial_fraction + (1. - self.initial_fraction) * progress;
+        let num_samples = ((fraction * losses.len() as f64).ceil() as usize).max(1).min(losses.len());
+
+        // The NaN losses are considered the hardest
+        let mut indices: Vec<u64> = (0..losses.len() as u64).collect();
+        indices.sort_by(|&a, &b| {
+            let (a, b) = (losses[a as usize], losses[b as usize]);
+            a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+        });
+        indices.truncate(num_samples);
+        indices
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use arrayfire::*;
+    use crate::training::*;
+
+    fn logs(epoch: u64, valid_loss: PrimitiveType, valid_accuracy: PrimitiveType) -> EpochLogs {
+        EpochLogs {
+            epoch,
+            train_loss: 1.,
+            train_metrics: Vec::new(),
+            valid_loss: Some(valid_loss),
+            valid_metrics: Some(vec![valid_accuracy]),
+        }
+    }
+
+    #[test]
+    fn test_history_best_epoch() {
+        let mut history = History::with_monitor(Some(Monitor::valid_loss()));
+        assert!(history.push(logs(1, 0.5, 0.7)));
+        assert!(history.push(logs(2, 0.3, 0.6)));
+        assert!(!history.push(logs(3, 0.4, 0.8)));
+        assert_eq!(history.best().unwrap().epoch, 2);
+
+        let mut history = History::with_monitor(Some(Monitor::new(MonitoredQuantity::ValidMetric(0), true)));
+        for (epoch, (loss, accuracy)) in [(0.5, 0.7), (0.3, 0.6), (0.4, 0.8)].iter().enumerate() {
+            history.push(logs(epoch as u64 + 1, *loss, *accuracy));
+        }
+        assert_eq!(history.best().unwrap().epoch, 3);
+        assert!(History::new().best().is_none());
+    }
+
+    #[test]
+    fn test_input_corruption() {
+        let x = constant(1 as PrimitiveType, Dim4::new(&[10, 10, 1, 2]));
+        let mut output = [0 as PrimitiveType; 200];
+
+        InputCorruption::Masking { probability: 1., value: -1. }.apply(&x).host(&mut output);
+        assert!(output.iter().all(|&value| value == -1.));
+        InputCorruption::Masking { probability: 0., value: -1. }.apply(&x).host(&mut output);
+        assert!(output.iter().all(|&value| value == 1.));
+
+        InputCorruption::GaussianNoise { std: 0.1 }.apply(&x).host(&mut output);
+        assert!(output.iter().any(|&value| value != 1.));
+        assert!(output.iter().all(|&value| (value - 1.).abs() < 1.));
+    }
+
+    #[test]
+    fn test_self_paced_curriculum() {
+        let losses = [0.4, 0.1, 0.3, 0.2];
+        let mut curriculum = SelfPacedCurriculum::new(0.5, 2);
+        assert_eq!(curriculum.select(1, &losses), vec![1, 3]);
+        assert_eq!(curriculum.select(2, &losses), vec![1, 3, 2]);
+        assert_eq!(curriculum.select(3, &losses), vec![1, 3, 2, 0]);
+    }
+}